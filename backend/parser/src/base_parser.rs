@@ -2,6 +2,10 @@ use crate::{ParsedMessage, ParseError};
 use std::io::Read;
 use tracing::{debug, trace};
 
+/// Lazily parsed messages. Parsers read their input as the stream is
+/// consumed, so memory stays flat regardless of file size.
+pub type MessageStream = Box<dyn Iterator<Item = Result<ParsedMessage, ParseError>>>;
+
 /// Base trait that all parsers must implement
 /// Uses Box<dyn Read> to be object-safe (dyn compatible)
 pub trait Parser: Send + Sync {
@@ -27,8 +31,28 @@ pub trait Parser: Send + Sync {
     fn can_parse_impl(&self, data: &[u8]) -> bool;
 
     /// Parse the data from a reader
-    /// Returns a Vec for simplicity (can be optimized to iterator later if needed)
-    fn parse(&self, reader: Box<dyn Read>) -> Result<Vec<ParsedMessage>, ParseError>;
+    /// Errors returned here mean the input is not in this format; errors
+    /// yielded by the stream are problems found further into the file.
+    fn parse(&self, reader: Box<dyn Read>) -> Result<MessageStream, ParseError>;
+
+    /// Parse everything into memory - convenient for small inputs and tests
+    fn parse_all(&self, reader: Box<dyn Read>) -> Result<Vec<ParsedMessage>, ParseError> {
+        self.parse(reader)?.collect()
+    }
+}
+
+/// Ends a stream after its first error, so callers never see parsing resume
+/// from a broken position
+pub(crate) fn stop_on_error<I>(messages: I) -> MessageStream
+where
+    I: Iterator<Item = Result<ParsedMessage, ParseError>> + 'static,
+{
+    let mut failed = false;
+    Box::new(messages.take_while(move |result| {
+        let keep = !failed;
+        failed |= result.is_err();
+        keep
+    }))
 }
 
 /// Helper to detect format from content
@@ -1,4 +1,4 @@
-use crate::{ParsedMessage, ParseError, base_parser::Parser};
+use crate::{ParsedMessage, ParseError, base_parser::{stop_on_error, MessageStream, Parser}};
 use csv::Reader;
 use serde::Deserialize;
 use std::io::Read;
@@ -37,48 +37,45 @@ impl Parser for CsvParser {
         trimmed.starts_with("ts_iso,") || trimmed.contains(",dir,") || trimmed.contains(",s,f,")
     }
 
-    fn parse(&self, reader: Box<dyn Read>) -> Result<Vec<ParsedMessage>, ParseError> {
+    fn parse(&self, reader: Box<dyn Read>) -> Result<MessageStream, ParseError> {
         info!("Starting CSV parsing");
         let mut csv_reader = Reader::from_reader(reader);
-        let mut messages = Vec::new();
-        let mut row_num = 0;
+        csv_reader.headers().map_err(|e| {
+            error!("Failed to read CSV header: {}", e);
+            e
+        })?;
 
-        for record_result in csv_reader.deserialize::<CsvRecord>() {
-            row_num += 1;
-            let record = match record_result {
-                Ok(r) => r,
-                Err(e) => {
+        let messages = csv_reader.into_deserialize::<CsvRecord>()
+            .enumerate()
+            .map(|(idx, record_result)| {
+                let row_num = idx + 1;
+                let record = record_result.map_err(|e| {
                     error!("Failed to deserialize CSV row {}: {}", row_num, e);
-                    return Err(e.into());
-                }
-            };
+                    e
+                })?;
 
-            // Parse the body_json string as JSON
-            let body_json: serde_json::Value = match serde_json::from_str(&record.body_json) {
-                Ok(json) => json,
-                Err(e) => {
+                // Parse the body_json string as JSON
+                let body_json: serde_json::Value = serde_json::from_str(&record.body_json).map_err(|e| {
                     error!("Failed to parse body_json on row {}: {}", row_num, e);
                     warn!("Problematic JSON: {}", &record.body_json[..record.body_json.len().min(100)]);
-                    return Err(e.into());
-                }
-            };
+                    e
+                })?;
 
-            debug!("Parsed CSV row {} successfully (s={}, f={})", row_num, record.s, record.f);
+                debug!("Parsed CSV row {} successfully (s={}, f={})", row_num, record.s, record.f);
 
-            messages.push(ParsedMessage {
-                ts_iso: record.ts_iso,
-                dir: record.dir,
-                s: record.s,
-                f: record.f,
-                wbit: record.wbit,
-                sysbytes: record.sysbytes,
-                ceid: record.ceid,
-                body_json,
+                Ok(ParsedMessage {
+                    ts_iso: record.ts_iso,
+                    dir: record.dir,
+                    s: record.s,
+                    f: record.f,
+                    wbit: record.wbit,
+                    sysbytes: record.sysbytes,
+                    ceid: record.ceid,
+                    body_json,
+                })
             });
-        }
 
-        info!("CSV parsing complete: {} messages parsed", messages.len());
-        Ok(messages)
+        Ok(stop_on_error(messages))
     }
 }
 
@@ -95,7 +92,7 @@ mod tests {
 2025-11-03T09:12:15.456Z,H->E,1,3,1,12346,0,"{""semantic"":{""kind"":""EventReport""}}"#;
         
         let cursor = Cursor::new(data);
-        let messages = parser.parse(Box::new(cursor)).unwrap();
+        let messages = parser.parse_all(Box::new(cursor)).unwrap();
         
         assert_eq!(messages.len(), 2);
         
@@ -0,0 +1,340 @@
+//! HSMS (SEMI E37) framing
+//!
+//! A frame on the wire is a 4-byte big-endian length followed by a 10-byte
+//! header and, for data messages, the SECS-II item bytes. Control messages
+//! (Select, Linktest, Separate, ...) become rows with `s = 0` and `f = SType`,
+//! so a request and its response pair up like a data transaction
+//! (`f` and `f+1`, same system bytes, opposite direction).
+
+use crate::secs2::{extract_ceid, SecsItem};
+use crate::{ParsedMessage, ParseError};
+use serde_json::json;
+use std::collections::HashMap;
+
+pub const HEADER_LEN: usize = 10;
+
+/// Frames larger than this are treated as corrupt rather than allocated
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Session type (header byte 5)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SType {
+    Data,
+    SelectReq,
+    SelectRsp,
+    DeselectReq,
+    DeselectRsp,
+    LinktestReq,
+    LinktestRsp,
+    RejectReq,
+    SeparateReq,
+}
+
+impl SType {
+    pub fn from_byte(b: u8) -> Option<Self> {
+        Some(match b {
+            0 => SType::Data,
+            1 => SType::SelectReq,
+            2 => SType::SelectRsp,
+            3 => SType::DeselectReq,
+            4 => SType::DeselectRsp,
+            5 => SType::LinktestReq,
+            6 => SType::LinktestRsp,
+            7 => SType::RejectReq,
+            9 => SType::SeparateReq,
+            _ => return None,
+        })
+    }
+
+    pub fn as_byte(self) -> u8 {
+        match self {
+            SType::Data => 0,
+            SType::SelectReq => 1,
+            SType::SelectRsp => 2,
+            SType::DeselectReq => 3,
+            SType::DeselectRsp => 4,
+            SType::LinktestReq => 5,
+            SType::LinktestRsp => 6,
+            SType::RejectReq => 7,
+            SType::SeparateReq => 9,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SType::Data => "Data",
+            SType::SelectReq => "Select.req",
+            SType::SelectRsp => "Select.rsp",
+            SType::DeselectReq => "Deselect.req",
+            SType::DeselectRsp => "Deselect.rsp",
+            SType::LinktestReq => "Linktest.req",
+            SType::LinktestRsp => "Linktest.rsp",
+            SType::RejectReq => "Reject.req",
+            SType::SeparateReq => "Separate.req",
+        }
+    }
+
+    /// Control requests that expect a response
+    pub fn expects_reply(self) -> bool {
+        matches!(self, SType::SelectReq | SType::DeselectReq | SType::LinktestReq)
+    }
+}
+
+/// Decoded HSMS frame (without the length prefix)
+#[derive(Debug, Clone, PartialEq)]
+pub struct HsmsFrame {
+    pub session_id: u16,
+    /// Header byte 2: W-bit + stream for data, 0 or status for control
+    pub byte2: u8,
+    /// Header byte 3: function for data, status/reason code for control
+    pub byte3: u8,
+    pub ptype: u8,
+    pub stype: SType,
+    pub system_bytes: u32,
+    pub body: Option<SecsItem>,
+}
+
+impl HsmsFrame {
+    /// Decode a frame from header + body bytes (length prefix already stripped)
+    pub fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        if data.len() < HEADER_LEN {
+            return Err(format!("HSMS frame too short: {} bytes", data.len()).into());
+        }
+
+        let ptype = data[4];
+        if ptype != 0 {
+            return Err(format!("Unsupported HSMS PType {}", ptype).into());
+        }
+        let stype = SType::from_byte(data[5])
+            .ok_or_else(|| ParseError::Custom(format!("Unknown HSMS SType {}", data[5])))?;
+
+        let body_bytes = &data[HEADER_LEN..];
+        let body = if body_bytes.is_empty() {
+            None
+        } else {
+            let (item, used) = SecsItem::decode(body_bytes)?;
+            if used != body_bytes.len() {
+                return Err(format!(
+                    "HSMS frame has {} trailing bytes after SECS-II body",
+                    body_bytes.len() - used
+                ).into());
+            }
+            Some(item)
+        };
+
+        Ok(HsmsFrame {
+            session_id: u16::from_be_bytes([data[0], data[1]]),
+            byte2: data[2],
+            byte3: data[3],
+            ptype,
+            stype,
+            system_bytes: u32::from_be_bytes([data[6], data[7], data[8], data[9]]),
+            body,
+        })
+    }
+
+    /// Encode with the 4-byte length prefix
+    pub fn encode(&self) -> Vec<u8> {
+        let body = self.body.as_ref().map(|b| b.encode()).unwrap_or_default();
+        let mut out = Vec::with_capacity(4 + HEADER_LEN + body.len());
+        out.extend_from_slice(&((HEADER_LEN + body.len()) as u32).to_be_bytes());
+        out.extend_from_slice(&self.session_id.to_be_bytes());
+        out.push(self.byte2);
+        out.push(self.byte3);
+        out.push(self.ptype);
+        out.push(self.stype.as_byte());
+        out.extend_from_slice(&self.system_bytes.to_be_bytes());
+        out.extend_from_slice(&body);
+        out
+    }
+
+    /// Data message `SxFy` with optional W-bit and body
+    pub fn data(session_id: u16, s: u8, f: u8, wbit: bool, system_bytes: u32, body: Option<SecsItem>) -> Self {
+        HsmsFrame {
+            session_id,
+            byte2: (s & 0x7F) | if wbit { 0x80 } else { 0 },
+            byte3: f,
+            ptype: 0,
+            stype: SType::Data,
+            system_bytes,
+            body,
+        }
+    }
+
+    /// Header-only control message
+    pub fn control(session_id: u16, stype: SType, status: u8, system_bytes: u32) -> Self {
+        HsmsFrame {
+            session_id,
+            byte2: 0,
+            byte3: status,
+            ptype: 0,
+            stype,
+            system_bytes,
+            body: None,
+        }
+    }
+
+    /// (s, f, wbit) as stored in the row; control messages use s=0, f=SType
+    pub fn sfw(&self) -> (u8, u8, u8) {
+        match self.stype {
+            SType::Data => (self.byte2 & 0x7F, self.byte3, self.byte2 >> 7),
+            control => (0, control.as_byte(), control.expects_reply() as u8),
+        }
+    }
+
+    pub fn into_message(self, ts_iso: String, dir: &str) -> ParsedMessage {
+        let (s, f, wbit) = self.sfw();
+
+        let (ceid, body_json) = match self.stype {
+            SType::Data => {
+                let ceid = self.body.as_ref().map(|b| extract_ceid(s, f, b)).unwrap_or(0);
+                let tree = self.body.as_ref().map(|b| b.to_json()).unwrap_or(serde_json::Value::Null);
+                (ceid, json!({"secs_tree": tree}))
+            }
+            control => (0, json!({"semantic": {
+                "kind": "HsmsControl",
+                "stype": control.name(),
+                "session_id": self.session_id,
+                "status": self.byte3,
+            }})),
+        };
+
+        ParsedMessage {
+            ts_iso,
+            dir: dir.to_string(),
+            s,
+            f,
+            wbit,
+            sysbytes: self.system_bytes,
+            ceid,
+            body_json,
+        }
+    }
+}
+
+/// Quick structural check used by `can_parse`: does `data` start with a
+/// plausible length-prefixed HSMS frame?
+pub fn looks_like_frame(data: &[u8]) -> bool {
+    if data.len() < 4 + HEADER_LEN {
+        return false;
+    }
+    let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let header = &data[4..4 + HEADER_LEN];
+    (HEADER_LEN..=MAX_FRAME_LEN).contains(&len)
+        && header[4] == 0
+        && SType::from_byte(header[5]).is_some()
+        && (header[5] == 0 || len == HEADER_LEN)
+}
+
+/// Conventional originator of a primary per SEMI E5, for streams where
+/// the frame alone does not say who sent it. `None` = either side.
+pub fn primary_direction(s: u8, f: u8) -> Option<&'static str> {
+    const H_TO_E: &str = "H->E";
+    const E_TO_H: &str = "E->H";
+    match (s, f) {
+        (1, 3) | (1, 11) | (1, 15) | (1, 17) | (1, 19) | (1, 21) | (1, 23) => Some(H_TO_E),
+        (2, 13) | (2, 15) | (2, 19) | (2, 21) | (2, 23) | (2, 29) | (2, 31) | (2, 33)
+        | (2, 35) | (2, 37) | (2, 39) | (2, 41) | (2, 43) | (2, 45) | (2, 47) | (2, 49) => Some(H_TO_E),
+        (2, 17) => Some(E_TO_H),
+        (5, 1) | (5, 9) | (5, 11) | (5, 13) | (5, 15) | (5, 17) => Some(E_TO_H),
+        (5, 3) | (5, 5) | (5, 7) => Some(H_TO_E),
+        (6, 1) | (6, 3) | (6, 5) | (6, 7) | (6, 9) | (6, 11) | (6, 13) => Some(E_TO_H),
+        (6, 15) | (6, 17) | (6, 19) | (6, 21) | (6, 23) => Some(H_TO_E),
+        (9, _) => Some(E_TO_H),
+        (10, 1) => Some(E_TO_H),
+        (10, 3) | (10, 5) => Some(H_TO_E),
+        // Control: the host is normally the active entity
+        (0, 1) | (0, 3) | (0, 5) | (0, 9) => Some(H_TO_E),
+        _ => None,
+    }
+}
+
+/// Assigns directions to frames from a single interleaved stream that does
+/// not record who sent what. Replies take the opposite direction of the
+/// open primary with the same system bytes; primaries fall back to
+/// `primary_direction`, then to `default_dir`.
+pub struct DirectionTracker {
+    default_dir: &'static str,
+    open: HashMap<(u8, u32), &'static str>,
+}
+
+impl DirectionTracker {
+    pub fn new(default_dir: &'static str) -> Self {
+        Self {
+            default_dir,
+            open: HashMap::new(),
+        }
+    }
+
+    pub fn direction(&mut self, frame: &HsmsFrame) -> &'static str {
+        let (s, f, wbit) = frame.sfw();
+        let key = (s, frame.system_bytes);
+
+        if wbit == 1 {
+            let dir = primary_direction(s, f).unwrap_or(self.default_dir);
+            self.open.insert(key, dir);
+            return dir;
+        }
+
+        let is_reply = f % 2 == 0;
+        match self.open.get(&key) {
+            Some(&primary_dir) if is_reply => {
+                self.open.remove(&key);
+                opposite(primary_dir)
+            }
+            _ => primary_direction(s, f).unwrap_or(self.default_dir),
+        }
+    }
+}
+
+pub fn opposite(dir: &str) -> &'static str {
+    if dir == "H->E" { "E->H" } else { "H->E" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let frame = HsmsFrame::data(1, 6, 11, true, 0x01020304, Some(SecsItem::List(vec![
+            SecsItem::U4(vec![0]),
+            SecsItem::U4(vec![201]),
+            SecsItem::List(vec![]),
+        ])));
+        let bytes = frame.encode();
+        assert!(looks_like_frame(&bytes));
+
+        let decoded = HsmsFrame::decode(&bytes[4..]).unwrap();
+        assert_eq!(decoded, frame);
+
+        let msg = decoded.into_message("2025-11-03T09:12:14.123Z".into(), "E->H");
+        assert_eq!((msg.s, msg.f, msg.wbit, msg.ceid), (6, 11, 1, 201));
+        assert_eq!(msg.sysbytes, 0x01020304);
+    }
+
+    #[test]
+    fn test_control_rows() {
+        let select = HsmsFrame::control(0xFFFF, SType::SelectReq, 0, 7);
+        let msg = select.into_message("2025-11-03T09:12:14Z".into(), "H->E");
+        assert_eq!((msg.s, msg.f, msg.wbit), (0, 1, 1));
+        assert_eq!(msg.body_json["semantic"]["stype"], "Select.req");
+
+        let separate = HsmsFrame::control(0xFFFF, SType::SeparateReq, 0, 8);
+        assert_eq!(separate.sfw(), (0, 9, 0));
+    }
+
+    #[test]
+    fn test_direction_tracker_pairs_replies() {
+        let mut tracker = DirectionTracker::new("H->E");
+        let s6f11 = HsmsFrame::data(1, 6, 11, true, 5, None);
+        let s6f12 = HsmsFrame::data(1, 6, 12, false, 5, None);
+        let linktest = HsmsFrame::control(0xFFFF, SType::LinktestReq, 0, 6);
+        let linktest_rsp = HsmsFrame::control(0xFFFF, SType::LinktestRsp, 0, 6);
+
+        assert_eq!(tracker.direction(&s6f11), "E->H");
+        assert_eq!(tracker.direction(&linktest), "H->E");
+        assert_eq!(tracker.direction(&s6f12), "H->E");
+        assert_eq!(tracker.direction(&linktest_rsp), "E->H");
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{ParseError, base_parser::{stop_on_error, MessageStream, Parser}};
+use crate::hsms::{looks_like_frame, DirectionTracker, HsmsFrame, HEADER_LEN, MAX_FRAME_LEN};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use std::io::{BufReader, ErrorKind, Read};
+use tracing::{debug, info, error};
+
+/// HSMS binary parser - handles raw SEMI E37 frame dumps
+/// (4-byte length, 10-byte header, SECS-II body, back to back).
+///
+/// Raw frames carry neither a timestamp nor a direction. Rows are stamped
+/// `base_time + index * frame_interval` to preserve order, and directions are
+/// inferred with `DirectionTracker` (replies oppose their primary).
+pub struct HsmsParser {
+    pub base_time: DateTime<Utc>,
+    pub frame_interval: Duration,
+}
+
+impl Default for HsmsParser {
+    fn default() -> Self {
+        Self {
+            base_time: DateTime::UNIX_EPOCH,
+            frame_interval: Duration::milliseconds(1),
+        }
+    }
+}
+
+impl Parser for HsmsParser {
+    fn name(&self) -> &'static str {
+        "hsms"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["hsms", "bin"]
+    }
+
+    fn can_parse_impl(&self, data: &[u8]) -> bool {
+        looks_like_frame(data)
+    }
+
+    fn parse(&self, reader: Box<dyn Read>) -> Result<MessageStream, ParseError> {
+        info!("Starting HSMS binary parsing");
+        let mut reader = BufReader::new(reader);
+        let mut directions = DirectionTracker::new("H->E");
+        let base_time = self.base_time;
+        let frame_interval = self.frame_interval;
+        let mut index: i32 = 0;
+        let mut offset: u64 = 0;
+
+        let messages = std::iter::from_fn(move || {
+            let mut len_buf = [0u8; 4];
+            match reader.read_exact(&mut len_buf) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    info!("HSMS parsing complete: {} messages parsed", index);
+                    return None;
+                }
+                Err(e) => return Some(Err(e.into())),
+            }
+
+            let len = u32::from_be_bytes(len_buf) as usize;
+            if !(HEADER_LEN..=MAX_FRAME_LEN).contains(&len) {
+                error!("Invalid HSMS frame length {} at byte offset {}", len, offset);
+                return Some(Err(format!("Invalid HSMS frame length {} at byte offset {}", len, offset).into()));
+            }
+
+            let mut frame_buf = vec![0u8; len];
+            if let Err(e) = reader.read_exact(&mut frame_buf) {
+                error!("Truncated HSMS frame at byte offset {}: {}", offset, e);
+                return Some(Err(e.into()));
+            }
+
+            let frame = match HsmsFrame::decode(&frame_buf) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    error!("Failed to decode HSMS frame at byte offset {}: {}", offset, e);
+                    return Some(Err(e));
+                }
+            };
+
+            let ts = base_time + frame_interval * index;
+            let dir = directions.direction(&frame);
+            let msg = frame.into_message(ts.to_rfc3339_opts(SecondsFormat::Nanos, true), dir);
+            debug!("Parsed HSMS frame at offset {} (s={}, f={})", offset, msg.s, msg.f);
+
+            index += 1;
+            offset += 4 + len as u64;
+            Some(Ok(msg))
+        });
+
+        Ok(stop_on_error(messages))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hsms::SType;
+    use crate::secs2::SecsItem;
+    use std::io::Cursor;
+
+    fn capture() -> Vec<u8> {
+        let frames = [
+            HsmsFrame::control(0xFFFF, SType::SelectReq, 0, 1),
+            HsmsFrame::control(0xFFFF, SType::SelectRsp, 0, 1),
+            HsmsFrame::data(1, 1, 3, true, 2, Some(SecsItem::List(vec![SecsItem::U4(vec![501])]))),
+            HsmsFrame::data(1, 1, 4, false, 2, Some(SecsItem::List(vec![SecsItem::Ascii("A001".into())]))),
+            HsmsFrame::control(0xFFFF, SType::SeparateReq, 0, 3),
+        ];
+        frames.iter().flat_map(|f| f.encode()).collect()
+    }
+
+    #[test]
+    fn test_hsms_can_parse() {
+        let parser = HsmsParser::default();
+        assert!(parser.can_parse(&capture()));
+        assert!(!parser.can_parse(b"ts_iso,dir,s,f,wbit,sysbytes,ceid,body_json\n"));
+    }
+
+    #[test]
+    fn test_hsms_parse() {
+        let parser = HsmsParser::default();
+        let messages = parser.parse_all(Box::new(Cursor::new(capture()))).unwrap();
+
+        assert_eq!(messages.len(), 5);
+        assert_eq!((messages[0].s, messages[0].f, messages[0].dir.as_str()), (0, 1, "H->E"));
+        assert_eq!((messages[1].s, messages[1].f, messages[1].dir.as_str()), (0, 2, "E->H"));
+        assert_eq!((messages[3].s, messages[3].f, messages[3].dir.as_str()), (1, 4, "E->H"));
+        assert_eq!(messages[3].body_json["secs_tree"]["items"][0]["v"], "A001");
+        assert_eq!(messages[4].body_json["semantic"]["stype"], "Separate.req");
+        assert_eq!(messages[1].ts_iso, "1970-01-01T00:00:00.001000000Z");
+    }
+
+    #[test]
+    fn test_hsms_truncated_frame() {
+        let parser = HsmsParser::default();
+        let mut data = capture();
+        data.truncate(data.len() - 3);
+        assert!(parser.parse_all(Box::new(Cursor::new(data))).is_err());
+    }
+}
@@ -1,5 +1,6 @@
-use crate::{ParsedMessage, ParseError, base_parser::Parser};
-use std::io::Read;
+use crate::{ParsedMessage, ParseError, base_parser::{MessageStream, Parser}};
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Read};
 use tracing::{info, error};
 
 /// JSON parser - handles regular JSON array format
@@ -23,25 +24,100 @@ impl Parser for JsonParser {
         trimmed.starts_with('[')
     }
 
-    fn parse(&self, mut reader: Box<dyn Read>) -> Result<Vec<ParsedMessage>, ParseError> {
+    fn parse(&self, reader: Box<dyn Read>) -> Result<MessageStream, ParseError> {
         info!("Starting JSON array parsing");
-        let mut buffer = Vec::new();
+        let mut stream = JsonArrayStream {
+            reader: BufReader::new(reader),
+            parsed: 0,
+            done: false,
+        };
 
-        if let Err(e) = reader.read_to_end(&mut buffer) {
-            error!("Failed to read JSON data: {}", e);
-            return Err(e.into());
+        match stream.next_byte()? {
+            Some(b'[') => Ok(Box::new(stream)),
+            _ => {
+                error!("Failed to parse JSON array: input does not start with '['");
+                Err("Expected a JSON array".into())
+            }
+        }
+    }
+}
+
+/// Yields the elements of a top-level JSON array one at a time, so the
+/// whole document never has to be held in memory
+struct JsonArrayStream {
+    reader: BufReader<Box<dyn Read>>,
+    parsed: usize,
+    done: bool,
+}
+
+impl JsonArrayStream {
+    /// Next non-whitespace byte, without consuming it
+    fn peek_byte(&mut self) -> std::io::Result<Option<u8>> {
+        loop {
+            let buf = self.reader.fill_buf()?;
+            let Some(&b) = buf.first() else {
+                return Ok(None);
+            };
+            if b.is_ascii_whitespace() {
+                self.reader.consume(1);
+            } else {
+                return Ok(Some(b));
+            }
+        }
+    }
+
+    fn next_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let b = self.peek_byte()?;
+        if b.is_some() {
+            self.reader.consume(1);
+        }
+        Ok(b)
+    }
+
+    fn next_message(&mut self) -> Result<Option<ParsedMessage>, ParseError> {
+        // Separator or end of array
+        match self.peek_byte()? {
+            Some(b']') => {
+                self.reader.consume(1);
+                if self.peek_byte()?.is_some() {
+                    return Err("Trailing characters after JSON array".into());
+                }
+                return Ok(None);
+            }
+            Some(b',') if self.parsed > 0 => self.reader.consume(1),
+            Some(_) if self.parsed == 0 => {}
+            Some(b) => return Err(format!("Expected ',' or ']' in JSON array, found '{}'", b as char).into()),
+            None => return Err("Unexpected end of JSON array".into()),
         }
 
-        info!("Read {} bytes of JSON data", buffer.len());
+        // Objects are self-delimiting, so the deserializer stops right after '}'
+        let mut de = serde_json::Deserializer::from_reader(&mut self.reader);
+        let msg = ParsedMessage::deserialize(&mut de)?;
+        Ok(Some(msg))
+    }
+}
 
-        match serde_json::from_slice::<Vec<ParsedMessage>>(&buffer) {
-            Ok(messages) => {
-                info!("JSON parsing complete: {} messages parsed", messages.len());
-                Ok(messages)
+impl Iterator for JsonArrayStream {
+    type Item = Result<ParsedMessage, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_message() {
+            Ok(Some(msg)) => {
+                self.parsed += 1;
+                Some(Ok(msg))
+            }
+            Ok(None) => {
+                info!("JSON parsing complete: {} messages parsed", self.parsed);
+                self.done = true;
+                None
             }
             Err(e) => {
-                error!("Failed to parse JSON array: {}", e);
-                Err(e.into())
+                error!("Failed to parse JSON array element {}: {}", self.parsed, e);
+                self.done = true;
+                Some(Err(e))
             }
         }
     }
@@ -72,11 +148,23 @@ mod tests {
         ]"#;
         
         let cursor = Cursor::new(data);
-        let messages = parser.parse(Box::new(cursor)).unwrap();
+        let messages = parser.parse_all(Box::new(cursor)).unwrap();
         
         assert_eq!(messages.len(), 2);
         assert_eq!(messages[0].s, 6);
         assert_eq!(messages[0].dir, "E->H");
     }
-}
 
+    #[test]
+    fn test_json_parse_malformed() {
+        let parser = JsonParser;
+        assert!(parser.parse(Box::new(Cursor::new("{}"))).is_err());
+        assert_eq!(parser.parse_all(Box::new(Cursor::new(" [ ] "))).unwrap().len(), 0);
+
+        let truncated = r#"[{"ts_iso":"2025-11-03T09:12:14.123Z","dir":"E->H","s":6,"f":11,"wbit":0,"sysbytes":1,"body_json":{}},"#;
+        let results: Vec<_> = parser.parse(Box::new(Cursor::new(truncated))).unwrap().collect();
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert_eq!(results.len(), 2);
+    }
+}
@@ -6,10 +6,18 @@ pub mod base_parser;
 pub mod registry_parser;
 pub mod parsers;  // Central parser registration module
 
+// SECS-II / HSMS protocol decoding shared by binary and text parsers
+pub mod secs2;
+pub mod hsms;
+pub mod pcap;
+
 // Individual parser implementations
 pub mod csv_parser;
 pub mod ndjson_parser;
 pub mod json_parser;
+pub mod hsms_parser;
+pub mod sml_parser;
+pub mod pcap_parser;
 
 // Legacy compatibility - keep old function names
 pub mod ndjson {
@@ -19,28 +27,23 @@ pub mod ndjson {
     /// Legacy function for backward compatibility
     pub fn parse_ndjson<R: BufRead + 'static>(reader: R) -> impl Iterator<Item = Result<ParsedMessage, ParseError>> {
         let parser = NdjsonParser;
-        
-        // Convert to Vec then into_iter for now (can optimize later)
-        match parser.parse(Box::new(reader)) {
-            Ok(messages) => {
-                let results: Vec<Result<ParsedMessage, ParseError>> = 
-                    messages.into_iter().map(Ok).collect();
-                results.into_iter()
-            }
-            Err(e) => {
-                vec![Err(e)].into_iter()
-            }
-        }
+
+        parser.parse(Box::new(reader))
+            .unwrap_or_else(|e| Box::new(std::iter::once(Err(e))))
     }
 }
 
 // Re-export main types
 pub use types::*;
-pub use base_parser::{Parser, FormatHint};
+pub use base_parser::{Parser, FormatHint, MessageStream};
 pub use registry_parser::ParserRegistry;
+pub use parsers::ParserOptions;
 
 // Re-export parsers
 pub use csv_parser::CsvParser;
 pub use ndjson_parser::NdjsonParser;
 pub use json_parser::JsonParser;
+pub use hsms_parser::HsmsParser;
+pub use sml_parser::SmlParser;
+pub use pcap_parser::{Endpoint, PcapOptions, PcapParser};
 
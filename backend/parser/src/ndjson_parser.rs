@@ -1,4 +1,4 @@
-use crate::{ParsedMessage, ParseError, base_parser::Parser};
+use crate::{ParsedMessage, ParseError, base_parser::{stop_on_error, MessageStream, Parser}};
 use std::io::{BufRead, BufReader, Read};
 use tracing::{debug, info, warn, error};
 
@@ -32,42 +32,41 @@ impl Parser for NdjsonParser {
         }
     }
 
-    fn parse(&self, reader: Box<dyn Read>) -> Result<Vec<ParsedMessage>, ParseError> {
+    fn parse(&self, reader: Box<dyn Read>) -> Result<MessageStream, ParseError> {
         info!("Starting NDJSON parsing");
         let buf_reader = BufReader::new(reader);
-        let mut messages = Vec::new();
-        let mut line_num = 0;
 
-        for line_result in buf_reader.lines() {
-            line_num += 1;
-            let line = match line_result {
-                Ok(l) => l,
-                Err(e) => {
-                    error!("Failed to read line {}: {}", line_num, e);
-                    return Err(e.into());
-                }
-            };
-            let line = line.trim();
-
-            if line.is_empty() {
-                continue;
-            }
+        let messages = buf_reader.lines()
+            .enumerate()
+            .filter_map(|(idx, line_result)| {
+                let line_num = idx + 1;
+                let line = match line_result {
+                    Ok(l) => l,
+                    Err(e) => {
+                        error!("Failed to read line {}: {}", line_num, e);
+                        return Some(Err(e.into()));
+                    }
+                };
 
-            match serde_json::from_str::<ParsedMessage>(&line) {
-                Ok(msg) => {
-                    debug!("Parsed message {} successfully (s={}, f={})", line_num, msg.s, msg.f);
-                    messages.push(msg);
+                let line = line.trim();
+                if line.is_empty() {
+                    return None;
                 }
-                Err(e) => {
-                    error!("Failed to parse JSON on line {}: {}", line_num, e);
-                    warn!("Problematic line content: {}", &line[..line.len().min(100)]);
-                    return Err(e.into());
+
+                match serde_json::from_str::<ParsedMessage>(line) {
+                    Ok(msg) => {
+                        debug!("Parsed message {} successfully (s={}, f={})", line_num, msg.s, msg.f);
+                        Some(Ok(msg))
+                    }
+                    Err(e) => {
+                        error!("Failed to parse JSON on line {}: {}", line_num, e);
+                        warn!("Problematic line content: {}", &line[..line.len().min(100)]);
+                        Some(Err(e.into()))
+                    }
                 }
-            }
-        }
+            });
 
-        info!("NDJSON parsing complete: {} messages parsed", messages.len());
-        Ok(messages)
+        Ok(stop_on_error(messages))
     }
 }
 
@@ -96,7 +95,7 @@ mod tests {
 {"ts_iso":"2025-11-03T09:12:15.456Z","dir":"H->E","s":1,"f":3,"wbit":1,"sysbytes":12346,"body_json":{"semantic":{"kind":"EventReport"}}}"#;
         
         let cursor = Cursor::new(data);
-        let messages = parser.parse(Box::new(cursor)).unwrap();
+        let messages = parser.parse_all(Box::new(cursor)).unwrap();
         
         assert_eq!(messages.len(), 2);
         assert_eq!(messages[0].s, 6);
@@ -1,17 +1,35 @@
-/// Central parser registration module
-///
-/// This module provides a simple way to register all parsers in one place.
-/// When adding a new parser, you only need to:
-/// 1. Create your parser file (e.g., xml_parser.rs)
-/// 2. Export it in lib.rs: `pub mod xml_parser; pub use xml_parser::XmlParser;`
-/// 3. Add it to the `all_parsers()` function below
-///
-/// That's it! No need to modify base_parser.rs or registry_parser.rs.
+//! Central parser registration module
+//!
+//! This module provides a simple way to register all parsers in one place.
+//! When adding a new parser, you only need to:
+//! 1. Create your parser file (e.g., xml_parser.rs)
+//! 2. Export it in lib.rs: `pub mod xml_parser; pub use xml_parser::XmlParser;`
+//! 3. Add it to the `all_parsers()` function below
+//!
+//! That's it! No need to modify base_parser.rs or registry_parser.rs.
 
 use crate::base_parser::Parser;
-use crate::{CsvParser, JsonParser, NdjsonParser};
+use crate::{CsvParser, HsmsParser, JsonParser, NdjsonParser, PcapOptions, PcapParser, SmlParser};
+use chrono::Duration;
 use tracing::info;
 
+/// Settings of the parsers that take any
+#[derive(Debug, Clone)]
+pub struct ParserOptions {
+    pub pcap: PcapOptions,
+    /// Spacing of the timestamps given to raw HSMS frames
+    pub hsms_frame_interval: Duration,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            pcap: PcapOptions::default(),
+            hsms_frame_interval: HsmsParser::default().frame_interval,
+        }
+    }
+}
+
 /// Returns a vector of all available parsers.
 ///
 /// ## Adding a New Parser
@@ -33,13 +51,24 @@ use tracing::info;
 ///     ]
 /// }
 /// ```
+///
+/// Parsers keep their defaults here (pcap: HSMS port 5000, no endpoint mapping);
+/// use `all_parsers_with` to apply configured `ParserOptions`.
 pub fn all_parsers() -> Vec<Box<dyn Parser>> {
+    all_parsers_with(&ParserOptions::default())
+}
+
+/// All parsers, configured with `options`
+pub fn all_parsers_with(options: &ParserOptions) -> Vec<Box<dyn Parser>> {
     info!("Initializing parser collection");
 
     vec![
         Box::new(NdjsonParser) as Box<dyn Parser>,
         Box::new(CsvParser) as Box<dyn Parser>,
         Box::new(JsonParser) as Box<dyn Parser>,
+        Box::new(SmlParser) as Box<dyn Parser>,
+        Box::new(PcapParser::new(options.pcap.clone())) as Box<dyn Parser>,
+        Box::new(HsmsParser { frame_interval: options.hsms_frame_interval, ..HsmsParser::default() }) as Box<dyn Parser>,
         // Add new parsers here:
         // Box::new(XmlParser) as Box<dyn Parser>,
         // Box::new(CustomParser) as Box<dyn Parser>,
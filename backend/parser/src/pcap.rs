@@ -0,0 +1,314 @@
+//! Packet capture decoding for the PCAP parser
+//!
+//! Reads classic libpcap and pcapng files and peels link, IP and TCP headers
+//! off each packet. Only what HSMS reassembly needs is kept: capture time,
+//! endpoints, TCP sequencing and payload.
+
+use crate::ParseError;
+use std::io::{ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const PCAP_MAGIC_US: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NS: u32 = 0xA1B2_3C4D;
+const PCAPNG_SHB: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+/// pcapng blocks above this size are treated as corrupt
+const MAX_BLOCK_LEN: usize = 64 * 1024 * 1024;
+
+/// Link-layer header types we can strip
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// One captured packet, still carrying its link-layer header
+pub struct RawPacket {
+    pub ts_ns: i64,
+    pub linktype: u32,
+    pub data: Vec<u8>,
+}
+
+/// True if the sample starts with a pcap or pcapng magic number
+pub fn is_capture(data: &[u8]) -> bool {
+    let Some(magic) = data.get(..4) else {
+        return false;
+    };
+    let be = u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]]);
+    let le = u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]);
+    be == PCAPNG_SHB
+        || [PCAP_MAGIC_US, PCAP_MAGIC_NS].contains(&be)
+        || [PCAP_MAGIC_US, PCAP_MAGIC_NS].contains(&le)
+}
+
+/// Iterates packets of a pcap or pcapng stream
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    format: Format,
+}
+
+enum Format {
+    Pcap { big_endian: bool, ns_per_tick: i64, linktype: u32 },
+    PcapNg { big_endian: bool, interfaces: Vec<Interface> },
+}
+
+struct Interface {
+    linktype: u32,
+    /// Timestamp units per second (10^6 unless `if_tsresol` says otherwise)
+    ticks_per_sec: u64,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, ParseError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        let format = if u32::from_be_bytes(magic) == PCAPNG_SHB {
+            let big_endian = read_section_header(&mut reader)?;
+            Format::PcapNg { big_endian, interfaces: Vec::new() }
+        } else {
+            let (big_endian, nanos) = match (u32::from_be_bytes(magic), u32::from_le_bytes(magic)) {
+                (PCAP_MAGIC_US, _) => (true, false),
+                (PCAP_MAGIC_NS, _) => (true, true),
+                (_, PCAP_MAGIC_US) => (false, false),
+                (_, PCAP_MAGIC_NS) => (false, true),
+                _ => return Err("Not a pcap/pcapng capture".into()),
+            };
+            let mut header = [0u8; 20];
+            reader.read_exact(&mut header)?;
+            let linktype = read_u32(&header[16..20], big_endian) & 0x0FFF_FFFF;
+            Format::Pcap { big_endian, ns_per_tick: if nanos { 1 } else { 1_000 }, linktype }
+        };
+
+        Ok(Self { reader, format })
+    }
+
+    /// Next packet, or `None` at a clean end of file
+    pub fn next_packet(&mut self) -> Result<Option<RawPacket>, ParseError> {
+        match &mut self.format {
+            Format::Pcap { big_endian, ns_per_tick, linktype } => {
+                let mut header = [0u8; 16];
+                if !read_exact_or_eof(&mut self.reader, &mut header)? {
+                    return Ok(None);
+                }
+                let secs = read_u32(&header[0..4], *big_endian) as i64;
+                let frac = read_u32(&header[4..8], *big_endian) as i64;
+                let caplen = read_u32(&header[8..12], *big_endian) as usize;
+                if caplen > MAX_BLOCK_LEN {
+                    return Err(format!("pcap record length {} is too large", caplen).into());
+                }
+
+                let mut data = vec![0u8; caplen];
+                self.reader.read_exact(&mut data)?;
+                Ok(Some(RawPacket {
+                    ts_ns: secs * 1_000_000_000 + frac * *ns_per_tick,
+                    linktype: *linktype,
+                    data,
+                }))
+            }
+            Format::PcapNg { big_endian, interfaces } => loop {
+                let mut header = [0u8; 8];
+                if !read_exact_or_eof(&mut self.reader, &mut header)? {
+                    return Ok(None);
+                }
+                let block_type = read_u32(&header[0..4], *big_endian);
+                let total_len = read_u32(&header[4..8], *big_endian) as usize;
+                if !(12..=MAX_BLOCK_LEN).contains(&total_len) {
+                    return Err(format!("pcapng block length {} is invalid", total_len).into());
+                }
+
+                // Body plus trailing length copy
+                let mut body = vec![0u8; total_len - 8];
+                self.reader.read_exact(&mut body)?;
+                let body = &body[..body.len() - 4];
+
+                match block_type {
+                    PCAPNG_SHB => {
+                        *big_endian = section_is_big_endian(body)?;
+                        interfaces.clear();
+                    }
+                    // Interface Description Block
+                    1 => {
+                        if body.len() < 8 {
+                            return Err("pcapng interface block truncated".into());
+                        }
+                        let linktype = read_u16(&body[0..2], *big_endian) as u32;
+                        let ticks_per_sec = parse_tsresol(&body[8..], *big_endian);
+                        interfaces.push(Interface { linktype, ticks_per_sec });
+                    }
+                    // Enhanced Packet Block
+                    6 => {
+                        if body.len() < 20 {
+                            return Err("pcapng packet block truncated".into());
+                        }
+                        let iface = read_u32(&body[0..4], *big_endian) as usize;
+                        let ts = ((read_u32(&body[4..8], *big_endian) as u64) << 32)
+                            | read_u32(&body[8..12], *big_endian) as u64;
+                        let caplen = read_u32(&body[12..16], *big_endian) as usize;
+                        let data = body.get(20..20 + caplen)
+                            .ok_or("pcapng packet data truncated")?
+                            .to_vec();
+                        let interface = interfaces.get(iface)
+                            .ok_or_else(|| ParseError::Custom(format!("pcapng packet for unknown interface {}", iface)))?;
+
+                        let ts_ns = (ts as u128 * 1_000_000_000 / interface.ticks_per_sec as u128) as i64;
+                        return Ok(Some(RawPacket { ts_ns, linktype: interface.linktype, data }));
+                    }
+                    // Other blocks (statistics, name resolution, simple packets) are skipped
+                    _ => {}
+                }
+            },
+        }
+    }
+}
+
+fn read_section_header<R: Read>(reader: &mut R) -> Result<bool, ParseError> {
+    let mut len_and_magic = [0u8; 8];
+    reader.read_exact(&mut len_and_magic)?;
+    let big_endian = section_is_big_endian(&len_and_magic[4..])?;
+    let total_len = read_u32(&len_and_magic[0..4], big_endian) as usize;
+    if !(28..=MAX_BLOCK_LEN).contains(&total_len) {
+        return Err(format!("pcapng section header length {} is invalid", total_len).into());
+    }
+    let mut rest = vec![0u8; total_len - 12];
+    reader.read_exact(&mut rest)?;
+    Ok(big_endian)
+}
+
+fn section_is_big_endian(body: &[u8]) -> Result<bool, ParseError> {
+    let magic = body.get(..4).ok_or("pcapng section header truncated")?;
+    let magic = [magic[0], magic[1], magic[2], magic[3]];
+    if u32::from_be_bytes(magic) == PCAPNG_BYTE_ORDER_MAGIC {
+        Ok(true)
+    } else if u32::from_le_bytes(magic) == PCAPNG_BYTE_ORDER_MAGIC {
+        Ok(false)
+    } else {
+        Err("pcapng byte-order magic missing".into())
+    }
+}
+
+/// `if_tsresol` (option 9) from interface block options; default microseconds
+fn parse_tsresol(mut options: &[u8], big_endian: bool) -> u64 {
+    while options.len() >= 4 {
+        let code = read_u16(&options[0..2], big_endian);
+        let len = read_u16(&options[2..4], big_endian) as usize;
+        if code == 0 {
+            break;
+        }
+        if code == 9 && len >= 1 && options.len() > 4 {
+            let v = options[4];
+            let exp = (v & 0x7F) as u32;
+            return if v & 0x80 == 0 {
+                10u64.checked_pow(exp).unwrap_or(1_000_000)
+            } else {
+                2u64.checked_pow(exp).unwrap_or(1_000_000)
+            };
+        }
+        let padded = 4 + (len + 3) / 4 * 4;
+        options = options.get(padded..).unwrap_or(&[]);
+    }
+    1_000_000
+}
+
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, ParseError> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let b = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let b = [bytes[0], bytes[1]];
+    if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) }
+}
+
+/// A decoded TCP segment
+#[derive(Debug, Clone)]
+pub struct TcpSegment<'a> {
+    pub src: (IpAddr, u16),
+    pub dst: (IpAddr, u16),
+    pub seq: u32,
+    pub syn: bool,
+    pub fin: bool,
+    pub rst: bool,
+    pub payload: &'a [u8],
+}
+
+/// Strip link, IP and TCP headers. Returns `None` for anything that is not
+/// an unfragmented TCP segment over IPv4/IPv6.
+pub fn decode_tcp(linktype: u32, data: &[u8]) -> Option<TcpSegment<'_>> {
+    let ip = match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut ethertype = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+            let mut offset = 14;
+            // 802.1Q / QinQ tags
+            while ethertype == 0x8100 || ethertype == 0x88A8 {
+                ethertype = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]);
+                offset += 4;
+            }
+            if ethertype != 0x0800 && ethertype != 0x86DD {
+                return None;
+            }
+            data.get(offset..)?
+        }
+        LINKTYPE_LINUX_SLL => data.get(16..)?,
+        LINKTYPE_LINUX_SLL2 => data.get(20..)?,
+        LINKTYPE_NULL | LINKTYPE_LOOP => data.get(4..)?,
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => data,
+        _ => return None,
+    };
+
+    let (src_ip, dst_ip, tcp) = match ip.first()? >> 4 {
+        4 => {
+            let ihl = ((ip[0] & 0x0F) as usize) * 4;
+            let total_len = u16::from_be_bytes([*ip.get(2)?, *ip.get(3)?]) as usize;
+            let flags_frag = u16::from_be_bytes([*ip.get(6)?, *ip.get(7)?]);
+            // More-fragments set or non-zero offset: not reassembled here
+            if ip.get(9)? != &6 || flags_frag & 0x3FFF != 0 {
+                return None;
+            }
+            let addrs = ip.get(12..20)?;
+            let src = Ipv4Addr::new(addrs[0], addrs[1], addrs[2], addrs[3]);
+            let dst = Ipv4Addr::new(addrs[4], addrs[5], addrs[6], addrs[7]);
+            // Ethernet padding may follow the IP datagram
+            let end = total_len.min(ip.len());
+            (IpAddr::V4(src), IpAddr::V4(dst), ip.get(ihl..end)?)
+        }
+        6 => {
+            let payload_len = u16::from_be_bytes([*ip.get(4)?, *ip.get(5)?]) as usize;
+            if ip.get(6)? != &6 {
+                return None;
+            }
+            let src: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            let end = (40 + payload_len).min(ip.len());
+            (IpAddr::V6(Ipv6Addr::from(src)), IpAddr::V6(Ipv6Addr::from(dst)), ip.get(40..end)?)
+        }
+        _ => return None,
+    };
+
+    let data_offset = ((*tcp.get(12)? >> 4) as usize) * 4;
+    let flags = *tcp.get(13)?;
+    if data_offset < 20 {
+        return None;
+    }
+    Some(TcpSegment {
+        src: (src_ip, u16::from_be_bytes([tcp[0], tcp[1]])),
+        dst: (dst_ip, u16::from_be_bytes([tcp[2], tcp[3]])),
+        seq: u32::from_be_bytes([tcp[4], tcp[5], tcp[6], tcp[7]]),
+        syn: flags & 0x02 != 0,
+        fin: flags & 0x01 != 0,
+        rst: flags & 0x04 != 0,
+        payload: tcp.get(data_offset..)?,
+    })
+}
@@ -0,0 +1,471 @@
+use crate::{ParsedMessage, ParseError, base_parser::{stop_on_error, MessageStream, Parser}};
+use crate::hsms::{looks_like_frame, HsmsFrame, HEADER_LEN, MAX_FRAME_LEN};
+use crate::pcap::{decode_tcp, is_capture, CaptureReader, TcpSegment};
+use chrono::{DateTime, SecondsFormat};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use tracing::{debug, info, warn};
+
+/// Out-of-order bytes buffered per flow before the gap is given up on
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+
+/// An IP and/or port identifying one side of the HSMS connection.
+/// Parsed from `10.0.0.5:5000`, `10.0.0.5`, `[fe80::1]:5000` or `:5000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub ip: Option<IpAddr>,
+    pub port: Option<u16>,
+}
+
+impl Endpoint {
+    pub fn matches(&self, addr: &(IpAddr, u16)) -> bool {
+        self.ip.map_or(true, |ip| ip == addr.0) && self.port.map_or(true, |port| port == addr.1)
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(addr) = SocketAddr::from_str(s) {
+            return Ok(Endpoint { ip: Some(addr.ip()), port: Some(addr.port()) });
+        }
+        if let Ok(ip) = IpAddr::from_str(s.trim_matches(|c| c == '[' || c == ']')) {
+            return Ok(Endpoint { ip: Some(ip), port: None });
+        }
+        if let Some(port) = s.strip_prefix(':') {
+            let port = port.parse().map_err(|_| format!("Invalid port in endpoint '{}'", s))?;
+            return Ok(Endpoint { ip: None, port: Some(port) });
+        }
+        Err(format!("Invalid endpoint '{}': expected ip, ip:port or :port", s))
+    }
+}
+
+/// Which TCP traffic to decode and who is who
+#[derive(Debug, Clone)]
+pub struct PcapOptions {
+    /// TCP ports carrying HSMS; segments on other ports are ignored
+    pub hsms_ports: Vec<u16>,
+    /// Host endpoints; traffic they send is H->E
+    pub host: Vec<Endpoint>,
+    /// Equipment endpoints; traffic they send is E->H
+    pub equipment: Vec<Endpoint>,
+}
+
+impl Default for PcapOptions {
+    fn default() -> Self {
+        Self {
+            hsms_ports: vec![5000],
+            host: Vec::new(),
+            equipment: Vec::new(),
+        }
+    }
+}
+
+impl PcapOptions {
+    /// Direction of a segment from `src` to `dst`. Explicit host/equipment
+    /// endpoints win; otherwise the side listening on the HSMS port is taken
+    /// to be the (passive) equipment.
+    pub fn direction(&self, src: &(IpAddr, u16), dst: &(IpAddr, u16)) -> &'static str {
+        if self.equipment.iter().any(|e| e.matches(src)) || self.host.iter().any(|h| h.matches(dst)) {
+            return "E->H";
+        }
+        if self.host.iter().any(|h| h.matches(src)) || self.equipment.iter().any(|e| e.matches(dst)) {
+            return "H->E";
+        }
+        if self.hsms_ports.contains(&src.1) { "E->H" } else { "H->E" }
+    }
+}
+
+/// PCAP parser - reassembles HSMS sessions from tcpdump/Wireshark captures
+/// (`.pcap` and `.pcapng`). Timestamps come from the packet that completes
+/// each message; directions from `PcapOptions`.
+#[derive(Default)]
+pub struct PcapParser {
+    pub options: PcapOptions,
+}
+
+impl PcapParser {
+    pub fn new(options: PcapOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl Parser for PcapParser {
+    fn name(&self) -> &'static str {
+        "pcap"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["pcap", "pcapng", "cap"]
+    }
+
+    fn can_parse_impl(&self, data: &[u8]) -> bool {
+        is_capture(data)
+    }
+
+    fn parse(&self, reader: Box<dyn std::io::Read>) -> Result<MessageStream, ParseError> {
+        info!("Starting PCAP parsing (HSMS ports {:?})", self.options.hsms_ports);
+        let mut capture = CaptureReader::new(reader)?;
+        let options = self.options.clone();
+        let mut flows: HashMap<FlowKey, TcpFlow> = HashMap::new();
+        // Frames completed by the last packet, not yet handed out
+        let mut ready: VecDeque<ParsedMessage> = VecDeque::new();
+        let mut packets = 0usize;
+        let mut emitted = 0usize;
+
+        let messages = std::iter::from_fn(move || loop {
+            if let Some(msg) = ready.pop_front() {
+                emitted += 1;
+                return Some(Ok(msg));
+            }
+
+            let packet = match capture.next_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    info!("PCAP parsing complete: {} messages from {} packets", emitted, packets);
+                    return None;
+                }
+                Err(e) => return Some(Err(e)),
+            };
+            packets += 1;
+
+            let Some(segment) = decode_tcp(packet.linktype, &packet.data) else {
+                continue;
+            };
+            if !options.hsms_ports.contains(&segment.src.1) && !options.hsms_ports.contains(&segment.dst.1) {
+                continue;
+            }
+
+            let key = (segment.src, segment.dst);
+            let flow = flows.entry(key).or_insert_with(TcpFlow::new);
+            let dir = options.direction(&segment.src, &segment.dst);
+
+            let frames = match flow.push(&segment) {
+                Ok(frames) => frames,
+                Err(e) => return Some(Err(e)),
+            };
+            for frame in frames {
+                let ts = DateTime::from_timestamp_nanos(packet.ts_ns)
+                    .to_rfc3339_opts(SecondsFormat::AutoSi, true);
+                let msg = frame.into_message(ts, dir);
+                debug!("Reassembled HSMS message from packet {} (s={}, f={})", packets, msg.s, msg.f);
+                ready.push_back(msg);
+            }
+
+            if segment.rst || segment.fin {
+                flows.remove(&key);
+            }
+        });
+
+        Ok(stop_on_error(messages))
+    }
+}
+
+type FlowKey = ((IpAddr, u16), (IpAddr, u16));
+
+/// One direction of a TCP connection, reassembled into HSMS frames
+struct TcpFlow {
+    next_seq: Option<u32>,
+    /// Segments that arrived ahead of `next_seq`
+    pending: BTreeMap<u32, Vec<u8>>,
+    pending_bytes: usize,
+    /// In-order stream bytes not yet consumed as frames
+    buffer: Vec<u8>,
+    /// Joined mid-connection, so the buffer may start inside a frame
+    needs_resync: bool,
+}
+
+impl TcpFlow {
+    fn new() -> Self {
+        Self {
+            next_seq: None,
+            pending: BTreeMap::new(),
+            pending_bytes: 0,
+            buffer: Vec::new(),
+            needs_resync: false,
+        }
+    }
+
+    /// Feed a segment; returns every frame it completes
+    fn push(&mut self, segment: &TcpSegment) -> Result<Vec<HsmsFrame>, ParseError> {
+        if segment.syn {
+            self.next_seq = Some(segment.seq.wrapping_add(1));
+            self.pending.clear();
+            self.pending_bytes = 0;
+            self.buffer.clear();
+            self.needs_resync = false;
+        }
+        if segment.payload.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let next = *self.next_seq.get_or_insert_with(|| {
+            // Capture started mid-connection
+            self.needs_resync = true;
+            segment.seq
+        });
+
+        let offset = segment.seq.wrapping_sub(next) as i32;
+        if offset > 0 {
+            let overflow = self.pending_bytes + segment.payload.len() > MAX_PENDING_BYTES;
+            self.pending_bytes += segment.payload.len();
+            if let Some(replaced) = self.pending.insert(segment.seq, segment.payload.to_vec()) {
+                self.pending_bytes -= replaced.len();
+            }
+            if !overflow {
+                return Ok(Vec::new());
+            }
+            warn!("Dropping TCP gap of {} bytes: too much out-of-order data", offset);
+            self.skip_gap();
+        } else {
+            self.accept(segment.seq, segment.payload);
+        }
+
+        self.drain_pending();
+        self.take_frames()
+    }
+
+    /// Append the part of `payload` at `seq` that is new (handles retransmits)
+    fn accept(&mut self, seq: u32, payload: &[u8]) {
+        let next = self.next_seq.unwrap_or(seq);
+        let overlap = next.wrapping_sub(seq) as i32;
+        if overlap < 0 || overlap as usize >= payload.len() {
+            return;
+        }
+        self.buffer.extend_from_slice(&payload[overlap as usize..]);
+        self.next_seq = Some(seq.wrapping_add(payload.len() as u32));
+    }
+
+    fn drain_pending(&mut self) {
+        while let Some(next) = self.next_seq {
+            let ready = self.pending.keys()
+                .copied()
+                .find(|&seq| (seq.wrapping_sub(next) as i32) <= 0);
+            let Some(seq) = ready else { break };
+            let payload = self.pending.remove(&seq).unwrap_or_default();
+            self.pending_bytes -= payload.len();
+            self.accept(seq, &payload);
+        }
+    }
+
+    /// Give up on missing bytes and continue from the earliest pending segment
+    fn skip_gap(&mut self) {
+        if let Some((&seq, _)) = self.pending.iter().next() {
+            self.next_seq = Some(seq);
+            self.buffer.clear();
+            self.needs_resync = true;
+        }
+    }
+
+    fn take_frames(&mut self) -> Result<Vec<HsmsFrame>, ParseError> {
+        let mut frames = Vec::new();
+        let mut pos = 0;
+
+        loop {
+            if self.needs_resync {
+                match (pos..self.buffer.len()).find(|&i| looks_like_frame(&self.buffer[i..])) {
+                    Some(start) => {
+                        pos = start;
+                        self.needs_resync = false;
+                    }
+                    None => {
+                        // Only a tail shorter than a frame header could still start one
+                        pos = pos.max(self.buffer.len().saturating_sub(4 + HEADER_LEN - 1));
+                        break;
+                    }
+                }
+            }
+
+            let Some(len_bytes) = self.buffer.get(pos..pos + 4) else { break };
+            let len = u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+            if !(HEADER_LEN..=MAX_FRAME_LEN).contains(&len) {
+                warn!("Invalid HSMS length {} in TCP stream, resynchronizing", len);
+                self.needs_resync = true;
+                pos += 1;
+                continue;
+            }
+
+            let Some(frame_bytes) = self.buffer.get(pos + 4..pos + 4 + len) else { break };
+            match HsmsFrame::decode(frame_bytes) {
+                Ok(frame) => {
+                    frames.push(frame);
+                    pos += 4 + len;
+                }
+                Err(e) => {
+                    warn!("Skipping undecodable HSMS frame: {}", e);
+                    self.needs_resync = true;
+                    pos += 1;
+                }
+            }
+        }
+
+        self.buffer.drain(..pos.min(self.buffer.len()));
+        Ok(frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hsms::SType;
+    use crate::secs2::SecsItem;
+    use std::io::Cursor;
+
+    const HOST: [u8; 4] = [10, 0, 0, 1];
+    const EQUIP: [u8; 4] = [10, 0, 0, 2];
+
+    /// Ethernet/IPv4/TCP packet carrying `payload`
+    fn packet(src: ([u8; 4], u16), dst: ([u8; 4], u16), seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut p = vec![0u8; 12];
+        p.extend_from_slice(&[0x08, 0x00]);
+        let total = (20 + 20 + payload.len()) as u16;
+        p.extend_from_slice(&[0x45, 0, (total >> 8) as u8, total as u8, 0, 0, 0x40, 0, 64, 6, 0, 0]);
+        p.extend_from_slice(&src.0);
+        p.extend_from_slice(&dst.0);
+        p.extend_from_slice(&src.1.to_be_bytes());
+        p.extend_from_slice(&dst.1.to_be_bytes());
+        p.extend_from_slice(&seq.to_be_bytes());
+        p.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xFF, 0xFF, 0, 0, 0, 0]);
+        p.extend_from_slice(payload);
+        p
+    }
+
+    /// Classic little-endian microsecond pcap
+    fn pcap(packets: &[(i64, Vec<u8>)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&0xA1B2_C3D4u32.to_le_bytes());
+        out.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0, 0, 1, 0, 0, 0]);
+        for (ts_us, data) in packets {
+            out.extend_from_slice(&((ts_us / 1_000_000) as u32).to_le_bytes());
+            out.extend_from_slice(&((ts_us % 1_000_000) as u32).to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(data);
+        }
+        out
+    }
+
+    fn session() -> Vec<(i64, Vec<u8>)> {
+        let h = (HOST, 40000);
+        let e = (EQUIP, 5000);
+        let select = HsmsFrame::control(0xFFFF, SType::SelectReq, 0, 1).encode();
+        let select_rsp = HsmsFrame::control(0xFFFF, SType::SelectRsp, 0, 1).encode();
+        let s1f3 = HsmsFrame::data(1, 1, 3, true, 2, Some(SecsItem::List(vec![SecsItem::U4(vec![501])]))).encode();
+        let s1f4 = HsmsFrame::data(1, 1, 4, false, 2, Some(SecsItem::List(vec![SecsItem::Ascii("A001".into())]))).encode();
+
+        let base = 1_762_160_400_000_000i64;
+        vec![
+            (base, packet(h, e, 999, 0x02, &[])),                       // SYN
+            (base + 10, packet(e, h, 4999, 0x12, &[])),                 // SYN/ACK
+            (base + 100, packet(h, e, 1000, 0x18, &select)),
+            (base + 200, packet(e, h, 5000, 0x18, &select_rsp)),
+            // S1F3 split in two, second half arrives first
+            (base + 300, packet(h, e, 1000 + 14 + 5, 0x18, &s1f3[5..])),
+            (base + 310, packet(h, e, 1000 + 14, 0x18, &s1f3[..5])),
+            // Retransmission of the first half is ignored
+            (base + 320, packet(h, e, 1000 + 14, 0x18, &s1f3[..5])),
+            (base + 400, packet(e, h, 5000 + 14, 0x18, &s1f4)),
+        ]
+    }
+
+    #[test]
+    fn test_pcap_can_parse() {
+        let parser = PcapParser::default();
+        assert!(parser.can_parse(&pcap(&session())));
+        assert!(!parser.can_parse(b"ts_iso,dir,s,f\n"));
+    }
+
+    #[test]
+    fn test_pcap_reassembly() {
+        let parser = PcapParser::default();
+        let messages = parser.parse_all(Box::new(Cursor::new(pcap(&session())))).unwrap();
+
+        let summary: Vec<(u8, u8, &str)> = messages.iter().map(|m| (m.s, m.f, m.dir.as_str())).collect();
+        assert_eq!(summary, vec![(0, 1, "H->E"), (0, 2, "E->H"), (1, 3, "H->E"), (1, 4, "E->H")]);
+        assert_eq!(messages[2].ts_iso, "2025-11-03T09:00:00.000310Z");
+        assert_eq!(messages[3].body_json["secs_tree"]["items"][0]["v"], "A001");
+    }
+
+    #[test]
+    fn test_pcap_configured_direction_and_ports() {
+        let options = PcapOptions {
+            hsms_ports: vec![5000],
+            host: vec!["10.0.0.2".parse().unwrap()],
+            equipment: vec!["10.0.0.1:40000".parse().unwrap()],
+        };
+        let messages = PcapParser::new(options).parse_all(Box::new(Cursor::new(pcap(&session())))).unwrap();
+        assert_eq!(messages[0].dir, "E->H");
+
+        let other_port = PcapOptions { hsms_ports: vec![6000], ..Default::default() };
+        let messages = PcapParser::new(other_port).parse_all(Box::new(Cursor::new(pcap(&session())))).unwrap();
+        assert!(messages.is_empty());
+    }
+
+    fn segment(seq: u32, syn: bool, payload: &[u8]) -> TcpSegment<'_> {
+        TcpSegment {
+            src: (HOST.into(), 40000),
+            dst: (EQUIP.into(), 5000),
+            seq,
+            syn,
+            fin: false,
+            rst: false,
+            payload,
+        }
+    }
+
+    #[test]
+    fn test_pcap_resync_discards_junk() {
+        let mut flow = TcpFlow::new();
+        let junk = vec![0xFF; 100_000];
+        assert!(flow.push(&segment(1, false, &junk)).unwrap().is_empty());
+        assert!(flow.buffer.len() < 4 + HEADER_LEN);
+
+        let linktest = HsmsFrame::control(0xFFFF, SType::LinktestReq, 0, 9).encode();
+        let frames = flow.push(&segment(1 + junk.len() as u32, false, &linktest)).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert!(flow.buffer.is_empty());
+    }
+
+    #[test]
+    fn test_pcap_gap_keeps_overflowing_segment() {
+        let linktest = HsmsFrame::control(0xFFFF, SType::LinktestReq, 0, 9).encode();
+
+        // Nothing pending: the segment that overflows is all there is to resume from
+        let mut flow = TcpFlow::new();
+        flow.push(&segment(0, true, &[])).unwrap();
+        let mut payload = vec![0xFF; MAX_PENDING_BYTES];
+        payload.extend_from_slice(&linktest);
+        assert_eq!(flow.push(&segment(100, false, &payload)).unwrap().len(), 1);
+
+        // Earlier segments pending: the overflowing one continues after them
+        let mut flow = TcpFlow::new();
+        flow.push(&segment(0, true, &[])).unwrap();
+        let junk = vec![0xFF; MAX_PENDING_BYTES - 10];
+        assert!(flow.push(&segment(100, false, &junk)).unwrap().is_empty());
+        let frames = flow.push(&segment(100 + junk.len() as u32, false, &linktest)).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert!(flow.pending.is_empty());
+        assert_eq!(flow.pending_bytes, 0);
+    }
+
+    #[test]
+    fn test_pcap_fixture_files() {
+        for fixture in [
+            &include_bytes!("../../../fixtures/hsms_session.pcap")[..],
+            &include_bytes!("../../../fixtures/hsms_session.pcapng")[..],
+        ] {
+            let messages = PcapParser::default().parse_all(Box::new(Cursor::new(fixture.to_vec()))).unwrap();
+            let summary: Vec<(u8, u8, &str)> = messages.iter().map(|m| (m.s, m.f, m.dir.as_str())).collect();
+            assert_eq!(summary, vec![
+                (0, 1, "H->E"), (0, 2, "E->H"),
+                (1, 3, "H->E"), (1, 4, "E->H"),
+                (6, 11, "E->H"), (6, 12, "H->E"),
+                (0, 5, "H->E"), (0, 6, "E->H"),
+            ]);
+            assert_eq!(messages[4].ceid, 201);
+        }
+    }
+}
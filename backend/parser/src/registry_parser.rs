@@ -1,6 +1,8 @@
-use crate::{ParsedMessage, ParseError, base_parser::{Parser, FormatHint, detect_format}};
-use crate::parsers::all_parsers;
-use std::io::{Read, Cursor};
+use crate::{ParseError, base_parser::{MessageStream, Parser, FormatHint, detect_format}};
+use crate::parsers::{all_parsers_with, ParserOptions};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+use std::path::Path;
 use tracing::{debug, info, warn, error};
 
 /// ParserRegistry - manages available parsers and auto-detects format
@@ -19,8 +21,13 @@ impl ParserRegistry {
     /// Parsers are automatically loaded from `parsers::all_parsers()`.
     /// To add a new parser, modify `parsers.rs` instead of this file.
     pub fn new() -> Self {
+        Self::with_options(&ParserOptions::default())
+    }
+
+    /// A registry whose parsers use `options` instead of their defaults
+    pub fn with_options(options: &ParserOptions) -> Self {
         info!("Initializing ParserRegistry");
-        let parsers = all_parsers();
+        let parsers = all_parsers_with(options);
 
         info!("Registered {} parsers: {}",
             parsers.len(),
@@ -70,7 +77,7 @@ impl ParserRegistry {
     }
     
     /// Auto-detect and parse data
-    pub fn parse_auto(&self, mut reader: Box<dyn Read>) -> Result<Vec<ParsedMessage>, ParseError> {
+    pub fn parse_auto(&self, mut reader: Box<dyn Read>) -> Result<MessageStream, ParseError> {
         info!("Starting auto-detection of file format");
 
         // Read a sample to detect format
@@ -125,41 +132,99 @@ impl ParserRegistry {
         }
     }
     
+    /// Parse a file on disk, reopening it for each parser tried
+    pub fn parse_path(&self, path: &Path, filename: &str) -> Result<MessageStream, ParseError> {
+        let path = path.to_path_buf();
+        self.parse_with_hint(move || Ok(Box::new(File::open(&path)?) as Box<dyn Read>), filename)
+    }
+
     /// Parse by trying each parser until one succeeds
-    pub fn parse_with_hint(
-        &self,
-        reader: Box<dyn Read>,
-        filename: &str,
-    ) -> Result<Vec<ParsedMessage>, ParseError> {
+    ///
+    /// `open` must return a fresh reader over the same input on every call.
+    /// Parsers claiming the file extension are tried first, then parsers whose
+    /// `can_parse` accepts the content, then the rest. A parser is chosen once
+    /// it yields its first message; later errors surface from the stream.
+    /// A parser that yields nothing from non-empty input is only used as a
+    /// last resort, since lenient text parsers can "succeed" on binary data.
+    pub fn parse_with_hint<F>(&self, open: F, filename: &str) -> Result<MessageStream, ParseError>
+    where
+        F: Fn() -> std::io::Result<Box<dyn Read>>,
+    {
+        Ok(self.parse_with_hint_named(open, filename)?.1)
+    }
+
+    /// `parse_with_hint`, also naming the parser chosen (`None` when no
+    /// parser produced a message)
+    pub fn parse_with_hint_named<F>(&self, open: F, filename: &str) -> Result<(Option<&'static str>, MessageStream), ParseError>
+    where
+        F: Fn() -> std::io::Result<Box<dyn Read>>,
+    {
         info!("Parsing file: {}", filename);
         info!("Will try all parsers in sequence until one succeeds");
 
-        // Read all data into memory so we can retry with different parsers
-        let mut data = Vec::new();
-        let mut reader = reader;
-        reader.read_to_end(&mut data)?;
-        debug!("Read {} bytes from file", data.len());
+        let mut sample = Vec::with_capacity(512);
+        open()?.take(512).read_to_end(&mut sample)?;
+        debug!("Read {} byte sample from file", sample.len());
+
+        let extension = std::path::Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        let mut ordered: Vec<&dyn Parser> = Vec::with_capacity(self.parsers.len());
+        ordered.extend(self.parsers.iter().map(|p| p.as_ref())
+            .filter(|p| p.extensions().contains(&extension.as_str())));
+        for parser in &self.parsers {
+            if !ordered.iter().any(|p| p.name() == parser.name()) && parser.can_parse(&sample) {
+                ordered.push(parser.as_ref());
+            }
+        }
+        for parser in &self.parsers {
+            if !ordered.iter().any(|p| p.name() == parser.name()) {
+                ordered.push(parser.as_ref());
+            }
+        }
 
         // Try each parser in sequence
+        let mut has_content = None;
+        let mut empty_result = false;
         let mut last_error = None;
-        for parser in &self.parsers {
+        for parser in ordered {
             info!("Trying parser: {}", parser.name());
 
-            // Create a fresh cursor for each attempt
-            let cursor = Box::new(Cursor::new(data.clone()));
+            let mut stream = match parser.parse(open()?) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Parser '{}' failed: {}", parser.name(), e);
+                    last_error = Some(e);
+                    continue;
+                }
+            };
 
-            match parser.parse(cursor) {
-                Ok(messages) => {
-                    info!("Successfully parsed {} messages with parser '{}'", messages.len(), parser.name());
-                    return Ok(messages);
+            match stream.next() {
+                Some(Ok(first)) => {
+                    info!("Selected parser '{}'", parser.name());
+                    return Ok((Some(parser.name()), Box::new(std::iter::once(Ok(first)).chain(stream))));
                 }
-                Err(e) => {
+                Some(Err(e)) => {
                     warn!("Parser '{}' failed: {}", parser.name(), e);
                     last_error = Some(e);
                 }
+                None => {
+                    if !*has_content.get_or_insert(has_non_whitespace(open()?)?) {
+                        return Ok((None, Box::new(std::iter::empty())));
+                    }
+                    warn!("Parser '{}' produced no messages, trying others", parser.name());
+                    empty_result = true;
+                }
             }
         }
 
+        if empty_result {
+            return Ok((None, Box::new(std::iter::empty())));
+        }
+
         // All parsers failed
         error!("All parsers failed to parse file '{}'", filename);
         Err(last_error.unwrap_or_else(|| {
@@ -171,6 +236,16 @@ impl ParserRegistry {
     }
 }
 
+/// Whether the input holds anything besides whitespace
+fn has_non_whitespace(reader: Box<dyn Read>) -> std::io::Result<bool> {
+    for byte in BufReader::new(reader).bytes() {
+        if !byte?.is_ascii_whitespace() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 impl Default for ParserRegistry {
     fn default() -> Self {
         Self::new()
@@ -214,8 +289,15 @@ impl Read for CombinedReader {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ParsedMessage;
     use std::io::Cursor;
 
+    fn parse_bytes(registry: &ParserRegistry, data: &[u8], filename: &str) -> Result<Vec<ParsedMessage>, ParseError> {
+        let data = data.to_vec();
+        registry.parse_with_hint(move || Ok(Box::new(Cursor::new(data.clone())) as Box<dyn Read>), filename)?
+            .collect()
+    }
+
     #[test]
     fn test_registry_auto_detect_ndjson() {
         let registry = ParserRegistry::new();
@@ -223,7 +305,8 @@ mod tests {
 {"ts_iso":"2025-11-03T09:12:15.456Z","dir":"H->E","s":1,"f":3,"wbit":1,"sysbytes":12346,"body_json":{"semantic":{"kind":"EventReport"}}}"#;
         
         let cursor = Cursor::new(data);
-        let messages = registry.parse_auto(Box::new(cursor)).unwrap();
+        let messages: Vec<_> = registry.parse_auto(Box::new(cursor)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
         
         assert_eq!(messages.len(), 2);
         assert_eq!(messages[0].s, 6);
@@ -235,11 +318,64 @@ mod tests {
         let data = r#"ts_iso,dir,s,f,wbit,sysbytes,ceid,body_json
 2025-11-03T09:12:14.123Z,E->H,6,11,0,12345,201,"{""secs_tree"":{""t"":""L"",""items"":[]}}"#;
         
-        let cursor = Cursor::new(data);
-        let messages = registry.parse_with_hint(Box::new(cursor), "test.csv").unwrap();
-        
+        let messages = parse_bytes(&registry, data.as_bytes(), "test.csv").unwrap();
+
         assert_eq!(messages.len(), 1);
         assert_eq!(messages[0].s, 6);
     }
-}
 
+    #[test]
+    fn test_registry_parse_with_hint_hsms_binary() {
+        use crate::hsms::{HsmsFrame, SType};
+
+        let registry = ParserRegistry::new();
+        let data: Vec<u8> = [
+            HsmsFrame::control(0xFFFF, SType::LinktestReq, 0, 1),
+            HsmsFrame::control(0xFFFF, SType::LinktestRsp, 0, 1),
+        ].iter().flat_map(|f| f.encode()).collect();
+
+        let messages = parse_bytes(&registry, &data, "capture.bin").unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!((messages[0].s, messages[0].f), (0, 5));
+
+        let options = ParserOptions { hsms_frame_interval: chrono::Duration::milliseconds(250), ..Default::default() };
+        let messages = parse_bytes(&ParserRegistry::with_options(&options), &data, "capture.bin").unwrap();
+        assert!(messages[1].ts_iso.contains("00:00:00.250"), "{}", messages[1].ts_iso);
+    }
+
+    #[test]
+    fn test_registry_passes_pcap_options() {
+        let data = include_bytes!("../../../fixtures/hsms_session.pcap");
+        assert_eq!(parse_bytes(&ParserRegistry::new(), data, "session.pcap").unwrap().len(), 8);
+
+        let mut options = ParserOptions::default();
+        options.pcap.hsms_ports = vec![6000];
+        let messages = parse_bytes(&ParserRegistry::with_options(&options), data, "session.pcap").unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn test_registry_falls_back_on_misleading_extension() {
+        let registry = ParserRegistry::new();
+        let data = r#"{"ts_iso":"2025-11-03T09:12:14.123Z","dir":"E->H","s":6,"f":11,"wbit":0,"sysbytes":1,"body_json":{}}
+{"ts_iso":"2025-11-03T09:12:15.456Z","dir":"H->E","s":6,"f":12,"wbit":0,"sysbytes":1,"body_json":{}}"#;
+
+        let messages = parse_bytes(&registry, data.as_bytes(), "export.csv").unwrap();
+        assert_eq!(messages.len(), 2);
+        let (parser, _) = registry.parse_with_hint_named(|| Ok(Box::new(Cursor::new(data)) as Box<dyn Read>), "export.csv").unwrap();
+        assert_eq!(parser, Some("ndjson"));
+
+        assert!(parse_bytes(&registry, b"  \n", "empty.csv").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_registry_parse_path_streams_file() {
+        let registry = ParserRegistry::new();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/pairs.ndjson");
+
+        let mut stream = registry.parse_path(&path, "pairs.ndjson").unwrap();
+        assert!(stream.next().unwrap().is_ok());
+        assert!(stream.all(|m| m.is_ok()));
+    }
+}
@@ -0,0 +1,295 @@
+//! SECS-II (SEMI E5) data items
+//!
+//! Shared by every parser that sees SECS-II message bodies: the binary HSMS
+//! decoder produces `SecsItem`s from item bytes, the SML parser from text.
+//! `SecsItem::to_json` yields the `secs_tree` shape from the sprint doc:
+//! `{"t":"L","items":[...]}` for lists, `{"t":"U4","v":201}` for scalars.
+//! Arrays with a length other than one keep `v` as a JSON array.
+
+use crate::ParseError;
+use base64::Engine;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecsItem {
+    List(Vec<SecsItem>),
+    Binary(Vec<u8>),
+    Boolean(Vec<bool>),
+    Ascii(String),
+    Jis8(String),
+    I1(Vec<i8>),
+    I2(Vec<i16>),
+    I4(Vec<i32>),
+    I8(Vec<i64>),
+    U1(Vec<u8>),
+    U2(Vec<u16>),
+    U4(Vec<u32>),
+    U8(Vec<u64>),
+    F4(Vec<f32>),
+    F8(Vec<f64>),
+}
+
+/// Format codes (upper six bits of the format byte)
+mod format {
+    pub const LIST: u8 = 0o00;
+    pub const BINARY: u8 = 0o10;
+    pub const BOOLEAN: u8 = 0o11;
+    pub const ASCII: u8 = 0o20;
+    pub const JIS8: u8 = 0o21;
+    pub const I8: u8 = 0o30;
+    pub const I1: u8 = 0o31;
+    pub const I2: u8 = 0o32;
+    pub const I4: u8 = 0o34;
+    pub const F8: u8 = 0o40;
+    pub const F4: u8 = 0o44;
+    pub const U8: u8 = 0o50;
+    pub const U1: u8 = 0o51;
+    pub const U2: u8 = 0o52;
+    pub const U4: u8 = 0o54;
+}
+
+/// Guards against corrupt length bytes building absurdly deep trees
+pub(crate) const MAX_DEPTH: usize = 64;
+
+impl SecsItem {
+    /// Type tag used in `secs_tree` and SML (`BOOLEAN` is `BOOL` in the tree)
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            SecsItem::List(_) => "L",
+            SecsItem::Binary(_) => "B",
+            SecsItem::Boolean(_) => "BOOL",
+            SecsItem::Ascii(_) => "A",
+            SecsItem::Jis8(_) => "J",
+            SecsItem::I1(_) => "I1",
+            SecsItem::I2(_) => "I2",
+            SecsItem::I4(_) => "I4",
+            SecsItem::I8(_) => "I8",
+            SecsItem::U1(_) => "U1",
+            SecsItem::U2(_) => "U2",
+            SecsItem::U4(_) => "U4",
+            SecsItem::U8(_) => "U8",
+            SecsItem::F4(_) => "F4",
+            SecsItem::F8(_) => "F8",
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let t = self.type_name();
+        match self {
+            SecsItem::List(items) => {
+                json!({"t": t, "items": items.iter().map(|i| i.to_json()).collect::<Vec<_>>()})
+            }
+            SecsItem::Binary(bytes) => {
+                json!({"t": t, "v": base64::engine::general_purpose::STANDARD.encode(bytes)})
+            }
+            SecsItem::Ascii(text) | SecsItem::Jis8(text) => json!({"t": t, "v": text}),
+            SecsItem::Boolean(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::I1(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::I2(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::I4(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::I8(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::U1(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::U2(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::U4(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::U8(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::F4(v) => json!({"t": t, "v": scalar_or_array(v)}),
+            SecsItem::F8(v) => json!({"t": t, "v": scalar_or_array(v)}),
+        }
+    }
+
+    /// First value of an unsigned/signed integer item, widened to u64
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            SecsItem::U1(v) => v.first().map(|&x| x as u64),
+            SecsItem::U2(v) => v.first().map(|&x| x as u64),
+            SecsItem::U4(v) => v.first().map(|&x| x as u64),
+            SecsItem::U8(v) => v.first().copied(),
+            SecsItem::I1(v) => v.first().and_then(|&x| u64::try_from(x).ok()),
+            SecsItem::I2(v) => v.first().and_then(|&x| u64::try_from(x).ok()),
+            SecsItem::I4(v) => v.first().and_then(|&x| u64::try_from(x).ok()),
+            SecsItem::I8(v) => v.first().and_then(|&x| u64::try_from(x).ok()),
+            _ => None,
+        }
+    }
+
+    /// Decode one item from the start of `data`; returns the item and bytes consumed
+    pub fn decode(data: &[u8]) -> Result<(SecsItem, usize), ParseError> {
+        decode_at_depth(data, 0)
+    }
+
+    /// Encode to SECS-II item bytes (used by tests and simulators)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        let (code, body): (u8, Vec<u8>) = match self {
+            SecsItem::List(items) => {
+                write_header(out, format::LIST, items.len());
+                for item in items {
+                    item.encode_into(out);
+                }
+                return;
+            }
+            SecsItem::Binary(v) => (format::BINARY, v.clone()),
+            SecsItem::Boolean(v) => (format::BOOLEAN, v.iter().map(|&b| b as u8).collect()),
+            SecsItem::Ascii(s) => (format::ASCII, s.as_bytes().to_vec()),
+            SecsItem::Jis8(s) => (format::JIS8, s.as_bytes().to_vec()),
+            SecsItem::I1(v) => (format::I1, v.iter().flat_map(|x| x.to_be_bytes()).collect()),
+            SecsItem::I2(v) => (format::I2, v.iter().flat_map(|x| x.to_be_bytes()).collect()),
+            SecsItem::I4(v) => (format::I4, v.iter().flat_map(|x| x.to_be_bytes()).collect()),
+            SecsItem::I8(v) => (format::I8, v.iter().flat_map(|x| x.to_be_bytes()).collect()),
+            SecsItem::U1(v) => (format::U1, v.clone()),
+            SecsItem::U2(v) => (format::U2, v.iter().flat_map(|x| x.to_be_bytes()).collect()),
+            SecsItem::U4(v) => (format::U4, v.iter().flat_map(|x| x.to_be_bytes()).collect()),
+            SecsItem::U8(v) => (format::U8, v.iter().flat_map(|x| x.to_be_bytes()).collect()),
+            SecsItem::F4(v) => (format::F4, v.iter().flat_map(|x| x.to_be_bytes()).collect()),
+            SecsItem::F8(v) => (format::F8, v.iter().flat_map(|x| x.to_be_bytes()).collect()),
+        };
+        write_header(out, code, body.len());
+        out.extend_from_slice(&body);
+    }
+}
+
+fn scalar_or_array<T: Clone + Into<Value>>(values: &[T]) -> Value {
+    match values {
+        [single] => single.clone().into(),
+        many => Value::Array(many.iter().cloned().map(Into::into).collect()),
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, code: u8, len: usize) {
+    let len_bytes = (len as u32).to_be_bytes();
+    let n = if len <= 0xFF { 1 } else if len <= 0xFFFF { 2 } else { 3 };
+    out.push((code << 2) | n as u8);
+    out.extend_from_slice(&len_bytes[4 - n..]);
+}
+
+fn decode_at_depth(data: &[u8], depth: usize) -> Result<(SecsItem, usize), ParseError> {
+    if depth > MAX_DEPTH {
+        return Err("SECS-II item nesting too deep".into());
+    }
+
+    let format_byte = *data.first().ok_or("SECS-II item truncated: missing format byte")?;
+    let code = format_byte >> 2;
+    let n_len = (format_byte & 0x03) as usize;
+    if n_len == 0 {
+        return Err(format!("SECS-II item has zero length bytes (format 0x{:02X})", format_byte).into());
+    }
+    if data.len() < 1 + n_len {
+        return Err("SECS-II item truncated: missing length bytes".into());
+    }
+
+    let length = data[1..1 + n_len].iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+    let mut pos = 1 + n_len;
+
+    if code == format::LIST {
+        let mut items = Vec::with_capacity(length.min(1024));
+        for _ in 0..length {
+            let (item, used) = decode_at_depth(&data[pos..], depth + 1)?;
+            items.push(item);
+            pos += used;
+        }
+        return Ok((SecsItem::List(items), pos));
+    }
+
+    let body = data.get(pos..pos + length).ok_or_else(|| {
+        ParseError::Custom(format!(
+            "SECS-II item truncated: need {} bytes, have {}",
+            length,
+            data.len() - pos
+        ))
+    })?;
+    pos += length;
+
+    let item = match code {
+        format::BINARY => SecsItem::Binary(body.to_vec()),
+        format::BOOLEAN => SecsItem::Boolean(body.iter().map(|&b| b != 0).collect()),
+        format::ASCII => SecsItem::Ascii(String::from_utf8_lossy(body).into_owned()),
+        format::JIS8 => SecsItem::Jis8(String::from_utf8_lossy(body).into_owned()),
+        format::I1 => SecsItem::I1(body.iter().map(|&b| b as i8).collect()),
+        format::I2 => SecsItem::I2(fixed(body, i16::from_be_bytes)?),
+        format::I4 => SecsItem::I4(fixed(body, i32::from_be_bytes)?),
+        format::I8 => SecsItem::I8(fixed(body, i64::from_be_bytes)?),
+        format::U1 => SecsItem::U1(body.to_vec()),
+        format::U2 => SecsItem::U2(fixed(body, u16::from_be_bytes)?),
+        format::U4 => SecsItem::U4(fixed(body, u32::from_be_bytes)?),
+        format::U8 => SecsItem::U8(fixed(body, u64::from_be_bytes)?),
+        format::F4 => SecsItem::F4(fixed(body, f32::from_be_bytes)?),
+        format::F8 => SecsItem::F8(fixed(body, f64::from_be_bytes)?),
+        other => return Err(format!("Unknown SECS-II format code 0o{:o}", other).into()),
+    };
+
+    Ok((item, pos))
+}
+
+fn fixed<T, const N: usize>(body: &[u8], convert: fn([u8; N]) -> T) -> Result<Vec<T>, ParseError> {
+    if body.len() % N != 0 {
+        return Err(format!("SECS-II item length {} is not a multiple of {}", body.len(), N).into());
+    }
+    Ok(body.chunks_exact(N)
+        .map(|c| convert(c.try_into().expect("chunks_exact yields N bytes")))
+        .collect())
+}
+
+/// CEID of an event report (S6F11/S6F13): `<L[3] DATAID CEID <L reports>>`
+pub fn extract_ceid(s: u8, f: u8, body: &SecsItem) -> u32 {
+    if s != 6 || !(f == 11 || f == 13) {
+        return 0;
+    }
+    match body {
+        SecsItem::List(items) if items.len() == 3 => {
+            items[1].as_u64().and_then(|v| u32::try_from(v).ok()).unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_nested() {
+        let item = SecsItem::List(vec![
+            SecsItem::U4(vec![1]),
+            SecsItem::U4(vec![201]),
+            SecsItem::List(vec![SecsItem::List(vec![
+                SecsItem::U4(vec![10]),
+                SecsItem::List(vec![SecsItem::Ascii("A001".into()), SecsItem::F8(vec![1.5, 2.0])]),
+            ])]),
+        ]);
+
+        let bytes = item.encode();
+        let (decoded, used) = SecsItem::decode(&bytes).unwrap();
+        assert_eq!(used, bytes.len());
+        assert_eq!(decoded, item);
+        assert_eq!(extract_ceid(6, 11, &decoded), 201);
+    }
+
+    #[test]
+    fn test_to_json_shape() {
+        let item = SecsItem::List(vec![
+            SecsItem::U4(vec![201]),
+            SecsItem::Boolean(vec![true]),
+            SecsItem::Binary(vec![0x01, 0x02]),
+            SecsItem::I2(vec![-1, 2]),
+        ]);
+
+        assert_eq!(item.to_json(), json!({"t": "L", "items": [
+            {"t": "U4", "v": 201},
+            {"t": "BOOL", "v": true},
+            {"t": "B", "v": "AQI="},
+            {"t": "I2", "v": [-1, 2]},
+        ]}));
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        // U4 claiming 8 bytes with only 4 present
+        let bytes = [0xB1, 0x08, 0, 0, 0, 1];
+        assert!(SecsItem::decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,517 @@
+use crate::{ParsedMessage, ParseError, base_parser::{stop_on_error, MessageStream, Parser}};
+use crate::secs2::{extract_ceid, SecsItem, MAX_DEPTH};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use tracing::{debug, info, error};
+
+/// SML parser - handles SECS-II SML text logs
+///
+/// Each message is preceded by a header line carrying a timestamp, a
+/// direction and optionally the system bytes, e.g.
+///
+/// ```text
+/// 2025-11-03 09:12:14.123 E->H SB=12345
+/// S6F11 W
+/// <L [3]
+///   <U4 1>
+///   <U4 201>
+///   <L [0]>
+/// >.
+/// ```
+///
+/// The message may also start on the header line itself. Timestamps without
+/// an offset are taken as UTC. When system bytes are not logged they are
+/// synthesized so that replies share the sysbytes of their primary.
+pub struct SmlParser;
+
+impl Parser for SmlParser {
+    fn name(&self) -> &'static str {
+        "sml"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["sml", "log"]
+    }
+
+    fn can_parse_impl(&self, data: &[u8]) -> bool {
+        let sample = String::from_utf8_lossy(data);
+        let mut lines = sample.lines().filter(|l| !l.trim().is_empty());
+
+        match lines.next().and_then(parse_header_line) {
+            Some((_, rest)) => {
+                let next = lines.next().unwrap_or("");
+                [rest.as_str(), next].iter()
+                    .any(|text| parse_sxfy(text.trim_start()).is_some())
+            }
+            None => false,
+        }
+    }
+
+    fn parse(&self, reader: Box<dyn Read>) -> Result<MessageStream, ParseError> {
+        info!("Starting SML parsing");
+        let mut lines = BufReader::new(reader).lines();
+        let mut sysbytes = SysbytesAllocator::default();
+        let mut current: Option<(usize, Header, String)> = None;
+        let mut line_num = 0;
+
+        // A message ends where the next header starts, so each one is only
+        // built once the following header (or EOF) has been read
+        let messages = std::iter::from_fn(move || loop {
+            line_num += 1;
+            let line = match lines.next() {
+                Some(Ok(l)) => l,
+                Some(Err(e)) => {
+                    error!("Failed to read line {}: {}", line_num, e);
+                    return Some(Err(e.into()));
+                }
+                None => {
+                    let (start, header, text) = current.take()?;
+                    return Some(build_message(start, header, &text, &mut sysbytes));
+                }
+            };
+
+            if let Some((header, rest)) = parse_header_line(&line) {
+                if let Some((start, header, text)) = current.replace((line_num, header, rest)) {
+                    return Some(build_message(start, header, &text, &mut sysbytes));
+                }
+                continue;
+            }
+
+            match current.as_mut() {
+                Some((_, _, text)) => {
+                    text.push('\n');
+                    text.push_str(&line);
+                }
+                None if line.trim().is_empty() => {}
+                None => {
+                    error!("SML line {} appears before any timestamp/direction header", line_num);
+                    return Some(Err(format!("Line {}: expected a timestamp and direction header", line_num).into()));
+                }
+            }
+        });
+
+        Ok(stop_on_error(messages))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Header {
+    ts_iso: String,
+    dir: &'static str,
+    sysbytes: Option<u32>,
+}
+
+/// Recognise `<timestamp> <dir> [SB=n]` and return it with any trailing text
+fn parse_header_line(line: &str) -> Option<(Header, String)> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let clean = |t: &str| t.trim_matches(|c| c == '[' || c == ']').to_string();
+
+    let first = clean(tokens.first()?);
+    let (ts, mut idx) = match parse_timestamp(&first) {
+        Some(ts) => (ts, 1),
+        None => {
+            let second = clean(tokens.get(1)?);
+            (parse_timestamp(&format!("{}T{}", first, second))?, 2)
+        }
+    };
+
+    let mut dir = None;
+    let mut sysbytes = None;
+    while let Some(token) = tokens.get(idx) {
+        let token = clean(token);
+        if let Some(d) = parse_direction(&token) {
+            dir = Some(d);
+        } else if let Some(sb) = parse_sysbytes(&token) {
+            sysbytes = Some(sb);
+        } else {
+            break;
+        }
+        idx += 1;
+    }
+
+    let rest = tokens[idx..].join(" ");
+    Some((Header { ts_iso: ts.to_rfc3339_opts(SecondsFormat::AutoSi, true), dir: dir?, sysbytes }, rest))
+}
+
+fn parse_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(text) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y/%m/%dT%H:%M:%S%.f"].iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(text, fmt).ok())
+        .map(|naive| naive.and_utc())
+}
+
+fn parse_direction(token: &str) -> Option<&'static str> {
+    match token.to_ascii_uppercase().as_str() {
+        "H->E" | "H>E" => Some("H->E"),
+        "E->H" | "E>H" => Some("E->H"),
+        _ => None,
+    }
+}
+
+fn parse_sysbytes(token: &str) -> Option<u32> {
+    let (key, value) = token.split_once(['=', ':'])?;
+    if !matches!(key.to_ascii_lowercase().as_str(), "sb" | "sysbytes" | "systembytes") {
+        return None;
+    }
+    parse_uint(value).and_then(|v| u32::try_from(v).ok())
+}
+
+fn parse_uint(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Parse a leading `SxFy` (optionally quoted); returns (s, f, rest)
+fn parse_sxfy(text: &str) -> Option<(u8, u8, &str)> {
+    let text = text.trim_start_matches('\'');
+    let after_s = text.strip_prefix(['S', 's'])?;
+    let f_pos = after_s.find(['F', 'f'])?;
+    let s: u8 = after_s[..f_pos].parse().ok()?;
+
+    let after_f = &after_s[f_pos + 1..];
+    let digits = after_f.find(|c: char| !c.is_ascii_digit()).unwrap_or(after_f.len());
+    let f: u8 = after_f[..digits].parse().ok()?;
+
+    Some((s, f, after_f[digits..].trim_start_matches('\'')))
+}
+
+fn build_message(
+    line_num: usize,
+    header: Header,
+    text: &str,
+    sysbytes: &mut SysbytesAllocator,
+) -> Result<ParsedMessage, ParseError> {
+    let err = |msg: String| ParseError::Custom(format!("SML message at line {}: {}", line_num, msg));
+
+    // Optional `Label:` before the SxFy
+    let mut text = text.trim_start();
+    if let Some((label, rest)) = text.split_once(':') {
+        if !label.contains(['<', '\'']) && parse_sxfy(rest.trim_start()).is_some() {
+            text = rest.trim_start();
+        }
+    }
+
+    let (s, f, rest) = parse_sxfy(text).ok_or_else(|| err("expected SxFy".into()))?;
+
+    let mut lexer = Lexer::new(rest);
+    let word = lexer.peek_word();
+    let wbit = if word.eq_ignore_ascii_case("W") || word.eq_ignore_ascii_case("W.") {
+        lexer.pos += 1;
+        1
+    } else {
+        0
+    };
+
+    let body = if lexer.peek() == Some('<') {
+        Some(lexer.parse_item(0).map_err(err)?)
+    } else {
+        None
+    };
+
+    lexer.skip_ws();
+    match lexer.peek() {
+        Some('.') | None => {}
+        Some(c) => return Err(err(format!("unexpected '{}' after message body", c))),
+    }
+
+    let sysbytes = sysbytes.assign(header.sysbytes, s, f, wbit, header.dir);
+    let ceid = body.as_ref().map(|b| extract_ceid(s, f, b)).unwrap_or(0);
+    let tree = body.as_ref().map(|b| b.to_json()).unwrap_or(serde_json::Value::Null);
+
+    debug!("Parsed SML message at line {} (s={}, f={})", line_num, s, f);
+
+    Ok(ParsedMessage {
+        ts_iso: header.ts_iso,
+        dir: header.dir.to_string(),
+        s,
+        f,
+        wbit,
+        sysbytes,
+        ceid,
+        body_json: json!({"secs_tree": tree}),
+    })
+}
+
+/// Hands out system bytes when the log omits them: primaries get a fresh
+/// value, replies reuse the one of the open primary they answer.
+#[derive(Default)]
+struct SysbytesAllocator {
+    next: u32,
+    /// (s, primary f, primary dir) -> sysbytes
+    open: HashMap<(u8, u8, &'static str), u32>,
+}
+
+impl SysbytesAllocator {
+    fn assign(&mut self, logged: Option<u32>, s: u8, f: u8, wbit: u8, dir: &'static str) -> u32 {
+        if let Some(sb) = logged {
+            return sb;
+        }
+
+        if wbit == 0 && f % 2 == 0 && f > 0 {
+            let primary_dir = if dir == "H->E" { "E->H" } else { "H->E" };
+            if let Some(sb) = self.open.remove(&(s, f - 1, primary_dir)) {
+                return sb;
+            }
+        }
+
+        self.next = self.next.wrapping_add(1);
+        if wbit == 1 {
+            self.open.insert((s, f, dir), self.next);
+        }
+        self.next
+    }
+}
+
+/// Character-level reader for SML item text
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ws();
+        self.text[self.pos..].chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.peek() {
+            Some(found) if found == c => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            Some(found) => Err(format!("expected '{}', found '{}'", c, found)),
+            None => Err(format!("expected '{}', found end of message", c)),
+        }
+    }
+
+    fn peek_word(&mut self) -> &'a str {
+        self.skip_ws();
+        let rest = &self.text[self.pos..];
+        let end = rest.find(|c: char| c.is_whitespace() || "<>[]\"'".contains(c)).unwrap_or(rest.len());
+        &rest[..end]
+    }
+
+    fn take_word(&mut self) -> &'a str {
+        let word = self.peek_word();
+        self.pos += word.len();
+        word
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        let quote = self.peek().ok_or("expected string")?;
+        self.pos += 1;
+        let rest = &self.text[self.pos..];
+        let end = rest.find(quote).ok_or("unterminated string")?;
+        self.pos += end + 1;
+        Ok(rest[..end].to_string())
+    }
+
+    /// `<TYPE [n] values...>`
+    fn parse_item(&mut self, depth: usize) -> Result<SecsItem, String> {
+        if depth > MAX_DEPTH {
+            return Err("item nesting too deep".into());
+        }
+
+        self.expect('<')?;
+        let type_name = self.take_word().to_ascii_uppercase();
+        if type_name.is_empty() {
+            return Err("missing item type".into());
+        }
+
+        // Optional declared length, informational only
+        if self.peek() == Some('[') {
+            self.pos += 1;
+            self.take_word();
+            self.expect(']')?;
+        }
+
+        let item = match type_name.as_str() {
+            "L" => {
+                let mut items = Vec::new();
+                while self.peek() == Some('<') {
+                    items.push(self.parse_item(depth + 1)?);
+                }
+                SecsItem::List(items)
+            }
+            "A" | "J" => {
+                let mut text = String::new();
+                loop {
+                    match self.peek() {
+                        Some('"') | Some('\'') => text.push_str(&self.parse_string()?),
+                        Some('>') | None => break,
+                        Some(_) => {
+                            let word = self.take_word();
+                            let code = parse_uint(word)
+                                .and_then(|c| char::from_u32(c as u32))
+                                .ok_or_else(|| format!("invalid character code '{}'", word))?;
+                            text.push(code);
+                        }
+                    }
+                }
+                if type_name == "A" { SecsItem::Ascii(text) } else { SecsItem::Jis8(text) }
+            }
+            "B" => SecsItem::Binary(self.values(|w| parse_uint(w).and_then(|v| u8::try_from(v).ok()))?),
+            "BOOLEAN" | "BOOL" => SecsItem::Boolean(self.values(|w| match w.to_ascii_uppercase().as_str() {
+                "T" | "TRUE" => Some(true),
+                "F" | "FALSE" => Some(false),
+                other => parse_uint(other).map(|v| v != 0),
+            })?),
+            "I1" => SecsItem::I1(self.values(parse_int)?),
+            "I2" => SecsItem::I2(self.values(parse_int)?),
+            "I4" => SecsItem::I4(self.values(parse_int)?),
+            "I8" => SecsItem::I8(self.values(parse_int)?),
+            "U1" => SecsItem::U1(self.values(parse_unsigned)?),
+            "U2" => SecsItem::U2(self.values(parse_unsigned)?),
+            "U4" => SecsItem::U4(self.values(parse_unsigned)?),
+            "U8" => SecsItem::U8(self.values(parse_unsigned)?),
+            "F4" => SecsItem::F4(self.values(|w| w.parse().ok())?),
+            "F8" => SecsItem::F8(self.values(|w| w.parse().ok())?),
+            other => return Err(format!("unknown item type '{}'", other)),
+        };
+
+        self.expect('>')?;
+        Ok(item)
+    }
+
+    fn values<T>(&mut self, parse: impl Fn(&str) -> Option<T>) -> Result<Vec<T>, String> {
+        let mut values = Vec::new();
+        while !matches!(self.peek(), Some('>') | None) {
+            let word = self.take_word();
+            if word.is_empty() {
+                return Err(format!("unexpected '{}' in item", self.peek().unwrap_or(' ')));
+            }
+            values.push(parse(word).ok_or_else(|| format!("invalid value '{}'", word))?);
+        }
+        Ok(values)
+    }
+}
+
+fn parse_int<T: TryFrom<i128>>(word: &str) -> Option<T> {
+    // Widen so every u64 magnitude negates exactly; out-of-range values fail try_from
+    let value = match word.strip_prefix('-') {
+        Some(abs) => -i128::from(parse_uint(abs)?),
+        None => i128::from(parse_uint(word)?),
+    };
+    T::try_from(value).ok()
+}
+
+fn parse_unsigned<T: TryFrom<u64>>(word: &str) -> Option<T> {
+    T::try_from(parse_uint(word)?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const LOG: &str = r#"2025-11-03 09:12:14.123 H->E
+S1F3 W
+<L [2]
+  <U4 501>
+  <U4 502>
+>.
+2025-11-03 09:12:14.180 E->H
+S1F4
+<L [2]
+  <A "A001">
+  <F4 1.5>
+>.
+2025-11-03T09:12:15.000Z E->H SB=0x10 S6F11 W <L [3] <U4 1> <U4 201> <L [1] <L [2] <U4 10> <L [2] <BOOLEAN T> <B 0x01 0x02>>>>>.
+2025-11-03 09:12:15.010 H->E SB=16
+S6F12 <B 0>.
+2025-11-03 09:12:16.000 H->E
+S2F41 W <L <A 'START'> <L>>.
+"#;
+
+    #[test]
+    fn test_sml_can_parse() {
+        assert!(SmlParser.can_parse(LOG.as_bytes()));
+        assert!(!SmlParser.can_parse(b"ts_iso,dir,s,f,wbit,sysbytes,ceid,body_json\n"));
+        assert!(!SmlParser.can_parse(br#"{"ts_iso":"2025-11-03T09:12:14.123Z"}"#));
+    }
+
+    #[test]
+    fn test_sml_parse() {
+        let messages = SmlParser.parse_all(Box::new(Cursor::new(LOG))).unwrap();
+        assert_eq!(messages.len(), 5);
+
+        let s1f3 = &messages[0];
+        assert_eq!((s1f3.s, s1f3.f, s1f3.wbit, s1f3.dir.as_str()), (1, 3, 1, "H->E"));
+        assert_eq!(s1f3.ts_iso, "2025-11-03T09:12:14.123Z");
+        assert_eq!(s1f3.body_json["secs_tree"]["items"][1], json!({"t": "U4", "v": 502}));
+
+        // Synthesized sysbytes pair the reply with its primary
+        let s1f4 = &messages[1];
+        assert_eq!(s1f4.sysbytes, s1f3.sysbytes);
+        assert_eq!(s1f4.body_json["secs_tree"]["items"][0], json!({"t": "A", "v": "A001"}));
+
+        let s6f11 = &messages[2];
+        assert_eq!((s6f11.sysbytes, s6f11.ceid), (16, 201));
+        let vids = &s6f11.body_json["secs_tree"]["items"][2]["items"][0]["items"][1]["items"];
+        assert_eq!(vids[0], json!({"t": "BOOL", "v": true}));
+        assert_eq!(vids[1], json!({"t": "B", "v": "AQI="}));
+
+        assert_eq!(messages[3].sysbytes, 16);
+        assert_eq!(messages[4].body_json["secs_tree"]["items"][1], json!({"t": "L", "items": []}));
+    }
+
+    #[test]
+    fn test_sml_all_numeric_types() {
+        let log = "2025-11-03T00:00:00Z E->H\nS1F4 <L <I1 -1> <I2 -2> <I4 -4> <I8 -8> <U1 1> <U2 2> <U8 8> <F8 -0.5> <J \"x\">>.\n";
+        let messages = SmlParser.parse_all(Box::new(Cursor::new(log))).unwrap();
+        let items = messages[0].body_json["secs_tree"]["items"].as_array().unwrap();
+        let types: Vec<&str> = items.iter().map(|i| i["t"].as_str().unwrap()).collect();
+        assert_eq!(types, ["I1", "I2", "I4", "I8", "U1", "U2", "U8", "F8", "J"]);
+        assert_eq!(items[3]["v"], -8);
+        assert_eq!(items[7]["v"], -0.5);
+    }
+
+    #[test]
+    fn test_sml_signed_bounds() {
+        let log = "2025-11-03T00:00:00Z E->H\nS1F4 <L <I1 -128> <I1 127> <I8 -9223372036854775808> <I8 9223372036854775807>>.\n";
+        let messages = SmlParser.parse_all(Box::new(Cursor::new(log))).unwrap();
+        let items = &messages[0].body_json["secs_tree"]["items"];
+        assert_eq!(items[0]["v"], -128);
+        assert_eq!(items[1]["v"], 127);
+        assert_eq!(items[2]["v"], i64::MIN);
+        assert_eq!(items[3]["v"], i64::MAX);
+
+        for item in ["<I1 128>", "<I1 -129>", "<I1 -18446744073709551615>", "<I8 9223372036854775808>",
+                     "<I8 -9223372036854775809>", "<I8 18446744073709551615>"] {
+            let log = format!("2025-11-03T00:00:00Z E->H\nS1F4 {}.\n", item);
+            assert!(SmlParser.parse_all(Box::new(Cursor::new(log))).is_err(), "{} should be rejected", item);
+        }
+    }
+
+    #[test]
+    fn test_sml_nesting_limit() {
+        let nested = |depth: usize| format!("2025-11-03T00:00:00Z E->H\nS1F4 {}{}.\n", "<L ".repeat(depth), ">".repeat(depth));
+        assert!(SmlParser.parse_all(Box::new(Cursor::new(nested(MAX_DEPTH + 1)))).is_ok());
+
+        let err = SmlParser.parse_all(Box::new(Cursor::new(nested(100_000)))).unwrap_err();
+        assert!(err.to_string().contains("nesting too deep"));
+    }
+
+    #[test]
+    fn test_sml_malformed_item() {
+        let log = "2025-11-03T00:00:00Z E->H\nS1F4 <L <U4 abc>>.\n";
+        let err = SmlParser.parse_all(Box::new(Cursor::new(log))).unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+    }
+}
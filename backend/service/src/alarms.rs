@@ -0,0 +1,231 @@
+//! Alarm timeline (`alarms.json`, `GET /sessions/:id/alarms`).
+//!
+//! S5F1 reports an alarm: `<L[3] ALCD ALID ALTX>`, where bit 8 of ALCD is set
+//! for "alarm set" and the low bits give the category. `AlarmTracker` pairs
+//! each set with the next clear of the same ALID. S5F3 (`<L[2] ALED ALID>`,
+//! empty ALID = all) records whether the host enabled an alarm, and the
+//! alarm lists of S5F6/S5F8 fill in texts.
+
+use crate::models::{AlarmInfo, AlarmInterval, ConvertedMessage, SessionAlarms};
+use crate::secs_tree::{body_tree, item_code, item_id, item_text, item_value, list_items, list_of};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+
+const ALARM_SET: u8 = 0x80;
+
+#[derive(Clone)]
+struct AlarmState {
+    info: AlarmInfo,
+    /// Any S5F1 seen yet
+    reported: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct AlarmTracker {
+    alarms: BTreeMap<u32, AlarmState>,
+    open: HashMap<u32, AlarmInterval>,
+    closed: Vec<AlarmInterval>,
+    /// S5F3 with an empty ALID; per-ALID settings made since override it
+    all_enabled: Option<bool>,
+}
+
+impl AlarmTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next message in log order
+    pub fn observe(&mut self, msg: &ConvertedMessage) {
+        let Some(tree) = body_tree(&msg.body_json) else {
+            return;
+        };
+        match (msg.s, msg.f) {
+            (5, 1) => self.report(msg, tree),
+            (5, 3) => self.enable(tree),
+            (5, 6 | 8) => self.list(tree, msg.f == 8),
+            _ => {}
+        }
+    }
+
+    fn state(&mut self, alid: u32) -> &mut AlarmState {
+        let all_enabled = self.all_enabled;
+        self.alarms.entry(alid).or_insert_with(|| AlarmState {
+            info: AlarmInfo { alid, altx: None, enabled: all_enabled, set_count: 0 },
+            reported: false,
+        })
+    }
+
+    fn report(&mut self, msg: &ConvertedMessage, tree: &serde_json::Value) {
+        let Some([alcd, alid, altx]) = list_of(tree, 3).map(|items| [&items[0], &items[1], &items[2]]) else {
+            return;
+        };
+        let (Some(alcd), Some(alid)) = (item_code(alcd), item_id(alid)) else {
+            return;
+        };
+        let altx = item_text(altx).map(|text| text.trim_end().to_string());
+
+        let state = self.state(alid);
+        let first_report = !state.reported;
+        state.reported = true;
+        if altx.is_some() {
+            state.info.altx = altx.clone();
+        }
+
+        if alcd & ALARM_SET != 0 {
+            // A repeated set while already set keeps the original start
+            if let Entry::Vacant(entry) = self.open.entry(alid) {
+                entry.insert(AlarmInterval {
+                    alid,
+                    category: alcd & !ALARM_SET,
+                    altx,
+                    set_ns: Some(msg.ts_ns),
+                    clear_ns: None,
+                    duration_ns: None,
+                    set_row_id: Some(msg.row_id),
+                    clear_row_id: None,
+                });
+                self.state(alid).info.set_count += 1;
+            }
+            return;
+        }
+
+        let interval = match self.open.remove(&alid) {
+            Some(interval) => interval,
+            // The alarm was already set when the log starts
+            None if first_report => AlarmInterval {
+                alid,
+                category: alcd,
+                altx,
+                set_ns: None,
+                clear_ns: None,
+                duration_ns: None,
+                set_row_id: None,
+                clear_row_id: None,
+            },
+            None => return,
+        };
+        self.closed.push(AlarmInterval {
+            clear_ns: Some(msg.ts_ns),
+            duration_ns: interval.set_ns.map(|set_ns| msg.ts_ns - set_ns),
+            clear_row_id: Some(msg.row_id),
+            ..interval
+        });
+    }
+
+    fn enable(&mut self, tree: &serde_json::Value) {
+        let Some(items) = list_of(tree, 2) else {
+            return;
+        };
+        let Some(aled) = item_code(&items[0]) else {
+            return;
+        };
+        let enabled = aled & ALARM_SET != 0;
+        match item_id(&items[1]) {
+            Some(alid) => self.state(alid).info.enabled = Some(enabled),
+            None if item_value(&items[1]).as_array().is_some_and(|v| v.is_empty()) => {
+                self.all_enabled = Some(enabled);
+                for state in self.alarms.values_mut() {
+                    state.info.enabled = Some(enabled);
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// S5F6 (requested alarms) / S5F8 (enabled alarms): `<L[n] <L[3] ALCD ALID ALTX>>`
+    fn list(&mut self, tree: &serde_json::Value, enabled_list: bool) {
+        for entry in list_items(tree).into_iter().flatten() {
+            let Some(items) = list_of(entry, 3) else {
+                continue;
+            };
+            let Some(alid) = item_id(&items[1]) else {
+                continue;
+            };
+            let altx = item_text(&items[2]).map(|text| text.trim_end().to_string());
+            let state = self.state(alid);
+            if altx.is_some() {
+                state.info.altx = altx;
+            }
+            if enabled_list {
+                state.info.enabled = Some(true);
+            }
+        }
+    }
+
+    /// Close the log: alarms still set stay open-ended
+    pub fn finish(self) -> SessionAlarms {
+        let mut intervals = self.closed;
+        intervals.extend(self.open.into_values());
+        intervals.sort_by_key(|i| (i.set_ns.or(i.clear_ns), i.alid));
+
+        SessionAlarms {
+            intervals,
+            alarms: self.alarms.into_values().map(|state| state.info).collect(),
+        }
+    }
+}
+
+/// Intervals overlapping `[from_ns, to_ns]` (0 = unbounded), optionally one ALID only
+pub fn filter_intervals(alarms: SessionAlarms, alid: Option<u32>, from_ns: i64, to_ns: i64) -> SessionAlarms {
+    let intervals = alarms.intervals.into_iter()
+        .filter(|i| alid.is_none_or(|alid| i.alid == alid))
+        .filter(|i| from_ns == 0 || i.clear_ns.is_none_or(|clear| clear >= from_ns))
+        .filter(|i| to_ns == 0 || i.set_ns.is_none_or(|set| set <= to_ns))
+        .collect();
+    let alarms = alarms.alarms.into_iter()
+        .filter(|a| alid.is_none_or(|alid| a.alid == alid))
+        .collect();
+    SessionAlarms { intervals, alarms }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg;
+    use serde_json::{json, Value};
+
+    fn s5(f: u8, ts_ms: i64, row_id: u32, tree: Value) -> ConvertedMessage {
+        msg(5, f).row(row_id).at_ms(ts_ms).dir(-1).wbit(1).sysbytes(row_id).body(json!({"secs_tree": tree}))
+    }
+
+    fn alarm(ts_ms: i64, row_id: u32, alcd: &str, alid: u32, altx: &str) -> ConvertedMessage {
+        s5(1, ts_ms, row_id, json!({"t": "L", "items": [
+            {"t": "B", "v": alcd}, {"t": "U4", "v": alid}, {"t": "A", "v": altx},
+        ]}))
+    }
+
+    #[test]
+    fn test_alarm_intervals() {
+        // ALCD 0x84 = set, category 4 ("gQ==" / "hA==" / "BA==" are 0x81 / 0x84 / 0x04)
+        let messages = [
+            alarm(0, 0, "BA==", 7, "Door open"),         // cleared: set before the log
+            alarm(10, 1, "hA==", 1, "Vacuum fault   "),
+            alarm(15, 2, "hA==", 1, "Vacuum fault"),     // repeated set
+            s5(3, 20, 3, json!({"t": "L", "items": [{"t": "B", "v": "AA=="}, {"t": "U4", "v": []}]})),
+            alarm(30, 4, "BA==", 1, "Vacuum fault"),
+            alarm(40, 5, "BA==", 1, "Vacuum fault"),     // clear without set: ignored
+            alarm(50, 6, "gQ==", 2, "Temp high"),        // still set at the end
+        ];
+        let mut tracker = AlarmTracker::new();
+        for msg in &messages {
+            tracker.observe(msg);
+        }
+        let alarms = tracker.finish();
+
+        let spans: Vec<_> = alarms.intervals.iter()
+            .map(|i| (i.alid, i.category, i.set_row_id, i.clear_row_id, i.duration_ns))
+            .collect();
+        assert_eq!(spans, vec![
+            (7, 4, None, Some(0), None),
+            (1, 4, Some(1), Some(4), Some(20_000_000)),
+            (2, 1, Some(6), None, None),
+        ]);
+        assert_eq!(alarms.intervals[1].altx.as_deref(), Some("Vacuum fault"));
+
+        let info: Vec<_> = alarms.alarms.iter().map(|a| (a.alid, a.enabled, a.set_count)).collect();
+        assert_eq!(info, vec![(1, Some(false), 1), (2, Some(false), 1), (7, Some(false), 0)]);
+
+        let filtered = filter_intervals(alarms, None, 25_000_000, 45_000_000);
+        assert_eq!(filtered.intervals.iter().map(|i| i.alid).collect::<Vec<_>>(), vec![1]);
+    }
+}
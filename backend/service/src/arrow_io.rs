@@ -1,11 +1,13 @@
-use crate::models::{ConvertedMessage, SessionMeta};
+use crate::models::{ChunkStats, ConvertedMessage, SessionMeta, SessionStatus};
+use crate::stats::CountCollector;
 use arrow::array::{
-    ArrayRef, Int64Array, Int8Array, UInt8Array, UInt32Array,
+    ArrayRef, BooleanArray, Int64Array, Int8Array, UInt8Array, UInt16Array, UInt32Array,
 };
 use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::reader::StreamReader;
 use arrow::ipc::writer::StreamWriter;
 use arrow::record_batch::RecordBatch;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
 use std::path::Path;
 use std::sync::Arc;
@@ -21,6 +23,9 @@ pub struct ArrowBuilder {
     sysbytes: Vec<u32>,
     ceid: Vec<u32>,
     row_id: Vec<u32>,
+    reply_row_id: Vec<Option<u32>>,
+    latency_ns: Vec<Option<i64>>,
+    violation_count: Vec<Option<u16>>,
 }
 
 impl ArrowBuilder {
@@ -34,6 +39,9 @@ impl ArrowBuilder {
             sysbytes: Vec::with_capacity(CHUNK_SIZE),
             ceid: Vec::with_capacity(CHUNK_SIZE),
             row_id: Vec::with_capacity(CHUNK_SIZE),
+            reply_row_id: Vec::with_capacity(CHUNK_SIZE),
+            latency_ns: Vec::with_capacity(CHUNK_SIZE),
+            violation_count: Vec::with_capacity(CHUNK_SIZE),
         }
     }
     
@@ -46,6 +54,15 @@ impl ArrowBuilder {
         self.sysbytes.push(msg.sysbytes);
         self.ceid.push(msg.ceid);
         self.row_id.push(msg.row_id);
+        self.reply_row_id.push(msg.reply_row_id);
+        self.latency_ns.push(msg.latency_ns);
+        self.violation_count.push(msg.violation_count);
+    }
+
+    /// Record the reply for the primary at `idx` (position within this builder)
+    pub fn set_reply(&mut self, idx: usize, reply_row_id: u32, latency_ns: i64) {
+        self.reply_row_id[idx] = Some(reply_row_id);
+        self.latency_ns[idx] = Some(latency_ns);
     }
     
     pub fn len(&self) -> usize {
@@ -56,6 +73,16 @@ impl ArrowBuilder {
         self.ts_ns.is_empty()
     }
     
+    /// Row and time range of the rows currently buffered
+    pub fn chunk_stats(&self) -> Option<ChunkStats> {
+        Some(ChunkStats {
+            rows: self.len(),
+            first_row_id: *self.row_id.first()?,
+            t_min_ns: *self.ts_ns.iter().min()?,
+            t_max_ns: *self.ts_ns.iter().max()?,
+        })
+    }
+    
     pub fn clear(&mut self) {
         self.ts_ns.clear();
         self.dir.clear();
@@ -65,6 +92,9 @@ impl ArrowBuilder {
         self.sysbytes.clear();
         self.ceid.clear();
         self.row_id.clear();
+        self.reply_row_id.clear();
+        self.latency_ns.clear();
+        self.violation_count.clear();
     }
     
     pub fn build_batch(&self) -> Result<RecordBatch, arrow::error::ArrowError> {
@@ -79,6 +109,9 @@ impl ArrowBuilder {
             Arc::new(UInt32Array::from(self.sysbytes.clone())),
             Arc::new(UInt32Array::from(self.ceid.clone())),
             Arc::new(UInt32Array::from(self.row_id.clone())),
+            Arc::new(UInt32Array::from(self.reply_row_id.clone())),
+            Arc::new(Int64Array::from(self.latency_ns.clone())),
+            Arc::new(UInt16Array::from(self.violation_count.clone())),
         ];
         
         RecordBatch::try_new(schema, columns)
@@ -95,9 +128,34 @@ pub fn get_arrow_schema() -> Arc<Schema> {
         Field::new("sysbytes", DataType::UInt32, false),
         Field::new("ceid", DataType::UInt32, false),
         Field::new("row_id", DataType::UInt32, false),
+        Field::new("reply_row_id", DataType::UInt32, true),
+        Field::new("latency_ns", DataType::Int64, true),
+        Field::new("violation_count", DataType::UInt16, true),
     ]))
 }
 
+/// Rewrite the reply columns of an already written chunk.
+/// `patches` are (row offset within the batch, reply_row_id, latency_ns).
+pub fn patch_reply_columns(
+    batch: &RecordBatch,
+    patches: &[(usize, u32, i64)],
+) -> Result<RecordBatch, arrow::error::ArrowError> {
+    let reply_arr = batch.column(8).as_any().downcast_ref::<UInt32Array>().unwrap();
+    let latency_arr = batch.column(9).as_any().downcast_ref::<Int64Array>().unwrap();
+
+    let mut reply_row_id: Vec<Option<u32>> = reply_arr.iter().collect();
+    let mut latency_ns: Vec<Option<i64>> = latency_arr.iter().collect();
+    for &(idx, reply, latency) in patches {
+        reply_row_id[idx] = Some(reply);
+        latency_ns[idx] = Some(latency);
+    }
+
+    let mut columns = batch.columns().to_vec();
+    columns[8] = Arc::new(UInt32Array::from(reply_row_id));
+    columns[9] = Arc::new(Int64Array::from(latency_ns));
+    RecordBatch::try_new(batch.schema(), columns)
+}
+
 pub fn write_arrow_chunk(
     batch: &RecordBatch,
     path: &Path,
@@ -109,6 +167,107 @@ pub fn write_arrow_chunk(
     Ok(())
 }
 
+/// Builds the `/transactions` result: one row per paired primary
+pub struct TransactionBuilder {
+    primary_row_id: Vec<u32>,
+    reply_row_id: Vec<u32>,
+    ts_ns: Vec<i64>,
+    dir: Vec<i8>,
+    s: Vec<u8>,
+    f: Vec<u8>,
+    sysbytes: Vec<u32>,
+    latency_ns: Vec<i64>,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self {
+            primary_row_id: Vec::new(),
+            reply_row_id: Vec::new(),
+            ts_ns: Vec::new(),
+            dir: Vec::new(),
+            s: Vec::new(),
+            f: Vec::new(),
+            sysbytes: Vec::new(),
+            latency_ns: Vec::new(),
+        }
+    }
+
+    /// Push a primary; rows without a recorded reply are skipped
+    pub fn push(&mut self, msg: &ConvertedMessage) {
+        let (Some(reply_row_id), Some(latency_ns)) = (msg.reply_row_id, msg.latency_ns) else {
+            return;
+        };
+        self.primary_row_id.push(msg.row_id);
+        self.reply_row_id.push(reply_row_id);
+        self.ts_ns.push(msg.ts_ns);
+        self.dir.push(msg.dir);
+        self.s.push(msg.s);
+        self.f.push(msg.f);
+        self.sysbytes.push(msg.sysbytes);
+        self.latency_ns.push(latency_ns);
+    }
+
+    pub fn len(&self) -> usize {
+        self.primary_row_id.len()
+    }
+
+    pub fn build_batch(&self) -> Result<RecordBatch, arrow::error::ArrowError> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("primary_row_id", DataType::UInt32, false),
+            Field::new("reply_row_id", DataType::UInt32, false),
+            Field::new("ts_ns", DataType::Int64, false),
+            Field::new("dir", DataType::Int8, false),
+            Field::new("s", DataType::UInt8, false),
+            Field::new("f", DataType::UInt8, false),
+            Field::new("sysbytes", DataType::UInt32, false),
+            Field::new("latency_ns", DataType::Int64, false),
+        ]));
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt32Array::from(self.primary_row_id.clone())),
+            Arc::new(UInt32Array::from(self.reply_row_id.clone())),
+            Arc::new(Int64Array::from(self.ts_ns.clone())),
+            Arc::new(Int8Array::from(self.dir.clone())),
+            Arc::new(UInt8Array::from(self.s.clone())),
+            Arc::new(UInt8Array::from(self.f.clone())),
+            Arc::new(UInt32Array::from(self.sysbytes.clone())),
+            Arc::new(Int64Array::from(self.latency_ns.clone())),
+        ];
+
+        RecordBatch::try_new(schema, columns)
+    }
+}
+
+pub fn read_arrow_chunk(path: &Path) -> Result<Vec<RecordBatch>, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+    let reader = StreamReader::try_new(file, None)?;
+    let mut batches = Vec::new();
+    for batch in reader {
+        batches.push(batch?);
+    }
+    Ok(batches)
+}
+
+/// Append non-nullable boolean columns (e.g. highlight flags) to a batch
+pub fn append_bool_columns(
+    batch: &RecordBatch,
+    columns: Vec<(&str, Vec<bool>)>,
+) -> Result<RecordBatch, arrow::error::ArrowError> {
+    let mut fields: Vec<Field> = batch.schema().fields().iter()
+        .map(|f| f.as_ref().clone())
+        .collect();
+    let mut arrays: Vec<ArrayRef> = batch.columns().to_vec();
+
+    for (name, values) in columns {
+        fields.push(Field::new(name, DataType::Boolean, false));
+        arrays.push(Arc::new(BooleanArray::from(values)));
+    }
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+}
+
+#[derive(Clone)]
 pub struct MetaCollector {
     pub row_count: usize,
     pub t_min_ns: i64,
@@ -116,6 +275,9 @@ pub struct MetaCollector {
     pub distinct_s: HashSet<u8>,
     pub distinct_f: HashSet<u8>,
     pub distinct_ceid: HashSet<u32>,
+    pub ceid_names: BTreeMap<u32, String>,
+    pub chunks: Vec<ChunkStats>,
+    pub counts: CountCollector,
 }
 
 impl MetaCollector {
@@ -127,6 +289,9 @@ impl MetaCollector {
             distinct_s: HashSet::new(),
             distinct_f: HashSet::new(),
             distinct_ceid: HashSet::new(),
+            ceid_names: BTreeMap::new(),
+            chunks: Vec::new(),
+            counts: CountCollector::new(),
         }
     }
     
@@ -138,6 +303,19 @@ impl MetaCollector {
         self.distinct_f.insert(msg.f);
         if msg.ceid > 0 {
             self.distinct_ceid.insert(msg.ceid);
+            if let std::collections::btree_map::Entry::Vacant(entry) = self.ceid_names.entry(msg.ceid) {
+                if let Some(name) = msg.body_json.pointer("/semantic/ceid_name").and_then(|n| n.as_str()) {
+                    entry.insert(name.to_string());
+                }
+            }
+        }
+        self.counts.push(msg);
+    }
+    
+    /// Record the stats of a chunk about to be written
+    pub fn push_chunk(&mut self, builder: &ArrowBuilder) {
+        if let Some(stats) = builder.chunk_stats() {
+            self.chunks.push(stats);
         }
     }
     
@@ -158,6 +336,14 @@ impl MetaCollector {
             distinct_s: s_vec,
             distinct_f: f_vec,
             distinct_ceid: ceid_vec,
+            ceid_names: self.ceid_names,
+            chunks: self.chunks,
+            counts: Some(self.counts.into_counts()),
+            tile_levels: Vec::new(),
+            live: false,
+            status: SessionStatus::Ready,
+            error: None,
+            parser: None,
         }
     }
 }
@@ -0,0 +1,446 @@
+//! Service configuration: built-in defaults, then a TOML file, then `HSMS_*`
+//! environment variables, then command-line flags, each overriding the last.
+//!
+//! The file is `--config`/`HSMS_CONFIG`, or `hsms.toml` in the working
+//! directory if there is one. Everything is validated before the service
+//! starts; every problem is reported with the key it concerns.
+
+use crate::arrow_io::CHUNK_SIZE;
+use crate::conformance::Catalog;
+use crate::sql::SqlLimits;
+use crate::sweeper::SweepConfig;
+use clap::Parser;
+use parser::{Endpoint, ParserOptions, PcapOptions};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_FILE: &str = "hsms.toml";
+/// Keeps a chunk and its text index comfortably in memory
+const MAX_CHUNK_SIZE: usize = 1_000_000;
+const MB: u64 = 1024 * 1024;
+const HOUR_SECS: u64 = 3600;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: SocketAddr,
+    pub data_dir: PathBuf,
+    /// Largest accepted request body, i.e. upload
+    pub body_limit_mb: u64,
+    /// Origins the browser may call from; `*` allows any
+    pub cors_origins: Vec<String>,
+    /// Rows per Arrow chunk of new sessions
+    pub chunk_size: usize,
+    /// Overrides of the built-in conformance catalog [default: catalog.json
+    /// in `data_dir`, if present]
+    pub conformance_catalog: Option<PathBuf>,
+    pub retention: RetentionConfig,
+    pub parser: ParserConfig,
+    pub sql: SqlConfig,
+    pub live: LiveConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            data_dir: PathBuf::from("./data"),
+            body_limit_mb: 1024,
+            cors_origins: vec!["*".to_string()],
+            chunk_size: CHUNK_SIZE,
+            conformance_catalog: None,
+            retention: RetentionConfig::default(),
+            parser: ParserConfig::default(),
+            sql: SqlConfig::default(),
+            live: LiveConfig::default(),
+        }
+    }
+}
+
+/// See `sweeper`; unset disables the TTL or quota
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    pub ttl_hours: Option<u64>,
+    pub quota_mb: Option<u64>,
+    pub sweep_interval_secs: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self { ttl_hours: None, quota_mb: None, sweep_interval_secs: SweepConfig::default().interval.as_secs() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParserConfig {
+    /// TCP ports a packet capture carries HSMS on
+    pub pcap_ports: Vec<u16>,
+    /// Host and equipment endpoints of captures (`ip`, `ip:port` or `:port`)
+    pub pcap_host: Vec<String>,
+    pub pcap_equipment: Vec<String>,
+    /// Spacing of the timestamps given to raw HSMS frames
+    pub hsms_frame_interval_ms: u64,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        let defaults = ParserOptions::default();
+        Self {
+            pcap_ports: defaults.pcap.hsms_ports,
+            pcap_host: Vec::new(),
+            pcap_equipment: Vec::new(),
+            hsms_frame_interval_ms: defaults.hsms_frame_interval.num_milliseconds() as u64,
+        }
+    }
+}
+
+/// Per-query bounds of `/sql`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqlConfig {
+    /// Memory for sorts, joins and aggregates before they spill to disk
+    pub memory_limit_mb: u64,
+    /// Largest result a query may return
+    pub max_rows: usize,
+}
+
+impl Default for SqlConfig {
+    fn default() -> Self {
+        let defaults = SqlLimits::default();
+        Self { memory_limit_mb: defaults.memory_bytes as u64 / MB, max_rows: defaults.max_rows }
+    }
+}
+
+/// Where `POST /sessions/live` may connect or listen
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LiveConfig {
+    /// Endpoints (`ip`, `ip:port` or `:port`) every address of a live session
+    /// must resolve to; empty disables live sessions
+    pub allowed_addresses: Vec<String>,
+}
+
+/// Command-line flags; each also reads the environment variable named
+#[derive(Debug, Parser)]
+#[command(name = "service", about = "HSMS log analysis service")]
+pub struct Cli {
+    /// TOML configuration file [default: ./hsms.toml if present]
+    #[arg(long, env = "HSMS_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on, e.g. 0.0.0.0:8080
+    #[arg(long, env = "HSMS_BIND")]
+    pub bind: Option<SocketAddr>,
+    /// Where sessions and profiles live [default: ./data]
+    #[arg(long, env = "HSMS_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+    /// Largest accepted upload [default: 1024]
+    #[arg(long, env = "HSMS_BODY_LIMIT_MB")]
+    pub body_limit_mb: Option<u64>,
+    /// Comma-separated allowed origins, `*` for any [default: *]
+    #[arg(long, env = "HSMS_CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Option<Vec<String>>,
+    /// Rows per Arrow chunk of new sessions [default: 50000]
+    #[arg(long, env = "HSMS_CHUNK_SIZE")]
+    pub chunk_size: Option<usize>,
+    /// JSON overrides of the conformance catalog [default: catalog.json in the data directory, if present]
+    #[arg(long, env = "HSMS_CONFORMANCE_CATALOG")]
+    pub conformance_catalog: Option<PathBuf>,
+    /// Delete sessions created longer ago than this
+    #[arg(long, env = "HSMS_TTL_HOURS")]
+    pub ttl_hours: Option<u64>,
+    /// Evict least recently accessed sessions beyond this much data
+    #[arg(long, env = "HSMS_QUOTA_MB")]
+    pub quota_mb: Option<u64>,
+    /// Time between retention sweeps [default: 300]
+    #[arg(long, env = "HSMS_SWEEP_INTERVAL_SECS")]
+    pub sweep_interval_secs: Option<u64>,
+    /// Comma-separated TCP ports captures carry HSMS on [default: 5000]
+    #[arg(long, env = "HSMS_PCAP_PORTS", value_delimiter = ',')]
+    pub pcap_ports: Option<Vec<u16>>,
+    /// Comma-separated host endpoints of captures (ip, ip:port or :port)
+    #[arg(long, env = "HSMS_PCAP_HOST", value_delimiter = ',')]
+    pub pcap_host: Option<Vec<String>>,
+    /// Comma-separated equipment endpoints of captures
+    #[arg(long, env = "HSMS_PCAP_EQUIPMENT", value_delimiter = ',')]
+    pub pcap_equipment: Option<Vec<String>>,
+    /// Spacing of the timestamps of raw HSMS frames [default: 1]
+    #[arg(long, env = "HSMS_FRAME_INTERVAL_MS")]
+    pub hsms_frame_interval_ms: Option<u64>,
+    /// Memory one SQL query may use before spilling to disk [default: 512]
+    #[arg(long, env = "HSMS_SQL_MEMORY_LIMIT_MB")]
+    pub sql_memory_limit_mb: Option<u64>,
+    /// Most rows one SQL query may return [default: 1000000]
+    #[arg(long, env = "HSMS_SQL_MAX_ROWS")]
+    pub sql_max_rows: Option<usize>,
+    /// Comma-separated endpoints live sessions may connect to or listen on [default: none]
+    #[arg(long, env = "HSMS_LIVE_ALLOWED_ADDRESSES", value_delimiter = ',')]
+    pub live_allowed_addresses: Option<Vec<String>>,
+}
+
+impl Config {
+    /// The configuration from the command line, environment and file
+    pub fn load() -> Result<Self, String> {
+        Self::resolve(Cli::parse())
+    }
+
+    pub fn resolve(cli: Cli) -> Result<Self, String> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_FILE).exists() => Self::from_file(Path::new(DEFAULT_FILE))?,
+            None => Self::default(),
+        };
+        config.apply(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn apply(&mut self, cli: Cli) {
+        let retention = &mut self.retention;
+        let parser = &mut self.parser;
+        overwrite(&mut self.bind, cli.bind);
+        overwrite(&mut self.data_dir, cli.data_dir);
+        overwrite(&mut self.body_limit_mb, cli.body_limit_mb);
+        overwrite(&mut self.cors_origins, cli.cors_origins);
+        overwrite(&mut self.chunk_size, cli.chunk_size);
+        overwrite(&mut self.conformance_catalog, cli.conformance_catalog.map(Some));
+        overwrite(&mut retention.ttl_hours, cli.ttl_hours.map(Some));
+        overwrite(&mut retention.quota_mb, cli.quota_mb.map(Some));
+        overwrite(&mut retention.sweep_interval_secs, cli.sweep_interval_secs);
+        overwrite(&mut parser.pcap_ports, cli.pcap_ports);
+        overwrite(&mut parser.pcap_host, cli.pcap_host);
+        overwrite(&mut parser.pcap_equipment, cli.pcap_equipment);
+        overwrite(&mut parser.hsms_frame_interval_ms, cli.hsms_frame_interval_ms);
+        overwrite(&mut self.sql.memory_limit_mb, cli.sql_memory_limit_mb);
+        overwrite(&mut self.sql.max_rows, cli.sql_max_rows);
+        overwrite(&mut self.live.allowed_addresses, cli.live_allowed_addresses);
+    }
+
+    /// Every problem at once, one per line
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        if self.body_limit_mb == 0 {
+            problems.push("body_limit_mb: must be positive".to_string());
+        } else if self.body_limit_mb.checked_mul(MB).and_then(|bytes| usize::try_from(bytes).ok()).is_none() {
+            problems.push(format!("body_limit_mb: {} MB is more bytes than this platform can address", self.body_limit_mb));
+        }
+        if self.cors_origins.is_empty() {
+            problems.push("cors_origins: list at least one origin, or \"*\"".to_string());
+        }
+        for origin in self.cors_origins.iter().filter(|origin| *origin != "*") {
+            let valid = (origin.starts_with("http://") || origin.starts_with("https://"))
+                && !origin.ends_with('/')
+                && origin.parse::<axum::http::HeaderValue>().is_ok();
+            if !valid {
+                problems.push(format!("cors_origins: '{}' is not an origin like https://host:port", origin));
+            }
+        }
+        if !(1..=MAX_CHUNK_SIZE).contains(&self.chunk_size) {
+            problems.push(format!("chunk_size: must be between 1 and {}, got {}", MAX_CHUNK_SIZE, self.chunk_size));
+        }
+        if let Some(path) = &self.conformance_catalog {
+            if !path.is_file() {
+                problems.push(format!("conformance_catalog: {} is not a file", path.display()));
+            } else if let Err(e) = Catalog::load(path) {
+                problems.push(format!("conformance_catalog: {}", e));
+            }
+        }
+        match self.retention.ttl_hours {
+            Some(0) => problems.push("retention.ttl_hours: must be positive (leave it unset to keep sessions)".to_string()),
+            Some(hours) if hours.checked_mul(HOUR_SECS).is_none() => {
+                problems.push(format!("retention.ttl_hours: {} hours is too long (leave it unset to keep sessions)", hours));
+            }
+            _ => {}
+        }
+        match self.retention.quota_mb {
+            Some(0) => problems.push("retention.quota_mb: must be positive (leave it unset for no quota)".to_string()),
+            Some(mb) if mb.checked_mul(MB).is_none() => {
+                problems.push(format!("retention.quota_mb: {} MB is more bytes than can be counted (leave it unset for no quota)", mb));
+            }
+            _ => {}
+        }
+        if self.retention.sweep_interval_secs == 0 {
+            problems.push("retention.sweep_interval_secs: must be positive".to_string());
+        }
+        if self.parser.pcap_ports.contains(&0) {
+            problems.push("parser.pcap_ports: 0 is not a port".to_string());
+        }
+        for (key, endpoints) in [
+            ("parser.pcap_host", &self.parser.pcap_host),
+            ("parser.pcap_equipment", &self.parser.pcap_equipment),
+            ("live.allowed_addresses", &self.live.allowed_addresses),
+        ] {
+            for endpoint in endpoints {
+                if let Err(e) = endpoint.parse::<Endpoint>() {
+                    problems.push(format!("{}: {}", key, e));
+                }
+            }
+        }
+        if self.parser.hsms_frame_interval_ms == 0 {
+            problems.push("parser.hsms_frame_interval_ms: must be positive".to_string());
+        }
+        if self.sql.memory_limit_mb == 0 {
+            problems.push("sql.memory_limit_mb: must be positive".to_string());
+        } else if self.sql.memory_limit_mb.checked_mul(MB).and_then(|bytes| usize::try_from(bytes).ok()).is_none() {
+            problems.push(format!("sql.memory_limit_mb: {} MB is more bytes than this platform can address", self.sql.memory_limit_mb));
+        }
+        if self.sql.max_rows == 0 {
+            problems.push("sql.max_rows: must be positive".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+
+    /// The configured catalog overrides, or `catalog.json` in the data directory
+    pub fn catalog_path(&self) -> PathBuf {
+        self.conformance_catalog.clone().unwrap_or_else(|| self.data_dir.join("catalog.json"))
+    }
+
+    /// The sizes below must have passed `validate`; they saturate otherwise
+    pub fn body_limit_bytes(&self) -> usize {
+        usize::try_from(self.body_limit_mb.saturating_mul(MB)).unwrap_or(usize::MAX)
+    }
+
+    pub fn sql_limits(&self) -> SqlLimits {
+        SqlLimits {
+            memory_bytes: usize::try_from(self.sql.memory_limit_mb.saturating_mul(MB)).unwrap_or(usize::MAX),
+            max_rows: self.sql.max_rows,
+        }
+    }
+
+    /// Endpoints live sessions may use; they must have passed `validate`
+    pub fn live_allowed(&self) -> Vec<Endpoint> {
+        self.live.allowed_addresses.iter().filter_map(|e| e.parse().ok()).collect()
+    }
+
+    pub fn sweep(&self) -> SweepConfig {
+        SweepConfig {
+            interval: Duration::from_secs(self.retention.sweep_interval_secs),
+            ttl: self.retention.ttl_hours.map(|hours| Duration::from_secs(hours.saturating_mul(HOUR_SECS))),
+            quota_bytes: self.retention.quota_mb.map(|mb| mb.saturating_mul(MB)),
+        }
+    }
+
+    /// Parser settings; the endpoints must have passed `validate`
+    pub fn parser_options(&self) -> ParserOptions {
+        let endpoints = |list: &[String]| list.iter().filter_map(|e| e.parse().ok()).collect();
+        ParserOptions {
+            pcap: PcapOptions {
+                hsms_ports: self.parser.pcap_ports.clone(),
+                host: endpoints(&self.parser.pcap_host),
+                equipment: endpoints(&self.parser.pcap_equipment),
+            },
+            hsms_frame_interval: chrono::Duration::milliseconds(self.parser.hsms_frame_interval_ms as i64),
+        }
+    }
+}
+
+fn overwrite<T>(value: &mut T, with: Option<T>) {
+    if let Some(with) = with {
+        *value = with;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_file_then_flags_then_validation() {
+        let dir = TempDir::new("hsms-config");
+        let path = dir.join("hsms.toml");
+        std::fs::write(&path, r#"
+bind = "0.0.0.0:9000"
+data_dir = "/srv/hsms"
+cors_origins = ["https://fab.example"]
+
+[retention]
+ttl_hours = 72
+
+[parser]
+pcap_ports = [5000, 5001]
+pcap_equipment = ["10.0.0.5:5000"]
+
+[sql]
+max_rows = 5000
+
+[live]
+allowed_addresses = ["10.0.0.5:5000"]
+"#).unwrap();
+
+        let cli = Cli::try_parse_from([
+            "service", "--config", path.to_str().unwrap(), "--chunk-size", "1000", "--quota-mb", "512", "--sql-memory-limit-mb", "64",
+            "--live-allowed-addresses", "10.0.0.5:5000,:5100",
+        ]).unwrap();
+        let config = Config::resolve(cli).unwrap();
+        assert_eq!(config.bind, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(config.data_dir, PathBuf::from("/srv/hsms"));
+        assert_eq!(config.body_limit_mb, 1024);
+        assert_eq!(config.chunk_size, 1000);
+        assert_eq!(config.sweep(), SweepConfig {
+            ttl: Some(Duration::from_secs(72 * 3600)),
+            quota_bytes: Some(512 * 1024 * 1024),
+            ..Default::default()
+        });
+        let options = config.parser_options();
+        assert_eq!(options.pcap.hsms_ports, vec![5000, 5001]);
+        assert_eq!(options.pcap.equipment[0].port, Some(5000));
+        assert_eq!(config.sql_limits(), SqlLimits { memory_bytes: 64 * 1024 * 1024, max_rows: 5000 });
+        assert_eq!(config.catalog_path(), PathBuf::from("/srv/hsms/catalog.json"));
+        assert_eq!(config.live_allowed(), vec!["10.0.0.5:5000".parse().unwrap(), ":5100".parse().unwrap()]);
+
+        // Unknown keys and bad values name what is wrong
+        std::fs::write(&path, "bind = \"0.0.0.0:9000\"\nchunk_sise = 10\n").unwrap();
+        assert!(Config::from_file(&path).unwrap_err().contains("chunk_sise"));
+        let config = Config {
+            chunk_size: 0,
+            cors_origins: vec!["fab.example".into()],
+            parser: ParserConfig { pcap_host: vec!["10.0.0.1:x".into()], ..Default::default() },
+            live: LiveConfig { allowed_addresses: vec!["tap.example".into()] },
+            ..Default::default()
+        };
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.lines().count(), 4, "{}", problems);
+        assert!(problems.contains("chunk_size") && problems.contains("cors_origins") && problems.contains("parser.pcap_host"));
+        assert!(problems.contains("live.allowed_addresses"));
+        assert!(Config::default().validate().is_ok());
+
+        // Sizes and durations that overflow once converted
+        let config = Config {
+            body_limit_mb: u64::MAX,
+            retention: RetentionConfig { ttl_hours: Some(u64::MAX / 1000), quota_mb: Some(u64::MAX / 1000), ..Default::default() },
+            sql: SqlConfig { memory_limit_mb: u64::MAX, ..Default::default() },
+            ..Default::default()
+        };
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.lines().count(), 4, "{}", problems);
+        assert!(problems.contains("body_limit_mb") && problems.contains("retention.ttl_hours") && problems.contains("retention.quota_mb"));
+        assert!(problems.contains("sql.memory_limit_mb"));
+        // Long but representable: a way to say "keep forever"
+        let config = Config { retention: RetentionConfig { ttl_hours: Some(10_000_000), ..Default::default() }, ..Default::default() };
+        assert!(config.validate().is_ok());
+
+        // A configured catalog must exist and parse
+        let catalog = dir.join("catalog.json");
+        let config = Config { conformance_catalog: Some(catalog.clone()), ..Default::default() };
+        assert!(config.validate().unwrap_err().starts_with("conformance_catalog: "));
+        std::fs::write(&catalog, r#"{"S1F1": {"dir": "up"}}"#).unwrap();
+        assert!(config.validate().unwrap_err().starts_with("conformance_catalog: "));
+        std::fs::write(&catalog, r#"{"S64F1": {"body": "<A>"}}"#).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.catalog_path(), catalog);
+    }
+}
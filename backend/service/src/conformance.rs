@@ -0,0 +1,471 @@
+//! SECS-II conformance checks against a SEMI E5 message-structure catalog.
+//!
+//! The catalog maps `SxFy` to the expected direction, W-bit and body:
+//!
+//! ```json
+//! {"S1F4": {"dir": "E->H", "wbit": false, "body": "<L[n] *>"}, "S99F1": null}
+//! ```
+//!
+//! `body` is an SML-like pattern: `<U4>` an item of that format, `<U|I|A>`
+//! any of several (`U`, `I` and `F` stand for every width), `<B[1]>` exactly one
+//! element, `<L[2] <A> <A>>` a list of those items, `<L[n] <A>>` a list of any
+//! length whose items all match, `<L>` any list and `*` any item. `""` means
+//! header only (an empty list is accepted too, as exporters write one);
+//! without `body` the body is not checked. `dir` is `H->E`,
+//! `E->H` or `both`; `wbit` is `true` (required) or `false` (forbidden).
+//!
+//! The built-in catalog (`catalog.json`) covers the common GEM messages; a
+//! catalog file given at startup overrides it per `SxFy`, and `null` drops an
+//! entry. Messages the catalog does not list are not checked, apart from the
+//! rule that a secondary never sets the W-bit.
+
+use crate::models::{ConformanceSummary, ConvertedMessage, RuleCount, SxFyViolations};
+use crate::secs_tree::{body_tree, list_items};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+const BUILTIN_CATALOG: &str = include_str!("catalog.json");
+
+/// Example row ids kept per rule in the summary
+const EXAMPLE_ROWS: usize = 10;
+
+const SCALAR_TYPES: [&str; 15] = ["A", "J", "B", "BOOL", "I1", "I2", "I4", "I8", "U1", "U2", "U4", "U8", "F4", "F8", "L"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    Any,
+    List { len: Option<usize>, items: ListItems },
+    Item { types: Vec<&'static str>, len: Option<usize> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ListItems {
+    Unchecked,
+    Fixed(Vec<Pattern>),
+    Each(Box<Pattern>),
+}
+
+#[derive(Debug, Clone)]
+enum BodySpec {
+    Unchecked,
+    Empty,
+    Item(Pattern),
+}
+
+#[derive(Debug, Clone)]
+struct MessageSpec {
+    dir: Option<i8>,
+    wbit: Option<bool>,
+    body: BodySpec,
+}
+
+/// Catalog entry as written in JSON
+#[derive(Debug, Deserialize)]
+struct EntryJson {
+    #[serde(default)]
+    dir: Option<String>,
+    #[serde(default)]
+    wbit: Option<bool>,
+    #[serde(default)]
+    body: Option<String>,
+}
+
+/// One broken rule on one message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Violation {
+    /// `wbit_on_reply`, `wbit`, `direction` or `body`
+    pub rule: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    entries: HashMap<(u8, u8), MessageSpec>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl Catalog {
+    pub fn builtin() -> Self {
+        let mut catalog = Catalog { entries: HashMap::new() };
+        catalog.extend_json(BUILTIN_CATALOG).expect("built-in catalog is valid");
+        catalog
+    }
+
+    /// The built-in catalog overridden by `path`, if it exists
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut catalog = Self::builtin();
+        if path.exists() {
+            let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            catalog.extend_json(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        Ok(catalog)
+    }
+
+    fn extend_json(&mut self, json: &str) -> Result<(), String> {
+        let entries: BTreeMap<String, Option<EntryJson>> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        for (name, entry) in entries {
+            let key = parse_sxfy(&name).ok_or_else(|| format!("{}: expected a key like \"S1F3\"", name))?;
+            match entry {
+                Some(entry) => {
+                    let spec = MessageSpec::parse(entry).map_err(|e| format!("{}: {}", name, e))?;
+                    self.entries.insert(key, spec);
+                }
+                None => {
+                    self.entries.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Every rule `msg` breaks; HSMS control messages are not checked
+    pub fn check(&self, msg: &ConvertedMessage) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if msg.s == 0 {
+            return violations;
+        }
+        let violation = |rule: &str, message: String| Violation { rule: rule.to_string(), message };
+        let is_reply = msg.f.is_multiple_of(2);
+        if is_reply && msg.wbit == 1 {
+            violations.push(violation("wbit_on_reply", format!("S{}F{} is a secondary but sets the W-bit", msg.s, msg.f)));
+        }
+        let Some(spec) = self.entries.get(&(msg.s, msg.f)) else {
+            return violations;
+        };
+
+        match spec.wbit {
+            Some(true) if msg.wbit == 0 => {
+                violations.push(violation("wbit", format!("S{}F{} requires the W-bit", msg.s, msg.f)));
+            }
+            Some(false) if msg.wbit == 1 && !is_reply => {
+                violations.push(violation("wbit", format!("S{}F{} must not set the W-bit", msg.s, msg.f)));
+            }
+            _ => {}
+        }
+        if spec.dir.is_some_and(|dir| dir != msg.dir) {
+            violations.push(violation("direction", format!("S{}F{} sent {}, expected {}",
+                msg.s, msg.f, dir_name(msg.dir), dir_name(-msg.dir))));
+        }
+
+        // Rows from exporters that dropped the tree cannot be checked
+        if let Some(tree) = body_tree(&msg.body_json) {
+            let problem = match (&spec.body, tree) {
+                (BodySpec::Unchecked, _) => None,
+                // Exporters write a header-only body as an empty list
+                (BodySpec::Empty, tree) if tree.is_null() || list_items(tree).is_some_and(Vec::is_empty) => None,
+                (BodySpec::Empty, _) => Some("expected a header-only message, found a body".to_string()),
+                (BodySpec::Item(_), Value::Null) => Some("expected a body, found a header-only message".to_string()),
+                (BodySpec::Item(pattern), tree) => pattern.check(tree, "$").err(),
+            };
+            if let Some(problem) = problem {
+                violations.push(violation("body", problem));
+            }
+        }
+        violations
+    }
+}
+
+fn parse_sxfy(name: &str) -> Option<(u8, u8)> {
+    let (s, f) = name.strip_prefix('S')?.split_once('F')?;
+    Some((s.parse().ok()?, f.parse().ok()?))
+}
+
+fn dir_name(dir: i8) -> &'static str {
+    if dir == 1 { "H->E" } else { "E->H" }
+}
+
+impl MessageSpec {
+    fn parse(entry: EntryJson) -> Result<Self, String> {
+        let dir = match entry.dir.as_deref() {
+            None | Some("both") => None,
+            Some("H->E") => Some(1),
+            Some("E->H") => Some(-1),
+            Some(other) => return Err(format!("dir must be \"H->E\", \"E->H\" or \"both\", not {:?}", other)),
+        };
+        let body = match entry.body.as_deref().map(str::trim) {
+            None => BodySpec::Unchecked,
+            Some("") => BodySpec::Empty,
+            Some(text) => BodySpec::Item(Pattern::parse(text)?),
+        };
+        Ok(MessageSpec { dir, wbit: entry.wbit, body })
+    }
+}
+
+impl Pattern {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = PatternParser { text, pos: 0 };
+        let pattern = parser.pattern()?;
+        parser.skip_ws();
+        if parser.pos < text.len() {
+            return Err(format!("unexpected {:?} at {}", &text[parser.pos..], parser.pos));
+        }
+        Ok(pattern)
+    }
+
+    /// `Err` describes the first mismatch, located by `path` (`$[2][0]`)
+    fn check(&self, item: &Value, path: &str) -> Result<(), String> {
+        let found = item.get("t").and_then(Value::as_str).unwrap_or("?");
+        match self {
+            Pattern::Any => Ok(()),
+            Pattern::List { len, items } => {
+                let Some(children) = list_items(item) else {
+                    return Err(format!("at {}: expected L, found {}", path, found));
+                };
+                if let Some(len) = len {
+                    if children.len() != *len {
+                        return Err(format!("at {}: expected L[{}], found L[{}]", path, len, children.len()));
+                    }
+                }
+                match items {
+                    ListItems::Unchecked => Ok(()),
+                    ListItems::Fixed(patterns) => patterns.iter().zip(children).enumerate()
+                        .try_for_each(|(i, (pattern, child))| pattern.check(child, &format!("{}[{}]", path, i))),
+                    ListItems::Each(pattern) => children.iter().enumerate()
+                        .try_for_each(|(i, child)| pattern.check(child, &format!("{}[{}]", path, i))),
+                }
+            }
+            Pattern::Item { types, len } => {
+                if !types.contains(&found) {
+                    return Err(format!("at {}: expected {}, found {}", path, types.join("|"), found));
+                }
+                match (len, item_len(item)) {
+                    (Some(len), Some(actual)) if actual != *len => {
+                        Err(format!("at {}: expected {}[{}], found {}[{}]", path, types.join("|"), len, found, actual))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Elements in a scalar item: array length, characters or bytes
+fn item_len(item: &Value) -> Option<usize> {
+    match item.get("v")? {
+        Value::Array(values) => Some(values.len()),
+        Value::String(text) if item.get("t")?.as_str()? == "B" => {
+            base64::engine::general_purpose::STANDARD.decode(text).ok().map(|bytes| bytes.len())
+        }
+        Value::String(text) => Some(text.chars().count()),
+        Value::Null => Some(0),
+        _ => Some(1),
+    }
+}
+
+struct PatternParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl PatternParser<'_> {
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.text[self.pos..].chars().next().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_ws();
+        if self.text[self.pos..].starts_with(c) {
+            self.pos += c.len_utf8();
+            return true;
+        }
+        false
+    }
+
+    fn word(&mut self) -> &str {
+        self.skip_ws();
+        let start = self.pos;
+        while self.text[self.pos..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '|') {
+            self.pos += 1;
+        }
+        &self.text[start..self.pos]
+    }
+
+    fn error(&self, expected: &str) -> String {
+        format!("expected {} at {} in {:?}", expected, self.pos, self.text)
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, String> {
+        if self.eat('*') {
+            return Ok(Pattern::Any);
+        }
+        if !self.eat('<') {
+            return Err(self.error("'<' or '*'"));
+        }
+
+        let mut types = Vec::new();
+        for name in self.word().to_ascii_uppercase().split('|') {
+            let expanded: &[&'static str] = match name {
+                "U" => &["U1", "U2", "U4", "U8"],
+                "I" => &["I1", "I2", "I4", "I8"],
+                "F" => &["F4", "F8"],
+                "BOOLEAN" | "BOOL" => &["BOOL"],
+                name => match SCALAR_TYPES.iter().find(|t| **t == name) {
+                    Some(t) => std::slice::from_ref(t),
+                    None => return Err(format!("unknown item format {:?} in {:?}", name, self.text)),
+                },
+            };
+            types.extend_from_slice(expanded);
+        }
+        let is_list = types.contains(&"L");
+        if is_list && types.len() > 1 {
+            return Err(format!("L cannot be combined with other formats in {:?}", self.text));
+        }
+
+        // `[N]` fixes the element count, `[n]` leaves it open
+        let mut len = None;
+        if self.eat('[') {
+            let word = self.word().to_string();
+            if word != "n" {
+                len = Some(word.parse::<usize>().map_err(|_| self.error("a count or 'n'"))?);
+            }
+            if !self.eat(']') {
+                return Err(self.error("']'"));
+            }
+        }
+
+        let mut children = Vec::new();
+        while !self.eat('>') {
+            if !is_list {
+                return Err(self.error("'>'"));
+            }
+            children.push(self.pattern()?);
+        }
+
+        if !is_list {
+            return Ok(Pattern::Item { types, len });
+        }
+        let items = match (len, children.len()) {
+            (_, 0) => ListItems::Unchecked,
+            (Some(n), count) if n == count => ListItems::Fixed(children),
+            (None, 1) => ListItems::Each(Box::new(children.remove(0))),
+            (Some(n), count) => return Err(format!("L[{}] lists {} items in {:?}", n, count, self.text)),
+            (None, count) => return Err(format!("L[n] takes one item pattern, found {} in {:?}", count, self.text)),
+        };
+        Ok(Pattern::List { len, items })
+    }
+}
+
+/// Per-SxFy tally of violations for `conformance.json`
+#[derive(Clone, Default)]
+pub struct ConformanceCollector {
+    rows: u64,
+    by_sxfy: BTreeMap<(u8, u8), (u64, BTreeMap<String, RuleCount>)>,
+}
+
+impl ConformanceCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, msg: &ConvertedMessage, violations: &[Violation]) {
+        if violations.is_empty() {
+            return;
+        }
+        self.rows += 1;
+        let (rows, rules) = self.by_sxfy.entry((msg.s, msg.f)).or_default();
+        *rows += 1;
+        for violation in violations {
+            let count = rules.entry(violation.rule.clone()).or_insert_with(|| RuleCount {
+                rule: violation.rule.clone(),
+                message: violation.message.clone(),
+                count: 0,
+                example_row_ids: Vec::new(),
+            });
+            count.count += 1;
+            if count.example_row_ids.len() < EXAMPLE_ROWS {
+                count.example_row_ids.push(msg.row_id);
+            }
+        }
+    }
+
+    pub fn into_summary(self) -> ConformanceSummary {
+        ConformanceSummary {
+            rows_with_violations: self.rows,
+            by_sxfy: self.by_sxfy.into_iter().map(|((s, f), (rows, rules))| SxFyViolations {
+                s,
+                f,
+                rows,
+                rules: rules.into_values().collect(),
+            }).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg;
+    use serde_json::json;
+
+    fn rules(catalog: &Catalog, msg: &ConvertedMessage) -> Vec<String> {
+        catalog.check(msg).into_iter().map(|v| format!("{}: {}", v.rule, v.message)).collect()
+    }
+
+    #[test]
+    fn test_builtin_catalog_checks() {
+        let catalog = Catalog::builtin();
+        let s1f14 = |items: Value| msg(1, 14).dir(-1).body(json!({"secs_tree": {"t": "L", "items": items}}));
+
+        let ok = s1f14(json!([{"t": "B", "v": "AA=="}, {"t": "L", "items": [{"t": "A", "v": "MDLN"}, {"t": "A", "v": "1.0"}]}]));
+        assert!(catalog.check(&ok).is_empty());
+
+        let wrong = s1f14(json!([{"t": "U1", "v": 0}, {"t": "L", "items": [{"t": "A", "v": "MDLN"}, {"t": "U4", "v": 1}]}]));
+        assert_eq!(rules(&catalog, &wrong), vec!["body: at $[0]: expected B, found U1"]);
+
+        let short = s1f14(json!([{"t": "B", "v": "AA=="}]));
+        assert_eq!(rules(&catalog, &short), vec!["body: at $: expected L[2], found L[1]"]);
+
+        // Header problems: W-bit on a secondary, missing W-bit, direction
+        let mut reply = ok.clone();
+        reply.wbit = 1;
+        assert_eq!(rules(&catalog, &reply), vec!["wbit_on_reply: S1F14 is a secondary but sets the W-bit"]);
+        let s1f3 = msg(1, 3).dir(-1).body(json!({"secs_tree": {"t": "L", "items": [{"t": "U4", "v": 1}]}}));
+        assert_eq!(rules(&catalog, &s1f3), vec![
+            "wbit: S1F3 requires the W-bit",
+            "direction: S1F3 sent E->H, expected H->E",
+        ]);
+
+        // Header-only, bodies without a tree, and unlisted messages
+        assert_eq!(rules(&catalog, &msg(1, 1).dir(1).wbit(1).body(json!({"secs_tree": {"t": "U1", "v": 1}}))),
+            vec!["body: expected a header-only message, found a body"]);
+        assert!(catalog.check(&msg(1, 1).dir(1).wbit(1).body(json!({"secs_tree": null}))).is_empty());
+        assert!(catalog.check(&msg(1, 1).dir(1).wbit(1).body(json!({"secs_tree": {"t": "L", "items": []}}))).is_empty());
+        assert!(catalog.check(&msg(1, 4).dir(-1).body(json!({"semantic": {}}))).is_empty());
+        assert!(catalog.check(&msg(64, 1).dir(1).body(json!({"secs_tree": null}))).is_empty());
+    }
+
+    #[test]
+    fn test_catalog_overrides_and_pattern_errors() {
+        let mut catalog = Catalog::builtin();
+        catalog.extend_json(r#"{"S1F3": null, "S64F1": {"dir": "E->H", "body": "<L[2] <U|A> <F[3]>>"}}"#).unwrap();
+        let s1f3 = msg(1, 3).dir(-1).body(json!({}));
+        assert!(catalog.check(&s1f3).is_empty());
+
+        let custom = |third: Value| msg(64, 1).dir(-1).body(json!({"secs_tree": {"t": "L", "items": [{"t": "A", "v": "x"}, third]}}));
+        assert!(catalog.check(&custom(json!({"t": "F8", "v": [1.0, 2.0, 3.0]}))).is_empty());
+        assert_eq!(rules(&catalog, &custom(json!({"t": "F4", "v": 1.0}))),
+            vec!["body: at $[1]: expected F4|F8[3], found F4[1]"]);
+
+        for bad in [r#"{"S1": {}}"#, r#"{"S1F1": {"dir": "up"}}"#, r#"{"S1F1": {"body": "<L[2] <A>>"}}"#,
+                    r#"{"S1F1": {"body": "<X4>"}}"#, r#"{"S1F1": {"body": "<A <A>>"}}"#, r#"{"S1F1": {"body": "<L"}}"#] {
+            assert!(Catalog::builtin().extend_json(bad).is_err(), "{}", bad);
+        }
+
+        // Non-ASCII spaces are whitespace too
+        catalog.extend_json("{\"S64F3\": {\"body\": \"<L\u{a0}<A>\u{3000}>\"}}").unwrap();
+        let s64f3 = |items: Value| msg(64, 3).dir(1).wbit(1).body(json!({"secs_tree": {"t": "L", "items": items}}));
+        assert!(catalog.check(&s64f3(json!([{"t": "A", "v": "x"}]))).is_empty());
+        assert_eq!(rules(&catalog, &s64f3(json!([{"t": "U4", "v": 1}]))), vec!["body: at $[0]: expected A, found U4"]);
+        assert_eq!(rules(&catalog, &s64f3(json!([{"t": "A", "v": "x"}, {"t": "B", "v": "AA=="}]))), vec!["body: at $[1]: expected A, found B"]);
+    }
+}
@@ -0,0 +1,566 @@
+//! Equipment dictionaries and GEM event report decoding.
+//!
+//! A dictionary names CEIDs and VIDs and lists the VIDs of each RPTID (as
+//! defined by S2F33/S2F35). Dictionaries are stored per equipment profile
+//! (`profiles/<id>.json`) and per session (`<session>/dictionary.json`):
+//!
+//! ```json
+//! {
+//!   "ceids": {"201": "LotStart"},
+//!   "reports": {"10": [501, 502]},
+//!   "vids": {"501": {"name": "CassetteID", "type": "A"}, "502": {"name": "SlotCount"}}
+//! }
+//! ```
+//!
+//! A dictionary may also hold the equipment's HSMS `timers` (see `timers.rs`).
+//!
+//! At ingest, S6F11/S6F13 bodies without a `semantic` are decoded from
+//! `secs_tree` into `{"kind": "EventReport", "ceid", "ceid_name", "dataid",
+//! "reports": [{"rptid", "vids": [{"vid", "name", "value"}]}]}`. S6F13 carries
+//! its VIDs; S6F11 takes them from the report definition by position.
+//!
+//! Hosts usually define their reports at the start of a connection, in the
+//! log itself. `ReportTracker` follows S2F33 (define report), S2F35 (link
+//! event report) and S2F37 (enable event report) in log order, so each event
+//! report is decoded with the definitions in effect when it was sent. A
+//! definition the equipment rejects (non-zero DRACK/LRACK/ERACK) is undone:
+//! the RPTIDs and CEIDs it changed get their previous values back. One left
+//! unanswered past `PAIRING_HORIZON_NS` stands.
+
+use crate::models::{ConvertedMessage, HsmsTimers};
+use crate::pairing::PAIRING_HORIZON_NS;
+use crate::secs_tree::{body_tree, item_bool, item_code, item_id, item_value, list_items, list_of};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EquipmentDictionary {
+    #[serde(default)]
+    pub ceids: BTreeMap<u32, String>,
+    /// RPTID -> VIDs, in report order
+    #[serde(default)]
+    pub reports: BTreeMap<u32, Vec<u32>>,
+    #[serde(default)]
+    pub vids: BTreeMap<u32, VidInfo>,
+    /// CEID -> RPTIDs (as linked by S2F35)
+    #[serde(default)]
+    pub links: BTreeMap<u32, Vec<u32>>,
+    /// HSMS timer settings; E37 defaults when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timers: Option<HsmsTimers>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VidInfo {
+    pub name: String,
+    /// Declared SECS-II format, e.g. `"U4"`
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<String>,
+}
+
+impl EquipmentDictionary {
+    /// Overlay `other`; its entries win
+    pub fn merge(&mut self, other: EquipmentDictionary) {
+        self.ceids.extend(other.ceids);
+        self.reports.extend(other.reports);
+        self.vids.extend(other.vids);
+        self.links.extend(other.links);
+        if other.timers.is_some() {
+            self.timers = other.timers;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ceids.is_empty() && self.reports.is_empty() && self.vids.is_empty() && self.links.is_empty()
+            && self.timers.is_none()
+    }
+
+    fn vid_json(&self, vid: Option<&Value>, value: Value) -> Value {
+        let mut entry = Map::new();
+        if let Some(vid) = vid {
+            entry.insert("vid".into(), vid.clone());
+            if let Some(info) = vid.as_u64().and_then(|v| u32::try_from(v).ok()).and_then(|v| self.vids.get(&v)) {
+                entry.insert("name".into(), Value::String(info.name.clone()));
+                if let Some(data_type) = &info.data_type {
+                    entry.insert("type".into(), Value::String(data_type.clone()));
+                }
+            }
+        }
+        entry.insert("value".into(), value);
+        Value::Object(entry)
+    }
+}
+
+/// Profile ids become file names: letters, digits, `-`, `_` and `.` only
+pub fn is_valid_profile_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && !id.starts_with('.')
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Decode or name the event report of an S6F11/S6F13; other messages are untouched
+pub fn annotate_event_report(msg: &mut ConvertedMessage, dictionary: &EquipmentDictionary) {
+    if msg.s != 6 || !(msg.f == 11 || msg.f == 13) {
+        return;
+    }
+
+    if let Some(semantic) = msg.body_json.get_mut("semantic").and_then(Value::as_object_mut) {
+        name_semantic(semantic, msg.ceid, dictionary);
+        return;
+    }
+
+    let Some(semantic) = body_tree(&msg.body_json).and_then(|tree| decode_event_report(tree, msg.f, dictionary)) else {
+        return;
+    };
+    if msg.ceid == 0 {
+        msg.ceid = semantic["ceid"].as_u64().and_then(|v| u32::try_from(v).ok()).unwrap_or(0);
+    }
+    if let Some(body) = msg.body_json.as_object_mut() {
+        body.insert("semantic".into(), semantic);
+    }
+}
+
+/// Fill in names an upstream exporter left out
+fn name_semantic(semantic: &mut Map<String, Value>, ceid: u32, dictionary: &EquipmentDictionary) {
+    let ceid = semantic.get("ceid").and_then(Value::as_u64).and_then(|v| u32::try_from(v).ok()).unwrap_or(ceid);
+    if !semantic.contains_key("ceid_name") {
+        if let Some(name) = dictionary.ceids.get(&ceid) {
+            semantic.insert("ceid_name".into(), Value::String(name.clone()));
+        }
+    }
+
+    let vids = semantic.get_mut("reports").and_then(Value::as_array_mut).into_iter().flatten()
+        .filter_map(|report| report.get_mut("vids").and_then(Value::as_array_mut))
+        .flatten()
+        .filter_map(Value::as_object_mut);
+    for vid in vids {
+        if vid.contains_key("name") {
+            continue;
+        }
+        let info = vid.get("vid").and_then(Value::as_u64).and_then(|v| u32::try_from(v).ok())
+            .and_then(|v| dictionary.vids.get(&v));
+        if let Some(info) = info {
+            vid.insert("name".into(), Value::String(info.name.clone()));
+        }
+    }
+}
+
+/// `<L[3] DATAID CEID <L[a] <L[2] RPTID <L[b] V | <L[2] VID V>>>>>`
+fn decode_event_report(tree: &Value, f: u8, dictionary: &EquipmentDictionary) -> Option<Value> {
+    let top = list_of(tree, 3)?;
+    let ceid = item_value(&top[1]);
+    let ceid_name = ceid.as_u64().and_then(|v| u32::try_from(v).ok()).and_then(|v| dictionary.ceids.get(&v));
+
+    let reports = list_items(&top[2])?.iter().map(|report| {
+        let report = list_of(report, 2)?;
+        let rptid = item_value(&report[0]);
+        let values = list_items(&report[1])?;
+
+        let vids: Vec<Value> = if f == 13 {
+            values.iter().map(|pair| {
+                let pair = list_of(pair, 2)?;
+                Some(dictionary.vid_json(Some(&item_value(&pair[0])), item_value(&pair[1])))
+            }).collect::<Option<_>>()?
+        } else {
+            let defined = rptid.as_u64().and_then(|v| u32::try_from(v).ok()).and_then(|v| dictionary.reports.get(&v));
+            values.iter().enumerate().map(|(i, value)| {
+                let vid = defined.and_then(|vids| vids.get(i)).map(|&vid| Value::from(vid));
+                dictionary.vid_json(vid.as_ref(), item_value(value))
+            }).collect()
+        };
+        Some(json!({"rptid": rptid, "vids": vids}))
+    }).collect::<Option<Vec<_>>>()?;
+
+    let mut semantic = Map::new();
+    semantic.insert("kind".into(), Value::String("EventReport".into()));
+    semantic.insert("ceid".into(), ceid.clone());
+    if let Some(name) = ceid_name {
+        semantic.insert("ceid_name".into(), Value::String(name.clone()));
+    }
+    semantic.insert("dataid".into(), item_value(&top[0]));
+    semantic.insert("reports".into(), Value::Array(reports));
+    Some(Value::Object(semantic))
+}
+
+/// Report state a definition replaces, restored if the equipment rejects it
+#[derive(Clone)]
+struct Definitions {
+    reports: BTreeMap<u32, Vec<u32>>,
+    links: BTreeMap<u32, Vec<u32>>,
+    events_enabled: bool,
+    event_overrides: BTreeMap<u32, bool>,
+}
+
+/// The entries a definition changed, with their values before it (`None`: absent)
+#[derive(Clone)]
+struct Undo {
+    sent_ns: i64,
+    reports: Vec<(u32, Option<Vec<u32>>)>,
+    links: Vec<(u32, Option<Vec<u32>>)>,
+    events_enabled: Option<bool>,
+    event_overrides: Vec<(u32, Option<bool>)>,
+}
+
+fn changed<V: Clone + PartialEq>(before: &BTreeMap<u32, V>, after: &BTreeMap<u32, V>) -> Vec<(u32, Option<V>)> {
+    let keys: BTreeSet<&u32> = before.keys().chain(after.keys()).collect();
+    keys.into_iter()
+        .filter(|k| before.get(k) != after.get(k))
+        .map(|k| (*k, before.get(k).cloned()))
+        .collect()
+}
+
+fn restore<V>(map: &mut BTreeMap<u32, V>, entries: Vec<(u32, Option<V>)>) {
+    for (key, value) in entries {
+        match value {
+            Some(value) => map.insert(key, value),
+            None => map.remove(&key),
+        };
+    }
+}
+
+/// Follows report definitions through a log and decodes event reports with them
+#[derive(Clone)]
+pub struct ReportTracker {
+    dictionary: EquipmentDictionary,
+    /// S2F37 with no CEIDs switches every event; listed CEIDs override it
+    events_enabled: bool,
+    event_overrides: BTreeMap<u32, bool>,
+    /// Definitions awaiting their acknowledge, keyed by the reply we expect:
+    /// (function, sysbytes, reply dir) -> what to put back
+    pending: HashMap<(u8, u32, i8), Undo>,
+}
+
+impl ReportTracker {
+    /// Start from the uploaded dictionary; the log's own definitions win
+    pub fn new(dictionary: EquipmentDictionary) -> Self {
+        Self {
+            dictionary,
+            events_enabled: true,
+            event_overrides: BTreeMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Feed the next message in log order, decoding it if it is an event
+    /// report or a report definition
+    pub fn observe(&mut self, msg: &mut ConvertedMessage) {
+        if !self.pending.is_empty() {
+            let cutoff = msg.ts_ns.saturating_sub(PAIRING_HORIZON_NS);
+            self.pending.retain(|_, undo| undo.sent_ns >= cutoff);
+        }
+        match (msg.s, msg.f) {
+            (2, 33 | 35 | 37) => self.define(msg),
+            (2, 0 | 34 | 36 | 38) => self.acknowledge(msg),
+            (6, 11 | 13) => {
+                annotate_event_report(msg, &self.dictionary);
+                let ceid = msg.ceid;
+                if !self.is_enabled(ceid) {
+                    if let Some(semantic) = msg.body_json.get_mut("semantic").and_then(Value::as_object_mut) {
+                        semantic.insert("enabled".into(), Value::Bool(false));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The dictionary as of the end of the log
+    pub fn into_dictionary(self) -> EquipmentDictionary {
+        self.dictionary
+    }
+
+    fn is_enabled(&self, ceid: u32) -> bool {
+        self.event_overrides.get(&ceid).copied().unwrap_or(self.events_enabled)
+    }
+
+    fn snapshot(&self) -> Definitions {
+        Definitions {
+            reports: self.dictionary.reports.clone(),
+            links: self.dictionary.links.clone(),
+            events_enabled: self.events_enabled,
+            event_overrides: self.event_overrides.clone(),
+        }
+    }
+
+    fn define(&mut self, msg: &mut ConvertedMessage) {
+        let Some(top) = body_tree(&msg.body_json).and_then(|tree| list_of(tree, 2)) else {
+            return;
+        };
+        let before = self.snapshot();
+        let semantic = match msg.f {
+            33 => self.define_reports(&top[0], &top[1]),
+            35 => self.link_events(&top[0], &top[1]),
+            _ => self.enable_events(&top[0], &top[1]),
+        };
+        let Some(semantic) = semantic else {
+            return;
+        };
+
+        if msg.wbit == 1 {
+            let after = self.snapshot();
+            let undo = Undo {
+                sent_ns: msg.ts_ns,
+                reports: changed(&before.reports, &after.reports),
+                links: changed(&before.links, &after.links),
+                events_enabled: (before.events_enabled != after.events_enabled).then_some(before.events_enabled),
+                event_overrides: changed(&before.event_overrides, &after.event_overrides),
+            };
+            self.pending.insert((msg.f + 1, msg.sysbytes, -msg.dir), undo);
+        }
+        if let Some(body) = msg.body_json.as_object_mut() {
+            body.entry("semantic").or_insert(semantic);
+        }
+    }
+
+    /// S2F33 `<L[2] DATAID <L[a] <L[2] RPTID <L[b] VID>>>>`: no reports deletes
+    /// them all, a report without VIDs deletes that report
+    fn define_reports(&mut self, dataid: &Value, reports: &Value) -> Option<Value> {
+        let reports = list_items(reports)?.iter().map(|report| {
+            let report = list_of(report, 2)?;
+            let vids = list_items(&report[1])?.iter().map(item_id).collect::<Option<Vec<u32>>>()?;
+            Some((item_id(&report[0])?, vids))
+        }).collect::<Option<Vec<_>>>()?;
+
+        let dictionary = &mut self.dictionary;
+        if reports.is_empty() {
+            dictionary.reports.clear();
+            dictionary.links.clear();
+        }
+        for (rptid, vids) in &reports {
+            if vids.is_empty() {
+                dictionary.reports.remove(rptid);
+                for linked in dictionary.links.values_mut() {
+                    linked.retain(|r| r != rptid);
+                }
+            } else {
+                dictionary.reports.insert(*rptid, vids.clone());
+            }
+        }
+
+        let reports: Vec<Value> = reports.into_iter().map(|(rptid, vids)| {
+            let vids: Vec<Value> = vids.into_iter().map(|vid| {
+                let mut entry = Map::new();
+                entry.insert("vid".into(), Value::from(vid));
+                if let Some(info) = dictionary.vids.get(&vid) {
+                    entry.insert("name".into(), Value::String(info.name.clone()));
+                }
+                Value::Object(entry)
+            }).collect();
+            json!({"rptid": rptid, "vids": vids})
+        }).collect();
+        Some(json!({"kind": "DefineReport", "dataid": item_value(dataid), "reports": reports}))
+    }
+
+    /// S2F35 `<L[2] DATAID <L[a] <L[2] CEID <L[b] RPTID>>>>`: no RPTIDs unlinks the event
+    fn link_events(&mut self, dataid: &Value, links: &Value) -> Option<Value> {
+        let links = list_items(links)?.iter().map(|link| {
+            let link = list_of(link, 2)?;
+            let rptids = list_items(&link[1])?.iter().map(item_id).collect::<Option<Vec<u32>>>()?;
+            Some((item_id(&link[0])?, rptids))
+        }).collect::<Option<Vec<_>>>()?;
+
+        let dictionary = &mut self.dictionary;
+        let links: Vec<Value> = links.into_iter().map(|(ceid, rptids)| {
+            let mut entry = Map::new();
+            entry.insert("ceid".into(), Value::from(ceid));
+            if let Some(name) = dictionary.ceids.get(&ceid) {
+                entry.insert("ceid_name".into(), Value::String(name.clone()));
+            }
+            entry.insert("rptids".into(), json!(rptids));
+            if rptids.is_empty() {
+                dictionary.links.remove(&ceid);
+            } else {
+                dictionary.links.insert(ceid, rptids);
+            }
+            Value::Object(entry)
+        }).collect();
+        Some(json!({"kind": "LinkEventReport", "dataid": item_value(dataid), "links": links}))
+    }
+
+    /// S2F37 `<L[2] CEED <L[n] CEID>>`: no CEIDs means every event
+    fn enable_events(&mut self, ceed: &Value, ceids: &Value) -> Option<Value> {
+        let enable = item_bool(ceed)?;
+        let ceids = list_items(ceids)?.iter().map(item_id).collect::<Option<Vec<u32>>>()?;
+
+        if ceids.is_empty() {
+            self.events_enabled = enable;
+            self.event_overrides.clear();
+        }
+        for &ceid in &ceids {
+            self.event_overrides.insert(ceid, enable);
+        }
+        Some(json!({"kind": "EnableEventReport", "enable": enable, "ceids": ceids}))
+    }
+
+    /// Undo a definition the equipment rejected or aborted, leaving the
+    /// entries other definitions changed meanwhile
+    fn acknowledge(&mut self, msg: &ConvertedMessage) {
+        let key = |f: u8| (f, msg.sysbytes, msg.dir);
+        let undo = match msg.f {
+            0 => [34, 36, 38].into_iter().find_map(|f| self.pending.remove(&key(f))),
+            f => self.pending.remove(&key(f)),
+        };
+        let Some(undo) = undo else {
+            return;
+        };
+        let accepted = msg.f != 0 && body_tree(&msg.body_json).and_then(item_code) == Some(0);
+        if !accepted {
+            restore(&mut self.dictionary.reports, undo.reports);
+            restore(&mut self.dictionary.links, undo.links);
+            if let Some(enabled) = undo.events_enabled {
+                self.events_enabled = enabled;
+            }
+            restore(&mut self.event_overrides, undo.event_overrides);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg;
+    use base64::Engine;
+
+    fn u4(v: u32) -> Value {
+        json!({"t": "U4", "v": v})
+    }
+
+    fn a(v: &str) -> Value {
+        json!({"t": "A", "v": v})
+    }
+
+    fn list(items: Vec<Value>) -> Value {
+        json!({"t": "L", "items": items})
+    }
+
+    fn dictionary() -> EquipmentDictionary {
+        serde_json::from_value(json!({
+            "ceids": {"201": "LotStart"},
+            "reports": {"10": [501, 502]},
+            "vids": {"501": {"name": "CassetteID", "type": "A"}, "502": {"name": "SlotCount"}}
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_decode_s6f11_with_report_definitions() {
+        let tree = list(vec![u4(1), u4(201), list(vec![
+            list(vec![u4(10), list(vec![a("A001"), u4(25)])]),
+            list(vec![u4(99), list(vec![a("X")])]),
+        ])]);
+        let mut m = msg(6, 11).body(json!({"secs_tree": tree}));
+        annotate_event_report(&mut m, &dictionary());
+
+        assert_eq!(m.ceid, 201);
+        let semantic = &m.body_json["semantic"];
+        assert_eq!(semantic["ceid_name"], "LotStart");
+        assert_eq!(semantic["reports"][0]["vids"][0], json!({"vid": 501, "name": "CassetteID", "type": "A", "value": "A001"}));
+        assert_eq!(semantic["reports"][0]["vids"][1], json!({"vid": 502, "name": "SlotCount", "value": 25}));
+        // Undefined report: values by position only
+        assert_eq!(semantic["reports"][1], json!({"rptid": 99, "vids": [{"value": "X"}]}));
+    }
+
+    #[test]
+    fn test_decode_s6f13_and_name_upstream_semantic() {
+        let tree = list(vec![u4(1), u4(202), list(vec![
+            list(vec![u4(11), list(vec![list(vec![u4(502), u4(3)]), list(vec![u4(777), a("?")])])]),
+        ])]);
+        let mut m = msg(6, 13).body(json!({"secs_tree": tree}));
+        annotate_event_report(&mut m, &dictionary());
+        let vids = &m.body_json["semantic"]["reports"][0]["vids"];
+        assert_eq!(vids[0], json!({"vid": 502, "name": "SlotCount", "value": 3}));
+        assert_eq!(vids[1], json!({"vid": 777, "value": "?"}));
+        assert!(m.body_json["semantic"].get("ceid_name").is_none());
+
+        let mut m = msg(6, 11).body(json!({"semantic": {"kind": "EventReport", "ceid": 201,
+            "reports": [{"rptid": 10, "vids": [{"vid": 501, "value": "A001"}, {"vid": 502, "name": "Slots", "value": 1}]}]}}));
+        annotate_event_report(&mut m, &dictionary());
+        assert_eq!(m.body_json["semantic"]["ceid_name"], "LotStart");
+        assert_eq!(m.body_json["semantic"]["reports"][0]["vids"][0]["name"], "CassetteID");
+        assert_eq!(m.body_json["semantic"]["reports"][0]["vids"][1]["name"], "Slots");
+
+        // Not an event report shape: left alone
+        let mut m = msg(6, 11).body(json!({"secs_tree": list(vec![u4(1)])}));
+        annotate_event_report(&mut m, &dictionary());
+        assert!(m.body_json.get("semantic").is_none());
+    }
+
+    fn b(v: u8) -> Value {
+        json!({"t": "B", "v": base64::engine::general_purpose::STANDARD.encode([v])})
+    }
+
+    /// A host primary (odd `f`) or the equipment's reply
+    fn sml(s: u8, f: u8, sysbytes: u32, tree: Value) -> ConvertedMessage {
+        msg(s, f).dir(if f % 2 == 1 { 1 } else { -1 }).wbit(f % 2).sysbytes(sysbytes).body(json!({"secs_tree": tree}))
+    }
+
+    fn define(sysbytes: u32, rptid: u32, vids: &[u32]) -> ConvertedMessage {
+        let vids = vids.iter().map(|&v| u4(v)).collect();
+        sml(2, 33, sysbytes, list(vec![u4(1), list(vec![list(vec![u4(rptid), list(vids)])])]))
+    }
+
+    fn event(tree_values: Vec<Value>) -> ConvertedMessage {
+        sml(6, 11, 100, list(vec![u4(1), u4(201), list(vec![list(vec![u4(10), list(tree_values)])])]))
+    }
+
+    #[test]
+    fn test_tracker_learns_definitions_in_log_order() {
+        let mut tracker = ReportTracker::new(dictionary());
+        let mut observe = |mut m: ConvertedMessage| {
+            tracker.observe(&mut m);
+            m.body_json
+        };
+
+        // Uploaded definition until the log redefines RPTID 10
+        let before = observe(event(vec![a("A001"), u4(25)]));
+        assert_eq!(before["semantic"]["reports"][0]["vids"][0]["name"], "CassetteID");
+
+        let defined = observe(define(7, 10, &[502, 600]));
+        assert_eq!(defined["semantic"]["kind"], "DefineReport");
+        assert_eq!(defined["semantic"]["reports"][0]["vids"][0], json!({"vid": 502, "name": "SlotCount"}));
+        observe(sml(2, 34, 7, b(0)));
+        let after = observe(event(vec![u4(25), a("X")]));
+        assert_eq!(after["semantic"]["reports"][0]["vids"][0], json!({"vid": 502, "name": "SlotCount", "value": 25}));
+        assert_eq!(after["semantic"]["reports"][0]["vids"][1], json!({"vid": 600, "value": "X"}));
+
+        // Rejected redefinition (DRACK 3) is undone
+        observe(define(8, 10, &[501]));
+        observe(sml(2, 34, 8, b(3)));
+        let rejected = observe(event(vec![u4(25), a("X")]));
+        assert_eq!(rejected["semantic"]["reports"][0]["vids"][1]["vid"], 600);
+
+        // A rejection only undoes its own RPTIDs, not one accepted in between
+        observe(define(20, 10, &[501]));
+        observe(define(21, 11, &[700]));
+        observe(sml(2, 34, 21, b(0)));
+        observe(sml(2, 34, 20, b(3)));
+
+        // Link, then disable every event
+        let linked = observe(sml(2, 35, 9, list(vec![u4(1), list(vec![list(vec![u4(201), list(vec![u4(10)])])])])));
+        assert_eq!(linked["semantic"], json!({"kind": "LinkEventReport", "dataid": 1,
+            "links": [{"ceid": 201, "ceid_name": "LotStart", "rptids": [10]}]}));
+        observe(sml(2, 37, 10, list(vec![json!({"t": "BOOL", "v": false}), list(vec![])])));
+        let disabled = observe(event(vec![u4(25), a("X")]));
+        assert_eq!(disabled["semantic"]["enabled"], false);
+
+        // Unanswered definitions stand and are dropped after the horizon
+        observe(define(30, 12, &[800]));
+        observe(ConvertedMessage { ts_ns: PAIRING_HORIZON_NS + 1, ..event(vec![u4(25), a("X")]) });
+        assert!(tracker.pending.is_empty());
+
+        let learned = tracker.into_dictionary();
+        assert_eq!(learned.reports[&10], vec![502, 600]);
+        assert_eq!(learned.reports[&11], vec![700]);
+        assert_eq!(learned.reports[&12], vec![800]);
+        assert_eq!(learned.links[&201], vec![10]);
+    }
+
+    #[test]
+    fn test_profile_ids() {
+        assert!(is_valid_profile_id("EQP-ABC-1"));
+        assert!(!is_valid_profile_id("../etc"));
+        assert!(!is_valid_profile_id(".hidden"));
+        assert!(!is_valid_profile_id(""));
+    }
+}
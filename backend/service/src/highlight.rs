@@ -0,0 +1,176 @@
+use crate::arrow_io::read_arrow_chunk;
+use crate::models::{ConvertedMessage, HighlightExpr};
+use crate::storage::SessionStorage;
+use arrow::array::{Int64Array, Int8Array, UInt8Array, UInt32Array};
+use std::collections::{HashMap, HashSet};
+
+/// Reply lookup key: (s, f, sysbytes, dir)
+type ReplyKey = (u8, u8, u32, i8);
+
+/// Timestamps of every candidate reply (wbit=0) in a session, built across all
+/// chunks so a primary in one chunk can be answered by a row in the next.
+pub struct ReplyIndex {
+    replies: HashMap<ReplyKey, Vec<i64>>,
+}
+
+impl ReplyIndex {
+    pub fn build(storage: &SessionStorage, session_id: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut replies: HashMap<ReplyKey, Vec<i64>> = HashMap::new();
+
+        for chunk_path in storage.list_chunks(session_id)? {
+            for batch in read_arrow_chunk(&chunk_path)? {
+                let ts_ns_arr = batch.column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+                let dir_arr = batch.column(1).as_any().downcast_ref::<Int8Array>().unwrap();
+                let s_arr = batch.column(2).as_any().downcast_ref::<UInt8Array>().unwrap();
+                let f_arr = batch.column(3).as_any().downcast_ref::<UInt8Array>().unwrap();
+                let wbit_arr = batch.column(4).as_any().downcast_ref::<UInt8Array>().unwrap();
+                let sysbytes_arr = batch.column(5).as_any().downcast_ref::<UInt32Array>().unwrap();
+
+                for i in 0..batch.num_rows() {
+                    if wbit_arr.value(i) != 0 {
+                        continue;
+                    }
+                    let key = (s_arr.value(i), f_arr.value(i), sysbytes_arr.value(i), dir_arr.value(i));
+                    replies.entry(key).or_default().push(ts_ns_arr.value(i));
+                }
+            }
+        }
+
+        for timestamps in replies.values_mut() {
+            timestamps.sort_unstable();
+        }
+
+        Ok(Self { replies })
+    }
+
+    /// True if `msg` is a W-bit primary with no reply `(s, f+1, sysbytes, -dir)`
+    /// in the `window_ns` after it.
+    pub fn is_unanswered(&self, msg: &ConvertedMessage, window_ns: i64) -> bool {
+        if msg.wbit == 0 {
+            return false;
+        }
+
+        let key = (msg.s, msg.f.wrapping_add(1), msg.sysbytes, -msg.dir);
+        let Some(timestamps) = self.replies.get(&key) else {
+            return true;
+        };
+
+        let upper = msg.ts_ns.saturating_add(window_ns);
+        let idx = timestamps.partition_point(|&ts| ts < msg.ts_ns);
+        !matches!(timestamps.get(idx), Some(&ts) if ts <= upper)
+    }
+}
+
+/// Per-row highlight flags for a search result, in result order
+pub struct HighlightMasks<'a> {
+    expr: &'a HighlightExpr,
+    replies: Option<ReplyIndex>,
+    /// Rows of the session's timer violations
+    timer_rows: Option<HashSet<u32>>,
+    ceid: Vec<bool>,
+    sxfy: Vec<bool>,
+    unanswered: Vec<bool>,
+    timer: Vec<bool>,
+}
+
+impl<'a> HighlightMasks<'a> {
+    pub fn new(
+        expr: &'a HighlightExpr,
+        storage: &SessionStorage,
+        session_id: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let replies = if expr.unanswered {
+            Some(ReplyIndex::build(storage, session_id)?)
+        } else {
+            None
+        };
+
+        // Sessions ingested before timer checks have no violations to show
+        let timer_rows = expr.timers.then(|| {
+            storage.read_timers(session_id)
+                .map(|timers| timers.violations.into_iter().flat_map(|v| v.row_ids).collect())
+                .unwrap_or_default()
+        });
+
+        Ok(Self {
+            expr,
+            replies,
+            timer_rows,
+            ceid: Vec::new(),
+            sxfy: Vec::new(),
+            unanswered: Vec::new(),
+            timer: Vec::new(),
+        })
+    }
+
+    pub fn push(&mut self, msg: &ConvertedMessage) {
+        if !self.expr.ceid.is_empty() {
+            self.ceid.push(msg.ceid > 0 && self.expr.ceid.contains(&msg.ceid));
+        }
+
+        if !self.expr.sxfy.is_empty() {
+            self.sxfy.push(self.expr.sxfy.iter().any(|p| p.s == msg.s && p.f == msg.f));
+        }
+
+        if let Some(replies) = &self.replies {
+            let window_ns = (self.expr.reply_window_ms as i64).saturating_mul(1_000_000);
+            self.unanswered.push(replies.is_unanswered(msg, window_ns));
+        }
+
+        if let Some(timer_rows) = &self.timer_rows {
+            self.timer.push(timer_rows.contains(&msg.row_id));
+        }
+    }
+
+    /// Columns to append to the result batch; only requested highlights are emitted
+    pub fn into_columns(self) -> Vec<(&'static str, Vec<bool>)> {
+        let mut columns = Vec::new();
+        if !self.expr.ceid.is_empty() {
+            columns.push(("hl_ceid", self.ceid));
+        }
+        if !self.expr.sxfy.is_empty() {
+            columns.push(("hl_sxfy", self.sxfy));
+        }
+        if self.replies.is_some() {
+            columns.push(("unanswered", self.unanswered));
+        }
+        if self.timer_rows.is_some() {
+            columns.push(("hl_timer", self.timer));
+        }
+        columns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrow_io::{write_arrow_chunk, ArrowBuilder};
+    use crate::test_support::{msg, TempDir};
+
+    #[test]
+    fn test_unanswered_across_chunks() {
+        let dir = TempDir::new("hsms-hl");
+        let storage = SessionStorage::new(&dir).unwrap();
+        let session_id = storage.create_session().unwrap();
+
+        let messages = [
+            msg(1, 3).row(0).at_ms(0).dir(1).wbit(1).sysbytes(100),      // answered in the next chunk
+            msg(1, 3).row(1).at_ms(1_000).dir(1).wbit(1).sysbytes(101),  // never answered
+            msg(1, 3).row(2).at_ms(2_000).dir(1).wbit(1).sysbytes(102),  // answered too late
+            msg(1, 4).row(3).at_ms(50).dir(-1).sysbytes(100),
+            msg(1, 4).row(4).at_ms(9_000).dir(-1).sysbytes(102),
+            msg(1, 3).row(5).at_ms(9_500).dir(1).wbit(1).sysbytes(102),  // the S1F4 before it is not its reply
+        ];
+        for (chunk_idx, rows) in messages.chunks(3).enumerate() {
+            let mut builder = ArrowBuilder::new();
+            rows.iter().for_each(|m| builder.push(m));
+            let batch = builder.build_batch().unwrap();
+            write_arrow_chunk(&batch, &storage.chunk_path(&session_id, chunk_idx)).unwrap();
+        }
+
+        let index = ReplyIndex::build(&storage, &session_id).unwrap();
+        let window_ns = 5_000_000_000;
+        let flags: Vec<bool> = messages.iter().map(|m| index.is_unanswered(m, window_ns)).collect();
+        assert_eq!(flags, vec![false, true, true, false, false, true]);
+    }
+}
@@ -0,0 +1,136 @@
+//! Background ingest of uploads: `POST /sessions` returns once the upload is
+//! spooled, and the session reads `ingesting` until its job is done.
+//!
+//! A job keeps `IngestCounters` that `GET /sessions/:id/events` polls. It
+//! ends by writing the final meta, `ready` on success or `failed` with the
+//! error (and no data) otherwise, before it leaves the registry, so that a
+//! session without a job always has its final status on disk.
+
+use crate::models::IngestProgress;
+use crate::storage::{ingest_file, IngestCounters, IngestOptions, InputError, SessionStorage};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// How often `/events` reports on a running ingest
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Running ingest jobs by session id
+#[derive(Default)]
+pub struct IngestJobs {
+    jobs: Mutex<HashMap<String, Arc<IngestCounters>>>,
+}
+
+impl IngestJobs {
+    /// Ingest the spooled upload at `spool` in the background; the spool is
+    /// removed when done
+    pub fn start(
+        self: &Arc<Self>,
+        storage: Arc<SessionStorage>,
+        session_id: String,
+        spool: PathBuf,
+        filename: String,
+        options: IngestOptions,
+    ) -> tokio::task::JoinHandle<()> {
+        let counters = Arc::new(IngestCounters::default());
+        self.jobs.lock().unwrap().insert(session_id.clone(), counters.clone());
+
+        let jobs = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = ingest_file(&storage, &session_id, &spool, &filename, &options, &counters);
+            let _ = std::fs::remove_file(&spool);
+
+            let settled = if counters.cancelled.load(Ordering::Relaxed) {
+                info!("Ingest of session {} cancelled", session_id);
+                storage.delete_session(&session_id).map_err(|e| e.into())
+            } else {
+                match result {
+                    Ok(meta) => {
+                        info!("Successfully ingested {} messages for session: {}", meta.row_count, session_id);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        let message = match e.downcast_ref::<InputError>() {
+                            Some(input) => input.to_string(),
+                            None => format!("Ingest failed: {}", e),
+                        };
+                        error!("Ingest of '{}' failed for session {}: {}", filename, session_id, message);
+                        storage.fail_session(&session_id, &message)
+                    }
+                }
+            };
+            if let Err(e) = settled {
+                error!("Failed to settle session {}: {}", session_id, e);
+            }
+            jobs.jobs.lock().unwrap().remove(&session_id);
+        })
+    }
+
+    /// Progress of the session's ingest, `None` once it is over
+    pub fn progress(&self, session_id: &str) -> Option<IngestProgress> {
+        self.jobs.lock().unwrap().get(session_id).map(|counters| counters.progress())
+    }
+
+    /// Abandon the session's ingest; the job then deletes the session.
+    /// `false` if it had no ingest running.
+    pub fn cancel(&self, session_id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(session_id) {
+            Some(counters) => {
+                counters.cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use crate::models::SessionStatus;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_jobs_settle_ready_or_failed() {
+        let dir = TempDir::new("hsms-jobs");
+        let storage = Arc::new(SessionStorage::new(&dir).unwrap());
+        let jobs = Arc::new(IngestJobs::default());
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/pairs.ndjson");
+
+        let run = |content: Option<String>| {
+            let session_id = storage.create_session().unwrap();
+            let spool = storage.spool_path(&session_id);
+            match content {
+                Some(content) => std::fs::write(&spool, content).unwrap(),
+                None => { std::fs::copy(&fixture, &spool).unwrap(); }
+            }
+            storage.write_pending_meta(&session_id, SessionStatus::Ingesting, None).unwrap();
+            let job = jobs.start(storage.clone(), session_id.clone(), spool, "upload.ndjson".into(), Default::default());
+            (session_id, job)
+        };
+
+        let (good, job) = run(None);
+        job.await.unwrap();
+        let meta = storage.read_meta(&good).unwrap();
+        assert_eq!(meta.status, SessionStatus::Ready);
+        assert!(meta.row_count > 0);
+        assert!(jobs.progress(&good).is_none());
+        assert!(!storage.spool_path(&good).exists());
+
+        // A good first line followed by garbage
+        let first_line = std::fs::read_to_string(&fixture).unwrap().lines().next().unwrap().to_string();
+        let (bad, job) = run(Some(format!("{}\n{{not json\n", first_line)));
+        job.await.unwrap();
+        let meta = storage.read_meta(&bad).unwrap();
+        assert_eq!(meta.status, SessionStatus::Failed);
+        assert!(meta.error.unwrap().starts_with("Parse error"));
+        assert_eq!(meta.row_count, 0);
+        assert!(storage.list_chunks(&bad).unwrap().is_empty());
+
+        assert!(!jobs.cancel(&good));
+    }
+}
@@ -0,0 +1,185 @@
+//! Reply-latency distributions for `/latency`: percentiles per primary SxFy
+//! and direction, overall and per time bucket, plus T3 reply timeouts.
+//!
+//! Latencies come from the transactions paired at ingest (`latency_ns` on the
+//! primary row). A W-bit primary whose reply came after T3, or never came, is
+//! a timeout; an unanswered primary sent less than T3 before the end of the
+//! log may still have been answered and is left out.
+
+use crate::models::{ConvertedMessage, LatencyBucket, LatencyGroup, LatencyResponse, LatencySummary, T3Timeout};
+use crate::stats::bucket_layout;
+use std::collections::BTreeMap;
+
+/// SEMI E37 default reply timeout
+pub const DEFAULT_T3_MS: u64 = 45_000;
+/// Timeouts listed per response; the rest are only counted
+pub const MAX_TIMEOUTS: usize = 10_000;
+
+#[derive(Default)]
+struct Group {
+    latencies: Vec<i64>,
+    by_bucket: BTreeMap<usize, Vec<i64>>,
+    timeouts: u64,
+}
+
+pub struct LatencyCollector {
+    from_ns: i64,
+    bucket_ns: i64,
+    buckets: usize,
+    t3_ns: i64,
+    /// Last timestamp of the session: unanswered primaries after `end_ns - t3_ns` are pending
+    end_ns: i64,
+    groups: BTreeMap<(u8, u8, i8), Group>,
+    timeouts: Vec<T3Timeout>,
+    dropped_timeouts: u64,
+}
+
+impl LatencyCollector {
+    /// Buckets over `[from_ns, to_ns]` as for `/stats`
+    pub fn new(
+        from_ns: i64,
+        to_ns: i64,
+        bucket_ns: i64,
+        buckets: usize,
+        t3_ms: u64,
+        end_ns: i64,
+    ) -> Result<Self, String> {
+        let (bucket_ns, buckets) = bucket_layout(from_ns, to_ns, bucket_ns, buckets)?;
+        let t3_ns = i64::try_from(t3_ms).ok()
+            .and_then(|ms| ms.checked_mul(1_000_000))
+            .filter(|&ns| ns > 0)
+            .ok_or("t3_ms must be positive")?;
+        Ok(Self {
+            from_ns,
+            bucket_ns,
+            buckets,
+            t3_ns,
+            end_ns,
+            groups: BTreeMap::new(),
+            timeouts: Vec::new(),
+            dropped_timeouts: 0,
+        })
+    }
+
+    /// Feed a matching row; only W-bit primaries count
+    pub fn push(&mut self, msg: &ConvertedMessage) {
+        if msg.wbit == 0 || msg.f.is_multiple_of(2) {
+            return;
+        }
+        let group = self.groups.entry((msg.s, msg.f, msg.dir)).or_default();
+
+        let timed_out = match msg.latency_ns {
+            Some(latency) => {
+                group.latencies.push(latency);
+                if msg.ts_ns >= self.from_ns {
+                    let idx = ((msg.ts_ns as i128 - self.from_ns as i128) / self.bucket_ns as i128) as usize;
+                    if idx < self.buckets {
+                        group.by_bucket.entry(idx).or_default().push(latency);
+                    }
+                }
+                latency > self.t3_ns
+            }
+            None => msg.ts_ns.saturating_add(self.t3_ns) <= self.end_ns,
+        };
+        if !timed_out {
+            return;
+        }
+        group.timeouts += 1;
+        if self.timeouts.len() >= MAX_TIMEOUTS {
+            self.dropped_timeouts += 1;
+            return;
+        }
+        self.timeouts.push(T3Timeout {
+            row_id: msg.row_id,
+            ts_ns: msg.ts_ns,
+            s: msg.s,
+            f: msg.f,
+            dir: msg.dir,
+            latency_ns: msg.latency_ns,
+        });
+    }
+
+    pub fn into_response(self) -> LatencyResponse {
+        let (from_ns, bucket_ns) = (self.from_ns, self.bucket_ns);
+        let groups = self.groups.into_iter().map(|((s, f, dir), mut group)| LatencyGroup {
+            s,
+            f,
+            dir,
+            summary: summarize(&mut group.latencies),
+            timeouts: group.timeouts,
+            buckets: group.by_bucket.into_iter().map(|(idx, mut latencies)| LatencyBucket {
+                from_ns: from_ns + idx as i64 * bucket_ns,
+                summary: summarize(&mut latencies),
+            }).collect(),
+        }).collect();
+
+        LatencyResponse {
+            t3_ns: self.t3_ns,
+            from_ns,
+            bucket_ns,
+            groups,
+            timeouts: self.timeouts,
+            dropped_timeouts: self.dropped_timeouts,
+        }
+    }
+}
+
+/// Nearest-rank percentiles; sorts `latencies`
+fn summarize(latencies: &mut [i64]) -> LatencySummary {
+    latencies.sort_unstable();
+    let n = latencies.len();
+    let rank = |p: usize| latencies.get((p * n).div_ceil(100).saturating_sub(1)).copied();
+    LatencySummary {
+        count: n as u64,
+        p50_ns: rank(50),
+        p90_ns: rank(90),
+        p99_ns: rank(99),
+        max_ns: latencies.last().copied(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg;
+
+    /// An S1F3 answered `latency_ns` later, or never
+    fn primary(row_id: u32, ts_ns: i64, latency_ns: Option<i64>) -> ConvertedMessage {
+        let primary = msg(1, 3).row(row_id).at_ns(ts_ns).dir(1).wbit(1).sysbytes(row_id);
+        match latency_ns {
+            Some(latency_ns) => primary.replied(row_id + 1000, latency_ns),
+            None => primary,
+        }
+    }
+
+    #[test]
+    fn test_percentiles_and_timeouts() {
+        // 1 ms T3 over a 0..=99 ms log in 50 ms buckets
+        let mut collector = LatencyCollector::new(0, 99_000_000, 50_000_000, 0, 1, 99_000_000).unwrap();
+        for i in 0..10u32 {
+            collector.push(&primary(i, i as i64 * 1_000_000, Some((i as i64 + 1) * 100_000)));
+        }
+        collector.push(&primary(10, 60_000_000, Some(2_000_000)));  // late reply
+        collector.push(&primary(11, 70_000_000, None));             // never answered
+        collector.push(&primary(12, 98_500_000, None));             // log ends before T3
+        collector.push(&msg(1, 4).row(13).dir(1).sysbytes(13));
+
+        let response = collector.into_response();
+        assert_eq!(response.groups.len(), 1);
+        let group = &response.groups[0];
+        assert_eq!(group.summary, LatencySummary {
+            count: 11,
+            p50_ns: Some(600_000),
+            p90_ns: Some(1_000_000),
+            p99_ns: Some(2_000_000),
+            max_ns: Some(2_000_000),
+        });
+        assert_eq!(group.buckets.iter().map(|b| (b.from_ns, b.summary.count)).collect::<Vec<_>>(),
+            vec![(0, 10), (50_000_000, 1)]);
+        assert_eq!(group.timeouts, 2);
+        assert_eq!(response.timeouts.iter().map(|t| (t.row_id, t.latency_ns)).collect::<Vec<_>>(),
+            vec![(10, Some(2_000_000)), (11, None)]);
+
+        assert!(LatencyCollector::new(0, 10, 0, 10, 0, 10).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+//! Live sessions (`POST /sessions/live`): a running HSMS link tailed into a
+//! session that grows while it is being read.
+//!
+//! The service only ever reads. It either connects to a tap that mirrors the
+//! link (`connect`) or accepts a host that forwards a copy of its traffic
+//! (`listen`); either way the socket carries the link's length-prefixed HSMS
+//! frames, both directions interleaved. Frames are stamped on arrival and
+//! get their direction as in binary logs. A dropped connection is retried
+//! (`connect`) or the next forwarder accepted (`listen`) until the tail is
+//! stopped. Addresses are resolved once and checked against the configured
+//! allowlist, so a reconnect cannot be pointed elsewhere.
+//!
+//! Rows go through the same `Ingest` as uploads, on a blocking thread that
+//! checkpoints every `CHECKPOINT_INTERVAL`: the open chunk and `meta.json`
+//! (with `live: true`) are rewritten, then the new rows are published to the
+//! session's `GET /sessions/:id/live` subscribers. Tiles and sidecars grow
+//! with the session, so they are only brought up to date every
+//! `DERIVED_INTERVAL`. The reader hands frames over through a buffer of
+//! `LINK_BUFFER`; when the ingest falls behind, the socket is no longer read
+//! until it catches up. Stopping the tail finishes the session like an upload.
+
+use crate::models::{ConvertedMessage, SessionMeta};
+use crate::storage::{Ingest, IngestOptions, SessionStorage};
+use chrono::{SecondsFormat, Utc};
+use parser::hsms::{DirectionTracker, HsmsFrame, HEADER_LEN, MAX_FRAME_LEN};
+use parser::Endpoint;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, RecvTimeoutError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// How often new rows become readable and are published
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+/// How often tiles and sidecars catch up with the rows
+pub const DERIVED_INTERVAL: Duration = Duration::from_secs(30);
+/// Frames read ahead of the ingest thread
+const LINK_BUFFER: usize = 4096;
+/// Wait before offering a frame to a full buffer again
+const BACKPRESSURE_DELAY: Duration = Duration::from_millis(10);
+/// Wait before reconnecting to a tap that dropped or refused the connection
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+/// Events buffered per subscriber; a slower one is told how many it missed
+const EVENT_CAPACITY: usize = 256;
+
+/// What subscribers of a live session receive
+#[derive(Debug, Clone)]
+pub enum LiveEvent {
+    /// Rows made readable by the last checkpoint, in `row_id` order
+    Rows(Arc<Vec<ConvertedMessage>>),
+    /// The tail was stopped and the session finished
+    Finished(Arc<SessionMeta>),
+    /// Ingest failed; the session stays as of its last checkpoint
+    Failed(String),
+}
+
+/// What `address` resolves to; `Ok(None)` unless every resolved address
+/// matches an `allowed` endpoint
+pub async fn resolve_allowed(address: &str, allowed: &[Endpoint]) -> std::io::Result<Option<Vec<SocketAddr>>> {
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host(address).await?.collect();
+    if addresses.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses found"));
+    }
+    let permitted = addresses.iter()
+        .all(|addr| allowed.iter().any(|endpoint| endpoint.matches(&(addr.ip(), addr.port()))));
+    Ok(permitted.then_some(addresses))
+}
+
+/// Where frames come from
+pub enum Link {
+    Connect { addresses: Vec<SocketAddr>, first: Option<TcpStream> },
+    Listen(TcpListener),
+}
+
+impl Link {
+    /// Connect or bind now, so that a bad address fails the request
+    pub async fn connect(addresses: Vec<SocketAddr>) -> std::io::Result<Self> {
+        let first = TcpStream::connect(addresses.as_slice()).await?;
+        Ok(Link::Connect { addresses, first: Some(first) })
+    }
+
+    pub async fn listen(addresses: &[SocketAddr]) -> std::io::Result<Self> {
+        Ok(Link::Listen(TcpListener::bind(addresses).await?))
+    }
+
+    async fn next_stream(&mut self) -> std::io::Result<TcpStream> {
+        match self {
+            Link::Connect { addresses, first } => match first.take() {
+                Some(stream) => Ok(stream),
+                None => TcpStream::connect(addresses.as_slice()).await,
+            },
+            Link::Listen(listener) => Ok(listener.accept().await?.0),
+        }
+    }
+}
+
+struct Tail {
+    stop: watch::Sender<bool>,
+    /// Dead once the ingest thread has ended
+    events: broadcast::WeakSender<LiveEvent>,
+    ingest: JoinHandle<Result<SessionMeta, String>>,
+}
+
+/// Live tails by session id; a tail whose ingest failed removes itself
+#[derive(Default)]
+pub struct LiveSessions {
+    tails: Arc<Mutex<HashMap<String, Tail>>>,
+}
+
+impl LiveSessions {
+    /// Tail `link` into the freshly created session `session_id`
+    pub fn start(&self, storage: Arc<SessionStorage>, session_id: String, link: Link, options: IngestOptions) {
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let (messages_tx, messages_rx) = mpsc::sync_channel(LINK_BUFFER);
+
+        let label = session_id.clone();
+        tokio::spawn(async move {
+            read_link(link, messages_tx, stop_rx).await;
+            info!("Live session {} stopped reading", label);
+        });
+
+        let weak = events.downgrade();
+        let sid = session_id.clone();
+        // Held until the tail is in, so a failing ingest finds it to remove
+        let mut tails = self.tails.lock().unwrap();
+        let all_tails = self.tails.clone();
+        let ingest = tokio::task::spawn_blocking(move || {
+            let result = run_ingest(&storage, &sid, &options, messages_rx, &events)
+                .map_err(|e| {
+                    error!("Live ingest failed for session {}: {}", sid, e);
+                    e.to_string()
+                });
+            let _ = events.send(match &result {
+                Ok(meta) => LiveEvent::Finished(Arc::new(meta.clone())),
+                Err(e) => LiveEvent::Failed(e.clone()),
+            });
+            if result.is_err() {
+                // Dropping the stop sender also ends the reader and frees the link
+                all_tails.lock().unwrap().remove(&sid);
+            }
+            result
+        });
+
+        tails.insert(session_id, Tail { stop: stop_tx, events: weak, ingest });
+    }
+
+    /// Events from the next checkpoint on; `None` once the tail has ended
+    pub fn subscribe(&self, session_id: &str) -> Option<broadcast::Receiver<LiveEvent>> {
+        let tails = self.tails.lock().unwrap();
+        tails.get(session_id)?.events.upgrade().map(|events| events.subscribe())
+    }
+
+    /// Stop reading, finish the session and forget the tail; `None` if it was not live
+    pub async fn stop(&self, session_id: &str) -> Option<Result<SessionMeta, String>> {
+        let tail = self.tails.lock().unwrap().remove(session_id)?;
+        let _ = tail.stop.send(true);
+        Some(tail.ingest.await.unwrap_or_else(|e| Err(format!("Live ingest task failed: {}", e))))
+    }
+}
+
+/// Read frames until stopped, reconnecting whenever the link drops. Returns
+/// early if the ingest thread has gone away.
+async fn read_link(mut link: Link, messages: mpsc::SyncSender<ConvertedMessage>, mut stop: watch::Receiver<bool>) {
+    let mut row_id = 0u32;
+    loop {
+        let stream = tokio::select! {
+            stream = link.next_stream() => stream,
+            _ = stop.changed() => return,
+        };
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Live link unavailable: {}", e);
+                tokio::select! {
+                    _ = tokio::time::sleep(RECONNECT_DELAY) => continue,
+                    _ = stop.changed() => return,
+                }
+            }
+        };
+        info!("Live link connected: {:?}", stream.peer_addr().ok());
+
+        // A new connection starts a new HSMS session: nothing stays open across it
+        let mut directions = DirectionTracker::new("H->E");
+        loop {
+            let frame = tokio::select! {
+                frame = read_frame(&mut stream) => frame,
+                _ = stop.changed() => return,
+            };
+            let frame = match frame {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    info!("Live link closed");
+                    break;
+                }
+                Err(e) => {
+                    warn!("Live link dropped: {}", e);
+                    break;
+                }
+            };
+            let frame = match HsmsFrame::decode(&frame) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    // The length prefix keeps the stream in step: skip just this frame
+                    warn!("Skipping undecodable HSMS frame: {}", e);
+                    continue;
+                }
+            };
+            let dir = directions.direction(&frame);
+            let ts_iso = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true);
+            let msg = match ConvertedMessage::from_parsed(frame.into_message(ts_iso, dir), row_id) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    warn!("Skipping HSMS frame: {}", e);
+                    continue;
+                }
+            };
+            if !hand_over(&messages, msg, &mut stop).await {
+                return;
+            }
+            row_id += 1;
+        }
+    }
+}
+
+/// Queue `msg` for the ingest thread, waiting while the buffer is full;
+/// false if stopped or the ingest thread has gone away
+async fn hand_over(messages: &mpsc::SyncSender<ConvertedMessage>, mut msg: ConvertedMessage, stop: &mut watch::Receiver<bool>) -> bool {
+    loop {
+        match messages.try_send(msg) {
+            Ok(()) => return true,
+            Err(TrySendError::Disconnected(_)) => return false,
+            Err(TrySendError::Full(back)) => {
+                msg = back;
+                tokio::select! {
+                    _ = tokio::time::sleep(BACKPRESSURE_DELAY) => {}
+                    _ = stop.changed() => return false,
+                }
+            }
+        }
+    }
+}
+
+/// One frame without its length prefix; `None` at a clean end of stream
+async fn read_frame(stream: &mut TcpStream) -> std::io::Result<Option<Vec<u8>>> {
+    let mut len_buf = [0u8; 4];
+    match stream.read_exact(&mut len_buf).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if !(HEADER_LEN..=MAX_FRAME_LEN).contains(&len) {
+        // Framing is lost; only a new connection can recover it
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid HSMS frame length {}", len)));
+    }
+    let mut frame = vec![0u8; len];
+    stream.read_exact(&mut frame).await?;
+    Ok(Some(frame))
+}
+
+/// Ingest messages until the reader hangs up, checkpointing and publishing
+/// on the way
+fn run_ingest(
+    storage: &SessionStorage,
+    session_id: &str,
+    options: &IngestOptions,
+    messages: mpsc::Receiver<ConvertedMessage>,
+    events: &broadcast::Sender<LiveEvent>,
+) -> Result<SessionMeta, Box<dyn std::error::Error>> {
+    let mut ingest = Ingest::new(storage, session_id, options, Arc::default())?;
+    // The session is readable, if empty, from the start
+    ingest.checkpoint(true)?;
+
+    let mut pending = Vec::new();
+    let mut next_checkpoint = Instant::now() + CHECKPOINT_INTERVAL;
+    let mut next_derived = Instant::now() + DERIVED_INTERVAL;
+    let mut derived_stale = false;
+    loop {
+        match messages.recv_timeout(next_checkpoint.saturating_duration_since(Instant::now())) {
+            Ok(mut msg) => {
+                ingest.push(&mut msg)?;
+                pending.push(msg);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if Instant::now() >= next_checkpoint {
+            derived_stale |= !pending.is_empty();
+            let derived = derived_stale && Instant::now() >= next_derived;
+            if !pending.is_empty() || derived {
+                ingest.checkpoint(derived)?;
+            }
+            if !pending.is_empty() {
+                let _ = events.send(LiveEvent::Rows(Arc::new(std::mem::take(&mut pending))));
+            }
+            if derived {
+                derived_stale = false;
+                next_derived = Instant::now() + DERIVED_INTERVAL;
+            }
+            next_checkpoint = Instant::now() + CHECKPOINT_INTERVAL;
+        }
+    }
+
+    let meta = ingest.finish()?;
+    if !pending.is_empty() {
+        let _ = events.send(LiveEvent::Rows(Arc::new(pending)));
+    }
+    info!("Live session {} finished with {} rows", session_id, meta.row_count);
+    Ok(meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrow_io::read_arrow_chunk;
+    use crate::test_support::TempDir;
+    use parser::hsms::SType;
+    use parser::secs2::SecsItem;
+    use tokio::io::AsyncWriteExt;
+
+    /// Loopback equipment: a select, S1F13/F14 and an event report with its
+    /// acknowledge, as a tap or forwarder would relay them
+    async fn play_equipment(stream: &mut TcpStream) {
+        let frames = [
+            HsmsFrame::control(0xFFFF, SType::SelectReq, 0, 1),
+            HsmsFrame::control(0xFFFF, SType::SelectRsp, 0, 1),
+            HsmsFrame::data(1, 1, 13, true, 2, Some(SecsItem::List(vec![]))),
+            HsmsFrame::data(1, 1, 14, false, 2, Some(SecsItem::List(vec![
+                SecsItem::Binary(vec![0]),
+                SecsItem::List(vec![]),
+            ]))),
+            HsmsFrame::data(1, 6, 11, true, 3, Some(SecsItem::List(vec![
+                SecsItem::U4(vec![1]),
+                SecsItem::U4(vec![201]),
+                SecsItem::List(vec![]),
+            ]))),
+            HsmsFrame::data(1, 6, 12, false, 3, Some(SecsItem::Binary(vec![0]))),
+        ];
+        for frame in frames {
+            stream.write_all(&frame.encode()).await.unwrap();
+        }
+        // An undecodable frame in between is skipped
+        stream.write_all(&[0, 0, 0, 10, 0xFF, 0xFF, 0, 0, 0, 0x7F, 0, 0, 0, 4]).await.unwrap();
+        stream.write_all(&HsmsFrame::control(0xFFFF, SType::LinktestReq, 0, 5).encode()).await.unwrap();
+        stream.flush().await.unwrap();
+    }
+
+    async fn tail_equipment(storage: &Arc<SessionStorage>, live: &LiveSessions, link: Link, equipment: JoinHandle<TcpStream>) {
+        let session_id = storage.create_session().unwrap();
+        live.start(storage.clone(), session_id.clone(), link, IngestOptions::default());
+        let mut events = live.subscribe(&session_id).unwrap();
+        let _equipment = equipment.await.unwrap();
+
+        let mut rows = Vec::new();
+        while rows.len() < 7 {
+            match tokio::time::timeout(Duration::from_secs(10), events.recv()).await.unwrap().unwrap() {
+                LiveEvent::Rows(batch) => rows.extend(batch.iter().map(|m| (m.row_id, m.s, m.f, m.dir))),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(rows, vec![
+            (0, 0, 1, 1), (1, 0, 2, -1),
+            (2, 1, 13, 1), (3, 1, 14, -1),
+            (4, 6, 11, -1), (5, 6, 12, 1),
+            (6, 0, 5, 1),
+        ]);
+
+        // Readable while still live
+        let meta = storage.read_meta(&session_id).unwrap();
+        assert!(meta.live);
+        assert_eq!(meta.row_count, 7);
+        let batch = &read_arrow_chunk(&storage.chunk_path(&session_id, 0)).unwrap()[0];
+        assert_eq!(batch.num_rows(), 7);
+        assert!(storage.read_payload(&session_id, 4).unwrap().pointer("/secs_tree").is_some());
+        // Sidecars from the first checkpoint, before any rows
+        assert!(storage.read_conformance(&session_id).is_ok());
+
+        let meta = live.stop(&session_id).await.unwrap().unwrap();
+        assert!(!meta.live);
+        assert_eq!(meta.row_count, 7);
+        assert!(!storage.read_meta(&session_id).unwrap().live);
+        assert!(matches!(events.recv().await.unwrap(), LiveEvent::Finished(_)));
+        assert!(live.subscribe(&session_id).is_none());
+        assert!(live.stop(&session_id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hand_over_waits_for_a_full_buffer() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let (stop_tx, mut stop) = watch::channel(false);
+        assert!(hand_over(&tx, ConvertedMessage::default(), &mut stop).await);
+
+        let drain = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            let rows = [rx.recv().unwrap(), rx.recv().unwrap()];
+            (rows, rx)
+        });
+        let second = ConvertedMessage { row_id: 1, ..Default::default() };
+        assert!(hand_over(&tx, second, &mut stop).await);
+        let ([first, second], _rx) = drain.join().unwrap();
+        assert_eq!((first.row_id, second.row_id), (0, 1));
+
+        // A full buffer gives up once stopped
+        let (tx, _rx) = mpsc::sync_channel(0);
+        stop_tx.send(true).unwrap();
+        assert!(!hand_over(&tx, ConvertedMessage::default(), &mut stop).await);
+    }
+
+    #[tokio::test]
+    async fn test_tail_loopback_equipment() {
+        let dir = TempDir::new("hsms-live");
+        let storage = Arc::new(SessionStorage::new(&dir).unwrap());
+        let live = LiveSessions::default();
+
+        // Connect to a tap
+        let tap = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let link = Link::connect(vec![tap.local_addr().unwrap()]).await.unwrap();
+        let equipment = tokio::spawn(async move {
+            let mut stream = tap.accept().await.unwrap().0;
+            play_equipment(&mut stream).await;
+            stream
+        });
+        tail_equipment(&storage, &live, link, equipment).await;
+
+        // Accept a forwarder
+        let link = Link::listen(&["127.0.0.1:0".parse().unwrap()]).await.unwrap();
+        let Link::Listen(listener) = &link else { unreachable!() };
+        let address = listener.local_addr().unwrap();
+        let equipment = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            play_equipment(&mut stream).await;
+            stream
+        });
+        tail_equipment(&storage, &live, link, equipment).await;
+    }
+
+    #[tokio::test]
+    async fn test_resolve_allowed() {
+        let allowed: Vec<Endpoint> = vec!["127.0.0.1:5000".parse().unwrap(), ":5100".parse().unwrap()];
+        let resolved = resolve_allowed("127.0.0.1:5000", &allowed).await.unwrap().unwrap();
+        assert_eq!(resolved, vec!["127.0.0.1:5000".parse().unwrap()]);
+        assert!(resolve_allowed("0.0.0.0:5100", &allowed).await.unwrap().is_some());
+        assert!(resolve_allowed("127.0.0.1:22", &allowed).await.unwrap().is_none());
+        assert!(resolve_allowed("169.254.169.254:5000", &allowed).await.unwrap().is_none());
+        // Nothing is allowed by default
+        assert!(resolve_allowed("127.0.0.1:5000", &[]).await.unwrap().is_none());
+        assert!(resolve_allowed("no port", &allowed).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_ingest_forgets_its_tail() {
+        let dir = TempDir::new("hsms-live-failed");
+        let storage = Arc::new(SessionStorage::new(&dir).unwrap());
+        let live = LiveSessions::default();
+
+        // A file where the session's chunks should go fails the first checkpoint
+        let session_id = storage.create_session().unwrap();
+        let session_dir = dir.join(&session_id);
+        std::fs::remove_dir_all(&session_dir).unwrap();
+        std::fs::write(&session_dir, b"").unwrap();
+
+        let link = Link::listen(&["127.0.0.1:0".parse().unwrap()]).await.unwrap();
+        let Link::Listen(listener) = &link else { unreachable!() };
+        let address = listener.local_addr().unwrap();
+        live.start(storage.clone(), session_id.clone(), link, IngestOptions::default());
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !live.tails.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+        assert!(live.subscribe(&session_id).is_none());
+        assert!(live.stop(&session_id).await.is_none());
+
+        // The reader went with it and released the port
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while TcpListener::bind(address).await.is_err() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+    }
+}
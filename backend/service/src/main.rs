@@ -1,15 +1,40 @@
 mod routes;
 mod storage;
 mod models;
+mod alarms;
+mod config;
 mod arrow_io;
+mod conformance;
+mod highlight;
+mod jobs;
+mod latency;
+mod live;
+mod dictionary;
+mod secs_tree;
+mod sessions;
+mod sweeper;
+mod pairing;
+mod payloads;
+mod query;
+mod sql;
+mod states;
+mod stats;
+mod text_index;
+mod timers;
+mod tiles;
+mod window;
+#[cfg(test)]
+mod test_support;
 
-use axum::{Router, extract::DefaultBodyLimit};
-use tower_http::cors::{CorsLayer, Any};
-use std::net::SocketAddr;
+use axum::{Router, extract::DefaultBodyLimit, http::{HeaderName, HeaderValue}};
+use config::Config;
+use tower_http::cors::{AllowOrigin, CorsLayer, Any};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() {
+    let config = Config::load().unwrap_or_else(|e| exit_with("Configuration error", e));
+
     // Initialize tracing
     tracing_subscriber::registry()
         .with(
@@ -19,24 +44,43 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // CORS configuration
+    // CORS configuration: any origin unless the config lists them
+    let origins = if config.cors_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        // Checked by `Config::validate`
+        AllowOrigin::list(config.cors_origins.iter().filter_map(|origin| HeaderValue::from_str(origin).ok()))
+    };
     let cors = CorsLayer::new()
-        .allow_origin(Any)
+        .allow_origin(origins)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers([HeaderName::from_static(routes::NEXT_CURSOR_HEADER)]);
 
-    // Build router with increased body limit for large file uploads
-    // Set limit to 1 GB to handle large HSMS log files
+    // Uploads are whole log files: the body limit is set by config
+    let routes = routes::create_routes(&config).unwrap_or_else(|e| exit_with("Cannot start", e));
     let app = Router::new()
-        .merge(routes::create_routes())
-        .layer(DefaultBodyLimit::max(1024 * 1024 * 1024)) // 1 GB limit
+        .merge(routes)
+        .layer(DefaultBodyLimit::max(config.body_limit_bytes()))
         .layer(cors);
 
     // Start server
-    let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
-    tracing::info!("Starting HSMS service on {}", addr);
+    tracing::info!("Starting HSMS service on {} with data in {}", config.bind, config.data_dir.display());
     
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(config.bind).await
+        .unwrap_or_else(|e| exit_with(&format!("Cannot listen on {}", config.bind), e));
     axum::serve(listener, app).await.unwrap();
 }
 
+/// Report a startup error without a panic's backtrace noise; several
+/// problems go one per line
+fn exit_with(context: &str, error: impl std::fmt::Display) -> ! {
+    let error = error.to_string();
+    if error.contains('\n') {
+        eprintln!("{}:\n  {}", context, error.lines().collect::<Vec<_>>().join("\n  "));
+    } else {
+        eprintln!("{}: {}", context, error);
+    }
+    std::process::exit(2)
+}
+
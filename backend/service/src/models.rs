@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionMeta {
@@ -8,6 +9,367 @@ pub struct SessionMeta {
     pub distinct_s: Vec<u8>,
     pub distinct_f: Vec<u8>,
     pub distinct_ceid: Vec<u32>,
+    /// Names of the CEIDs seen, from the equipment dictionary or upstream `semantic`
+    #[serde(default)]
+    pub ceid_names: BTreeMap<u32, String>,
+    /// Per-chunk row and time ranges, in chunk order (empty for old sessions)
+    #[serde(default)]
+    pub chunks: Vec<ChunkStats>,
+    /// Whole-session counts for legends (absent for old sessions)
+    #[serde(default)]
+    pub counts: Option<MessageCounts>,
+    /// Bucket width of each `/tiles` level (empty for sessions without tiles)
+    #[serde(default)]
+    pub tile_levels: Vec<i64>,
+    /// Still being appended to by a live tail: everything above may grow
+    #[serde(default)]
+    pub live: bool,
+    #[serde(default)]
+    pub status: SessionStatus,
+    /// Why ingest failed
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Parser that read the upload (none for live sessions or empty input)
+    #[serde(default)]
+    pub parser: Option<String>,
+}
+
+/// Whether a session's data is complete
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStatus {
+    /// Upload still being ingested (nothing readable yet), or a live tail
+    /// (readable as of its last checkpoint)
+    Ingesting,
+    #[default]
+    Ready,
+    /// Ingest failed: the session holds no data, only `error`
+    Failed,
+}
+
+/// `progress` event of `GET /sessions/:id/events`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IngestProgress {
+    /// Upload bytes read by the parser so far, and in all (0 for live sessions)
+    pub bytes_read: u64,
+    pub bytes_total: u64,
+    pub rows: u64,
+    /// Chunk files written so far, a live session's open chunk included
+    pub chunks: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkStats {
+    pub rows: usize,
+    pub first_row_id: u32,
+    pub t_min_ns: i64,
+    pub t_max_ns: i64,
+}
+
+impl ChunkStats {
+    /// Whether any row could fall in `[from_ns, to_ns]` (0 = unbounded)
+    pub fn overlaps(&self, from_ns: i64, to_ns: i64) -> bool {
+        (from_ns == 0 || self.t_max_ns >= from_ns) && (to_ns == 0 || self.t_min_ns <= to_ns)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessageCounts {
+    pub total: u64,
+    pub by_dir: BTreeMap<i8, u64>,
+    pub by_s: BTreeMap<u8, u64>,
+    pub by_f: BTreeMap<u8, u64>,
+    pub by_sxfy: Vec<SxFyCount>,
+    /// Rows with a CEID only
+    pub by_ceid: BTreeMap<u32, u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SxFyCount {
+    pub s: u8,
+    pub f: u8,
+    pub count: u64,
+}
+
+/// Bucket `i` covers `[from_ns + i * bucket_ns, from_ns + (i + 1) * bucket_ns)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Histogram {
+    pub from_ns: i64,
+    pub bucket_ns: i64,
+    pub counts: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsRequest {
+    #[serde(flatten)]
+    pub filter: FilterExpr,
+    #[serde(default)]
+    pub query: Option<String>,
+    /// Histogram bucket width; 0 = split the range into `buckets`
+    #[serde(default)]
+    pub bucket_ns: i64,
+    #[serde(default = "default_buckets")]
+    pub buckets: usize,
+}
+
+fn default_buckets() -> usize {
+    crate::stats::DEFAULT_BUCKETS
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsResponse {
+    #[serde(flatten)]
+    pub counts: MessageCounts,
+    pub histogram: Histogram,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyRequest {
+    #[serde(flatten)]
+    pub filter: FilterExpr,
+    #[serde(default)]
+    pub query: Option<String>,
+    /// Bucket width; 0 = split the range into `buckets`
+    #[serde(default)]
+    pub bucket_ns: i64,
+    #[serde(default = "default_buckets")]
+    pub buckets: usize,
+    /// Reply timeout; a later reply, or none at all, is a T3 timeout
+    #[serde(default = "default_t3_ms")]
+    pub t3_ms: u64,
+}
+
+fn default_t3_ms() -> u64 {
+    crate::latency::DEFAULT_T3_MS
+}
+
+/// Nearest-rank percentiles of the reply latencies; null without replies
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50_ns: Option<i64>,
+    pub p90_ns: Option<i64>,
+    pub p99_ns: Option<i64>,
+    pub max_ns: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyBucket {
+    pub from_ns: i64,
+    #[serde(flatten)]
+    pub summary: LatencySummary,
+}
+
+/// Latencies of one primary SxFy in one direction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyGroup {
+    pub s: u8,
+    pub f: u8,
+    pub dir: i8,
+    #[serde(flatten)]
+    pub summary: LatencySummary,
+    pub timeouts: u64,
+    /// Buckets holding at least one reply, by the primary's timestamp
+    pub buckets: Vec<LatencyBucket>,
+}
+
+/// A primary answered after T3, or not at all (`latency_ns` null)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct T3Timeout {
+    pub row_id: u32,
+    pub ts_ns: i64,
+    pub s: u8,
+    pub f: u8,
+    pub dir: i8,
+    pub latency_ns: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyResponse {
+    pub t3_ns: i64,
+    pub from_ns: i64,
+    pub bucket_ns: i64,
+    pub groups: Vec<LatencyGroup>,
+    pub timeouts: Vec<T3Timeout>,
+    pub dropped_timeouts: u64,
+}
+
+/// HSMS timer settings of one piece of equipment (SEMI E37 defaults)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HsmsTimers {
+    /// Reply timeout
+    #[serde(default = "default_t3_ms")]
+    pub t3_ms: u64,
+    /// Connect separation: minimum gap before reconnecting
+    #[serde(default = "default_t5_ms")]
+    pub t5_ms: u64,
+    /// Control transaction timeout
+    #[serde(default = "default_t6_ms")]
+    pub t6_ms: u64,
+    /// Longest a connection may stay NOT SELECTED
+    #[serde(default = "default_t7_ms")]
+    pub t7_ms: u64,
+    /// Longest gap between linktests while selected; unchecked when absent
+    #[serde(default)]
+    pub linktest_ms: Option<u64>,
+}
+
+impl Default for HsmsTimers {
+    fn default() -> Self {
+        Self {
+            t3_ms: default_t3_ms(),
+            t5_ms: default_t5_ms(),
+            t6_ms: default_t6_ms(),
+            t7_ms: default_t7_ms(),
+            linktest_ms: None,
+        }
+    }
+}
+
+fn default_t5_ms() -> u64 {
+    10_000
+}
+
+fn default_t6_ms() -> u64 {
+    5_000
+}
+
+fn default_t7_ms() -> u64 {
+    10_000
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HsmsTimer {
+    T3,
+    T5,
+    T6,
+    T7,
+    Linktest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimerViolation {
+    pub timer: HsmsTimer,
+    /// When the timer started
+    pub ts_ns: i64,
+    /// The row that started the timer, then the one that ended it (if any)
+    pub row_ids: Vec<u32>,
+    /// Time until the ending row; null when nothing ended the timer in time
+    pub elapsed_ns: Option<i64>,
+    pub limit_ns: i64,
+    pub message: String,
+}
+
+/// `timers.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionTimers {
+    /// Settings the session was checked with
+    pub timers: HsmsTimers,
+    pub violations: Vec<TimerViolation>,
+    pub dropped_violations: u64,
+}
+
+/// One alarm from set to clear (S5F1). Either end is missing when it lies
+/// outside the log: set before the first row, or still set after the last.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlarmInterval {
+    pub alid: u32,
+    /// ALCD category bits (without the set bit)
+    pub category: u8,
+    pub altx: Option<String>,
+    pub set_ns: Option<i64>,
+    pub clear_ns: Option<i64>,
+    pub duration_ns: Option<i64>,
+    pub set_row_id: Option<u32>,
+    pub clear_row_id: Option<u32>,
+}
+
+/// Everything the log says about one ALID
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlarmInfo {
+    pub alid: u32,
+    pub altx: Option<String>,
+    /// As last set by S5F3 (or listed by S5F8); `None` if the log never says
+    pub enabled: Option<bool>,
+    pub set_count: u64,
+}
+
+/// `alarms.json`: intervals sorted by start
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionAlarms {
+    pub intervals: Vec<AlarmInterval>,
+    pub alarms: Vec<AlarmInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateMachine {
+    /// HSMS selection (SEMI E37)
+    Link,
+    /// GEM communication (SEMI E30, S1F13/S1F14)
+    Communication,
+    /// GEM control state (SEMI E30, S1F15-S1F18 and control-state events)
+    Control,
+}
+
+/// A state from the row that entered it until the next transition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateInterval {
+    pub machine: StateMachine,
+    pub state: String,
+    pub from_ns: i64,
+    /// `None` while the state lasts to the end of the log
+    pub to_ns: Option<i64>,
+    /// Row that caused the transition
+    pub row_id: u32,
+    /// Deduced from traffic rather than from a state-changing message
+    pub inferred: bool,
+}
+
+/// A message that is not allowed in the state its machine was in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateAnomaly {
+    pub machine: StateMachine,
+    pub state: String,
+    pub ts_ns: i64,
+    pub row_id: u32,
+    pub message: String,
+}
+
+/// `states.json`: intervals and anomalies in log order
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionStates {
+    pub intervals: Vec<StateInterval>,
+    pub anomalies: Vec<StateAnomaly>,
+    /// Anomalies beyond `states::MAX_ANOMALIES`, counted but not listed
+    #[serde(default)]
+    pub dropped_anomalies: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleCount {
+    pub rule: String,
+    /// Message of the first violation seen
+    pub message: String,
+    pub count: u64,
+    pub example_row_ids: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SxFyViolations {
+    pub s: u8,
+    pub f: u8,
+    /// Rows of this SxFy with at least one violation
+    pub rows: u64,
+    pub rules: Vec<RuleCount>,
+}
+
+/// `conformance.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConformanceSummary {
+    pub rows_with_violations: u64,
+    pub by_sxfy: Vec<SxFyViolations>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +377,110 @@ pub struct CreateSessionResponse {
     pub session_id: String,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionSource {
+    #[default]
+    Upload,
+    Live,
+}
+
+/// `info.json`: what a session is, for the `GET /sessions` catalog
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// Display name: the file name or live address until renamed
+    pub name: String,
+    #[serde(default)]
+    pub source: SessionSource,
+    /// Uploaded file name, or the address a live session reads from
+    #[serde(default)]
+    pub filename: String,
+    /// When the session was created (upload or tail start), Unix ns
+    #[serde(default)]
+    pub created_ns: i64,
+    /// Upload size (0 for live sessions)
+    #[serde(default)]
+    pub size_bytes: u64,
+    #[serde(default)]
+    pub equipment_id: Option<String>,
+    /// Equipment profile the session was decoded with
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub notes: String,
+    /// Never deleted by the TTL sweeper or quota eviction
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// `PATCH /sessions/:id`: the fields to change
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionPatch {
+    pub name: Option<String>,
+    /// Replaces the tags
+    pub tags: Option<Vec<String>>,
+    pub notes: Option<String>,
+    /// Empty clears it
+    pub equipment_id: Option<String>,
+    pub pinned: Option<bool>,
+}
+
+/// One `GET /sessions` entry: the catalog info plus the session's status
+/// and extent from its meta
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub info: SessionInfo,
+    pub status: SessionStatus,
+    pub live: bool,
+    pub error: Option<String>,
+    pub parser: Option<String>,
+    pub row_count: usize,
+    pub t_min_ns: i64,
+    pub t_max_ns: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionList {
+    /// Sessions matching the filters, before paging
+    pub total: usize,
+    pub sessions: Vec<SessionSummary>,
+}
+
+/// How a live session reaches the link it observes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LiveMode {
+    /// Connect to a tap that mirrors the link
+    Connect,
+    /// Accept a host that forwards a copy of its traffic
+    Listen,
+}
+
+/// `POST /sessions/live`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveRequest {
+    pub mode: LiveMode,
+    /// `host:port` to connect to, or the local address to listen on
+    pub address: String,
+    /// Equipment profile, as for uploads
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Per-session dictionary on top of the profile
+    #[serde(default)]
+    pub dictionary: Option<crate::dictionary::EquipmentDictionary>,
+    /// Catalog name (default: the address)
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub equipment_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilterExpr {
     #[serde(default)]
@@ -29,6 +495,9 @@ pub struct FilterExpr {
     pub ceid: Vec<u32>,
     #[serde(default)]
     pub text: String,
+    /// Only rows with (`true`) or without (`false`) conformance violations
+    #[serde(default)]
+    pub has_violation: Option<bool>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -47,6 +516,16 @@ pub struct HighlightExpr {
     pub sxfy: Vec<SxFy>,
     #[serde(default)]
     pub unanswered: bool,
+    /// Max delay from a primary to its reply (spec: 5 s)
+    #[serde(default = "default_reply_window_ms")]
+    pub reply_window_ms: u64,
+    /// Rows involved in an HSMS timer violation
+    #[serde(default)]
+    pub timers: bool,
+}
+
+fn default_reply_window_ms() -> u64 {
+    5_000
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,12 +538,21 @@ pub struct SxFy {
 pub struct SearchRequest {
     #[serde(flatten)]
     pub filter: FilterExpr,
+    /// Boolean query (see `query.rs`), ANDed with `filter`
+    #[serde(default)]
+    pub query: Option<String>,
     #[serde(default)]
     pub highlight: Option<HighlightExpr>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SqlRequest {
+    pub sql: String,
+}
+
 /// Converted message ready for Arrow storage
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(test, derive(Default))]
 pub struct ConvertedMessage {
     pub ts_ns: i64,
     pub dir: i8,
@@ -74,6 +562,12 @@ pub struct ConvertedMessage {
     pub sysbytes: u32,
     pub ceid: u32,
     pub row_id: u32,
+    /// Row of the matching secondary (primaries only, filled in at ingest)
+    pub reply_row_id: Option<u32>,
+    /// Reply ts minus primary ts (primaries only, filled in at ingest)
+    pub latency_ns: Option<i64>,
+    /// Conformance rules broken (`None` until checked at ingest)
+    pub violation_count: Option<u16>,
     pub body_json: serde_json::Value,
 }
 
@@ -98,6 +592,9 @@ impl ConvertedMessage {
             sysbytes: msg.sysbytes,
             ceid: msg.ceid,
             row_id,
+            reply_row_id: None,
+            latency_ns: None,
+            violation_count: None,
             body_json: msg.body_json,
         })
     }
@@ -109,6 +606,6 @@ fn parse_timestamp(ts_iso: &str) -> Result<i64, String> {
     let dt = DateTime::parse_from_rfc3339(ts_iso)
         .map_err(|e| format!("Invalid timestamp {}: {}", ts_iso, e))?;
     
-    Ok(dt.with_timezone(&Utc).timestamp_nanos_opt()
-        .ok_or_else(|| format!("Timestamp out of range: {}", ts_iso))?)
+    dt.with_timezone(&Utc).timestamp_nanos_opt()
+        .ok_or_else(|| format!("Timestamp out of range: {}", ts_iso))
 }
@@ -0,0 +1,134 @@
+use crate::models::ConvertedMessage;
+use std::collections::HashMap;
+
+/// Replies arriving later than this after their primary are not paired.
+/// Bounds the open-primary table on long logs full of unanswered requests.
+pub const PAIRING_HORIZON_NS: i64 = 10 * 60 * 1_000_000_000;
+
+/// How many primaries to open between sweeps of stale entries
+const SWEEP_EVERY: usize = 10_000;
+
+/// Where an open primary was written: (chunk index, offset within chunk)
+pub type RowLocation = (usize, usize);
+
+/// A primary matched with its secondary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub primary_row_id: u32,
+    pub primary_location: RowLocation,
+    pub reply_row_id: u32,
+    pub latency_ns: i64,
+}
+
+#[derive(Debug, Clone)]
+struct OpenPrimary {
+    f: u8,
+    row_id: u32,
+    ts_ns: i64,
+    location: RowLocation,
+}
+
+/// Pairs each W-bit primary with the first later reply on the same stream,
+/// system bytes and opposite direction.
+///
+/// Sysbytes wrap around on long logs, so a new primary with the same key
+/// replaces the open one (which is then left unanswered) instead of queueing
+/// behind it, and replies beyond `PAIRING_HORIZON_NS` are ignored.
+pub struct TransactionPairer {
+    /// Keyed by the reply we expect: (s, sysbytes, reply dir)
+    open: HashMap<(u8, u32, i8), OpenPrimary>,
+    opened_since_sweep: usize,
+    latest_ts_ns: i64,
+}
+
+impl TransactionPairer {
+    pub fn new() -> Self {
+        Self {
+            open: HashMap::new(),
+            opened_since_sweep: 0,
+            latest_ts_ns: i64::MIN,
+        }
+    }
+
+    /// Feed the next message in log order. Returns the completed transaction
+    /// when `msg` is the reply to an open primary.
+    pub fn observe(&mut self, msg: &ConvertedMessage, location: RowLocation) -> Option<Transaction> {
+        self.latest_ts_ns = self.latest_ts_ns.max(msg.ts_ns);
+
+        if msg.wbit == 1 {
+            self.open.insert((msg.s, msg.sysbytes, -msg.dir), OpenPrimary {
+                f: msg.f,
+                row_id: msg.row_id,
+                ts_ns: msg.ts_ns,
+                location,
+            });
+            self.opened_since_sweep += 1;
+            if self.opened_since_sweep >= SWEEP_EVERY {
+                self.sweep();
+            }
+            return None;
+        }
+
+        let key = (msg.s, msg.sysbytes, msg.dir);
+        let primary = self.open.get(&key)?;
+
+        // SxF0 aborts the transaction regardless of the primary's function
+        let is_reply = msg.f == 0 || msg.f == primary.f.wrapping_add(1);
+        let latency_ns = msg.ts_ns - primary.ts_ns;
+        if !is_reply || latency_ns > PAIRING_HORIZON_NS {
+            return None;
+        }
+
+        let primary = self.open.remove(&key)?;
+        Some(Transaction {
+            primary_row_id: primary.row_id,
+            primary_location: primary.location,
+            reply_row_id: msg.row_id,
+            latency_ns,
+        })
+    }
+
+    fn sweep(&mut self) {
+        let cutoff = self.latest_ts_ns.saturating_sub(PAIRING_HORIZON_NS);
+        self.open.retain(|_, p| p.ts_ns >= cutoff);
+        self.opened_since_sweep = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg;
+
+    #[test]
+    fn test_pairs_reused_sysbytes() {
+        let mut pairer = TransactionPairer::new();
+        let messages = [
+            msg(1, 3).row(0).at_ms(0).dir(1).wbit(1).sysbytes(7),
+            msg(1, 4).row(1).at_ms(40).dir(-1).sysbytes(7),
+            msg(1, 3).row(2).at_ms(1_000).dir(1).wbit(1).sysbytes(7),  // sysbytes reused, never answered
+            msg(1, 3).row(3).at_ms(2_000).dir(1).wbit(1).sysbytes(7),  // supersedes row 2
+            msg(1, 4).row(4).at_ms(2_030).dir(-1).sysbytes(7),
+            msg(1, 4).row(5).at_ms(2_050).dir(-1).sysbytes(7),         // stray duplicate reply
+        ];
+
+        let pairs: Vec<(u32, u32, i64)> = messages.iter()
+            .enumerate()
+            .filter_map(|(i, m)| pairer.observe(m, (0, i)))
+            .map(|t| (t.primary_row_id, t.reply_row_id, t.latency_ns))
+            .collect();
+
+        assert_eq!(pairs, vec![(0, 1, 40_000_000), (3, 4, 30_000_000)]);
+    }
+
+    #[test]
+    fn test_ignores_wrong_direction_and_function() {
+        let mut pairer = TransactionPairer::new();
+        assert!(pairer.observe(&msg(2, 13).row(0).at_ms(0).dir(1).wbit(1).sysbytes(9), (0, 0)).is_none());
+        assert!(pairer.observe(&msg(2, 14).row(1).at_ms(10).dir(1).sysbytes(9), (0, 1)).is_none());
+        assert!(pairer.observe(&msg(2, 16).row(2).at_ms(20).dir(-1).sysbytes(9), (0, 2)).is_none());
+
+        let t = pairer.observe(&msg(2, 0).row(3).at_ms(30).dir(-1).sysbytes(9), (0, 3)).unwrap();
+        assert_eq!((t.primary_row_id, t.reply_row_id), (0, 3));
+    }
+}
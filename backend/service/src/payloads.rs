@@ -0,0 +1,236 @@
+//! Payload storage: MsgPack bodies packed into append-only segment files
+//! (`payloads/seg-NNN.bin`) with a fixed-width offset index (`payloads/index.bin`).
+//!
+//! Index entry for `row_id` lives at byte `row_id * INDEX_ENTRY_LEN`:
+//! `segment: u32, len: u32, offset: u64`, little-endian. `len == 0` marks a
+//! row without a payload. Sessions written before this layout keep one
+//! `payloads/<row_id>.mp` file per row until `migrate_legacy` packs them.
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+pub const INDEX_ENTRY_LEN: u64 = 16;
+pub const SEGMENT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+const INDEX_FILE: &str = "index.bin";
+
+fn segment_path(dir: &Path, segment: u32) -> PathBuf {
+    dir.join(format!("seg-{:03}.bin", segment))
+}
+
+fn legacy_path(dir: &Path, row_id: u32) -> PathBuf {
+    dir.join(format!("{}.mp", row_id))
+}
+
+/// Appends payloads to segment files and records their location in the index
+pub struct PayloadWriter {
+    dir: PathBuf,
+    index: BufWriter<File>,
+    /// Row whose entry the index cursor currently points at
+    next_row: u32,
+    segment: u32,
+    segment_file: BufWriter<File>,
+    segment_len: u64,
+}
+
+impl PayloadWriter {
+    /// Start a new payload store in `dir`, replacing any existing one
+    pub fn create(dir: &Path) -> std::io::Result<Self> {
+        Self::create_with_index(dir, INDEX_FILE)
+    }
+
+    fn create_with_index(dir: &Path, index_name: &str) -> std::io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            index: BufWriter::new(File::create(dir.join(index_name))?),
+            next_row: 0,
+            segment: 0,
+            segment_file: BufWriter::new(File::create(segment_path(dir, 0))?),
+            segment_len: 0,
+        })
+    }
+
+    pub fn append(&mut self, row_id: u32, body_json: &serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
+        let msgpack = rmp_serde::to_vec(body_json)?;
+        self.append_raw(row_id, &msgpack)?;
+        Ok(())
+    }
+
+    fn append_raw(&mut self, row_id: u32, msgpack: &[u8]) -> std::io::Result<()> {
+        if self.segment_len > 0 && self.segment_len + msgpack.len() as u64 > SEGMENT_MAX_BYTES {
+            self.segment_file.flush()?;
+            self.segment += 1;
+            self.segment_file = BufWriter::new(File::create(segment_path(&self.dir, self.segment))?);
+            self.segment_len = 0;
+        }
+
+        let offset = self.segment_len;
+        self.segment_file.write_all(msgpack)?;
+        self.segment_len += msgpack.len() as u64;
+
+        // Rows normally arrive in order; anything else costs a seek
+        if row_id != self.next_row {
+            self.index.seek(SeekFrom::Start(row_id as u64 * INDEX_ENTRY_LEN))?;
+        }
+        let mut entry = [0u8; INDEX_ENTRY_LEN as usize];
+        entry[0..4].copy_from_slice(&self.segment.to_le_bytes());
+        entry[4..8].copy_from_slice(&(msgpack.len() as u32).to_le_bytes());
+        entry[8..16].copy_from_slice(&offset.to_le_bytes());
+        self.index.write_all(&entry)?;
+        self.next_row = row_id.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Hand everything appended so far to the OS, so readers can find it
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.segment_file.flush()?;
+        self.index.flush()
+    }
+
+    /// Flush everything to disk
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.segment_file.flush()?;
+        self.segment_file.get_ref().sync_data()?;
+        self.index.flush()?;
+        self.index.get_ref().sync_data()?;
+        Ok(())
+    }
+}
+
+/// Reads payloads from one store, keeping the index and every segment it has
+/// read from open between rows
+pub struct PayloadReader {
+    dir: PathBuf,
+    /// `None` for the legacy per-row layout
+    index: Option<File>,
+    segments: HashMap<u32, File>,
+}
+
+impl PayloadReader {
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        let index = match File::open(dir.join(INDEX_FILE)) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Self { dir: dir.to_path_buf(), index, segments: HashMap::new() })
+    }
+
+    /// One index lookup and one segment read
+    pub fn read(&mut self, row_id: u32) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let Some(index) = self.index.as_mut() else {
+            let msgpack = fs::read(legacy_path(&self.dir, row_id))?;
+            return Ok(rmp_serde::from_slice(&msgpack)?);
+        };
+
+        index.seek(SeekFrom::Start(row_id as u64 * INDEX_ENTRY_LEN))?;
+        let mut entry = [0u8; INDEX_ENTRY_LEN as usize];
+        index.read_exact(&mut entry).map_err(|_| format!("No payload for row {}", row_id))?;
+
+        let segment = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+        let len = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]) as usize;
+        let offset = u64::from_le_bytes([
+            entry[8], entry[9], entry[10], entry[11], entry[12], entry[13], entry[14], entry[15],
+        ]);
+        if len == 0 {
+            return Err(format!("No payload for row {}", row_id).into());
+        }
+
+        let file = match self.segments.entry(segment) {
+            Entry::Occupied(open) => open.into_mut(),
+            Entry::Vacant(slot) => slot.insert(File::open(segment_path(&self.dir, segment))?),
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut msgpack = vec![0u8; len];
+        file.read_exact(&mut msgpack)?;
+        Ok(rmp_serde::from_slice(&msgpack)?)
+    }
+}
+
+/// Read one payload; use a `PayloadReader` for many
+pub fn read_payload(dir: &Path, row_id: u32) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    PayloadReader::open(dir)?.read(row_id)
+}
+
+/// Pack a legacy per-row `.mp` directory into segments. The index is renamed
+/// into place last, so readers see either the old layout or the complete new
+/// one; the `.mp` files are removed afterwards. Returns the rows migrated.
+pub fn migrate_legacy(dir: &Path) -> std::io::Result<usize> {
+    if dir.join(INDEX_FILE).exists() {
+        return Ok(0);
+    }
+
+    let mut rows: Vec<u32> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some("mp") {
+            if let Some(row_id) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+                rows.push(row_id);
+            }
+        }
+    }
+    if rows.is_empty() {
+        return Ok(0);
+    }
+    rows.sort_unstable();
+
+    let tmp_index = format!("{}.tmp", INDEX_FILE);
+    let mut writer = PayloadWriter::create_with_index(dir, &tmp_index)?;
+    for &row_id in &rows {
+        writer.append_raw(row_id, &fs::read(legacy_path(dir, row_id))?)?;
+    }
+    writer.finish()?;
+    fs::rename(dir.join(&tmp_index), dir.join(INDEX_FILE))?;
+
+    for &row_id in &rows {
+        fs::remove_file(legacy_path(dir, row_id))?;
+    }
+    info!("Migrated {} legacy payload files in {}", rows.len(), dir.display());
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use serde_json::json;
+
+    #[test]
+    fn test_payload_roundtrip_and_legacy_migration() {
+        let dir = TempDir::new("hsms-payloads");
+
+        let mut writer = PayloadWriter::create(&dir).unwrap();
+        writer.append(0, &json!({"a": 1})).unwrap();
+        writer.append(1, &json!({"secs_tree": {"t": "U4", "v": 201}})).unwrap();
+        writer.append(3, &json!("out of order")).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(read_payload(&dir, 1).unwrap()["secs_tree"]["v"], 201);
+        assert_eq!(read_payload(&dir, 3).unwrap(), json!("out of order"));
+        assert!(read_payload(&dir, 2).is_err());
+        assert!(read_payload(&dir, 9).is_err());
+
+        // One reader for many rows, in any order
+        let mut reader = PayloadReader::open(&dir).unwrap();
+        assert_eq!(reader.read(3).unwrap(), json!("out of order"));
+        assert_eq!(reader.read(0).unwrap()["a"], 1);
+        assert!(reader.read(2).is_err());
+        assert_eq!(reader.read(1).unwrap()["secs_tree"]["v"], 201);
+
+        // Old per-row layout: readable before and after migration
+        let legacy = dir.join("legacy");
+        fs::create_dir_all(&legacy).unwrap();
+        for row_id in 0..3u32 {
+            let msgpack = rmp_serde::to_vec(&json!({"row": row_id})).unwrap();
+            fs::write(legacy_path(&legacy, row_id), msgpack).unwrap();
+        }
+        assert_eq!(read_payload(&legacy, 2).unwrap()["row"], 2);
+        assert_eq!(migrate_legacy(&legacy).unwrap(), 3);
+        assert_eq!(read_payload(&legacy, 2).unwrap()["row"], 2);
+        assert!(!legacy_path(&legacy, 0).exists());
+        assert_eq!(migrate_legacy(&legacy).unwrap(), 0);
+    }
+}
@@ -0,0 +1,848 @@
+//! Boolean query language for `/search`, evaluated after `FilterExpr`.
+//!
+//! ```text
+//! (S6F11 AND ceid = 201 OR S5F1) AND NOT (S0F5 OR S0F6) AND vid(501) = 'A001'
+//! ```
+//!
+//! - `AND`, `OR`, `NOT` (also `&&`, `||`, `!`) and parentheses; AND binds tighter
+//! - Columns: `ts_ns dir s f wbit sysbytes ceid row_id reply_row_id latency_ns
+//!   violation_count`
+//! - `= != < <= > >=`, `x IN (1, 2, 5..9)`, `x BETWEEN 1 AND 9`
+//! - `~` / `!~` regex match, e.g. `text ~ '(?i)lot-\d+'` on the whole payload
+//! - `json('$.semantic.values[*].vid') = 501` (`.key`, `['key']`, `[n]`, `[*]`)
+//! - `vid(501)`: the `value` of any `{"vid": 501, "value": ...}` in `semantic`
+//! - `S6F11` / `S6` shorthand for `s = 6 AND f = 11` / `s = 6`
+//! - `json(...)` or `vid(...)` alone tests that the value exists
+//!
+//! Payload predicates see every value a path yields and match if any does.
+
+use crate::models::ConvertedMessage;
+use regex::Regex;
+use serde_json::Value;
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub pos: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Query error at position {}: {}", self.pos, self.message)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    TsNs,
+    Dir,
+    S,
+    F,
+    Wbit,
+    Sysbytes,
+    Ceid,
+    RowId,
+    ReplyRowId,
+    LatencyNs,
+    ViolationCount,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "ts_ns" => Field::TsNs,
+            "dir" => Field::Dir,
+            "s" => Field::S,
+            "f" => Field::F,
+            "wbit" => Field::Wbit,
+            "sysbytes" => Field::Sysbytes,
+            "ceid" => Field::Ceid,
+            "row_id" => Field::RowId,
+            "reply_row_id" => Field::ReplyRowId,
+            "latency_ns" => Field::LatencyNs,
+            "violation_count" => Field::ViolationCount,
+            _ => return None,
+        })
+    }
+
+    fn value(self, msg: &ConvertedMessage) -> Option<i128> {
+        Some(match self {
+            Field::TsNs => msg.ts_ns as i128,
+            Field::Dir => msg.dir as i128,
+            Field::S => msg.s as i128,
+            Field::F => msg.f as i128,
+            Field::Wbit => msg.wbit as i128,
+            Field::Sysbytes => msg.sysbytes as i128,
+            Field::Ceid => msg.ceid as i128,
+            Field::RowId => msg.row_id as i128,
+            Field::ReplyRowId => msg.reply_row_id? as i128,
+            Field::LatencyNs => msg.latency_ns? as i128,
+            Field::ViolationCount => msg.violation_count? as i128,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PathStep {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Field(Field),
+    Json(Vec<PathStep>),
+    Vid(u64),
+    /// The whole payload as JSON text
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn holds(self, ord: Ordering) -> bool {
+        match self {
+            CmpOp::Eq => ord == Ordering::Equal,
+            CmpOp::Ne => ord != Ordering::Equal,
+            CmpOp::Lt => ord == Ordering::Less,
+            CmpOp::Le => ord != Ordering::Greater,
+            CmpOp::Gt => ord == Ordering::Greater,
+            CmpOp::Ge => ord != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum InItem {
+    Value(Literal),
+    Range(Literal, Literal),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CmpOp, Literal),
+    In(Operand, Vec<InItem>),
+    Regex(Operand, Regex, bool),
+    Exists(Operand),
+}
+
+/// A parsed query, ready to evaluate against rows
+#[derive(Debug, Clone)]
+pub struct Query {
+    expr: Expr,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, QueryError> {
+        let tokens = lex(text)?;
+        let mut parser = QueryParser { tokens, pos: 0, end: text.len(), depth: 0 };
+        let expr = parser.parse_or()?;
+        if let Some((pos, token)) = parser.tokens.get(parser.pos) {
+            return Err(QueryError { pos: *pos, message: format!("Unexpected {}", token) });
+        }
+        Ok(Self { expr })
+    }
+
+    /// Evaluate against a row; `load_payload` is called at most once, and
+    /// only if a payload predicate is reached
+    pub fn matches(&self, msg: &ConvertedMessage, load_payload: &dyn Fn() -> Option<Value>) -> bool {
+        let ctx = RowContext { msg, payload: OnceCell::new(), load_payload };
+        ctx.eval(&self.expr)
+    }
+}
+
+struct RowContext<'a> {
+    msg: &'a ConvertedMessage,
+    payload: OnceCell<Option<Value>>,
+    load_payload: &'a dyn Fn() -> Option<Value>,
+}
+
+impl RowContext<'_> {
+    fn payload(&self) -> Option<&Value> {
+        self.payload.get_or_init(|| (self.load_payload)()).as_ref()
+    }
+
+    fn values(&self, operand: &Operand) -> Vec<Value> {
+        match operand {
+            Operand::Field(field) => field.value(self.msg)
+                .map(|v| vec![Value::from(v as i64)])
+                .unwrap_or_default(),
+            Operand::Json(path) => {
+                let mut out = Vec::new();
+                if let Some(payload) = self.payload() {
+                    select_path(payload, path, &mut out);
+                }
+                out.into_iter().cloned().collect()
+            }
+            Operand::Vid(vid) => {
+                let mut out = Vec::new();
+                if let Some(semantic) = self.payload().and_then(|p| p.get("semantic")) {
+                    collect_vid_values(semantic, *vid, &mut out);
+                }
+                out.into_iter().cloned().collect()
+            }
+            Operand::Text => self.payload()
+                .map(|p| vec![Value::String(serde_json::to_string(p).unwrap_or_default())])
+                .unwrap_or_default(),
+        }
+    }
+
+    fn eval(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::And(a, b) => self.eval(a) && self.eval(b),
+            Expr::Or(a, b) => self.eval(a) || self.eval(b),
+            Expr::Not(e) => !self.eval(e),
+            Expr::Compare(operand, op, literal) => {
+                let values = self.values(operand);
+                if *literal == Literal::Null {
+                    let is_null = values.iter().all(Value::is_null);
+                    return if *op == CmpOp::Ne { !is_null } else { is_null };
+                }
+                values.iter().any(|v| compare(v, literal).is_some_and(|ord| op.holds(ord)))
+            }
+            Expr::In(operand, items) => self.values(operand).iter().any(|v| {
+                items.iter().any(|item| match item {
+                    InItem::Value(lit) => compare(v, lit) == Some(Ordering::Equal),
+                    InItem::Range(lo, hi) => compare(v, lo).is_some_and(|o| o != Ordering::Less)
+                        && compare(v, hi).is_some_and(|o| o != Ordering::Greater),
+                })
+            }),
+            Expr::Regex(operand, regex, negate) => {
+                let hit = self.values(operand).iter().any(|v| regex.is_match(&value_text(v)));
+                hit != *negate
+            }
+            Expr::Exists(operand) => self.values(operand).iter().any(|v| !v.is_null()),
+        }
+    }
+}
+
+pub(crate) fn select_path<'v>(value: &'v Value, path: &[PathStep], out: &mut Vec<&'v Value>) {
+    let Some((step, rest)) = path.split_first() else {
+        out.push(value);
+        return;
+    };
+    match (step, value) {
+        (PathStep::Key(key), Value::Object(map)) => {
+            if let Some(child) = map.get(key) {
+                select_path(child, rest, out);
+            }
+        }
+        (PathStep::Index(idx), Value::Array(items)) => {
+            if let Some(child) = items.get(*idx) {
+                select_path(child, rest, out);
+            }
+        }
+        (PathStep::Wildcard, Value::Array(items)) => items.iter().for_each(|c| select_path(c, rest, out)),
+        (PathStep::Wildcard, Value::Object(map)) => map.values().for_each(|c| select_path(c, rest, out)),
+        _ => {}
+    }
+}
+
+pub(crate) fn collect_vid_values<'v>(value: &'v Value, vid: u64, out: &mut Vec<&'v Value>) {
+    match value {
+        Value::Object(map) => {
+            if map.get("vid").and_then(Value::as_u64) == Some(vid) {
+                if let Some(v) = map.get("value") {
+                    out.push(v);
+                }
+            }
+            map.values().for_each(|c| collect_vid_values(c, vid, out));
+        }
+        Value::Array(items) => items.iter().for_each(|c| collect_vid_values(c, vid, out)),
+        _ => {}
+    }
+}
+
+pub(crate) fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Numbers (and numeric strings) compare numerically, strings lexically
+fn compare(value: &Value, literal: &Literal) -> Option<Ordering> {
+    let number = match value {
+        Value::Number(n) => n.as_i64().map(|i| Literal::Int(i as i128))
+            .or_else(|| n.as_u64().map(|u| Literal::Int(u as i128)))
+            .or_else(|| n.as_f64().map(Literal::Float)),
+        Value::String(s) => parse_number(s),
+        _ => None,
+    };
+
+    match (value, literal) {
+        (Value::Bool(a), Literal::Bool(b)) => Some(a.cmp(b)),
+        (Value::String(a), Literal::Str(b)) => Some(a.as_str().cmp(b.as_str())),
+        (_, Literal::Int(_) | Literal::Float(_)) => match (number?, literal) {
+            (Literal::Int(a), Literal::Int(b)) => Some(a.cmp(b)),
+            (a, b) => as_f64(&a)?.partial_cmp(&as_f64(b)?),
+        },
+        (Value::Number(_), Literal::Str(s)) => compare(value, &parse_number(s)?),
+        _ => None,
+    }
+}
+
+fn as_f64(literal: &Literal) -> Option<f64> {
+    match literal {
+        Literal::Int(i) => Some(*i as f64),
+        Literal::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+fn parse_number(text: &str) -> Option<Literal> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let int = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i128::from_str_radix(hex, 16).ok(),
+        None => digits.parse::<i128>().ok(),
+    };
+    match int {
+        Some(i) => Some(Literal::Int(if negative { -i } else { i })),
+        None => text.parse::<f64>().ok().filter(|f| f.is_finite()).map(Literal::Float),
+    }
+}
+
+// ---------------------------------------------------------------- lexer
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(Literal),
+    Str(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+    DotDot,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "'{}'", s),
+            Token::Number(Literal::Int(i)) => write!(f, "number {}", i),
+            Token::Number(Literal::Float(x)) => write!(f, "number {}", x),
+            Token::Number(_) => write!(f, "number"),
+            Token::Str(s) => write!(f, "string '{}'", s),
+            Token::Op(op) => write!(f, "'{}'", op),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+            Token::DotDot => write!(f, "'..'"),
+        }
+    }
+}
+
+/// Bounds on query size, so a hostile query can not exhaust the stack while
+/// parsing or evaluating
+const MAX_TOKENS: usize = 1024;
+const MAX_DEPTH: usize = 64;
+
+const OPERATORS: [&str; 13] = ["&&", "||", "!=", "!~", "<=", ">=", "==", "=", "<", ">", "~", "!", ".."];
+
+fn lex(text: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let token = match c {
+            b'(' => { i += 1; Token::LParen }
+            b')' => { i += 1; Token::RParen }
+            b',' => { i += 1; Token::Comma }
+            b'\'' | b'"' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match text[i..].chars().next() {
+                        None => return Err(QueryError { pos: start, message: "Unterminated string".into() }),
+                        Some('\\') if text[i + 1..].starts_with(c as char) => {
+                            value.push(c as char);
+                            i += 2;
+                        }
+                        Some(ch) if ch == c as char => {
+                            i += 1;
+                            break;
+                        }
+                        Some(ch) => {
+                            value.push(ch);
+                            i += ch.len_utf8();
+                        }
+                    }
+                }
+                Token::Str(value)
+            }
+            b'0'..=b'9' | b'-' => {
+                i += 1;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.' || bytes[i] == b'_') {
+                    // Stop before a range operator: `5..9`
+                    if bytes[i] == b'.' && bytes.get(i + 1) == Some(&b'.') {
+                        break;
+                    }
+                    i += 1;
+                }
+                let raw = text[start..i].replace('_', "");
+                match parse_number(&raw) {
+                    Some(number) => Token::Number(number),
+                    None => return Err(QueryError { pos: start, message: format!("Invalid number '{}'", raw) }),
+                }
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' || c == b'$' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                Token::Ident(text[start..i].to_string())
+            }
+            _ => match OPERATORS.iter().find(|op| text[i..].starts_with(**op)) {
+                Some(&"..") => { i += 2; Token::DotDot }
+                Some(op) => { i += op.len(); Token::Op(op) }
+                None => {
+                    let ch = text[i..].chars().next().unwrap_or('?');
+                    return Err(QueryError { pos: start, message: format!("Unexpected character '{}'", ch) });
+                }
+            },
+        };
+        if tokens.len() == MAX_TOKENS {
+            return Err(QueryError { pos: start, message: format!("Query has more than {} tokens", MAX_TOKENS) });
+        }
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+// ---------------------------------------------------------------- parser
+
+struct QueryParser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+    /// Open parentheses and NOTs around the current position
+    depth: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(p, _)| *p)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, QueryError> {
+        Err(QueryError { pos: self.offset(), message: message.into() })
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, QueryError> {
+        match self.peek() {
+            Some(token) => self.error(format!("Expected {}, found {}", expected, token)),
+            None => self.error(format!("Expected {}, found end of query", expected)),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(keyword)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token, expected: &str) -> Result<(), QueryError> {
+        if self.eat(&token) { Ok(()) } else { self.unexpected(expected) }
+    }
+
+    fn descend(&mut self) -> Result<(), QueryError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return self.error(format!("Query nests deeper than {} levels", MAX_DEPTH));
+        }
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_and()?;
+        while self.eat_keyword("OR") || self.eat(&Token::Op("||")) {
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_not()?;
+        while self.eat_keyword("AND") || self.eat(&Token::Op("&&")) {
+            left = Expr::And(Box::new(left), Box::new(self.parse_not()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, QueryError> {
+        if self.eat_keyword("NOT") || self.eat(&Token::Op("!")) {
+            self.descend()?;
+            let expr = self.parse_not()?;
+            self.depth -= 1;
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, QueryError> {
+        if self.eat(&Token::LParen) {
+            self.descend()?;
+            let expr = self.parse_or()?;
+            self.expect(Token::RParen, "')'")?;
+            self.depth -= 1;
+            return Ok(expr);
+        }
+
+        if let Some(Token::Ident(name)) = self.peek() {
+            if let Some(expr) = parse_sxfy(name) {
+                self.pos += 1;
+                return Ok(expr);
+            }
+        }
+
+        let operand_pos = self.offset();
+        let operand = self.parse_operand()?;
+        self.parse_predicate(operand, operand_pos)
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, QueryError> {
+        let name = match self.peek() {
+            Some(Token::Ident(name)) => name.to_ascii_lowercase(),
+            _ => return self.unexpected("a field, json(...), vid(...) or text"),
+        };
+        self.pos += 1;
+
+        match name.as_str() {
+            "text" => Ok(Operand::Text),
+            "json" => {
+                self.expect(Token::LParen, "'(' after json")?;
+                let path_pos = self.offset();
+                let path = match self.next() {
+                    Some(Token::Str(path)) => parse_json_path(&path)
+                        .map_err(|message| QueryError { pos: path_pos, message })?,
+                    _ => {
+                        self.pos -= 1;
+                        return self.unexpected("a quoted JSON path such as '$.semantic.kind'");
+                    }
+                };
+                self.expect(Token::RParen, "')'")?;
+                Ok(Operand::Json(path))
+            }
+            "vid" => {
+                self.expect(Token::LParen, "'(' after vid")?;
+                let vid = match self.next() {
+                    Some(Token::Number(Literal::Int(v))) if v >= 0 => v as u64,
+                    _ => {
+                        self.pos -= 1;
+                        return self.unexpected("a VID number");
+                    }
+                };
+                self.expect(Token::RParen, "')'")?;
+                Ok(Operand::Vid(vid))
+            }
+            _ => match Field::from_name(&name) {
+                Some(field) => Ok(Operand::Field(field)),
+                None => {
+                    self.pos -= 1;
+                    self.error(format!("Unknown field '{}'", name))
+                }
+            },
+        }
+    }
+
+    fn parse_predicate(&mut self, operand: Operand, operand_pos: usize) -> Result<Expr, QueryError> {
+        let is_payload = matches!(operand, Operand::Json(_) | Operand::Vid(_));
+
+        if self.eat_keyword("IN") {
+            self.expect(Token::LParen, "'(' after IN")?;
+            let mut items = Vec::new();
+            loop {
+                let lo = self.parse_literal(&operand)?;
+                if self.eat(&Token::DotDot) {
+                    items.push(InItem::Range(lo, self.parse_literal(&operand)?));
+                } else {
+                    items.push(InItem::Value(lo));
+                }
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(Token::RParen, "',' or ')'")?;
+            return Ok(Expr::In(operand, items));
+        }
+
+        if self.eat_keyword("BETWEEN") {
+            let lo = self.parse_literal(&operand)?;
+            if !self.eat_keyword("AND") {
+                return self.unexpected("AND in BETWEEN");
+            }
+            let hi = self.parse_literal(&operand)?;
+            return Ok(Expr::In(operand, vec![InItem::Range(lo, hi)]));
+        }
+
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            _ if is_payload => return Ok(Expr::Exists(operand)),
+            _ => return self.unexpected("a comparison operator, IN or BETWEEN"),
+        };
+
+        if op == "~" || op == "!~" {
+            self.pos += 1;
+            let pattern_pos = self.offset();
+            let pattern = match self.next() {
+                Some(Token::Str(pattern)) => pattern,
+                _ => {
+                    self.pos -= 1;
+                    return self.unexpected("a quoted regular expression");
+                }
+            };
+            let regex = Regex::new(&pattern).map_err(|e| QueryError {
+                pos: pattern_pos,
+                message: format!("Invalid regex: {}", e),
+            })?;
+            return Ok(Expr::Regex(operand, regex, op == "!~"));
+        }
+
+        if matches!(operand, Operand::Text) {
+            return Err(QueryError { pos: operand_pos, message: "text only supports ~ and !~".into() });
+        }
+
+        let cmp = match op {
+            "=" | "==" => CmpOp::Eq,
+            "!=" => CmpOp::Ne,
+            "<" => CmpOp::Lt,
+            "<=" => CmpOp::Le,
+            ">" => CmpOp::Gt,
+            ">=" => CmpOp::Ge,
+            _ => return self.unexpected("a comparison operator"),
+        };
+        self.pos += 1;
+        let literal = self.parse_literal(&operand)?;
+        Ok(Expr::Compare(operand, cmp, literal))
+    }
+
+    fn parse_literal(&mut self, operand: &Operand) -> Result<Literal, QueryError> {
+        let pos = self.offset();
+        let literal = match self.next() {
+            Some(Token::Number(n)) => n,
+            Some(Token::Str(s)) => Literal::Str(s),
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("true") => Literal::Bool(true),
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("false") => Literal::Bool(false),
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("null") => Literal::Null,
+            _ => {
+                self.pos -= 1;
+                return self.unexpected("a value");
+            }
+        };
+
+        // Columns are numeric; directions may be written the way they are logged
+        match (operand, literal) {
+            (Operand::Field(Field::Dir), Literal::Str(s)) => match s.as_str() {
+                "H->E" => Ok(Literal::Int(1)),
+                "E->H" => Ok(Literal::Int(-1)),
+                _ => Err(QueryError { pos, message: format!("Unknown direction '{}', use 'H->E' or 'E->H'", s) }),
+            },
+            (Operand::Field(field), lit @ (Literal::Str(_) | Literal::Bool(_))) => {
+                Err(QueryError { pos, message: format!("{:?} is numeric, {:?} cannot match it", field, lit) })
+            }
+            (_, lit) => Ok(lit),
+        }
+    }
+}
+
+/// `S6F11` -> `s = 6 AND f = 11`, `S6` -> `s = 6`
+fn parse_sxfy(word: &str) -> Option<Expr> {
+    let rest = word.strip_prefix(['S', 's'])?;
+    let (s, f) = match rest.find(['F', 'f']) {
+        Some(idx) => (&rest[..idx], Some(&rest[idx + 1..])),
+        None => (rest, None),
+    };
+    let stream = Expr::Compare(Operand::Field(Field::S), CmpOp::Eq, Literal::Int(s.parse::<u8>().ok()? as i128));
+    match f {
+        None => Some(stream),
+        Some(f) => {
+            let function = Expr::Compare(Operand::Field(Field::F), CmpOp::Eq, Literal::Int(f.parse::<u8>().ok()? as i128));
+            Some(Expr::And(Box::new(stream), Box::new(function)))
+        }
+    }
+}
+
+pub(crate) fn parse_json_path(path: &str) -> Result<Vec<PathStep>, String> {
+    let rest = path.strip_prefix('$').ok_or("JSON path must start with '$'")?;
+    let mut steps = Vec::new();
+    let mut chars = rest.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '.' => {
+                let start = i + 1;
+                let mut end = start;
+                while let Some(&(j, c)) = chars.peek() {
+                    if c == '.' || c == '[' {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                match &rest[start..end] {
+                    "" => return Err(format!("Empty key in JSON path '{}'", path)),
+                    "*" => steps.push(PathStep::Wildcard),
+                    key => steps.push(PathStep::Key(key.to_string())),
+                }
+            }
+            '[' => {
+                let close = rest[i..].find(']').ok_or_else(|| format!("Unclosed '[' in JSON path '{}'", path))? + i;
+                let inner = rest[i + 1..close].trim();
+                let step = if inner == "*" {
+                    PathStep::Wildcard
+                } else if let Ok(idx) = inner.parse::<usize>() {
+                    PathStep::Index(idx)
+                } else if inner.len() >= 2 && (inner.starts_with('\'') && inner.ends_with('\'')
+                    || inner.starts_with('"') && inner.ends_with('"'))
+                {
+                    PathStep::Key(inner[1..inner.len() - 1].to_string())
+                } else {
+                    return Err(format!("Invalid segment '[{}]' in JSON path '{}'", inner, path));
+                };
+                steps.push(step);
+                while chars.peek().is_some_and(|&(j, _)| j <= close) {
+                    chars.next();
+                }
+            }
+            _ => return Err(format!("Unexpected '{}' in JSON path '{}'", c, path)),
+        }
+    }
+
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg;
+    use serde_json::json;
+
+    fn matches(query: &str, msg: &ConvertedMessage, payload: Value) -> bool {
+        Query::parse(query).unwrap().matches(msg, &|| Some(payload.clone()))
+    }
+
+    #[test]
+    fn test_boolean_structure_and_shorthand() {
+        let q = "(S6F11 AND ceid = 201 OR S5F1) AND NOT (S0F5 OR S0F6) AND vid(501) = 'A001'";
+        let payload = json!({"semantic": {"values": [{"vid": 501, "value": "A001"}]}});
+        assert!(matches(q, &msg(6, 11).ceid(201), payload.clone()));
+        assert!(matches(q, &msg(5, 1), payload.clone()));
+        assert!(!matches(q, &msg(6, 11).ceid(202), payload.clone()));
+        assert!(!matches(q, &msg(5, 1), json!({"semantic": {"values": [{"vid": 501, "value": "B"}]}})));
+        assert!(!matches("NOT S0F5 AND s = 0", &msg(0, 5), Value::Null));
+    }
+
+    #[test]
+    fn test_ranges_regex_and_json_paths() {
+        let row = msg(6, 11).ceid(205).at_ns(1_762_160_400_000_000_000).dir(-1);
+        let payload = json!({"secs_tree": {"t": "L", "items": [{"t": "U4", "v": 1}, {"t": "A", "v": "LOT-0042"}]}});
+        assert!(matches("ceid IN (1, 200..210)", &row, Value::Null));
+        assert!(matches("ceid BETWEEN 205 AND 205 AND dir = 'E->H'", &row, Value::Null));
+        assert!(!matches("ceid IN (1..3)", &row, Value::Null));
+        assert!(matches("json('$.secs_tree.items[1].v') ~ '^LOT-\\d+$'", &row, payload.clone()));
+        assert!(matches("json(\"$.secs_tree.items[*]['t']\") = 'U4'", &row, payload.clone()));
+        assert!(matches("json('$.secs_tree.items[0].v') >= 1", &row, payload.clone()));
+        assert!(matches("text ~ '(?i)lot-00'", &row, payload.clone()));
+        assert!(!matches("json('$.semantic')", &row, payload.clone()));
+        assert!(matches("reply_row_id = null AND latency_ns = null", &row, Value::Null));
+        assert!(matches("ts_ns > 1762160399999999999", &row, Value::Null));
+    }
+
+    #[test]
+    fn test_payload_loaded_lazily() {
+        let query = Query::parse("s = 1 AND vid(1) = 2").unwrap();
+        let loaded = std::cell::Cell::new(0);
+        let load = || { loaded.set(loaded.get() + 1); None };
+        assert!(!query.matches(&msg(6, 11), &load));
+        assert_eq!(loaded.get(), 0);
+    }
+
+    #[test]
+    fn test_parse_errors_have_positions() {
+        let err = |q: &str| Query::parse(q).unwrap_err();
+        assert_eq!(err("s = ").pos, 4);
+        assert_eq!(err("foo = 1").message, "Unknown field 'foo'");
+        assert_eq!(err("S6F11 AND (ceid = 1").message, "Expected ')', found end of query");
+        assert_eq!(err("json('$.a') ~ '('").pos, 14);
+        assert!(err("json('a.b')").message.contains("must start with '$'"));
+        assert!(err("dir = 'X'").message.contains("Unknown direction"));
+        assert!(err("s = 'A001'").message.contains("numeric"));
+        assert!(err("text = 'x'").message.contains("~"));
+        assert_eq!(err("s = 1 s = 2").pos, 6);
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let nested = |depth: usize| format!("{}s = 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Query::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Query::parse(&nested(MAX_DEPTH + 1)).unwrap_err().message.contains("nests deeper"));
+        assert!(Query::parse(&format!("{}s = 1", "NOT ".repeat(MAX_DEPTH + 1))).unwrap_err().message.contains("nests deeper"));
+        assert!(Query::parse(&nested(100_000)).unwrap_err().message.contains("tokens"));
+
+        let terms = |n: usize| vec!["s = 1"; n].join(" OR ");
+        assert!(Query::parse(&terms(MAX_TOKENS / 4)).is_ok());
+        let err = Query::parse(&terms(MAX_TOKENS)).unwrap_err();
+        assert!(err.message.contains("more than 1024 tokens"));
+        assert_eq!(err.pos, (MAX_TOKENS / 4) * "s = 1 OR ".len());
+    }
+}
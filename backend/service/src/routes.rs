@@ -1,40 +1,160 @@
 use axum::{
     Router,
     routing::{get, post, delete},
-    response::{IntoResponse, Response},
-    http::{StatusCode, header},
-    extract::{Path, Query, State, Multipart},
+    response::{IntoResponse, Response, sse::{Event, KeepAlive, Sse}},
+    http::{Method, StatusCode, header},
+    extract::{Path, Query, Request, State, Multipart},
+    middleware::{self, Next},
     Json,
 };
-use crate::models::{CreateSessionResponse, SessionMeta, SearchRequest, ConvertedMessage, FilterExpr};
-use crate::storage::{SessionStorage, ingest_messages};
-use crate::arrow_io::{get_arrow_schema, ArrowBuilder};
+use crate::models::{ConformanceSummary, CreateSessionResponse, HsmsTimer, IngestProgress, LiveMode, LiveRequest, SessionInfo, SessionList, SessionPatch, SessionSource, SessionStatus, SessionSummary, SessionTimers, LatencyRequest, LatencyResponse, SessionAlarms, SessionMeta, SessionStates, StateMachine, SearchRequest, SqlRequest, StatsRequest, StatsResponse, ConvertedMessage, FilterExpr};
+use crate::storage::{SessionStorage, IngestOptions, NewSession};
+use crate::arrow_io::{get_arrow_schema, append_bool_columns, read_arrow_chunk, ArrowBuilder, TransactionBuilder};
+use crate::highlight::HighlightMasks;
+use crate::jobs::{IngestJobs, PROGRESS_INTERVAL};
+use crate::latency::LatencyCollector;
+use crate::live::{self, LiveEvent, LiveSessions, Link};
+use crate::sessions::{self, SessionQuery};
+use crate::config::Config;
+use crate::sweeper;
+use crate::text_index::{searchable_text, TextIndex};
+use crate::window::{read_window, PageCursor, WindowQuery};
+use crate::alarms;
+use crate::conformance::Catalog;
+use crate::query;
+use crate::sql;
+use crate::states;
+use crate::timers;
+use crate::dictionary::{self, EquipmentDictionary};
+use crate::stats::{CountCollector, HistogramCollector};
+use crate::tiles;
 use arrow::ipc::writer::StreamWriter;
+use futures::Stream;
+use parser::{Endpoint, ParserOptions};
 use serde::Deserialize;
+use std::convert::Infallible;
 use std::sync::Arc;
-use std::io::Cursor;
-use tracing::{info, debug, warn, error, instrument};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, debug, error, instrument};
+
+/// Response header carrying the cursor for the next `messages.arrow` page
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// Reads closer together than this record one access
+const ACCESS_RESOLUTION: Duration = Duration::from_secs(60);
 
 #[derive(Clone)]
 pub struct AppState {
     pub storage: Arc<SessionStorage>,
+    /// Message-structure catalog every upload is checked against
+    pub catalog: Arc<Catalog>,
+    /// Uploads being ingested in the background
+    pub jobs: Arc<IngestJobs>,
+    /// Sessions being tailed from a running link
+    pub live: Arc<LiveSessions>,
+    pub parser: Arc<ParserOptions>,
+    /// Rows per chunk of new sessions
+    pub chunk_size: usize,
+    pub sql: sql::SqlLimits,
+    /// Endpoints live sessions may connect to or listen on
+    pub live_allowed: Arc<Vec<Endpoint>>,
+}
+
+impl AppState {
+    fn ingest_options(&self, dictionary: EquipmentDictionary) -> IngestOptions {
+        IngestOptions {
+            dictionary,
+            catalog: self.catalog.clone(),
+            parser: self.parser.clone(),
+            chunk_size: self.chunk_size,
+        }
+    }
 }
 
-pub fn create_routes() -> Router {
-    let storage = SessionStorage::new("./data").expect("Failed to create storage");
+/// The service's routes, with sessions stored under `config.data_dir`
+pub fn create_routes(config: &Config) -> Result<Router, String> {
+    let storage = Arc::new(SessionStorage::new(&config.data_dir)
+        .map_err(|e| format!("Cannot use data directory {}: {}", config.data_dir.display(), e))?);
+
+    // Sessions from before segment payload storage stay readable meanwhile
+    let migrating = storage.clone();
+    tokio::task::spawn_blocking(move || match migrating.migrate_legacy_payloads() {
+        Ok(0) => {}
+        Ok(sessions) => info!("Migrated payloads of {} legacy sessions", sessions),
+        Err(e) => error!("Legacy payload migration failed: {}", e),
+    });
+
+    // Built-in E5 catalog, with site overrides (checked by `Config::validate`
+    // when configured)
+    let catalog = Catalog::load(&config.catalog_path())
+        .map_err(|e| format!("Invalid conformance catalog {}", e))?;
+
+    // Neither ingest jobs nor live tails survive a restart
+    match storage.recover_interrupted_sessions() {
+        Ok(0) => {}
+        Ok(sessions) => info!("Settled {} sessions interrupted by the previous run", sessions),
+        Err(e) => error!("Failed to recover interrupted sessions: {}", e),
+    }
+
+    sweeper::spawn(storage.clone(), config.sweep());
+
     let state = AppState {
-        storage: Arc::new(storage),
+        storage,
+        catalog: Arc::new(catalog),
+        jobs: Arc::new(IngestJobs::default()),
+        live: Arc::new(LiveSessions::default()),
+        parser: Arc::new(config.parser_options()),
+        chunk_size: config.chunk_size,
+        sql: config.sql_limits(),
+        live_allowed: Arc::new(config.live_allowed()),
     };
     
-    Router::new()
+    Ok(Router::new()
         .route("/health", get(health_check))
-        .route("/sessions", post(create_session))
+        .route("/sessions", post(create_session).get(list_sessions))
+        .route("/sessions/live", post(start_live))
+        .route("/sessions/:id/live", get(live_stream).delete(stop_live))
         .route("/sessions/:id/meta", get(get_meta))
+        .route("/sessions/:id/events", get(session_events))
         .route("/sessions/:id/messages.arrow", get(get_messages_arrow))
         .route("/sessions/:id/search", post(search_messages))
+        .route("/sessions/:id/stats", post(get_stats))
+        .route("/sessions/:id/latency", post(get_latency))
+        .route("/sessions/:id/tiles", get(get_tiles))
+        .route("/sessions/:id/alarms", get(get_alarms))
+        .route("/sessions/:id/states", get(get_states))
+        .route("/sessions/:id/timers", get(get_timers))
+        .route("/sessions/:id/conformance", get(get_conformance))
+        .route("/sessions/:id/transactions", get(get_transactions))
+        .route("/sessions/:id/sql", post(run_sql))
         .route("/sessions/:id/payload/:row_id", get(get_payload))
-        .route("/sessions/:id", delete(delete_session))
-        .with_state(state)
+        .route("/sessions/:id", delete(delete_session).patch(patch_session))
+        .route("/profiles", get(list_profiles))
+        .route("/profiles/:id", get(get_profile).put(put_profile))
+        .layer(middleware::from_fn_with_state(state.clone(), track_access))
+        .with_state(state))
+}
+
+/// Mark the session in `/sessions/:id/...` as read, for quota eviction
+async fn track_access(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let session_id = request.uri().path().strip_prefix("/sessions/")
+        .and_then(|rest| rest.split('/').next())
+        .filter(|id| uuid::Uuid::parse_str(id).is_ok());
+    // Deleting must not race a marker into the directory it removes
+    if let Some(session_id) = session_id.filter(|_| request.method() != Method::DELETE) {
+        let now = SystemTime::now();
+        let recent = state.storage.last_accessed(session_id)
+            .and_then(|at| now.duration_since(at).ok())
+            .is_some_and(|age| age < ACCESS_RESOLUTION);
+        if !recent && state.storage.session_path(session_id).is_dir() {
+            if let Err(e) = state.storage.touch_session(session_id, now) {
+                debug!("Failed to record access to session {}: {}", session_id, e);
+            }
+        }
+    }
+    next.run(request).await
 }
 
 async fn health_check() -> impl IntoResponse {
@@ -45,14 +165,31 @@ async fn health_check() -> impl IntoResponse {
 async fn create_session(
     State(state): State<AppState>,
     mut multipart: Multipart,
-) -> Result<Json<CreateSessionResponse>, (StatusCode, String)> {
+) -> Result<(StatusCode, Json<CreateSessionResponse>), (StatusCode, String)> {
     info!("Received file upload request");
 
-    // Get the uploaded file
-    let mut file_data = Vec::new();
+    // Create session; every early return below deletes it again
+    info!("Creating new session");
+    let session = NewSession::create(&state.storage)
+        .map_err(|e| {
+            error!("Failed to create session: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create session: {}", e))
+        })?;
+    let session_id = session.id();
+    info!("Created session: {}", session_id);
+
+    // Spool the uploaded file to disk instead of buffering it
+    let spool_path = state.storage.spool_path(session_id);
     let mut filename = String::new();
+    let mut file_size = 0usize;
+    let mut profile_id: Option<String> = None;
+    let mut dictionary_json = None;
+    let mut name = None;
+    let mut equipment_id = None;
+    let mut tags = String::new();
+    let mut notes = String::new();
 
-    while let Some(field) = multipart.next_field().await
+    while let Some(mut field) = multipart.next_field().await
         .map_err(|e| {
             error!("Multipart error: {}", e);
             (StatusCode::BAD_REQUEST, format!("Multipart error: {}", e))
@@ -62,66 +199,250 @@ async fn create_session(
             filename = field.file_name().unwrap_or("unknown").to_string();
             info!("Receiving file: {}", filename);
 
-            let data = field.bytes().await
+            let mut spool = tokio::fs::File::create(&spool_path).await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to spool upload: {}", e)))?;
+            file_size = 0;
+
+            while let Some(chunk) = field.chunk().await
                 .map_err(|e| {
                     error!("Failed to read file data: {}", e);
                     (StatusCode::BAD_REQUEST, format!("Failed to read file: {}", e))
-                })?;
-            file_data = data.to_vec();
-            info!("File data received: {} bytes", file_data.len());
+                })?
+            {
+                spool.write_all(&chunk).await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to spool upload: {}", e)))?;
+                file_size += chunk.len();
+            }
+            spool.flush().await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to spool upload: {}", e)))?;
+            info!("File data received: {} bytes", file_size);
+        } else if field.name() == Some("profile") {
+            profile_id = Some(field.text().await
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to read profile: {}", e)))?);
+        } else if field.name() == Some("dictionary") {
+            dictionary_json = Some(field.bytes().await
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to read dictionary: {}", e)))?);
+        } else if let Some(field_name @ ("name" | "equipment_id" | "tags" | "notes")) = field.name() {
+            let field_name = field_name.to_string();
+            let text = field.text().await
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to read {}: {}", field_name, e)))?;
+            match field_name.as_str() {
+                "name" => name = Some(text),
+                "equipment_id" => equipment_id = Some(text),
+                "tags" => tags = text,
+                _ => notes = text,
+            }
         }
     }
 
-    if file_data.is_empty() {
+    if file_size == 0 {
         error!("No file data provided in request");
         return Err((StatusCode::BAD_REQUEST, "No file provided".to_string()));
     }
 
-    // Create session
-    info!("Creating new session");
-    let session_id = state.storage.create_session()
-        .map_err(|e| {
-            error!("Failed to create session: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create session: {}", e))
-        })?;
-    info!("Created session: {}", session_id);
+    let tags = sessions::parse_tags(&tags)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
 
-    // Use parser registry to auto-detect format
-    let cursor = Cursor::new(file_data);
-    let registry = parser::ParserRegistry::new();
+    // Equipment profile first, then the per-session dictionary on top
+    let mut dictionary = EquipmentDictionary::default();
+    let profile_id = profile_id.as_deref().map(str::trim).filter(|id| !id.is_empty());
+    if let Some(profile_id) = profile_id {
+        let profile = load_profile(&state.storage, profile_id)
+            .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Unknown profile: {}", profile_id)))?;
+        dictionary.merge(profile);
+    }
+    if let Some(json) = dictionary_json {
+        let overrides = serde_json::from_slice::<EquipmentDictionary>(&json)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid dictionary: {}", e)))?;
+        dictionary.merge(overrides);
+    }
 
+    // Parse and ingest in the background; messages stream from the spool file
     info!("Starting parse with filename hint: {}", filename);
-    let parsed = registry.parse_with_hint(Box::new(cursor), &filename)
-        .map_err(|e| {
-            error!("Parse error for file '{}': {}", filename, e);
-            (StatusCode::BAD_REQUEST, format!("Parse error: {}", e))
-        })?;
+    let info = SessionInfo {
+        name: non_empty(name).unwrap_or_else(|| filename.clone()),
+        source: SessionSource::Upload,
+        filename: filename.clone(),
+        created_ns: now_ns(),
+        size_bytes: file_size as u64,
+        equipment_id: non_empty(equipment_id).or_else(|| profile_id.map(String::from)),
+        profile: profile_id.map(String::from),
+        tags,
+        notes,
+        pinned: false,
+    };
+    state.storage.write_info(session_id, &info)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write session info: {}", e)))?;
+    state.storage.write_pending_meta(session_id, SessionStatus::Ingesting, None)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write meta: {}", e)))?;
+    let options = state.ingest_options(dictionary);
+    let session_id = session.keep();
+    state.jobs.start(state.storage.clone(), session_id.clone(), spool_path, filename, options);
 
-    info!("Successfully parsed {} messages", parsed.len());
+    Ok((StatusCode::ACCEPTED, Json(CreateSessionResponse { session_id })))
+}
 
-    debug!("Converting parsed messages to internal format");
-    let messages: Vec<ConvertedMessage> = parsed.into_iter()
-        .enumerate()
-        .map(|(idx, msg)| ConvertedMessage::from_parsed(msg, idx as u32))
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| {
-            error!("Message conversion error: {}", e);
-            (StatusCode::BAD_REQUEST, format!("Conversion error: {}", e))
-        })?;
+fn non_empty(text: Option<String>) -> Option<String> {
+    text.map(|text| text.trim().to_string()).filter(|text| !text.is_empty())
+}
 
-    info!("Converted {} messages, starting ingestion", messages.len());
+fn now_ns() -> i64 {
+    chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0)
+}
 
-    // Ingest messages
-    ingest_messages(&state.storage, &session_id, messages.into_iter())
-        .map_err(|e| {
-            error!("Ingest failed for session {}: {}", session_id, e);
-            (StatusCode::INTERNAL_SERVER_ERROR, format!("Ingest failed: {}", e))
-        })?;
+fn load_profile(storage: &SessionStorage, profile_id: &str) -> Option<EquipmentDictionary> {
+    dictionary::is_valid_profile_id(profile_id)
+        .then(|| storage.read_profile(profile_id).ok())
+        .flatten()
+}
+
+#[instrument(skip(state, request), fields(mode = ?request.mode, address = %request.address))]
+async fn start_live(
+    State(state): State<AppState>,
+    Json(request): Json<LiveRequest>,
+) -> Result<Json<CreateSessionResponse>, (StatusCode, String)> {
+    let tags = sessions::normalize_tags(request.tags)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let mut dictionary = EquipmentDictionary::default();
+    let profile_id = request.profile.as_deref().map(str::trim).filter(|id| !id.is_empty());
+    if let Some(profile_id) = profile_id {
+        let profile = load_profile(&state.storage, profile_id)
+            .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Unknown profile: {}", profile_id)))?;
+        dictionary.merge(profile);
+    }
+    if let Some(overrides) = request.dictionary {
+        dictionary.merge(overrides);
+    }
+
+    let addresses = live::resolve_allowed(&request.address, &state.live_allowed).await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Cannot resolve {}: {}", request.address, e)))?
+        .ok_or_else(|| (StatusCode::FORBIDDEN, format!("Address not allowed for live sessions: {}", request.address)))?;
+    let link = match request.mode {
+        LiveMode::Connect => Link::connect(addresses).await
+            .map_err(|e| format!("Cannot connect to {}: {}", request.address, e)),
+        LiveMode::Listen => Link::listen(&addresses).await
+            .map_err(|e| format!("Cannot listen on {}: {}", request.address, e)),
+    }
+    .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let session = NewSession::create(&state.storage)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create session: {}", e)))?;
+    let info = SessionInfo {
+        name: non_empty(request.name).unwrap_or_else(|| request.address.clone()),
+        source: SessionSource::Live,
+        filename: request.address.clone(),
+        created_ns: now_ns(),
+        size_bytes: 0,
+        equipment_id: non_empty(request.equipment_id).or_else(|| profile_id.map(String::from)),
+        profile: profile_id.map(String::from),
+        tags,
+        notes: String::new(),
+        pinned: false,
+    };
+    state.storage.write_info(session.id(), &info)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write session info: {}", e)))?;
+    let options = state.ingest_options(dictionary);
+    let session_id = session.keep();
+    state.live.start(state.storage.clone(), session_id.clone(), link, options);
+    info!("Started live session {}", session_id);
 
-    info!("Successfully ingested messages for session: {}", session_id);
     Ok(Json(CreateSessionResponse { session_id }))
 }
 
+/// Server-sent events: `rows` (a JSON array of new rows, `id` = last row_id)
+/// after every checkpoint, `lagged` (batches missed by a slow client), then
+/// `finished` (the final meta) or `failed` (the error)
+async fn live_stream(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let events = state.live.subscribe(&session_id)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Session {} is not live", session_id)))?;
+
+    let stream = futures::stream::unfold(Some(events), |events| async move {
+        let mut events = events?;
+        let (event, events) = match events.recv().await {
+            Ok(LiveEvent::Rows(rows)) => {
+                let last = rows.last().map(|row| row.row_id).unwrap_or_default();
+                (json_event("rows", &*rows).id(last.to_string()), Some(events))
+            }
+            Ok(LiveEvent::Finished(meta)) => (json_event("finished", &*meta), None),
+            Ok(LiveEvent::Failed(e)) => (Event::default().event("failed").data(e), None),
+            Err(RecvError::Lagged(missed)) => (Event::default().event("lagged").data(missed.to_string()), Some(events)),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), events))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Server-sent events on a session's ingest: `progress` (an `IngestProgress`)
+/// whenever it moved, then `ready` (the meta) or `failed` (the error). A
+/// finished session gets its final event at once; a live session reports
+/// its rows and chunks until the tail is stopped.
+async fn session_events(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    if state.jobs.progress(&session_id).is_none() {
+        state.storage.read_meta(&session_id)
+            .map_err(|e| (StatusCode::NOT_FOUND, format!("Session not found: {}", e)))?;
+    }
+
+    let stream = futures::stream::unfold(Some(None), move |last: Option<Option<IngestProgress>>| {
+        let (state, session_id) = (state.clone(), session_id.clone());
+        async move {
+            let last = last?;
+            loop {
+                let progress = match state.jobs.progress(&session_id) {
+                    Some(progress) => progress,
+                    None => match state.storage.read_meta(&session_id) {
+                        Ok(meta) if meta.status == SessionStatus::Ready => {
+                            return Some((Ok(json_event("ready", &meta)), None));
+                        }
+                        Ok(meta) if meta.status == SessionStatus::Failed => {
+                            let error = meta.error.unwrap_or_default();
+                            return Some((Ok(Event::default().event("failed").data(error)), None));
+                        }
+                        // Live, or an upload whose job is about to register
+                        Ok(meta) => IngestProgress {
+                            bytes_read: 0,
+                            bytes_total: 0,
+                            rows: meta.row_count as u64,
+                            chunks: meta.chunks.len() as u64,
+                        },
+                        Err(e) => {
+                            return Some((Ok(Event::default().event("failed").data(format!("Session not found: {}", e))), None));
+                        }
+                    },
+                };
+                if last.as_ref() != Some(&progress) {
+                    return Some((Ok(json_event("progress", &progress)), Some(Some(progress))));
+                }
+                tokio::time::sleep(PROGRESS_INTERVAL).await;
+            }
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+fn json_event(name: &str, value: &impl serde::Serialize) -> Event {
+    Event::default().event(name).json_data(value)
+        .unwrap_or_else(|e| Event::default().event("failed").data(e.to_string()))
+}
+
+/// Stop tailing and finish the session
+async fn stop_live(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<Json<SessionMeta>, (StatusCode, String)> {
+    match state.live.stop(&session_id).await {
+        Some(Ok(meta)) => Ok(Json(meta)),
+        Some(Err(e)) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Live ingest failed: {}", e))),
+        None => Err((StatusCode::NOT_FOUND, format!("Session {} is not live", session_id))),
+    }
+}
+
 async fn get_meta(
     State(state): State<AppState>,
     Path(session_id): Path<String>,
@@ -140,8 +461,9 @@ struct MessagesQuery {
     to_ns: i64,
     #[serde(default = "default_limit")]
     limit: usize,
+    /// Opaque token from a previous page's `X-Next-Cursor` header
     #[serde(default)]
-    cursor: usize,
+    cursor: Option<String>,
 }
 
 fn default_limit() -> usize {
@@ -155,7 +477,7 @@ async fn get_messages_arrow(
     Query(query): Query<MessagesQuery>,
 ) -> Result<Response, (StatusCode, String)> {
     info!("Fetching messages for session: {}", session_id);
-    debug!("Query params: from_ns={}, to_ns={}, limit={}, cursor={}",
+    debug!("Query params: from_ns={}, to_ns={}, limit={}, cursor={:?}",
         query.from_ns, query.to_ns, query.limit, query.cursor);
 
     // Read all chunks and concatenate
@@ -167,47 +489,53 @@ async fn get_messages_arrow(
 
     info!("Found {} chunks for session {}", chunks.len(), session_id);
     
-    let mut all_batches = Vec::new();
-    
-    for chunk_path in chunks {
-        let file = std::fs::File::open(chunk_path)
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read chunk: {}", e)))?;
-        
-        let reader = arrow::ipc::reader::StreamReader::try_new(file, None)
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read Arrow: {}", e)))?;
-        
-        for batch_result in reader {
-            let batch = batch_result
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read batch: {}", e)))?;
-            all_batches.push(batch);
-        }
+    if query.limit == 0 {
+        return Err((StatusCode::BAD_REQUEST, "limit must be positive".to_string()));
     }
+    let cursor = match query.cursor.as_deref() {
+        Some(token) => PageCursor::decode(token).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        None => PageCursor::default(),
+    };
+    
+    // Chunk stats let the window skip chunks outside the time range
+    let stats = state.storage.read_meta(&session_id)
+        .map(|meta| meta.chunks)
+        .unwrap_or_default();
+    
+    let window_query = WindowQuery {
+        from_ns: query.from_ns,
+        to_ns: query.to_ns,
+        limit: query.limit,
+        cursor,
+    };
+    let window = read_window(&chunks, &stats, &window_query)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read window: {}", e)))?;
+    debug!("Window holds {} batches, next cursor: {:?}", window.batches.len(), window.next);
     
-    // Apply filters and limits
-    // For now, just concatenate and return (filtering will be added in search endpoint)
-    let schema = get_arrow_schema();
+    let schema = window.batches.first()
+        .map(|b| b.schema())
+        .unwrap_or_else(get_arrow_schema);
     let mut buffer = Vec::new();
     {
         let mut writer = StreamWriter::try_new(&mut buffer, &schema)
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create writer: {}", e)))?;
         
-        let mut count = 0;
-        for batch in all_batches {
-            if count >= query.limit {
-                break;
-            }
-            writer.write(&batch)
+        for batch in &window.batches {
+            writer.write(batch)
                 .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write batch: {}", e)))?;
-            count += batch.num_rows();
         }
         
         writer.finish()
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to finish writer: {}", e)))?;
     }
     
-    Ok(Response::builder()
+    let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/vnd.apache.arrow.stream")
+        .header(header::CONTENT_TYPE, "application/vnd.apache.arrow.stream");
+    if let Some(next) = window.next {
+        response = response.header(NEXT_CURSOR_HEADER, next.encode());
+    }
+    Ok(response
         .body(axum::body::Body::from(buffer))
         .unwrap())
 }
@@ -232,8 +560,25 @@ async fn search_messages(
     debug!("Processing {} chunks for search", chunks.len());
     
     let mut builder = ArrowBuilder::new();
+
+    // Highlight flags are computed against the whole session, not just the filtered rows
+    let mut highlights = match &search_req.highlight {
+        Some(expr) => Some(HighlightMasks::new(expr, &state.storage, &session_id)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Highlight failed: {}", e)))?),
+        None => None,
+    };
+    
+    let row_query = parse_row_query(search_req.query.as_deref())?;
+
+    let text_query = search_req.filter.text.to_lowercase();
     
     for chunk_path in chunks {
+        let candidates = text_candidates(&chunk_path, &text_query)?;
+        if candidates.as_ref().is_some_and(|c| c.is_empty()) {
+            // Nothing in this chunk can match
+            continue;
+        }
+        
         let file = std::fs::File::open(chunk_path)
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read chunk: {}", e)))?;
         
@@ -245,21 +590,29 @@ async fn search_messages(
                 .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read batch: {}", e)))?;
             
             // Apply filters with storage for text search
-            let filtered = apply_filter(&batch, &search_req.filter, Some(&state.storage), Some(&session_id))
+            let filtered = apply_filter(&batch, &search_req.filter, Some(&state.storage), Some(&session_id), candidates.as_deref(), row_query.as_ref())
                 .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Filter failed: {}", e)))?;
             
             for msg in filtered {
+                if let Some(highlights) = highlights.as_mut() {
+                    highlights.push(&msg);
+                }
                 builder.push(&msg);
             }
         }
     }
     
     // Build result batch
-    let result_batch = builder.build_batch()
+    let mut result_batch = builder.build_batch()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to build batch: {}", e)))?;
+
+    if let Some(highlights) = highlights {
+        result_batch = append_bool_columns(&result_batch, highlights.into_columns())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to build batch: {}", e)))?;
+    }
     
     // Serialize to Arrow IPC
-    let schema = get_arrow_schema();
+    let schema = result_batch.schema();
     let mut buffer = Vec::new();
     {
         let mut writer = StreamWriter::try_new(&mut buffer, &schema)
@@ -279,6 +632,122 @@ async fn search_messages(
         .unwrap())
 }
 
+/// Parse the optional boolean `query` of a request; syntax errors are 400s
+fn parse_row_query(text: Option<&str>) -> Result<Option<query::Query>, (StatusCode, String)> {
+    match text.map(str::trim) {
+        Some(text) if !text.is_empty() => query::Query::parse(text)
+            .map(Some)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string())),
+        _ => Ok(None),
+    }
+}
+
+/// Rows of a chunk the text index says may match; None means check every row
+fn text_candidates(chunk_path: &std::path::Path, text_query: &str) -> Result<Option<Vec<u32>>, (StatusCode, String)> {
+    if text_query.is_empty() {
+        return Ok(None);
+    }
+    let index = TextIndex::read(&chunk_path.with_extension("tix"))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read text index: {}", e)))?;
+    match index {
+        Some(index) => index.candidates(text_query)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read text index: {}", e))),
+        None => Ok(None),
+    }
+}
+
+/// Counts and a time histogram of the rows matching a filter
+#[instrument(skip(state, stats_req), fields(session_id = %session_id))]
+async fn get_stats(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(stats_req): Json<StatsRequest>,
+) -> Result<Json<StatsResponse>, (StatusCode, String)> {
+    let meta = state.storage.read_meta(&session_id)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("Session not found: {}", e)))?;
+    let row_query = parse_row_query(stats_req.query.as_deref())?;
+
+    let (from_ns, to_ns) = histogram_range(&meta, &stats_req.filter);
+    let mut histogram = HistogramCollector::new(from_ns, to_ns, stats_req.bucket_ns, stats_req.buckets)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let mut counts = CountCollector::new();
+
+    scan_matching(&state.storage, &session_id, &meta, &stats_req.filter, row_query.as_ref(), |msg| {
+        counts.push(msg);
+        histogram.push(msg.ts_ns);
+    })?;
+
+    Ok(Json(StatsResponse { counts: counts.into_counts(), histogram: histogram.into_histogram() }))
+}
+
+/// Reply-latency percentiles per primary SxFy and direction, and T3 timeouts
+#[instrument(skip(state, latency_req), fields(session_id = %session_id))]
+async fn get_latency(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(latency_req): Json<LatencyRequest>,
+) -> Result<Json<LatencyResponse>, (StatusCode, String)> {
+    let meta = state.storage.read_meta(&session_id)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("Session not found: {}", e)))?;
+    let row_query = parse_row_query(latency_req.query.as_deref())?;
+
+    let (from_ns, to_ns) = histogram_range(&meta, &latency_req.filter);
+    let mut collector = LatencyCollector::new(
+        from_ns, to_ns, latency_req.bucket_ns, latency_req.buckets, latency_req.t3_ms, meta.t_max_ns,
+    ).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    scan_matching(&state.storage, &session_id, &meta, &latency_req.filter, row_query.as_ref(), |msg| {
+        collector.push(msg);
+    })?;
+
+    Ok(Json(collector.into_response()))
+}
+
+/// Time range of a histogram: the filter's, or the session's where unbounded
+fn histogram_range(meta: &SessionMeta, filter: &FilterExpr) -> (i64, i64) {
+    match (filter.time.from_ns, filter.time.to_ns) {
+        (0, 0) => (meta.t_min_ns, meta.t_max_ns),
+        (0, to) => (meta.t_min_ns.min(to), to),
+        (from, 0) => (from, meta.t_max_ns.max(from)),
+        (from, to) => (from, to),
+    }
+}
+
+/// Call `visit` on every row matching `filter` and `row_query`, in storage
+/// order, skipping chunks outside the time range or without text candidates
+fn scan_matching(
+    storage: &SessionStorage,
+    session_id: &str,
+    meta: &SessionMeta,
+    filter: &FilterExpr,
+    row_query: Option<&query::Query>,
+    mut visit: impl FnMut(&ConvertedMessage),
+) -> Result<(), (StatusCode, String)> {
+    let chunks = storage.list_chunks(session_id)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("Session not found: {}", e)))?;
+    let text_query = filter.text.to_lowercase();
+    let time = &filter.time;
+
+    for (chunk_idx, chunk_path) in chunks.iter().enumerate() {
+        if meta.chunks.get(chunk_idx).is_some_and(|c| !c.overlaps(time.from_ns, time.to_ns)) {
+            continue;
+        }
+        let candidates = text_candidates(chunk_path, &text_query)?;
+        if candidates.as_ref().is_some_and(|c| c.is_empty()) {
+            continue;
+        }
+
+        let batches = read_arrow_chunk(chunk_path)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read chunk: {}", e)))?;
+        for batch in batches {
+            let rows = apply_filter(&batch, filter, Some(storage), Some(session_id), candidates.as_deref(), row_query)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Filter failed: {}", e)))?;
+            rows.iter().for_each(&mut visit);
+        }
+    }
+    Ok(())
+}
+
 /// Helper function to load payload from MsgPack for text search
 fn load_payload_for_search(
     storage: &SessionStorage,
@@ -293,6 +762,8 @@ fn apply_filter(
     filter: &FilterExpr,
     storage: Option<&SessionStorage>,
     session_id: Option<&str>,
+    text_candidates: Option<&[u32]>,
+    row_query: Option<&query::Query>,
 ) -> Result<Vec<ConvertedMessage>, Box<dyn std::error::Error>> {
     use arrow::array::*;
     
@@ -304,6 +775,14 @@ fn apply_filter(
     let sysbytes_arr = batch.column(5).as_any().downcast_ref::<UInt32Array>().unwrap();
     let ceid_arr = batch.column(6).as_any().downcast_ref::<UInt32Array>().unwrap();
     let row_id_arr = batch.column(7).as_any().downcast_ref::<UInt32Array>().unwrap();
+    // Reply columns are absent in sessions ingested before pairing existed
+    let reply_row_id_arr = batch.column_by_name("reply_row_id")
+        .and_then(|c| c.as_any().downcast_ref::<UInt32Array>());
+    let latency_ns_arr = batch.column_by_name("latency_ns")
+        .and_then(|c| c.as_any().downcast_ref::<Int64Array>());
+    // Absent before conformance checking; such rows count as unchecked
+    let violation_count_arr = batch.column_by_name("violation_count")
+        .and_then(|c| c.as_any().downcast_ref::<UInt16Array>());
     
     // Prepare text search (case-insensitive)
     let search_text = if !filter.text.is_empty() {
@@ -347,16 +826,25 @@ fn apply_filter(
             continue;
         }
         
-        // Text search in payload
+        let violation_count = violation_count_arr.and_then(|a| a.is_valid(i).then(|| a.value(i)));
+        if let Some(has_violation) = filter.has_violation {
+            // Unchecked rows match neither side
+            if violation_count.is_none_or(|count| (count > 0) != has_violation) {
+                continue;
+            }
+        }
+        
+        // Text search in payload, verifying only rows the text index allows
         if let Some(ref search_term) = search_text {
+            if text_candidates.is_some_and(|c| c.binary_search(&row_id).is_err()) {
+                continue;
+            }
             if let (Some(storage), Some(session_id)) = (storage, session_id) {
                 // Load payload and search
                 match load_payload_for_search(storage, session_id, row_id) {
                     Ok(payload) => {
                         // Convert payload to searchable string
-                        let payload_str = serde_json::to_string(&payload)
-                            .unwrap_or_default()
-                            .to_lowercase();
+                        let payload_str = searchable_text(&payload);
                         
                         // Check if payload contains search term
                         if !payload_str.contains(search_term) {
@@ -371,7 +859,7 @@ fn apply_filter(
             }
         }
         
-        results.push(ConvertedMessage {
+        let msg = ConvertedMessage {
             ts_ns,
             dir,
             s,
@@ -380,13 +868,266 @@ fn apply_filter(
             sysbytes: sysbytes_arr.value(i),
             ceid,
             row_id,
+            reply_row_id: reply_row_id_arr.and_then(|a| a.is_valid(i).then(|| a.value(i))),
+            latency_ns: latency_ns_arr.and_then(|a| a.is_valid(i).then(|| a.value(i))),
+            violation_count,
             body_json: serde_json::Value::Null, // Not needed for search
-        });
+        };
+
+        // Structured query last: column predicates short-circuit before any payload read
+        if let Some(row_query) = row_query {
+            let load = || match (storage, session_id) {
+                (Some(storage), Some(session_id)) => load_payload_for_search(storage, session_id, row_id).ok(),
+                _ => None,
+            };
+            if !row_query.matches(&msg, &load) {
+                continue;
+            }
+        }
+
+        results.push(msg);
     }
     
     Ok(results)
 }
 
+#[derive(Debug, Deserialize)]
+struct TransactionsQuery {
+    #[serde(default)]
+    s: Option<u8>,
+    #[serde(default)]
+    f: Option<u8>,
+}
+
+/// Primary/reply pairs recorded at ingest, e.g. `?s=1&f=3` for every S1F3 and its S1F4
+#[instrument(skip(state))]
+async fn get_transactions(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Query(query): Query<TransactionsQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let chunks = state.storage.list_chunks(&session_id)
+        .map_err(|e| {
+            error!("Session not found or error listing chunks: {}", e);
+            (StatusCode::NOT_FOUND, format!("Session not found: {}", e))
+        })?;
+
+    let filter = FilterExpr {
+        time: Default::default(),
+        dir: 0,
+        s: query.s.into_iter().collect(),
+        f: query.f.into_iter().collect(),
+        ceid: Vec::new(),
+        text: String::new(),
+        has_violation: None,
+    };
+    let mut builder = TransactionBuilder::new();
+
+    for chunk_path in chunks {
+        let batches = read_arrow_chunk(&chunk_path)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read chunk: {}", e)))?;
+
+        for batch in batches {
+            let rows = apply_filter(&batch, &filter, None, None, None, None)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Filter failed: {}", e)))?;
+
+            for msg in rows {
+                builder.push(&msg);
+            }
+        }
+    }
+
+    debug!("Found {} transactions", builder.len());
+
+    let batch = builder.build_batch()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to build batch: {}", e)))?;
+
+    let mut buffer = Vec::new();
+    {
+        let mut writer = StreamWriter::try_new(&mut buffer, &batch.schema())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create writer: {}", e)))?;
+
+        writer.write(&batch)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write batch: {}", e)))?;
+
+        writer.finish()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to finish writer: {}", e)))?;
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/vnd.apache.arrow.stream")
+        .body(axum::body::Body::from(buffer))
+        .unwrap())
+}
+
+#[derive(Debug, Deserialize)]
+struct TilesQuery {
+    level: usize,
+    #[serde(default)]
+    from_ns: i64,
+    #[serde(default)]
+    to_ns: i64,
+}
+
+/// One level of the timeline pyramid, restricted to buckets overlapping the range
+#[instrument(skip(state))]
+async fn get_tiles(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Query(query): Query<TilesQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    if query.level >= tiles::TILE_LEVELS {
+        return Err((StatusCode::BAD_REQUEST, format!("level must be below {}", tiles::TILE_LEVELS)));
+    }
+
+    let tiles_dir = state.storage.tiles_dir(&session_id);
+    if !tiles::level_path(&tiles_dir, query.level).exists() {
+        return Err((StatusCode::NOT_FOUND, "No tiles for this session; re-upload it to build them".to_string()));
+    }
+
+    let batch = tiles::read_tiles(&tiles_dir, query.level, query.from_ns, query.to_ns)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read tiles: {}", e)))?;
+
+    let mut buffer = Vec::new();
+    {
+        let mut writer = StreamWriter::try_new(&mut buffer, &batch.schema())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create writer: {}", e)))?;
+
+        writer.write(&batch)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write batch: {}", e)))?;
+
+        writer.finish()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to finish writer: {}", e)))?;
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/vnd.apache.arrow.stream")
+        .body(axum::body::Body::from(buffer))
+        .unwrap())
+}
+
+#[derive(Debug, Deserialize)]
+struct AlarmsQuery {
+    #[serde(default)]
+    alid: Option<u32>,
+    #[serde(default)]
+    from_ns: i64,
+    #[serde(default)]
+    to_ns: i64,
+}
+
+/// Alarm intervals overlapping the range, with the alarms they belong to
+#[instrument(skip(state))]
+async fn get_alarms(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Query(query): Query<AlarmsQuery>,
+) -> Result<Json<SessionAlarms>, (StatusCode, String)> {
+    let alarms = state.storage.read_alarms(&session_id)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("No alarms for this session: {}", e)))?;
+    Ok(Json(alarms::filter_intervals(alarms, query.alid, query.from_ns, query.to_ns)))
+}
+
+#[derive(Debug, Deserialize)]
+struct StatesQuery {
+    #[serde(default)]
+    machine: Option<StateMachine>,
+    #[serde(default)]
+    from_ns: i64,
+    #[serde(default)]
+    to_ns: i64,
+}
+
+/// Link, communication and control state intervals overlapping the range
+#[instrument(skip(state))]
+async fn get_states(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Query(query): Query<StatesQuery>,
+) -> Result<Json<SessionStates>, (StatusCode, String)> {
+    let states = state.storage.read_states(&session_id)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("No states for this session: {}", e)))?;
+    Ok(Json(states::filter_states(states, query.machine, query.from_ns, query.to_ns)))
+}
+
+#[derive(Debug, Deserialize)]
+struct TimersQuery {
+    #[serde(default)]
+    timer: Option<HsmsTimer>,
+    #[serde(default)]
+    from_ns: i64,
+    #[serde(default)]
+    to_ns: i64,
+}
+
+/// HSMS timer violations starting within the range
+#[instrument(skip(state))]
+async fn get_timers(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Query(query): Query<TimersQuery>,
+) -> Result<Json<SessionTimers>, (StatusCode, String)> {
+    let timers = state.storage.read_timers(&session_id)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("No timer checks for this session: {}", e)))?;
+    Ok(Json(timers::filter_violations(timers, query.timer, query.from_ns, query.to_ns)))
+}
+
+/// Conformance violations of the session, tallied per SxFy and rule
+#[instrument(skip(state))]
+async fn get_conformance(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<Json<ConformanceSummary>, (StatusCode, String)> {
+    let summary = state.storage.read_conformance(&session_id)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("No conformance summary for this session: {}", e)))?;
+    Ok(Json(summary))
+}
+
+/// Read-only SQL over the session's `messages` table, returned as Arrow IPC
+#[instrument(skip(state, request), fields(session_id = %session_id))]
+async fn run_sql(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(request): Json<SqlRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    debug!("SQL: {}", request.sql);
+
+    let ctx = sql::session_context(state.storage.clone(), &session_id, &state.sql)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("Session not found: {}", e)))?;
+
+    let (schema, batches) = sql::run_query(&ctx, &request.sql, state.sql.max_rows).await
+        .map_err(|e| {
+            if sql::is_query_error(&e) {
+                (StatusCode::BAD_REQUEST, format!("SQL error: {}", e))
+            } else {
+                error!("SQL execution failed: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, format!("SQL execution failed: {}", e))
+            }
+        })?;
+
+    let mut buffer = Vec::new();
+    {
+        let mut writer = StreamWriter::try_new(&mut buffer, &schema)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create writer: {}", e)))?;
+
+        for batch in &batches {
+            writer.write(batch)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write batch: {}", e)))?;
+        }
+
+        writer.finish()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to finish writer: {}", e)))?;
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/vnd.apache.arrow.stream")
+        .body(axum::body::Body::from(buffer))
+        .unwrap())
+}
+
 async fn get_payload(
     State(state): State<AppState>,
     Path((session_id, row_id)): Path<(String, u32)>,
@@ -397,12 +1138,172 @@ async fn get_payload(
     Ok(Json(payload))
 }
 
+async fn list_sessions(
+    State(state): State<AppState>,
+    Query(query): Query<SessionQuery>,
+) -> Result<Json<SessionList>, (StatusCode, String)> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || sessions::list_sessions(&storage, &query).map_err(|e| e.to_string()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Listing failed: {}", e)))?
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// Rename, retag or annotate a session
+async fn patch_session(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(patch): Json<SessionPatch>,
+) -> Result<Json<SessionSummary>, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, format!("Session not found: {}", session_id));
+    if uuid::Uuid::parse_str(&session_id).is_err() {
+        return Err(not_found());
+    }
+    let mut info = sessions::summarize(&state.storage, &session_id).ok_or_else(not_found)?.info;
+    sessions::apply_patch(&mut info, patch)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    state.storage.write_info(&session_id, &info)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write session info: {}", e)))?;
+    info!("Updated session {}", session_id);
+    sessions::summarize(&state.storage, &session_id).map(Json).ok_or_else(not_found)
+}
+
 async fn delete_session(
     State(state): State<AppState>,
     Path(session_id): Path<String>,
 ) -> Result<StatusCode, (StatusCode, String)> {
+    // An ingest job deletes its session itself once it has stopped writing
+    if state.jobs.cancel(&session_id) {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    // The tail writes into the directory: stop it first
+    let _ = state.live.stop(&session_id).await;
     state.storage.delete_session(&session_id)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to delete: {}", e)))?;
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => (StatusCode::NOT_FOUND, e.to_string()),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to delete: {}", e)),
+        })?;
     
     Ok(StatusCode::NO_CONTENT)
 }
+
+async fn list_profiles(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    let ids = state.storage.list_profiles()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to list profiles: {}", e)))?;
+    Ok(Json(ids))
+}
+
+async fn get_profile(
+    State(state): State<AppState>,
+    Path(profile_id): Path<String>,
+) -> Result<Json<EquipmentDictionary>, (StatusCode, String)> {
+    if !dictionary::is_valid_profile_id(&profile_id) {
+        return Err((StatusCode::BAD_REQUEST, format!("Invalid profile id: {}", profile_id)));
+    }
+    let profile = state.storage.read_profile(&profile_id)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("Profile not found: {}", e)))?;
+    Ok(Json(profile))
+}
+
+/// Create or replace an equipment profile's dictionary
+async fn put_profile(
+    State(state): State<AppState>,
+    Path(profile_id): Path<String>,
+    Json(profile): Json<EquipmentDictionary>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if !dictionary::is_valid_profile_id(&profile_id) {
+        return Err((StatusCode::BAD_REQUEST, format!("Invalid profile id: {}", profile_id)));
+    }
+    state.storage.write_profile(&profile_id, &profile)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to store profile: {}", e)))?;
+    info!("Stored profile {}", profile_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use axum::body::Body;
+    use axum::extract::FromRequest;
+
+    const BOUNDARY: &str = "hsms-test";
+
+    fn state(dir: &TempDir) -> AppState {
+        AppState {
+            storage: Arc::new(SessionStorage::new(dir).unwrap()),
+            catalog: Arc::new(Catalog::default()),
+            jobs: Arc::new(IngestJobs::default()),
+            live: Arc::new(LiveSessions::default()),
+            parser: Arc::new(ParserOptions::default()),
+            chunk_size: crate::arrow_io::CHUNK_SIZE,
+            sql: sql::SqlLimits::default(),
+            live_allowed: Arc::new(Vec::new()),
+        }
+    }
+
+    fn file_part(file: &str) -> String {
+        format!("--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"log.ndjson\"\r\n\r\n{file}")
+    }
+
+    fn tags_part(tags: &str) -> String {
+        format!("\r\n--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"tags\"\r\n\r\n{tags}\r\n--{BOUNDARY}--\r\n")
+    }
+
+    async fn upload(state: AppState, body: Body) -> Result<String, (StatusCode, String)> {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/sessions")
+            .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={BOUNDARY}"))
+            .body(body)
+            .unwrap();
+        let multipart = Multipart::from_request(request, &()).await.unwrap();
+        create_session(State(state), multipart).await.map(|(_, Json(response))| response.session_id)
+    }
+
+    fn entries(dir: &std::path::Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    #[tokio::test]
+    async fn test_failed_upload_leaves_no_session() {
+        let dir = TempDir::new("hsms-routes");
+        let state = state(&dir);
+        let fixture = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/../../fixtures/pairs.ndjson")).unwrap();
+
+        // The client goes away while the file is being spooled
+        let (parts, body) = futures::channel::mpsc::unbounded::<Result<String, std::io::Error>>();
+        let request = tokio::spawn(upload(state.clone(), Body::from_stream(body)));
+        parts.unbounded_send(Ok(file_part(&fixture))).unwrap();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while entries(&dir) == 0 || std::fs::read_dir(&*dir).unwrap()
+                .all(|session| !session.unwrap().path().join("upload.spool").exists())
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+        parts.unbounded_send(Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "client went away"))).unwrap();
+        let (status, message) = request.await.unwrap().unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(message.starts_with("Failed to read file"), "{}", message);
+        assert_eq!(entries(&dir), 0);
+
+        // Spooled, then rejected for a tag over the length limit
+        let body = format!("{}{}", file_part(&fixture), tags_part(&"x".repeat(100)));
+        let (status, _) = upload(state.clone(), Body::from(body)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(entries(&dir), 0);
+
+        // Kept once its job has started
+        let body = format!("{}{}", file_part(&fixture), tags_part(""));
+        let session_id = upload(state.clone(), Body::from(body)).await.unwrap();
+        assert_eq!(entries(&dir), 1);
+        while state.jobs.progress(&session_id).is_some() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(state.storage.read_meta(&session_id).unwrap().status, SessionStatus::Ready);
+    }
+}
@@ -0,0 +1,67 @@
+//! Reading decoded SECS-II bodies (`secs_tree`, see `parser::secs2`):
+//! `{"t":"L","items":[...]}` for lists, `{"t":"U4","v":201}` for scalars and
+//! base64 text for binary items.
+
+use base64::Engine;
+use serde_json::Value;
+
+/// The message's `secs_tree`, if it has one
+pub fn body_tree(body_json: &Value) -> Option<&Value> {
+    body_json.get("secs_tree")
+}
+
+pub fn list_items(item: &Value) -> Option<&Vec<Value>> {
+    if item.get("t")?.as_str()? != "L" {
+        return None;
+    }
+    item.get("items")?.as_array()
+}
+
+/// A list of exactly `len` items
+pub fn list_of(item: &Value, len: usize) -> Option<&Vec<Value>> {
+    list_items(item).filter(|items| items.len() == len)
+}
+
+/// Plain JSON for an item: lists become arrays, single-element arrays scalars
+pub fn item_value(item: &Value) -> Value {
+    match list_items(item) {
+        Some(items) => Value::Array(items.iter().map(item_value).collect()),
+        None => item.get("v").cloned().unwrap_or(Value::Null),
+    }
+}
+
+/// A numeric ID item
+pub fn item_id(item: &Value) -> Option<u32> {
+    match item_value(item) {
+        Value::Array(values) if values.len() == 1 => values[0].as_u64(),
+        value => value.as_u64(),
+    }.and_then(|v| u32::try_from(v).ok())
+}
+
+/// A one-byte code such as an ACK or ALCD: binary, or integer from some exporters
+pub fn item_code(item: &Value) -> Option<u8> {
+    let value = item.get("v")?;
+    if item.get("t")?.as_str()? == "B" {
+        let bytes = base64::engine::general_purpose::STANDARD.decode(value.as_str()?).ok()?;
+        return bytes.first().copied();
+    }
+    let code = match value {
+        Value::Array(values) => values.first()?.as_u64(),
+        value => value.as_u64(),
+    };
+    code.and_then(|v| u8::try_from(v).ok())
+}
+
+/// A boolean item (`BOOLEAN`, or a 0/1 code)
+pub fn item_bool(item: &Value) -> Option<bool> {
+    match item.get("v")? {
+        Value::Bool(b) => Some(*b),
+        Value::Array(values) if values.len() == 1 && values[0].is_boolean() => values[0].as_bool(),
+        _ => item_code(item).map(|code| code != 0),
+    }
+}
+
+/// An ASCII/JIS-8 item
+pub fn item_text(item: &Value) -> Option<&str> {
+    item.get("v")?.as_str().filter(|_| matches!(item.get("t").and_then(Value::as_str), Some("A" | "J")))
+}
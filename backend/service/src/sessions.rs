@@ -0,0 +1,286 @@
+//! The session catalog behind `GET /sessions` and `PATCH /sessions/:id`.
+//!
+//! Each session keeps its catalog entry in `info.json` (name, origin, tags,
+//! notes); status, row count and time range come from `meta.json`, so the
+//! listing never disagrees with the session itself. Sessions from before the
+//! catalog are listed under their id, created when their meta was written.
+
+use crate::models::{SessionInfo, SessionList, SessionPatch, SessionSource, SessionStatus, SessionSummary};
+use crate::storage::SessionStorage;
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_LIMIT: usize = 50;
+pub const MAX_LIMIT: usize = 1000;
+const MAX_TAG_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionSort {
+    #[default]
+    Created,
+    Name,
+    Rows,
+    Size,
+    /// First message of the log
+    Start,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// `GET /sessions` query
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SessionQuery {
+    /// Case-insensitive substring of the name, file name, equipment id, notes or a tag
+    #[serde(default)]
+    pub q: Option<String>,
+    /// Comma-separated tags the session must all have
+    #[serde(default)]
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub equipment_id: Option<String>,
+    #[serde(default)]
+    pub status: Option<SessionStatus>,
+    #[serde(default)]
+    pub source: Option<SessionSource>,
+    #[serde(default)]
+    pub pinned: Option<bool>,
+    /// Creation time range (0 = unbounded)
+    #[serde(default)]
+    pub created_from_ns: i64,
+    #[serde(default)]
+    pub created_to_ns: i64,
+    /// Sessions whose log overlaps `[from_ns, to_ns]` (0 = unbounded)
+    #[serde(default)]
+    pub from_ns: i64,
+    #[serde(default)]
+    pub to_ns: i64,
+    #[serde(default)]
+    pub sort: SessionSort,
+    /// Default: ascending by name, newest/largest first otherwise
+    #[serde(default)]
+    pub order: Option<SortOrder>,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Catalog entry of one session; `None` if it has neither info nor meta yet
+pub fn summarize(storage: &SessionStorage, session_id: &str) -> Option<SessionSummary> {
+    let meta = storage.read_meta(session_id).ok();
+    let info = match storage.read_info(session_id) {
+        Ok(info) => info,
+        Err(_) if meta.is_some() => SessionInfo {
+            name: session_id.to_string(),
+            created_ns: storage.meta_modified(session_id).map_or(0, unix_ns),
+            ..Default::default()
+        },
+        Err(_) => return None,
+    };
+    let meta = meta.unwrap_or_else(|| {
+        let mut meta = crate::arrow_io::MetaCollector::new().into_meta();
+        meta.status = SessionStatus::Ingesting;
+        meta
+    });
+    Some(SessionSummary {
+        session_id: session_id.to_string(),
+        info,
+        status: meta.status,
+        live: meta.live,
+        error: meta.error,
+        parser: meta.parser,
+        row_count: meta.row_count,
+        t_min_ns: meta.t_min_ns,
+        t_max_ns: meta.t_max_ns,
+    })
+}
+
+pub fn list_sessions(storage: &SessionStorage, query: &SessionQuery) -> Result<SessionList, Box<dyn std::error::Error>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit > MAX_LIMIT {
+        return Err(format!("limit must be at most {}", MAX_LIMIT).into());
+    }
+    let needle = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()).map(str::to_lowercase);
+    let tags = query.tags.as_deref().map(parse_tags).transpose()?.unwrap_or_default();
+
+    let mut sessions: Vec<SessionSummary> = storage.list_session_ids()?.iter()
+        .filter_map(|id| summarize(storage, id))
+        .filter(|s| needle.as_deref().is_none_or(|needle| matches_text(s, needle)))
+        .filter(|s| tags.iter().all(|tag| s.info.tags.contains(tag)))
+        .filter(|s| query.equipment_id.as_ref().is_none_or(|id| s.info.equipment_id.as_ref() == Some(id)))
+        .filter(|s| query.status.is_none_or(|status| s.status == status))
+        .filter(|s| query.source.is_none_or(|source| s.info.source == source))
+        .filter(|s| query.pinned.is_none_or(|pinned| s.info.pinned == pinned))
+        .filter(|s| query.created_from_ns == 0 || s.info.created_ns >= query.created_from_ns)
+        .filter(|s| query.created_to_ns == 0 || s.info.created_ns <= query.created_to_ns)
+        .filter(|s| {
+            (query.from_ns == 0 && query.to_ns == 0)
+                || (s.row_count > 0
+                    && (query.from_ns == 0 || s.t_max_ns >= query.from_ns)
+                    && (query.to_ns == 0 || s.t_min_ns <= query.to_ns))
+        })
+        .collect();
+
+    let order = query.order.unwrap_or(match query.sort {
+        SessionSort::Name => SortOrder::Asc,
+        _ => SortOrder::Desc,
+    });
+    sessions.sort_by(|a, b| {
+        let ordering = match query.sort {
+            SessionSort::Created => a.info.created_ns.cmp(&b.info.created_ns),
+            SessionSort::Name => a.info.name.to_lowercase().cmp(&b.info.name.to_lowercase()),
+            SessionSort::Rows => a.row_count.cmp(&b.row_count),
+            SessionSort::Size => a.info.size_bytes.cmp(&b.info.size_bytes),
+            SessionSort::Start => a.t_min_ns.cmp(&b.t_min_ns),
+        };
+        let ordering = if order == SortOrder::Desc { ordering.reverse() } else { ordering };
+        // Stable paging across equal keys
+        ordering.then_with(|| a.session_id.cmp(&b.session_id))
+    });
+
+    let total = sessions.len();
+    let sessions = sessions.into_iter().skip(query.offset).take(limit).collect();
+    Ok(SessionList { total, sessions })
+}
+
+fn matches_text(summary: &SessionSummary, needle: &str) -> bool {
+    let info = &summary.info;
+    [info.name.as_str(), info.filename.as_str(), info.notes.as_str(), info.equipment_id.as_deref().unwrap_or("")]
+        .into_iter()
+        .chain(info.tags.iter().map(String::as_str))
+        .any(|text| text.to_lowercase().contains(needle))
+}
+
+/// Comma-separated tags, trimmed, without empties or duplicates
+pub fn parse_tags(tags: &str) -> Result<Vec<String>, String> {
+    normalize_tags(tags.split(',').map(String::from).collect())
+}
+
+/// Trimmed, sorted and deduplicated; a tag may not hold a comma
+pub fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut tags: Vec<String> = tags.iter()
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| {
+            if tag.contains(',') {
+                Err(format!("Tag may not contain a comma: {}", tag))
+            } else if tag.len() > MAX_TAG_LEN {
+                Err(format!("Tag longer than {} bytes: {}", MAX_TAG_LEN, tag))
+            } else {
+                Ok(tag.to_string())
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    tags.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()).then_with(|| a.cmp(b)));
+    tags.dedup();
+    Ok(tags)
+}
+
+/// Apply a `PATCH /sessions/:id` body
+pub fn apply_patch(info: &mut SessionInfo, patch: SessionPatch) -> Result<(), String> {
+    if let Some(name) = patch.name {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("name may not be empty".to_string());
+        }
+        info.name = name.to_string();
+    }
+    if let Some(tags) = patch.tags {
+        info.tags = normalize_tags(tags)?;
+    }
+    if let Some(notes) = patch.notes {
+        info.notes = notes;
+    }
+    if let Some(equipment_id) = patch.equipment_id {
+        let equipment_id = equipment_id.trim();
+        info.equipment_id = (!equipment_id.is_empty()).then(|| equipment_id.to_string());
+    }
+    if let Some(pinned) = patch.pinned {
+        info.pinned = pinned;
+    }
+    Ok(())
+}
+
+/// Unix ns of a file time (0 before the epoch)
+pub fn unix_ns(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_list_filters_sorts_and_pages() {
+        let dir = TempDir::new("hsms-sessions");
+        let storage = SessionStorage::new(&dir).unwrap();
+        std::fs::create_dir_all(dir.join("profiles")).unwrap();
+
+        let mut ids = Vec::new();
+        for (i, (name, tags, rows)) in [
+            ("etcher-3 nightly.ndjson", vec!["etch", "Night"], 100),
+            ("cmp.sml", vec!["cmp"], 5),
+            ("Etcher-1.csv", vec!["etch"], 40),
+        ].into_iter().enumerate() {
+            let id = storage.create_session().unwrap();
+            storage.write_info(&id, &SessionInfo {
+                name: name.to_string(),
+                filename: name.to_string(),
+                created_ns: (i as i64 + 1) * 1_000,
+                size_bytes: rows * 10,
+                tags: normalize_tags(tags.into_iter().map(String::from).collect()).unwrap(),
+                ..Default::default()
+            }).unwrap();
+            let mut meta = crate::arrow_io::MetaCollector::new().into_meta();
+            meta.row_count = rows as usize;
+            meta.t_min_ns = i as i64 * 100;
+            meta.t_max_ns = i as i64 * 100 + 50;
+            storage.write_meta(&id, &meta).unwrap();
+            ids.push(id);
+        }
+        // From before the catalog
+        let legacy = storage.create_session().unwrap();
+        storage.write_pending_meta(&legacy, SessionStatus::Failed, Some("bad".into())).unwrap();
+        // Created, nothing written yet
+        storage.create_session().unwrap();
+
+        let list = |query: SessionQuery| {
+            list_sessions(&storage, &query).unwrap().sessions.into_iter().map(|s| s.session_id).collect::<Vec<_>>()
+        };
+
+        // Newest first by default; the legacy session dates from its meta
+        assert_eq!(list(SessionQuery::default()), vec![legacy.clone(), ids[2].clone(), ids[1].clone(), ids[0].clone()]);
+        assert_eq!(list(SessionQuery { q: Some("ETCHER".into()), ..Default::default() }), vec![ids[2].clone(), ids[0].clone()]);
+        assert_eq!(list(SessionQuery { q: Some("night".into()), ..Default::default() }), vec![ids[0].clone()]);
+        assert_eq!(list(SessionQuery { tags: Some("etch, Night".into()), ..Default::default() }), vec![ids[0].clone()]);
+        assert_eq!(list(SessionQuery { status: Some(SessionStatus::Failed), ..Default::default() }), vec![legacy.clone()]);
+        assert_eq!(list(SessionQuery { from_ns: 120, to_ns: 210, ..Default::default() }), vec![ids[2].clone(), ids[1].clone()]);
+        assert_eq!(list(SessionQuery { sort: SessionSort::Name, status: Some(SessionStatus::Ready), ..Default::default() }),
+            vec![ids[1].clone(), ids[2].clone(), ids[0].clone()]);
+
+        let page = list_sessions(&storage, &SessionQuery {
+            sort: SessionSort::Rows, order: Some(SortOrder::Asc), offset: 1, limit: Some(2), ..Default::default()
+        }).unwrap();
+        assert_eq!(page.total, 4);
+        assert_eq!(page.sessions.iter().map(|s| s.row_count).collect::<Vec<_>>(), vec![5, 40]);
+        assert!(list_sessions(&storage, &SessionQuery { limit: Some(MAX_LIMIT + 1), ..Default::default() }).is_err());
+
+        let mut info = storage.read_info(&ids[1]).unwrap();
+        apply_patch(&mut info, SessionPatch {
+            name: Some(" CMP line 2 ".into()),
+            tags: Some(vec!["b".into(), "a".into(), "b".into(), " ".into()]),
+            equipment_id: Some("CMP-02".into()),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!((info.name.as_str(), info.tags.clone(), info.equipment_id.as_deref()), ("CMP line 2", vec!["a".to_string(), "b".to_string()], Some("CMP-02")));
+        assert!(apply_patch(&mut info, SessionPatch { tags: Some(vec!["a,b".into()]), ..Default::default() }).is_err());
+        assert!(apply_patch(&mut info, SessionPatch { name: Some("".into()), ..Default::default() }).is_err());
+    }
+}
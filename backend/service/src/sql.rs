@@ -0,0 +1,265 @@
+//! SQL over a session (`POST /sessions/:id/sql`), embedded DataFusion.
+//!
+//! The session is the table `messages`, one partition per Arrow chunk, read
+//! only when the plan pulls from it. Payloads are reached through functions
+//! that return text (NULL when absent):
+//!
+//! - `payload(row_id)`: the whole payload as JSON
+//! - `json_get(row_id, '$.path')`: first value at a JSON path (see `query.rs`)
+//! - `vid(row_id, 501)`: the `value` of VID 501 in `semantic`
+//!
+//! Only queries run: DDL, DML and `SET`-style statements are rejected. Each
+//! query gets its own memory pool and a cap on the rows it returns
+//! (`SqlLimits`).
+
+use crate::arrow_io::{get_arrow_schema, read_arrow_chunk};
+use crate::query::{collect_vid_values, parse_json_path, select_path, value_text};
+use crate::storage::SessionStorage;
+use arrow::array::{new_null_array, Array, ArrayRef, AsArray, StringBuilder};
+use arrow::datatypes::{DataType, SchemaRef, UInt32Type, Int64Type};
+use arrow::record_batch::RecordBatch;
+use datafusion::catalog::streaming::StreamingTable;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::memory_pool::FairSpillPool;
+use datafusion::execution::runtime_env::RuntimeEnvBuilder;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::{create_udf, ColumnarValue, ScalarUDF, Volatility};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+use datafusion::prelude::{SQLOptions, SessionConfig, SessionContext};
+use futures::StreamExt;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub const TABLE_NAME: &str = "messages";
+
+/// Bounds on one query
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SqlLimits {
+    /// Memory for sorts, joins and aggregates, which spill to disk beyond it
+    pub memory_bytes: usize,
+    /// Largest result returned; a query yielding more rows fails
+    pub max_rows: usize,
+}
+
+impl Default for SqlLimits {
+    fn default() -> Self {
+        Self { memory_bytes: 512 * 1024 * 1024, max_rows: 1_000_000 }
+    }
+}
+
+/// One chunk file as a table partition
+#[derive(Debug)]
+struct ChunkPartition {
+    schema: SchemaRef,
+    path: PathBuf,
+}
+
+impl PartitionStream for ChunkPartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let schema = self.schema.clone();
+        let path = self.path.clone();
+        let batches = futures::stream::once(async move {
+            tokio::task::spawn_blocking(move || load_chunk(&path, &schema))
+                .await
+                .map_err(|e| DataFusionError::ExecutionJoin(Box::new(e)))?
+        })
+        .flat_map(|result| {
+            let items: Vec<Result<RecordBatch>> = match result {
+                Ok(batches) => batches.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            futures::stream::iter(items)
+        });
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
+
+/// Read a chunk, adding null reply columns to chunks written before pairing
+fn load_chunk(path: &std::path::Path, schema: &SchemaRef) -> Result<Vec<RecordBatch>> {
+    let batches = read_arrow_chunk(path)
+        .map_err(|e| DataFusionError::Execution(format!("Failed to read {}: {}", path.display(), e)))?;
+    batches.into_iter().map(|batch| {
+        if batch.schema() == *schema {
+            return Ok(batch);
+        }
+        let columns: Vec<ArrayRef> = schema.fields().iter()
+            .map(|field| batch.column_by_name(field.name()).cloned()
+                .unwrap_or_else(|| new_null_array(field.data_type(), batch.num_rows())))
+            .collect();
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }).collect()
+}
+
+/// A context with `messages` and the payload functions registered, running
+/// in a memory pool of `limits.memory_bytes`
+pub fn session_context(storage: Arc<SessionStorage>, session_id: &str, limits: &SqlLimits) -> Result<SessionContext, Box<dyn std::error::Error>> {
+    let chunks = storage.list_chunks(session_id)?;
+    let schema = get_arrow_schema();
+    let partitions: Vec<Arc<dyn PartitionStream>> = chunks.into_iter()
+        .map(|path| Arc::new(ChunkPartition { schema: schema.clone(), path }) as Arc<dyn PartitionStream>)
+        .collect();
+
+    let runtime = RuntimeEnvBuilder::new()
+        .with_memory_pool(Arc::new(FairSpillPool::new(limits.memory_bytes)))
+        .build_arc()?;
+    let ctx = SessionContext::new_with_config_rt(SessionConfig::new(), runtime);
+    ctx.register_table(TABLE_NAME, Arc::new(StreamingTable::try_new(schema, partitions)?))?;
+    for udf in payload_functions(storage, session_id) {
+        ctx.register_udf(udf);
+    }
+    Ok(ctx)
+}
+
+/// Plan and run a read-only query, failing once it yields more than
+/// `max_rows` rows
+pub async fn run_query(ctx: &SessionContext, sql: &str, max_rows: usize) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+    let options = SQLOptions::new()
+        .with_allow_ddl(false)
+        .with_allow_dml(false)
+        .with_allow_statements(false);
+    let df = ctx.sql_with_options(sql, options).await?;
+    let mut stream = df.execute_stream().await?;
+    let schema = stream.schema();
+    let mut batches = Vec::new();
+    let mut rows = 0;
+    while let Some(batch) = stream.next().await {
+        let batch = batch?;
+        rows += batch.num_rows();
+        if rows > max_rows {
+            return Err(DataFusionError::ResourcesExhausted(format!(
+                "Query returns more than {} rows; add a LIMIT or aggregate", max_rows
+            )));
+        }
+        batches.push(batch);
+    }
+    Ok((schema, batches))
+}
+
+/// Whether the error is the query's fault (400) rather than the server's
+/// (500); that includes exceeding `SqlLimits`
+pub fn is_query_error(err: &DataFusionError) -> bool {
+    !matches!(
+        err.find_root(),
+        DataFusionError::IoError(_)
+            | DataFusionError::ObjectStore(_)
+            | DataFusionError::ExecutionJoin(_)
+            | DataFusionError::Internal(_)
+            | DataFusionError::External(_)
+    )
+}
+
+type Extract = fn(&Value, &[ArrayRef], usize) -> Result<Option<String>>;
+
+fn payload_functions(storage: Arc<SessionStorage>, session_id: &str) -> Vec<ScalarUDF> {
+    let functions: [(&str, Vec<DataType>, Extract); 3] = [
+        ("payload", vec![DataType::UInt32], |payload, _, _| {
+            Ok(Some(serde_json::to_string(payload).unwrap_or_default()))
+        }),
+        ("json_get", vec![DataType::UInt32, DataType::Utf8], |payload, args, i| {
+            let paths = args[1].as_string::<i32>();
+            if paths.is_null(i) {
+                return Ok(None);
+            }
+            let path = parse_json_path(paths.value(i)).map_err(DataFusionError::Execution)?;
+            let mut out = Vec::new();
+            select_path(payload, &path, &mut out);
+            Ok(out.first().map(|v| value_text(v)))
+        }),
+        ("vid", vec![DataType::UInt32, DataType::Int64], |payload, args, i| {
+            let vids = args[1].as_primitive::<Int64Type>();
+            if vids.is_null(i) || vids.value(i) < 0 {
+                return Ok(None);
+            }
+            let mut out = Vec::new();
+            if let Some(semantic) = payload.get("semantic") {
+                collect_vid_values(semantic, vids.value(i) as u64, &mut out);
+            }
+            Ok(out.first().map(|v| value_text(v)))
+        }),
+    ];
+
+    functions.into_iter().map(|(name, input_types, extract)| {
+        let storage = storage.clone();
+        let session_id = session_id.to_string();
+        let fun = Arc::new(move |args: &[ColumnarValue]| {
+            let arrays = ColumnarValue::values_to_arrays(args)?;
+            let row_ids = arrays[0].as_primitive::<UInt32Type>();
+            // Each call gets one batch of one partition; its rows share the
+            // open index and segment files
+            let mut reader = storage.payload_reader(&session_id).ok();
+            let mut out = StringBuilder::new();
+            for i in 0..row_ids.len() {
+                let text = match (row_ids.is_valid(i), reader.as_mut()) {
+                    (true, Some(reader)) => match reader.read(row_ids.value(i)) {
+                        Ok(payload) => extract(&payload, &arrays, i)?,
+                        Err(_) => None,
+                    },
+                    _ => None,
+                };
+                out.append_option(text);
+            }
+            Ok(ColumnarValue::Array(Arc::new(out.finish())))
+        });
+        create_udf(name, input_types, DataType::Utf8, Volatility::Stable, fun)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use crate::storage::ingest_file;
+    use arrow::array::{Int64Array, StringArray, UInt64Array};
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_sql_over_session() {
+        let dir = TempDir::new("hsms-sql");
+        let storage = Arc::new(SessionStorage::new(&dir).unwrap());
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/pairs.ndjson");
+        let session_id = storage.create_session().unwrap();
+        ingest_file(&storage, &session_id, &fixture, "pairs.ndjson", &Default::default(), &Default::default()).unwrap();
+        let ctx = session_context(storage.clone(), &session_id, &SqlLimits::default()).unwrap();
+        let max_rows = SqlLimits::default().max_rows;
+
+        let (_, batches) = run_query(&ctx, "SELECT s, f, count(*) AS n, max(latency_ns) AS slowest \
+             FROM messages WHERE s = 1 AND f = 3 GROUP BY s, f", max_rows).await.unwrap();
+        assert_eq!(batches[0].column(2).as_any().downcast_ref::<Int64Array>().unwrap().value(0), 3);
+        assert_eq!(batches[0].column(3).as_any().downcast_ref::<Int64Array>().unwrap().value(0), 50_000_000);
+
+        let (_, batches) = run_query(&ctx, "SELECT vid(row_id, 501) AS lot, json_get(row_id, '$.semantic.kind') AS kind \
+             FROM messages WHERE row_id = 1", max_rows).await.unwrap();
+        let column = |i: usize| batches[0].column(i).as_any().downcast_ref::<StringArray>().unwrap().value(0).to_string();
+        assert_eq!(column(0), "A001");
+        assert_eq!(column(1), "VariableResponse");
+
+        let (_, batches) = run_query(&ctx, "SELECT count(*) FROM messages \
+             WHERE date_trunc('minute', to_timestamp_nanos(ts_ns)) = TIMESTAMP '2025-11-03T10:00:00'", max_rows).await.unwrap();
+        let count = batches[0].column(0).as_any().downcast_ref::<Int64Array>()
+            .map(|a| a.value(0) as u64)
+            .or_else(|| batches[0].column(0).as_any().downcast_ref::<UInt64Array>().map(|a| a.value(0)));
+        assert_eq!(count, Some(storage.read_meta(&session_id).unwrap().row_count as u64));
+
+        for bad in ["SELEC 1", "SELECT nope FROM messages", "CREATE TABLE t AS SELECT 1", "DROP TABLE messages"] {
+            let err = run_query(&ctx, bad, max_rows).await.unwrap_err();
+            assert!(is_query_error(&err), "{}: {}", bad, err);
+        }
+
+        // Results over the row cap, and plans over the memory pool, are refused
+        let row_count = storage.read_meta(&session_id).unwrap().row_count;
+        assert!(run_query(&ctx, "SELECT * FROM messages", row_count).await.is_ok());
+        let err = run_query(&ctx, "SELECT * FROM messages", row_count - 1).await.unwrap_err();
+        assert!(is_query_error(&err) && err.to_string().contains("more than"), "{}", err);
+
+        let tiny = SqlLimits { memory_bytes: 1024, ..Default::default() };
+        let ctx = session_context(storage.clone(), &session_id, &tiny).unwrap();
+        let err = run_query(&ctx, "SELECT row_id, payload(row_id) AS p FROM messages ORDER BY p", max_rows).await.unwrap_err();
+        assert!(is_query_error(&err) && matches!(err.find_root(), DataFusionError::ResourcesExhausted(_)), "{}", err);
+    }
+}
@@ -0,0 +1,344 @@
+//! Link, communication and control state machines (`states.json`,
+//! `GET /sessions/:id/states`).
+//!
+//! `StateTracker` replays the log through three machines:
+//!
+//! - link (SEMI E37): `SELECTED` after a successful Select.rsp, `NOT_SELECTED`
+//!   after Deselect.rsp, `NOT_CONNECTED` after Separate.req
+//! - communication (SEMI E30): `COMMUNICATING` after S1F14 with COMMACK 0,
+//!   `NOT_COMMUNICATING` after a denied S1F14 or any link transition
+//! - control (SEMI E30): `HOST_OFFLINE` after S1F16 with OFLACK 0, `ONLINE`
+//!   after S1F18 with ONLACK 0, and `ONLINE_LOCAL` / `ONLINE_REMOTE` /
+//!   `EQUIPMENT_OFFLINE` / `HOST_OFFLINE` from event reports whose CEID name
+//!   says so (e.g. `ControlStateRemote`)
+//!
+//! A machine starts unknown. Traffic that is only possible in one state
+//! (data messages on an unknown link, say) enters it as `inferred`, so logs
+//! that start mid-session still get states. Messages the current state does
+//! not allow are recorded as anomalies; a data message on a link that is not
+//! selected, or before communication is established, also moves that machine
+//! to the state the traffic implies, so a missed transition is flagged once
+//! rather than on every row. Acks missing from the body count as accepted.
+
+use crate::models::{ConvertedMessage, SessionStates, StateAnomaly, StateInterval, StateMachine};
+use crate::secs_tree::{body_tree, item_code, list_items};
+use serde_json::Value;
+
+/// Anomalies listed per session; the rest are only counted
+pub const MAX_ANOMALIES: usize = 100_000;
+
+pub const SELECTED: &str = "SELECTED";
+pub const NOT_SELECTED: &str = "NOT_SELECTED";
+pub const NOT_CONNECTED: &str = "NOT_CONNECTED";
+pub const COMMUNICATING: &str = "COMMUNICATING";
+pub const NOT_COMMUNICATING: &str = "NOT_COMMUNICATING";
+pub const ONLINE: &str = "ONLINE";
+pub const ONLINE_LOCAL: &str = "ONLINE_LOCAL";
+pub const ONLINE_REMOTE: &str = "ONLINE_REMOTE";
+pub const HOST_OFFLINE: &str = "HOST_OFFLINE";
+pub const EQUIPMENT_OFFLINE: &str = "EQUIPMENT_OFFLINE";
+
+fn machine_index(machine: StateMachine) -> usize {
+    match machine {
+        StateMachine::Link => 0,
+        StateMachine::Communication => 1,
+        StateMachine::Control => 2,
+    }
+}
+
+fn is_offline(state: Option<&str>) -> bool {
+    matches!(state, Some(HOST_OFFLINE | EQUIPMENT_OFFLINE))
+}
+
+#[derive(Clone, Default)]
+pub struct StateTracker {
+    intervals: Vec<StateInterval>,
+    /// Open interval of each machine and its state, by `machine_index`
+    current: [Option<(usize, &'static str)>; 3],
+    anomalies: Vec<StateAnomaly>,
+    dropped_anomalies: u64,
+}
+
+impl StateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next message in log order
+    pub fn observe(&mut self, msg: &ConvertedMessage) {
+        self.observe_link(msg);
+        if msg.s > 0 {
+            self.observe_communication(msg);
+            self.observe_control(msg);
+        }
+    }
+
+    fn state(&self, machine: StateMachine) -> Option<&'static str> {
+        self.current[machine_index(machine)].map(|(_, state)| state)
+    }
+
+    fn enter(&mut self, machine: StateMachine, state: &'static str, msg: &ConvertedMessage, inferred: bool) {
+        let slot = &mut self.current[machine_index(machine)];
+        if let Some((idx, current)) = *slot {
+            if current == state {
+                return;
+            }
+            self.intervals[idx].to_ns = Some(msg.ts_ns);
+        }
+        *slot = Some((self.intervals.len(), state));
+        self.intervals.push(StateInterval {
+            machine,
+            state: state.to_string(),
+            from_ns: msg.ts_ns,
+            to_ns: None,
+            row_id: msg.row_id,
+            inferred,
+        });
+    }
+
+    fn anomaly(&mut self, machine: StateMachine, msg: &ConvertedMessage, message: String) {
+        if self.anomalies.len() >= MAX_ANOMALIES {
+            self.dropped_anomalies += 1;
+            return;
+        }
+        self.anomalies.push(StateAnomaly {
+            machine,
+            state: self.state(machine).unwrap_or("UNKNOWN").to_string(),
+            ts_ns: msg.ts_ns,
+            row_id: msg.row_id,
+            message,
+        });
+    }
+
+    fn observe_link(&mut self, msg: &ConvertedMessage) {
+        let link = StateMachine::Link;
+        let state = self.state(link);
+        let next = match (msg.s, msg.f) {
+            (0, 1) if state == Some(SELECTED) => {
+                self.anomaly(link, msg, "Select.req while already SELECTED".into());
+                None
+            }
+            (0, 2) if control_status(msg) == 0 => {
+                if state == Some(SELECTED) {
+                    self.anomaly(link, msg, "Select.rsp accepted while already SELECTED".into());
+                }
+                Some(SELECTED)
+            }
+            (0, 3) if state.is_some() && state != Some(SELECTED) => {
+                self.anomaly(link, msg, format!("Deselect.req while {}", state.unwrap_or_default()));
+                None
+            }
+            (0, 4) if control_status(msg) == 0 => Some(NOT_SELECTED),
+            (0, 9) => Some(NOT_CONNECTED),
+            (0, _) => None,
+            _ => {
+                match state {
+                    None => self.enter(link, SELECTED, msg, true),
+                    Some(SELECTED) => {}
+                    Some(other) => {
+                        self.anomaly(link, msg, format!("S{}F{} while {}", msg.s, msg.f, other));
+                        self.enter(link, SELECTED, msg, true);
+                    }
+                }
+                None
+            }
+        };
+
+        if let Some(next) = next {
+            if state != Some(next) {
+                self.enter(link, next, msg, false);
+                // A new or lost selection restarts GEM communication
+                self.enter(StateMachine::Communication, NOT_COMMUNICATING, msg, false);
+            }
+        }
+    }
+
+    fn observe_communication(&mut self, msg: &ConvertedMessage) {
+        let communication = StateMachine::Communication;
+        match (msg.s, msg.f) {
+            (1, 13) | (9, _) | (_, 0) => {}
+            (1, 14) => {
+                let next = match first_code(msg).unwrap_or(0) {
+                    0 => COMMUNICATING,
+                    _ => NOT_COMMUNICATING,
+                };
+                self.enter(communication, next, msg, false);
+            }
+            _ => match self.state(communication) {
+                None => self.enter(communication, COMMUNICATING, msg, true),
+                Some(NOT_COMMUNICATING) => {
+                    self.anomaly(communication, msg, format!("S{}F{} while NOT_COMMUNICATING", msg.s, msg.f));
+                    self.enter(communication, COMMUNICATING, msg, true);
+                }
+                Some(_) => {}
+            },
+        }
+    }
+
+    fn observe_control(&mut self, msg: &ConvertedMessage) {
+        let control = StateMachine::Control;
+        let state = self.state(control);
+        match (msg.s, msg.f) {
+            (1, 16) if first_code(msg).unwrap_or(0) == 0 => self.enter(control, HOST_OFFLINE, msg, false),
+            (1, 18) => match first_code(msg).unwrap_or(0) {
+                0 if !matches!(state, Some(ONLINE | ONLINE_LOCAL | ONLINE_REMOTE)) => {
+                    self.enter(control, ONLINE, msg, false);
+                }
+                // ONLACK 2: already online
+                2 if is_offline(state) => {
+                    self.anomaly(control, msg, format!("S1F18 says already online while {}", state.unwrap_or_default()));
+                }
+                2 if state.is_none() => self.enter(control, ONLINE, msg, true),
+                _ => {}
+            },
+            (6, 11 | 13) => match control_event(msg) {
+                Some(next) => self.enter(control, next, msg, false),
+                None if msg.dir == -1 && is_offline(state) => {
+                    self.anomaly(control, msg, format!("Event report while {}", state.unwrap_or_default()));
+                }
+                None => {}
+            },
+            (2, 41 | 49) if msg.dir == 1 && state == Some(ONLINE_LOCAL) => {
+                self.anomaly(control, msg, format!("Remote command S2F{} while ONLINE_LOCAL", msg.f));
+            }
+            // Offline equipment only accepts S1F13 and S1F17 from the host
+            (1, 13 | 15 | 17) | (9, _) => {}
+            (s, f) if msg.dir == 1 && f % 2 == 1 && is_offline(state) => {
+                self.anomaly(control, msg, format!("Host sent S{}F{} while {}", s, f, state.unwrap_or_default()));
+            }
+            _ => {}
+        }
+    }
+
+    /// Close the log: every machine's last state stays open-ended
+    pub fn finish(self) -> SessionStates {
+        SessionStates {
+            intervals: self.intervals,
+            anomalies: self.anomalies,
+            dropped_anomalies: self.dropped_anomalies,
+        }
+    }
+}
+
+/// Select/Deselect status from an HSMS control row (0 = success)
+fn control_status(msg: &ConvertedMessage) -> u64 {
+    msg.body_json.pointer("/semantic/status").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// COMMACK / OFLACK / ONLACK: the body, or its first item
+fn first_code(msg: &ConvertedMessage) -> Option<u8> {
+    let tree = body_tree(&msg.body_json)?;
+    match list_items(tree) {
+        Some(items) => items.first().and_then(item_code),
+        None => item_code(tree),
+    }
+}
+
+/// Control state announced by an event report's CEID name
+fn control_event(msg: &ConvertedMessage) -> Option<&'static str> {
+    let name = msg.body_json.pointer("/semantic/ceid_name")?.as_str()?.to_ascii_lowercase();
+    if name.contains("offline") {
+        return Some(if name.contains("host") { HOST_OFFLINE } else { EQUIPMENT_OFFLINE });
+    }
+    if !["online", "control", "state"].iter().any(|word| name.contains(word)) {
+        return None;
+    }
+    if name.contains("remote") {
+        Some(ONLINE_REMOTE)
+    } else if name.contains("local") {
+        Some(ONLINE_LOCAL)
+    } else {
+        None
+    }
+}
+
+/// Intervals of one machine (or all) overlapping `[from_ns, to_ns]` (0 = unbounded),
+/// with the anomalies inside the range
+pub fn filter_states(states: SessionStates, machine: Option<StateMachine>, from_ns: i64, to_ns: i64) -> SessionStates {
+    let in_machine = |m: StateMachine| machine.is_none_or(|machine| m == machine);
+    let intervals = states.intervals.into_iter()
+        .filter(|i| in_machine(i.machine))
+        .filter(|i| from_ns == 0 || i.to_ns.is_none_or(|to| to >= from_ns))
+        .filter(|i| to_ns == 0 || i.from_ns <= to_ns)
+        .collect();
+    let anomalies = states.anomalies.into_iter()
+        .filter(|a| in_machine(a.machine))
+        .filter(|a| (from_ns == 0 || a.ts_ns >= from_ns) && (to_ns == 0 || a.ts_ns <= to_ns))
+        .collect();
+    SessionStates { intervals, anomalies, dropped_anomalies: states.dropped_anomalies }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg;
+    use serde_json::json;
+
+    /// Rows 1 µs apart; odd functions are W-bit primaries
+    fn row(row_id: u32, dir: i8, s: u8, f: u8, body_json: Value) -> ConvertedMessage {
+        msg(s, f).row(row_id).at_ns(row_id as i64 * 1_000).dir(dir).wbit(f % 2).sysbytes(row_id).body(body_json)
+    }
+
+    fn ack(code: &str) -> Value {
+        json!({"secs_tree": {"t": "B", "v": code}})
+    }
+
+    fn event(row_id: u32, ceid_name: &str) -> ConvertedMessage {
+        row(row_id, -1, 6, 11, json!({"semantic": {"kind": "EventReport", "ceid_name": ceid_name}}))
+    }
+
+    #[test]
+    fn test_state_machines() {
+        let messages = [
+            row(0, -1, 6, 11, json!({})),                                   // mid-session: inferred
+            row(1, 0, 0, 9, json!({})),                                     // Separate.req
+            row(2, 1, 6, 12, json!({})),                                    // data while NOT_CONNECTED
+            row(3, 1, 0, 3, json!({})),
+            row(4, -1, 0, 4, json!({"semantic": {"status": 0}})),           // deselected
+            row(5, 1, 0, 1, json!({})),
+            row(6, -1, 0, 2, json!({"semantic": {"status": 0}})),           // selected
+            row(7, 1, 1, 13, json!({})),
+            row(8, -1, 1, 14, json!({"secs_tree": {"t": "L", "items": [{"t": "B", "v": "AA=="}, {"t": "L", "items": []}]}})),
+            row(9, 1, 1, 17, json!({})),
+            row(10, -1, 1, 18, ack("AA==")),
+            event(11, "ControlStateLocal"),
+            row(12, 1, 2, 41, json!({})),                                   // remote command while local
+            row(13, 1, 1, 15, json!({})),
+            row(14, -1, 1, 16, ack("AA==")),
+            row(15, 1, 2, 13, json!({})),                                   // host data while offline
+            row(16, 1, 1, 17, json!({})),
+            row(17, -1, 1, 18, ack("Ag==")),                                // "already online" while offline
+        ];
+        let mut tracker = StateTracker::new();
+        for msg in &messages {
+            tracker.observe(msg);
+        }
+        let states = tracker.finish();
+
+        let of = |machine: StateMachine| states.intervals.iter()
+            .filter(|i| i.machine == machine)
+            .map(|i| (i.state.as_str(), i.row_id, i.inferred))
+            .collect::<Vec<_>>();
+        assert_eq!(of(StateMachine::Link), vec![
+            (SELECTED, 0, true), (NOT_CONNECTED, 1, false), (SELECTED, 2, true), (NOT_SELECTED, 4, false), (SELECTED, 6, false),
+        ]);
+        assert_eq!(of(StateMachine::Communication), vec![
+            (COMMUNICATING, 0, true), (NOT_COMMUNICATING, 1, false), (COMMUNICATING, 2, true),
+            (NOT_COMMUNICATING, 4, false), (COMMUNICATING, 8, false),
+        ]);
+        assert_eq!(of(StateMachine::Control), vec![
+            (ONLINE, 10, false), (ONLINE_LOCAL, 11, false), (HOST_OFFLINE, 14, false),
+        ]);
+        assert_eq!(states.intervals[0].to_ns, Some(1_000));
+        assert_eq!(states.intervals.last().unwrap().to_ns, None);
+
+        let anomalies = states.anomalies.iter().map(|a| (a.row_id, a.state.as_str())).collect::<Vec<_>>();
+        assert_eq!(anomalies, vec![
+            (2, NOT_CONNECTED), (2, NOT_COMMUNICATING), (12, ONLINE_LOCAL), (15, HOST_OFFLINE), (17, HOST_OFFLINE),
+        ]);
+
+        let filtered = filter_states(states, Some(StateMachine::Control), 12_000, 0);
+        assert_eq!(filtered.intervals.len(), 2);
+        assert_eq!(filtered.anomalies.len(), 3);
+    }
+}
@@ -0,0 +1,145 @@
+//! Aggregations for `/stats` and `meta.json`: message counts and a
+//! fixed-width time histogram, so overviews never ship rows to the browser.
+
+use crate::models::{ConvertedMessage, Histogram, MessageCounts, SxFyCount};
+use std::collections::HashMap;
+
+/// Upper bound on histogram buckets per request
+pub const MAX_BUCKETS: usize = 100_000;
+pub const DEFAULT_BUCKETS: usize = 200;
+
+#[derive(Clone, Default)]
+pub struct CountCollector {
+    total: u64,
+    by_dir: HashMap<i8, u64>,
+    by_s: HashMap<u8, u64>,
+    by_f: HashMap<u8, u64>,
+    by_sxfy: HashMap<(u8, u8), u64>,
+    by_ceid: HashMap<u32, u64>,
+}
+
+impl CountCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, msg: &ConvertedMessage) {
+        self.total += 1;
+        *self.by_dir.entry(msg.dir).or_default() += 1;
+        *self.by_s.entry(msg.s).or_default() += 1;
+        *self.by_f.entry(msg.f).or_default() += 1;
+        *self.by_sxfy.entry((msg.s, msg.f)).or_default() += 1;
+        if msg.ceid > 0 {
+            *self.by_ceid.entry(msg.ceid).or_default() += 1;
+        }
+    }
+
+    pub fn into_counts(self) -> MessageCounts {
+        let mut by_sxfy: Vec<SxFyCount> = self.by_sxfy.into_iter()
+            .map(|((s, f), count)| SxFyCount { s, f, count })
+            .collect();
+        by_sxfy.sort_unstable_by_key(|c| (c.s, c.f));
+
+        MessageCounts {
+            total: self.total,
+            by_dir: self.by_dir.into_iter().collect(),
+            by_s: self.by_s.into_iter().collect(),
+            by_f: self.by_f.into_iter().collect(),
+            by_sxfy,
+            by_ceid: self.by_ceid.into_iter().collect(),
+        }
+    }
+}
+
+pub struct HistogramCollector {
+    from_ns: i64,
+    bucket_ns: i64,
+    counts: Vec<u64>,
+}
+
+/// Width and number of buckets covering `[from_ns, to_ns]`: `bucket_ns` wide,
+/// or with `bucket_ns == 0` the range split into `buckets` equal buckets
+pub fn bucket_layout(from_ns: i64, to_ns: i64, bucket_ns: i64, buckets: usize) -> Result<(i64, usize), String> {
+    if to_ns < from_ns {
+        return Err(format!("Empty time range: {} > {}", from_ns, to_ns));
+    }
+    if bucket_ns < 0 {
+        return Err("bucket_ns must be positive".into());
+    }
+    let span = to_ns as i128 - from_ns as i128 + 1;
+    let bucket_ns = match bucket_ns {
+        0 => {
+            if buckets == 0 || buckets > MAX_BUCKETS {
+                return Err(format!("buckets must be between 1 and {}", MAX_BUCKETS));
+            }
+            ((span + buckets as i128 - 1) / buckets as i128).max(1) as i64
+        }
+        width => width,
+    };
+    let count = (span + bucket_ns as i128 - 1) / bucket_ns as i128;
+    if count > MAX_BUCKETS as i128 {
+        return Err(format!("{} ns buckets would produce {} buckets (max {})", bucket_ns, count, MAX_BUCKETS));
+    }
+    Ok((bucket_ns, count as usize))
+}
+
+impl HistogramCollector {
+    /// Buckets as laid out by `bucket_layout`
+    pub fn new(from_ns: i64, to_ns: i64, bucket_ns: i64, buckets: usize) -> Result<Self, String> {
+        let (bucket_ns, count) = bucket_layout(from_ns, to_ns, bucket_ns, buckets)?;
+        Ok(Self { from_ns, bucket_ns, counts: vec![0; count] })
+    }
+
+    pub fn push(&mut self, ts_ns: i64) {
+        if ts_ns < self.from_ns {
+            return;
+        }
+        let idx = ((ts_ns as i128 - self.from_ns as i128) / self.bucket_ns as i128) as usize;
+        if let Some(count) = self.counts.get_mut(idx) {
+            *count += 1;
+        }
+    }
+
+    pub fn into_histogram(self) -> Histogram {
+        Histogram { from_ns: self.from_ns, bucket_ns: self.bucket_ns, counts: self.counts }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg;
+
+    #[test]
+    fn test_counts_and_histogram() {
+        let rows = [
+            msg(6, 11).at_ns(0).dir(-1).ceid(201),
+            msg(6, 12).at_ns(5).dir(1),
+            msg(6, 11).at_ns(10).dir(-1).ceid(202),
+            msg(1, 1).at_ns(99).dir(-1),
+        ];
+        let mut counts = CountCollector::new();
+        let mut hist = HistogramCollector::new(0, 99, 0, 10).unwrap();
+        for row in &rows {
+            counts.push(row);
+            hist.push(row.ts_ns);
+        }
+
+        let counts = counts.into_counts();
+        assert_eq!(counts.total, 4);
+        assert_eq!(counts.by_dir[&-1], 3);
+        assert_eq!(counts.by_s[&6], 3);
+        assert_eq!((counts.by_sxfy[1].s, counts.by_sxfy[1].f, counts.by_sxfy[1].count), (6, 11, 2));
+        assert_eq!(counts.by_ceid.len(), 2);
+        let json = serde_json::to_value(&counts).unwrap();
+        assert_eq!(json["by_dir"]["-1"], 3);
+
+        let hist = hist.into_histogram();
+        assert_eq!(hist.bucket_ns, 10);
+        assert_eq!(hist.counts, vec![2, 1, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        assert_eq!(HistogramCollector::new(0, 99, 7, 0).unwrap().into_histogram().counts.len(), 15);
+        assert!(HistogramCollector::new(0, i64::MAX, 1, 0).is_err());
+        assert!(HistogramCollector::new(10, 0, 0, 10).is_err());
+    }
+}
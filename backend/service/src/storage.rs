@@ -1,14 +1,126 @@
-use crate::arrow_io::{ArrowBuilder, MetaCollector, write_arrow_chunk, CHUNK_SIZE};
-use crate::models::{ConvertedMessage, SessionMeta};
+use crate::alarms::AlarmTracker;
+use crate::conformance::{Catalog, ConformanceCollector};
+use crate::arrow_io::{ArrowBuilder, MetaCollector, patch_reply_columns, read_arrow_chunk, write_arrow_chunk, CHUNK_SIZE};
+use crate::dictionary::{EquipmentDictionary, ReportTracker};
+use crate::models::{ConformanceSummary, ConvertedMessage, IngestProgress, SessionAlarms, SessionInfo, SessionMeta, SessionStates, SessionStatus, SessionTimers};
+use crate::pairing::TransactionPairer;
+use crate::payloads::{self, PayloadReader, PayloadWriter};
+use crate::states::StateTracker;
+use crate::timers::TimerTracker;
+use crate::text_index::TextIndexBuilder;
+use crate::tiles::{self, TileBuilder};
+use parser::ParserOptions;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::Write;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{info, warn};
 use uuid::Uuid;
 
+/// The uploaded data is malformed, as opposed to a storage failure
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct InputError(pub String);
+
+/// How messages are parsed, decoded, checked and chunked during ingest
+#[derive(Debug, Clone)]
+pub struct IngestOptions {
+    pub dictionary: EquipmentDictionary,
+    pub catalog: Arc<Catalog>,
+    pub parser: Arc<ParserOptions>,
+    /// Rows per Arrow chunk
+    pub chunk_size: usize,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        Self {
+            dictionary: EquipmentDictionary::default(),
+            catalog: Arc::default(),
+            parser: Arc::default(),
+            chunk_size: CHUNK_SIZE,
+        }
+    }
+}
+
+/// Counters of an ingest in progress, shared with whoever reports on it
+#[derive(Debug, Default)]
+pub struct IngestCounters {
+    pub bytes_total: AtomicU64,
+    pub bytes_read: AtomicU64,
+    pub rows: AtomicU64,
+    pub chunks: AtomicU64,
+    /// Abandon the ingest at the next message
+    pub cancelled: AtomicBool,
+}
+
+impl IngestCounters {
+    pub fn progress(&self) -> IngestProgress {
+        IngestProgress {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_total: self.bytes_total.load(Ordering::Relaxed),
+            rows: self.rows.load(Ordering::Relaxed),
+            chunks: self.chunks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counts the bytes the parser reads from the upload
+struct CountingReader<R> {
+    inner: R,
+    counters: Arc<IngestCounters>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counters.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
 pub struct SessionStorage {
     base_path: PathBuf,
 }
 
+/// A session being created by a request: deleted on drop unless kept, so a
+/// request that fails partway, or is dropped with its connection, leaves
+/// nothing behind
+pub struct NewSession<'a> {
+    storage: &'a SessionStorage,
+    session_id: String,
+    kept: bool,
+}
+
+impl<'a> NewSession<'a> {
+    pub fn create(storage: &'a SessionStorage) -> std::io::Result<Self> {
+        Ok(Self { storage, session_id: storage.create_session()?, kept: false })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Hand the session over to whatever ingests it
+    pub fn keep(mut self) -> String {
+        self.kept = true;
+        std::mem::take(&mut self.session_id)
+    }
+}
+
+impl Drop for NewSession<'_> {
+    fn drop(&mut self) {
+        if !self.kept {
+            if let Err(e) = self.storage.delete_session(&self.session_id) {
+                warn!("Failed to remove abandoned session {}: {}", self.session_id, e);
+            }
+        }
+    }
+}
+
 impl SessionStorage {
     pub fn new(base_path: impl AsRef<Path>) -> std::io::Result<Self> {
         let base_path = base_path.as_ref().to_path_buf();
@@ -18,20 +130,125 @@ impl SessionStorage {
     
     pub fn create_session(&self) -> std::io::Result<String> {
         let session_id = Uuid::new_v4().to_string();
-        let session_path = self.session_path(&session_id);
-        
+        self.init_session_dir(&session_id)?;
+        Ok(session_id)
+    }
+    
+    fn init_session_dir(&self, session_id: &str) -> std::io::Result<()> {
+        let session_path = self.session_path(session_id);
         fs::create_dir_all(&session_path)?;
         fs::create_dir_all(session_path.join("chunks"))?;
         fs::create_dir_all(session_path.join("payloads"))?;
-        
-        Ok(session_id)
+        Ok(())
+    }
+    
+    /// Meta of a session whose data is not there yet
+    pub fn write_pending_meta(&self, session_id: &str, status: SessionStatus, error: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut meta = MetaCollector::new().into_meta();
+        meta.status = status;
+        meta.error = error;
+        self.write_meta(session_id, &meta)
+    }
+    
+    /// Drop whatever a failed ingest left behind, except the catalog info,
+    /// and record why it failed
+    pub fn fail_session(&self, session_id: &str, error: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let info = self.read_info(session_id).ok();
+        let session_path = self.session_path(session_id);
+        if session_path.exists() {
+            fs::remove_dir_all(&session_path)?;
+        }
+        self.init_session_dir(session_id)?;
+        if let Some(info) = info {
+            self.write_info(session_id, &info)?;
+        }
+        self.write_pending_meta(session_id, SessionStatus::Failed, Some(error.to_string()))
+    }
+    
+    /// Every session directory; `profiles/` and other non-UUID entries are skipped
+    pub fn list_session_ids(&self) -> std::io::Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.base_path)? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(String::from) else { continue };
+            if Uuid::parse_str(&name).is_ok() && entry.file_type()?.is_dir() {
+                ids.push(name);
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+    
+    pub fn write_info(&self, session_id: &str, info: &SessionInfo) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string_pretty(info)?;
+        replace_file(&self.session_path(session_id).join("info.json"), |path| Ok(fs::write(path, json)?))
+    }
+    
+    pub fn read_info(&self, session_id: &str) -> Result<SessionInfo, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(self.session_path(session_id).join("info.json"))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+    
+    /// When `meta.json` was last written, for sessions from before `info.json`
+    pub fn meta_modified(&self, session_id: &str) -> std::io::Result<SystemTime> {
+        fs::metadata(self.session_path(session_id).join("meta.json"))?.modified()
+    }
+    
+    /// False until the request creating the session has set it up
+    pub fn has_meta(&self, session_id: &str) -> bool {
+        self.session_path(session_id).join("meta.json").exists()
+    }
+
+    /// Latest change to the session directory or a file directly in it; an
+    /// upload being spooled keeps it current
+    pub fn last_modified(&self, session_id: &str) -> std::io::Result<SystemTime> {
+        let path = self.session_path(session_id);
+        let mut latest = fs::metadata(&path)?.modified()?;
+        for entry in fs::read_dir(&path)? {
+            latest = latest.max(entry?.metadata()?.modified()?);
+        }
+        Ok(latest)
+    }
+
+    /// Record a read of the session, for quota eviction
+    pub fn touch_session(&self, session_id: &str, at: SystemTime) -> std::io::Result<()> {
+        let marker = fs::File::create(self.session_path(session_id).join("accessed"))?;
+        marker.set_modified(at)
+    }
+    
+    /// When the session was last read, if ever since it was created
+    pub fn last_accessed(&self, session_id: &str) -> Option<SystemTime> {
+        fs::metadata(self.session_path(session_id).join("accessed")).and_then(|m| m.modified()).ok()
+    }
+    
+    /// Bytes the session takes on disk
+    pub fn session_size(&self, session_id: &str) -> std::io::Result<u64> {
+        fn dir_size(path: &Path) -> std::io::Result<u64> {
+            let mut size = 0;
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                size += if metadata.is_dir() { dir_size(&entry.path())? } else { metadata.len() };
+            }
+            Ok(size)
+        }
+        dir_size(&self.session_path(session_id))
     }
     
     pub fn session_path(&self, session_id: &str) -> PathBuf {
         self.base_path.join(session_id)
     }
     
+    /// Where an upload is spooled while it is being ingested
+    pub fn spool_path(&self, session_id: &str) -> PathBuf {
+        self.session_path(session_id).join("upload.spool")
+    }
+    
     pub fn delete_session(&self, session_id: &str) -> std::io::Result<()> {
+        // Only session directories: `profiles/` lives alongside them
+        if Uuid::parse_str(session_id).is_err() {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No session {}", session_id)));
+        }
         let session_path = self.session_path(session_id);
         if session_path.exists() {
             fs::remove_dir_all(session_path)?;
@@ -42,9 +259,7 @@ impl SessionStorage {
     pub fn write_meta(&self, session_id: &str, meta: &SessionMeta) -> Result<(), Box<dyn std::error::Error>> {
         let meta_path = self.session_path(session_id).join("meta.json");
         let json = serde_json::to_string_pretty(meta)?;
-        let mut file = fs::File::create(meta_path)?;
-        file.write_all(json.as_bytes())?;
-        Ok(())
+        replace_file(&meta_path, |path| Ok(fs::write(path, json)?))
     }
     
     pub fn read_meta(&self, session_id: &str) -> Result<SessionMeta, Box<dyn std::error::Error>> {
@@ -54,24 +269,154 @@ impl SessionStorage {
         Ok(meta)
     }
     
-    pub fn write_payload(&self, session_id: &str, row_id: u32, body_json: &serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
-        let payload_path = self.session_path(session_id)
-            .join("payloads")
-            .join(format!("{}.mp", row_id));
-        
-        let msgpack = rmp_serde::to_vec(body_json)?;
-        fs::write(payload_path, msgpack)?;
+    /// Dictionary as of the end of the log: profile, uploaded overrides and
+    /// the report definitions learned from the log
+    pub fn write_dictionary(&self, session_id: &str, dictionary: &EquipmentDictionary) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string_pretty(dictionary)?;
+        replace_file(&self.session_path(session_id).join("dictionary.json"), |path| Ok(fs::write(path, json)?))
+    }
+    
+    pub fn write_alarms(&self, session_id: &str, alarms: &SessionAlarms) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string(alarms)?;
+        replace_file(&self.session_path(session_id).join("alarms.json"), |path| Ok(fs::write(path, json)?))
+    }
+    
+    pub fn read_alarms(&self, session_id: &str) -> Result<SessionAlarms, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(self.session_path(session_id).join("alarms.json"))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+    
+    pub fn write_states(&self, session_id: &str, states: &SessionStates) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string(states)?;
+        replace_file(&self.session_path(session_id).join("states.json"), |path| Ok(fs::write(path, json)?))
+    }
+    
+    pub fn read_states(&self, session_id: &str) -> Result<SessionStates, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(self.session_path(session_id).join("states.json"))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+    
+    pub fn write_timers(&self, session_id: &str, timers: &SessionTimers) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string(timers)?;
+        replace_file(&self.session_path(session_id).join("timers.json"), |path| Ok(fs::write(path, json)?))
+    }
+
+    pub fn read_timers(&self, session_id: &str) -> Result<SessionTimers, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(self.session_path(session_id).join("timers.json"))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn write_conformance(&self, session_id: &str, summary: &ConformanceSummary) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string(summary)?;
+        replace_file(&self.session_path(session_id).join("conformance.json"), |path| Ok(fs::write(path, json)?))
+    }
+
+    pub fn read_conformance(&self, session_id: &str) -> Result<ConformanceSummary, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(self.session_path(session_id).join("conformance.json"))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn profile_path(&self, profile_id: &str) -> PathBuf {
+        self.base_path.join("profiles").join(format!("{}.json", profile_id))
+    }
+    
+    pub fn read_profile(&self, profile_id: &str) -> Result<EquipmentDictionary, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(self.profile_path(profile_id))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+    
+    pub fn write_profile(&self, profile_id: &str, dictionary: &EquipmentDictionary) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.profile_path(profile_id);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(dictionary)?)?;
         Ok(())
     }
     
+    pub fn list_profiles(&self) -> std::io::Result<Vec<String>> {
+        let dir = self.base_path.join("profiles");
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut ids: Vec<String> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                (path.extension().and_then(|e| e.to_str()) == Some("json"))
+                    .then(|| path.file_stem()?.to_str().map(String::from))
+                    .flatten()
+            })
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+    
+    pub fn payloads_dir(&self, session_id: &str) -> PathBuf {
+        self.session_path(session_id).join("payloads")
+    }
+    
+    pub fn payload_writer(&self, session_id: &str) -> std::io::Result<PayloadWriter> {
+        PayloadWriter::create(&self.payloads_dir(session_id))
+    }
+    
     pub fn read_payload(&self, session_id: &str, row_id: u32) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
-        let payload_path = self.session_path(session_id)
-            .join("payloads")
-            .join(format!("{}.mp", row_id));
-        
-        let msgpack = fs::read(payload_path)?;
-        let body_json = rmp_serde::from_slice(&msgpack)?;
-        Ok(body_json)
+        payloads::read_payload(&self.payloads_dir(session_id), row_id)
+    }
+
+    /// For reading many payloads of a session
+    pub fn payload_reader(&self, session_id: &str) -> std::io::Result<PayloadReader> {
+        PayloadReader::open(&self.payloads_dir(session_id))
+    }
+    
+    /// Settle sessions the previous process was still writing: a live tail
+    /// ends as of its last checkpoint, an upload ingest fails, and a session
+    /// whose request never got as far as its meta is deleted
+    pub fn recover_interrupted_sessions(&self) -> std::io::Result<usize> {
+        let mut recovered = 0;
+        for session_id in self.list_session_ids()? {
+            if !self.has_meta(&session_id) {
+                match self.delete_session(&session_id) {
+                    Ok(()) => {
+                        info!("Deleted session {}, never set up", session_id);
+                        recovered += 1;
+                    }
+                    Err(e) => warn!("Failed to delete session {}: {}", session_id, e),
+                }
+                continue;
+            }
+            let Ok(mut meta) = self.read_meta(&session_id) else { continue };
+            let result = if meta.live {
+                meta.live = false;
+                meta.status = SessionStatus::Ready;
+                self.write_meta(&session_id, &meta)
+            } else if meta.status == SessionStatus::Ingesting {
+                self.fail_session(&session_id, "Ingest interrupted by a service restart")
+            } else {
+                continue;
+            };
+            match result {
+                Ok(()) => recovered += 1,
+                Err(e) => warn!("Failed to recover session {}: {}", session_id, e),
+            }
+        }
+        Ok(recovered)
+    }
+    
+    /// Pack every session still using per-row `.mp` payload files
+    pub fn migrate_legacy_payloads(&self) -> std::io::Result<usize> {
+        let mut migrated = 0;
+        for entry in fs::read_dir(&self.base_path)? {
+            let path = entry?.path().join("payloads");
+            if path.is_dir() {
+                match payloads::migrate_legacy(&path) {
+                    Ok(rows) if rows > 0 => migrated += 1,
+                    Ok(_) => {}
+                    Err(e) => warn!("Failed to migrate payloads in {}: {}", path.display(), e),
+                }
+            }
+        }
+        Ok(migrated)
     }
     
     pub fn chunk_path(&self, session_id: &str, chunk_idx: usize) -> PathBuf {
@@ -80,6 +425,16 @@ impl SessionStorage {
             .join(format!("{:03}.arrow", chunk_idx))
     }
     
+    /// Text index written alongside each chunk
+    pub fn text_index_path(&self, session_id: &str, chunk_idx: usize) -> PathBuf {
+        self.chunk_path(session_id, chunk_idx).with_extension("tix")
+    }
+    
+    /// Level-of-detail pyramid, one `level_XX.arrow` per level
+    pub fn tiles_dir(&self, session_id: &str) -> PathBuf {
+        self.session_path(session_id).join("tiles")
+    }
+    
     pub fn list_chunks(&self, session_id: &str) -> std::io::Result<Vec<PathBuf>> {
         let chunks_dir = self.session_path(session_id).join("chunks");
         let mut chunks = Vec::new();
@@ -96,47 +451,351 @@ impl SessionStorage {
     }
 }
 
+/// Parse a spooled upload and ingest it, one message at a time, keeping
+/// `counters` up to date
+pub fn ingest_file(
+    storage: &SessionStorage,
+    session_id: &str,
+    path: &Path,
+    filename: &str,
+    options: &IngestOptions,
+    counters: &Arc<IngestCounters>,
+) -> Result<SessionMeta, Box<dyn std::error::Error>> {
+    counters.bytes_total.store(fs::metadata(path)?.len(), Ordering::Relaxed);
+    let registry = parser::ParserRegistry::with_options(&options.parser);
+    let (path, reader_counters) = (path.to_path_buf(), counters.clone());
+    let (parser, parsed) = registry.parse_with_hint_named(move || {
+        // Parsers that were tried and dropped do not count
+        reader_counters.bytes_read.store(0, Ordering::Relaxed);
+        let inner = fs::File::open(&path)?;
+        Ok(Box::new(CountingReader { inner, counters: reader_counters.clone() }) as Box<dyn Read>)
+    }, filename)
+        .map_err(|e| InputError(format!("Parse error: {}", e)))?;
+
+    let messages = parsed.enumerate().map(|(idx, result)| {
+        if counters.cancelled.load(Ordering::Relaxed) {
+            return Err(InputError("Ingest cancelled".to_string()));
+        }
+        let msg = result.map_err(|e| InputError(format!("Parse error: {}", e)))?;
+        counters.rows.store(idx as u64 + 1, Ordering::Relaxed);
+        ConvertedMessage::from_parsed(msg, idx as u32)
+            .map_err(|e| InputError(format!("Conversion error: {}", e)))
+    });
+
+    let mut meta = ingest_messages(storage, session_id, messages, options, counters)?;
+    if let Some(parser) = parser {
+        meta.parser = Some(parser.to_string());
+        storage.write_meta(session_id, &meta)?;
+    }
+    Ok(meta)
+}
+
 /// Process messages and write to storage
+///
+/// Only the current chunk is held in memory. The first `InputError` aborts
+/// the ingest before any metadata is written. Event reports are decoded with
+/// the options' dictionary plus the report definitions seen so far in the
+/// log; the dictionary as of the end of the log is stored with the session.
+/// HSMS timers are checked with the dictionary's `timers` (E37 defaults
+/// without). Every message is checked against the catalog: the row gets its
+/// `violation_count` and the payload a `violations` list when there are any.
+/// `counters.chunks` follows the chunks written.
 pub fn ingest_messages(
     storage: &SessionStorage,
     session_id: &str,
-    messages: impl Iterator<Item = ConvertedMessage>,
+    messages: impl Iterator<Item = Result<ConvertedMessage, InputError>>,
+    options: &IngestOptions,
+    counters: &Arc<IngestCounters>,
 ) -> Result<SessionMeta, Box<dyn std::error::Error>> {
-    let mut builder = ArrowBuilder::new();
-    let mut meta_collector = MetaCollector::new();
-    let mut chunk_idx = 0;
-    
+    let mut ingest = Ingest::new(storage, session_id, options, counters.clone())?;
     for msg in messages {
+        ingest.push(&mut msg?)?;
+    }
+    ingest.finish()
+}
+
+/// Everything summarized into `meta.json` and the sidecars
+struct Summaries {
+    meta: MetaCollector,
+    reports: ReportTracker,
+    conformance: ConformanceCollector,
+    alarms: AlarmTracker,
+    states: StateTracker,
+    timers: TimerTracker,
+}
+
+impl Summaries {
+    /// Write the sidecars, then `meta.json` last
+    fn write(self, storage: &SessionStorage, session_id: &str, live: bool) -> Result<SessionMeta, Box<dyn std::error::Error>> {
+        let dictionary = self.reports.into_dictionary();
+        if !dictionary.is_empty() {
+            storage.write_dictionary(session_id, &dictionary)?;
+        }
+        storage.write_alarms(session_id, &self.alarms.finish())?;
+        storage.write_states(session_id, &self.states.finish())?;
+        storage.write_timers(session_id, &self.timers.finish())?;
+        storage.write_conformance(session_id, &self.conformance.into_summary())?;
+        write_meta(self.meta, storage, session_id, live)
+    }
+}
+
+fn write_meta(meta: MetaCollector, storage: &SessionStorage, session_id: &str, live: bool) -> Result<SessionMeta, Box<dyn std::error::Error>> {
+    let mut meta = meta.into_meta();
+    meta.tile_levels = (0..tiles::TILE_LEVELS).map(tiles::bucket_ns).collect();
+    meta.live = live;
+    meta.status = if live { SessionStatus::Ingesting } else { SessionStatus::Ready };
+    storage.write_meta(session_id, &meta)?;
+    Ok(meta)
+}
+
+/// An ingest in progress: messages are pushed in log order, and `checkpoint`
+/// makes everything pushed so far readable while more keep coming
+pub struct Ingest<'a> {
+    storage: &'a SessionStorage,
+    session_id: &'a str,
+    catalog: &'a Catalog,
+    chunk_size: usize,
+    builder: ArrowBuilder,
+    summaries: Summaries,
+    pairer: TransactionPairer,
+    payloads: PayloadWriter,
+    text_index: TextIndexBuilder,
+    tile_builder: TileBuilder,
+    counters: Arc<IngestCounters>,
+    chunk_idx: usize,
+    // Replies whose primary sits in an already written chunk: chunk -> (offset, reply, latency)
+    late_replies: BTreeMap<usize, Vec<(usize, u32, i64)>>,
+}
+
+impl<'a> Ingest<'a> {
+    pub fn new(
+        storage: &'a SessionStorage,
+        session_id: &'a str,
+        options: &'a IngestOptions,
+        counters: Arc<IngestCounters>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            storage,
+            session_id,
+            catalog: &options.catalog,
+            chunk_size: options.chunk_size,
+            builder: ArrowBuilder::new(),
+            summaries: Summaries {
+                meta: MetaCollector::new(),
+                reports: ReportTracker::new(options.dictionary.clone()),
+                conformance: ConformanceCollector::new(),
+                alarms: AlarmTracker::new(),
+                states: StateTracker::new(),
+                timers: TimerTracker::new(options.dictionary.timers.clone().unwrap_or_default()),
+            },
+            pairer: TransactionPairer::new(),
+            payloads: storage.payload_writer(session_id)?,
+            text_index: TextIndexBuilder::new(),
+            tile_builder: TileBuilder::create(&storage.tiles_dir(session_id))?,
+            counters,
+            chunk_idx: 0,
+            late_replies: BTreeMap::new(),
+        })
+    }
+
+    /// Decode, check and store the next message; `msg` is left as stored
+    pub fn push(&mut self, msg: &mut ConvertedMessage) -> Result<(), Box<dyn std::error::Error>> {
+        let summaries = &mut self.summaries;
+        summaries.reports.observe(msg);
+        let violations = self.catalog.check(msg);
+        msg.violation_count = Some(violations.len().min(u16::MAX as usize) as u16);
+        summaries.conformance.push(msg, &violations);
+        if !violations.is_empty() {
+            if let Some(body) = msg.body_json.as_object_mut() {
+                body.insert("violations".to_string(), serde_json::to_value(&violations)?);
+            }
+        }
+        summaries.alarms.observe(msg);
+        summaries.states.observe(msg);
+
         // Update metadata
-        meta_collector.update(&msg);
+        summaries.meta.update(msg);
         
         // Write payload
-        storage.write_payload(session_id, msg.row_id, &msg.body_json)?;
+        self.payloads.append(msg.row_id, &msg.body_json)?;
+        self.text_index.push(msg.row_id, &msg.body_json);
+        self.tile_builder.push(msg);
+        
+        // Pair with an open primary
+        let txn = self.pairer.observe(msg, (self.chunk_idx, self.builder.len()));
+        summaries.timers.observe(msg, txn.as_ref().map(|txn| txn.primary_row_id));
+        if let Some(txn) = txn {
+            let (primary_chunk, offset) = txn.primary_location;
+            if primary_chunk == self.chunk_idx {
+                self.builder.set_reply(offset, txn.reply_row_id, txn.latency_ns);
+            } else {
+                self.late_replies.entry(primary_chunk).or_default()
+                    .push((offset, txn.reply_row_id, txn.latency_ns));
+            }
+        }
         
         // Add to Arrow builder
-        builder.push(&msg);
+        self.builder.push(msg);
         
         // Write chunk if full
-        if builder.len() >= CHUNK_SIZE {
-            let batch = builder.build_batch()?;
-            let chunk_path = storage.chunk_path(session_id, chunk_idx);
-            write_arrow_chunk(&batch, &chunk_path)?;
+        if self.builder.len() >= self.chunk_size {
+            self.summaries.meta.push_chunk(&self.builder);
+            self.write_chunk()?;
+            self.tile_builder.flush_chunk()?;
             
-            builder.clear();
-            chunk_idx += 1;
+            self.builder.clear();
+            self.text_index.clear();
+            self.chunk_idx += 1;
         }
+        Ok(())
     }
-    
-    // Write remaining messages
-    if !builder.is_empty() {
-        let batch = builder.build_batch()?;
-        let chunk_path = storage.chunk_path(session_id, chunk_idx);
-        write_arrow_chunk(&batch, &chunk_path)?;
+
+    /// Write the open chunk as it stands and `meta.json` as if the log ended
+    /// here; with `derived`, also the tiles and sidecars, which cost as much
+    /// as the session is long. The partial chunk is rewritten by the next
+    /// checkpoint; every file is replaced atomically, so readers see either
+    /// the previous checkpoint or this one.
+    pub fn checkpoint(&mut self, derived: bool) -> Result<SessionMeta, Box<dyn std::error::Error>> {
+        self.payloads.flush()?;
+        let mut meta = self.summaries.meta.clone();
+        if !self.builder.is_empty() {
+            meta.push_chunk(&self.builder);
+            self.write_chunk()?;
+        }
+        self.patch_late_replies()?;
+        if !derived {
+            return write_meta(meta, self.storage, self.session_id, true);
+        }
+
+        self.tile_builder.flush()?;
+        let summaries = &self.summaries;
+        let snapshot = Summaries {
+            meta,
+            reports: summaries.reports.clone(),
+            conformance: summaries.conformance.clone(),
+            alarms: summaries.alarms.clone(),
+            states: summaries.states.clone(),
+            timers: summaries.timers.clone(),
+        };
+        snapshot.write(self.storage, self.session_id, true)
+    }
+
+    pub fn finish(mut self) -> Result<SessionMeta, Box<dyn std::error::Error>> {
+        // Write remaining messages
+        if !self.builder.is_empty() {
+            self.summaries.meta.push_chunk(&self.builder);
+            self.write_chunk()?;
+        }
+        self.patch_late_replies()?;
+        self.payloads.finish()?;
+        self.tile_builder.finish()?;
+        self.summaries.write(self.storage, self.session_id, false)
+    }
+
+    /// Write the builder and its text index as the current chunk
+    fn write_chunk(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let batch = self.builder.build_batch()?;
+        replace_file(&self.storage.chunk_path(self.session_id, self.chunk_idx), |path| write_arrow_chunk(&batch, path))?;
+        replace_file(&self.storage.text_index_path(self.session_id, self.chunk_idx), |path| self.text_index.write(path))?;
+        // A live checkpoint rewrites the open chunk: count it once
+        self.counters.chunks.fetch_max(self.chunk_idx as u64 + 1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Patch chunks whose primaries were answered after the chunk was flushed
+    fn patch_late_replies(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        for (patched_idx, patches) in std::mem::take(&mut self.late_replies) {
+            let chunk_path = self.storage.chunk_path(self.session_id, patched_idx);
+            // Chunks are written as a single batch
+            let batch = read_arrow_chunk(&chunk_path)?.into_iter().next()
+                .ok_or("empty chunk")?;
+            let patched = patch_reply_columns(&batch, &patches)?;
+            replace_file(&chunk_path, |path| write_arrow_chunk(&patched, path))?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `path` through a temporary file renamed over it, so that a reader
+/// never sees it half written
+fn replace_file(
+    path: &Path,
+    write: impl FnOnce(&Path) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    write(&tmp)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_ingest_file_streams_and_rejects_bad_input() {
+        let dir = TempDir::new("hsms-ingest");
+        let storage = SessionStorage::new(&dir).unwrap();
+
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/pairs.ndjson");
+        let session_id = storage.create_session().unwrap();
+        let counters = Arc::new(IngestCounters::default());
+        let meta = ingest_file(&storage, &session_id, &fixture, "pairs.ndjson", &Default::default(), &counters).unwrap();
+        assert!(meta.row_count > 0);
+        assert_eq!(storage.read_meta(&session_id).unwrap().row_count, meta.row_count);
+        assert!(storage.read_conformance(&session_id).is_ok());
+        let progress = counters.progress();
+        assert_eq!(progress.rows, meta.row_count as u64);
+        assert_eq!(progress.bytes_read, fs::metadata(&fixture).unwrap().len());
+        assert_eq!(progress.bytes_total, progress.bytes_read);
+        assert_eq!(storage.read_meta(&session_id).unwrap().parser.as_deref(), Some("ndjson"));
+
+        // Smaller chunks, as configured
+        let small = storage.create_session().unwrap();
+        let counters = Arc::new(IngestCounters::default());
+        let options = IngestOptions { chunk_size: 4, ..Default::default() };
+        let meta = ingest_file(&storage, &small, &fixture, "pairs.ndjson", &options, &counters).unwrap();
+        assert_eq!(meta.chunks.len(), meta.row_count.div_ceil(4));
+        assert_eq!(storage.list_chunks(&small).unwrap().len(), meta.chunks.len());
+        assert_eq!(counters.progress().chunks, meta.chunks.len() as u64);
+
+        // A good first line followed by garbage fails the whole upload
+        let spool = storage.spool_path(&session_id);
+        let first_line = fs::read_to_string(&fixture).unwrap().lines().next().unwrap().to_string();
+        fs::write(&spool, format!("{}\n{{not json\n", first_line)).unwrap();
+        let bad_session = storage.create_session().unwrap();
+        let err = ingest_file(&storage, &bad_session, &spool, "broken.ndjson", &Default::default(), &Default::default()).unwrap_err();
+        assert!(err.downcast_ref::<InputError>().is_some());
+        assert!(storage.read_meta(&bad_session).is_err());
+    }
+
+    #[test]
+    fn test_recover_interrupted_sessions() {
+        let dir = TempDir::new("hsms-recover");
+        let storage = SessionStorage::new(&dir).unwrap();
+        let session = |status: Option<SessionStatus>| {
+            let id = storage.create_session().unwrap();
+            storage.write_info(&id, &SessionInfo::default()).unwrap();
+            if let Some(status) = status {
+                storage.write_pending_meta(&id, status, None).unwrap();
+            }
+            id
+        };
+        let ready = session(Some(SessionStatus::Ready));
+        let ingesting = session(Some(SessionStatus::Ingesting));
+        let never_set_up = session(None);
+        let bare = storage.create_session().unwrap();
+
+        assert_eq!(storage.recover_interrupted_sessions().unwrap(), 3);
+        let mut expected = vec![ready.clone(), ingesting.clone()];
+        expected.sort();
+        assert_eq!(storage.list_session_ids().unwrap(), expected);
+        assert_eq!(storage.read_meta(&ready).unwrap().status, SessionStatus::Ready);
+        assert_eq!(storage.read_meta(&ingesting).unwrap().status, SessionStatus::Failed);
+        assert!(!storage.session_path(&never_set_up).exists() && !storage.session_path(&bare).exists());
+        assert_eq!(storage.recover_interrupted_sessions().unwrap(), 0);
     }
-    
-    // Write metadata
-    let meta = meta_collector.into_meta();
-    storage.write_meta(session_id, &meta)?;
-    
-    Ok(meta)
 }
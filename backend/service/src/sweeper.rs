@@ -0,0 +1,250 @@
+//! Keeps `./data` bounded: a background task deletes sessions older than a
+//! TTL, then evicts the least recently accessed ones while the sessions take
+//! more than the storage quota.
+//!
+//! Pinned sessions and sessions still being written (uploads ingesting, live
+//! tails) are never deleted; they do count towards the quota. Age is taken
+//! from the catalog's `created_ns`, access from the `accessed` marker every
+//! request on the session touches (its creation if never read).
+//!
+//! A session without meta is still being set up by its request, which may
+//! have died. It does not count until nothing in it has changed for
+//! `ORPHAN_GRACE`; then it is deleted as orphaned.
+
+use crate::models::SessionStatus;
+use crate::sessions::{self, unix_ns};
+use crate::storage::SessionStorage;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(300);
+/// How long a session may go without meta and without changes
+pub const ORPHAN_GRACE: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SweepConfig {
+    pub interval: Duration,
+    /// Delete sessions created longer ago than this
+    pub ttl: Option<Duration>,
+    /// Evict sessions while they take more bytes than this
+    pub quota_bytes: Option<u64>,
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        Self { interval: DEFAULT_INTERVAL, ttl: None, quota_bytes: None }
+    }
+}
+
+impl SweepConfig {
+    pub fn enabled(&self) -> bool {
+        self.ttl.is_some() || self.quota_bytes.is_some()
+    }
+}
+
+/// What one sweep did
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SweepReport {
+    pub orphaned: Vec<String>,
+    pub expired: Vec<String>,
+    pub evicted: Vec<String>,
+    pub freed_bytes: u64,
+    /// Bytes the sessions take after the sweep
+    pub used_bytes: u64,
+}
+
+struct Candidate {
+    session_id: String,
+    size: u64,
+    created_ns: i64,
+    accessed_ns: i64,
+}
+
+/// Run the sweeper every `config.interval`; `None` if it has nothing to do
+pub fn spawn(storage: Arc<SessionStorage>, config: SweepConfig) -> Option<tokio::task::JoinHandle<()>> {
+    if !config.enabled() {
+        return None;
+    }
+    info!("Session sweeper: TTL {:?}, quota {:?} bytes, every {:?}", config.ttl, config.quota_bytes, config.interval);
+    Some(tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(config.interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let (storage, config) = (storage.clone(), config.clone());
+            match tokio::task::spawn_blocking(move || sweep(&storage, &config, SystemTime::now()).map_err(|e| e.to_string())).await {
+                Ok(Ok(report)) if report.orphaned.is_empty() && report.expired.is_empty() && report.evicted.is_empty() => {}
+                Ok(Ok(report)) => info!(
+                    "Sweep removed {} orphaned, {} expired and {} evicted sessions, freed {} bytes, {} bytes in use",
+                    report.orphaned.len(), report.expired.len(), report.evicted.len(), report.freed_bytes, report.used_bytes
+                ),
+                Ok(Err(e)) => error!("Session sweep failed: {}", e),
+                Err(e) => error!("Session sweep panicked: {}", e),
+            }
+        }
+    }))
+}
+
+/// One pass: orphans, then the TTL, then the quota
+pub fn sweep(storage: &SessionStorage, config: &SweepConfig, now: SystemTime) -> Result<SweepReport, Box<dyn std::error::Error>> {
+    let mut report = SweepReport::default();
+    let mut candidates = Vec::new();
+    for session_id in storage.list_session_ids()? {
+        if !storage.has_meta(&session_id) {
+            let abandoned = storage.last_modified(&session_id).is_ok_and(|modified| modified + ORPHAN_GRACE < now);
+            if abandoned {
+                let Ok(size) = storage.session_size(&session_id) else { continue };
+                report.used_bytes += size;
+                let orphan = Candidate { session_id, size, created_ns: 0, accessed_ns: 0 };
+                if remove(storage, &orphan, "orphaned, never set up", &mut report) {
+                    report.orphaned.push(orphan.session_id);
+                }
+            }
+            continue;
+        }
+        // Gone meanwhile, e.g. deleted by a request
+        let Ok(size) = storage.session_size(&session_id) else { continue };
+        report.used_bytes += size;
+        let Some(summary) = sessions::summarize(storage, &session_id) else { continue };
+        if summary.info.pinned || summary.status == SessionStatus::Ingesting {
+            continue;
+        }
+        let accessed_ns = storage.last_accessed(&session_id).map_or(0, unix_ns);
+        candidates.push(Candidate {
+            session_id,
+            size,
+            created_ns: summary.info.created_ns,
+            accessed_ns: accessed_ns.max(summary.info.created_ns),
+        });
+    }
+
+    // A TTL beyond what i64 nanoseconds can hold never expires anything
+    if let Some(ttl_ns) = config.ttl.and_then(|ttl| i64::try_from(ttl.as_nanos()).ok()) {
+        let cutoff = unix_ns(now).saturating_sub(ttl_ns);
+        let (expired, kept): (Vec<_>, Vec<_>) = candidates.into_iter().partition(|c| c.created_ns < cutoff);
+        candidates = kept;
+        for candidate in expired {
+            let age = Duration::from_nanos((unix_ns(now) - candidate.created_ns).max(0) as u64);
+            if remove(storage, &candidate, &format!("expired, created {}h ago", age.as_secs() / 3600), &mut report) {
+                report.expired.push(candidate.session_id);
+            }
+        }
+    }
+
+    if let Some(quota) = config.quota_bytes {
+        candidates.sort_by(|a, b| a.accessed_ns.cmp(&b.accessed_ns).then_with(|| a.session_id.cmp(&b.session_id)));
+        for candidate in candidates {
+            if report.used_bytes <= quota {
+                break;
+            }
+            let reason = format!("over the {} byte quota, last accessed at {} ns", quota, candidate.accessed_ns);
+            if remove(storage, &candidate, &reason, &mut report) {
+                report.evicted.push(candidate.session_id);
+            }
+        }
+        if report.used_bytes > quota {
+            warn!(
+                "Sessions take {} bytes, over the {} byte quota, but the rest are pinned or being written",
+                report.used_bytes, quota
+            );
+        }
+    }
+    Ok(report)
+}
+
+fn remove(storage: &SessionStorage, candidate: &Candidate, reason: &str, report: &mut SweepReport) -> bool {
+    match storage.delete_session(&candidate.session_id) {
+        Ok(()) => {
+            info!("Deleted session {} ({} bytes): {}", candidate.session_id, candidate.size, reason);
+            report.freed_bytes += candidate.size;
+            report.used_bytes -= candidate.size;
+            true
+        }
+        Err(e) => {
+            warn!("Failed to delete session {}: {}", candidate.session_id, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use crate::models::SessionInfo;
+
+    #[test]
+    fn test_sweep_expires_and_evicts_backdated_sessions() {
+        let dir = TempDir::new("hsms-sweep");
+        let storage = SessionStorage::new(&dir).unwrap();
+        std::fs::create_dir_all(dir.join("profiles")).unwrap();
+        std::fs::write(dir.join("profiles/big.json"), vec![b' '; 100_000]).unwrap();
+
+        let now = SystemTime::now();
+        let hours_ago = |hours: u64| now - Duration::from_secs(hours * 3600);
+        let session = |created_hours: u64, accessed_hours: Option<u64>, pinned: bool, status: SessionStatus| {
+            let id = storage.create_session().unwrap();
+            storage.write_info(&id, &SessionInfo {
+                name: id.clone(),
+                created_ns: unix_ns(hours_ago(created_hours)),
+                pinned,
+                ..Default::default()
+            }).unwrap();
+            storage.write_pending_meta(&id, status, None).unwrap();
+            std::fs::write(storage.session_path(&id).join("chunks/0.arrow"), vec![0u8; 10_000]).unwrap();
+            if let Some(hours) = accessed_hours {
+                storage.touch_session(&id, hours_ago(hours)).unwrap();
+            }
+            id
+        };
+
+        let expired = session(48, Some(1), false, SessionStatus::Ready);
+        let expired_pinned = session(48, None, true, SessionStatus::Ready);
+        let stale = session(10, Some(5), false, SessionStatus::Failed);
+        let fresh = session(10, Some(1), false, SessionStatus::Ready);
+        let never_read = session(3, None, false, SessionStatus::Ready);
+        let ingesting = session(20, None, false, SessionStatus::Ingesting);
+        assert_eq!(storage.last_accessed(&never_read), None);
+
+        // Without meta: an upload still being received, and one whose request died
+        let receiving = storage.create_session().unwrap();
+        std::fs::write(storage.spool_path(&receiving), vec![0u8; 10_000]).unwrap();
+        let orphan = storage.create_session().unwrap();
+        std::fs::write(storage.spool_path(&orphan), vec![0u8; 10_000]).unwrap();
+        let orphan_path = storage.session_path(&orphan);
+        for entry in std::fs::read_dir(&orphan_path).unwrap() {
+            std::fs::File::open(entry.unwrap().path()).unwrap().set_modified(hours_ago(2)).unwrap();
+        }
+        std::fs::File::open(&orphan_path).unwrap().set_modified(hours_ago(2)).unwrap();
+
+        let size = |id: &String| storage.session_size(id).unwrap();
+        assert!(size(&fresh) > 10_000);
+        let kept = size(&expired_pinned) + size(&fresh) + size(&ingesting);
+        let freed = size(&expired) + size(&stale) + size(&never_read);
+        // A TTL too long for i64 nanoseconds ("keep forever") expires nothing
+        // Orphans go regardless
+        let forever = SweepConfig { ttl: Some(Duration::from_secs(3_000_000 * 3600)), ..Default::default() };
+        let report = sweep(&storage, &forever, now).unwrap();
+        assert!(report.expired.is_empty());
+        assert_eq!(report.orphaned, vec![orphan]);
+        assert_eq!(storage.list_session_ids().unwrap().len(), 7);
+
+        // Room for the three that must stay, not for a fourth; the upload being
+        // received does not count
+        let config = SweepConfig { ttl: Some(Duration::from_secs(24 * 3600)), quota_bytes: Some(kept + 1_000), ..Default::default() };
+        let report = sweep(&storage, &config, now).unwrap();
+
+        assert_eq!(report.expired, vec![expired.clone()]);
+        // Read 5h ago, then created 3h ago and never read; the one read 1h ago fits
+        assert_eq!(report.evicted, vec![stale.clone(), never_read.clone()]);
+        assert_eq!((report.freed_bytes, report.used_bytes), (freed, kept));
+        let mut expected = vec![expired_pinned, fresh, ingesting, receiving];
+        expected.sort();
+        assert_eq!(storage.list_session_ids().unwrap(), expected);
+        assert!(dir.join("profiles/big.json").exists());
+
+        // Nothing more to do
+        assert_eq!(sweep(&storage, &config, now).unwrap(), SweepReport { used_bytes: kept, ..Default::default() });
+    }
+}
@@ -0,0 +1,92 @@
+//! Fixtures shared by the unit tests
+
+use crate::models::ConvertedMessage;
+use serde_json::Value;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An `SxFy` message with every other column zeroed; chain the setters below
+/// for the rest.
+pub fn msg(s: u8, f: u8) -> ConvertedMessage {
+    ConvertedMessage { s, f, ..Default::default() }
+}
+
+impl ConvertedMessage {
+    pub fn row(mut self, row_id: u32) -> Self {
+        self.row_id = row_id;
+        self
+    }
+
+    pub fn at_ns(mut self, ts_ns: i64) -> Self {
+        self.ts_ns = ts_ns;
+        self
+    }
+
+    pub fn at_ms(self, ts_ms: i64) -> Self {
+        self.at_ns(ts_ms * 1_000_000)
+    }
+
+    pub fn dir(mut self, dir: i8) -> Self {
+        self.dir = dir;
+        self
+    }
+
+    pub fn wbit(mut self, wbit: u8) -> Self {
+        self.wbit = wbit;
+        self
+    }
+
+    pub fn sysbytes(mut self, sysbytes: u32) -> Self {
+        self.sysbytes = sysbytes;
+        self
+    }
+
+    pub fn ceid(mut self, ceid: u32) -> Self {
+        self.ceid = ceid;
+        self
+    }
+
+    pub fn body(mut self, body_json: Value) -> Self {
+        self.body_json = body_json;
+        self
+    }
+
+    /// Paired at ingest with the reply at `reply_row_id`, `latency_ns` later
+    pub fn replied(mut self, reply_row_id: u32, latency_ns: i64) -> Self {
+        self.reply_row_id = Some(reply_row_id);
+        self.latency_ns = Some(latency_ns);
+        self
+    }
+}
+
+/// A fresh directory under the system temp dir, removed on drop so a failing
+/// assertion does not leave it behind.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(prefix: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("{prefix}-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
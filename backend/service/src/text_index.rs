@@ -0,0 +1,282 @@
+//! Per-chunk inverted index for the `text` filter (`chunks/NNN.tix`).
+//!
+//! The text filter is a case-insensitive substring match on the serialized
+//! payload. Tokens are the alphanumeric runs of that same lowercase string, so
+//! every key, `A` item, semantic name and number is indexed. The index only
+//! narrows the rows to check: candidates are still verified against the payload.
+//!
+//! Layout: `TIX2`, the dictionary length as a little-endian `u64`, the
+//! MsgPack dictionary (sorted tokens and their postings' start, in rows), then
+//! every token's ascending `row_id`s as little-endian `u32`s. Searches load the
+//! dictionary and seek to the postings of matching tokens only, so tokens found
+//! in every row (`secs`, `tree`, `t`, `v`, ...) cost nothing unless queried.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"TIX2";
+
+/// The string the text filter matches against
+pub fn searchable_text(body_json: &serde_json::Value) -> String {
+    serde_json::to_string(body_json).unwrap_or_default().to_lowercase()
+}
+
+/// Tokens with their byte offsets
+fn token_spans(text: &str) -> Vec<(usize, &str)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, &text[s..]));
+    }
+    spans
+}
+
+/// Collects token postings for the chunk being written
+#[derive(Default)]
+pub struct TextIndexBuilder {
+    postings: HashMap<String, Vec<u32>>,
+}
+
+impl TextIndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, row_id: u32, body_json: &serde_json::Value) {
+        let text = searchable_text(body_json);
+        for (_, token) in token_spans(&text) {
+            let rows = self.postings.entry(token.to_string()).or_default();
+            if rows.last() != Some(&row_id) {
+                rows.push(row_id);
+            }
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut entries: Vec<(&String, &Vec<u32>)> = self.postings.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+        let mut starts = Vec::with_capacity(entries.len() + 1);
+        let mut next = 0u64;
+        for (_, rows) in &entries {
+            starts.push(next);
+            next += rows.len() as u64;
+        }
+        starts.push(next);
+        let dictionary = Dictionary {
+            tokens: entries.iter().map(|(t, _)| (*t).clone()).collect(),
+            starts,
+        };
+        let dictionary = rmp_serde::to_vec(&dictionary)?;
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&(dictionary.len() as u64).to_le_bytes())?;
+        file.write_all(&dictionary)?;
+        for (_, rows) in &entries {
+            for row_id in rows.iter() {
+                file.write_all(&row_id.to_le_bytes())?;
+            }
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.postings.clear();
+    }
+}
+
+/// Sorted tokens; the postings of `tokens[i]` are rows `starts[i]..starts[i + 1]`
+#[derive(Serialize, Deserialize)]
+struct Dictionary {
+    tokens: Vec<String>,
+    starts: Vec<u64>,
+}
+
+/// A chunk's token dictionary; postings stay on disk until a token matches
+pub struct TextIndex {
+    /// Kept open: a live ingest may rename a newer index over the path
+    file: File,
+    dictionary: Dictionary,
+    postings_offset: u64,
+}
+
+impl TextIndex {
+    /// Load a chunk's dictionary; `None` for chunks ingested before indexing
+    /// existed or with an older index layout
+    pub fn read(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut file = File::open(path)?;
+        let mut header = [0u8; 12];
+        if file.read_exact(&mut header).is_err() || &header[..4] != MAGIC {
+            return Ok(None);
+        }
+        let len = u64::from_le_bytes(header[4..].try_into()?);
+        let mut dictionary = vec![0u8; len as usize];
+        file.read_exact(&mut dictionary)?;
+        Ok(Some(Self {
+            file,
+            dictionary: rmp_serde::from_slice(&dictionary)?,
+            postings_offset: header.len() as u64 + len,
+        }))
+    }
+
+    /// Sorted row ids that may contain `query` (already lowercased).
+    /// `None` when the query has no alphanumeric text to look up.
+    ///
+    /// A query token bounded by punctuation on a side must match a whole
+    /// token on that side; an unbounded side may extend into the rest of a
+    /// payload token (`"a00"` matches `a001`, `"00 1"` needs `..00` then `1..`).
+    pub fn candidates(&self, query: &str) -> Result<Option<Vec<u32>>, Box<dyn std::error::Error>> {
+        let mut result: Option<Vec<u32>> = None;
+
+        for (start, part) in token_spans(query) {
+            let left_bounded = start > 0;
+            let right_bounded = start + part.len() < query.len();
+
+            let tokens = &self.dictionary.tokens;
+            let matching: Vec<usize> = match (left_bounded, right_bounded) {
+                (true, true) => tokens.binary_search_by(|t| t.as_str().cmp(part)).into_iter().collect(),
+                (true, false) => self.prefix_range(part).collect(),
+                (false, true) => (0..tokens.len()).filter(|&i| tokens[i].ends_with(part)).collect(),
+                (false, false) => (0..tokens.len()).filter(|&i| tokens[i].contains(part)).collect(),
+            };
+
+            let mut rows: Vec<u32> = Vec::new();
+            for i in matching {
+                self.read_postings(i, &mut rows)?;
+            }
+            rows.sort_unstable();
+            rows.dedup();
+
+            let rows = match result {
+                None => rows,
+                Some(prev) => prev.into_iter().filter(|r| rows.binary_search(r).is_ok()).collect(),
+            };
+            if rows.is_empty() {
+                return Ok(Some(rows));
+            }
+            result = Some(rows);
+        }
+
+        Ok(result)
+    }
+
+    /// Positions of the tokens starting with `prefix`, contiguous in sorted order
+    fn prefix_range(&self, prefix: &str) -> Range<usize> {
+        let tokens = &self.dictionary.tokens;
+        let from = tokens.partition_point(|t| t.as_str() < prefix);
+        let to = from + tokens[from..].partition_point(|t| t.starts_with(prefix));
+        from..to
+    }
+
+    fn read_postings(&self, token: usize, rows: &mut Vec<u32>) -> std::io::Result<()> {
+        let (from, to) = (self.dictionary.starts[token], self.dictionary.starts[token + 1]);
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(self.postings_offset + from * 4))?;
+        let mut bytes = vec![0u8; ((to - from) * 4) as usize];
+        file.read_exact(&mut bytes)?;
+        rows.extend(bytes.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use serde_json::json;
+
+    #[test]
+    fn test_candidates_are_superset_of_matches() {
+        let bodies = [
+            json!({"secs_tree": {"t": "A", "v": "LOT-A001"}}),
+            json!({"semantic": {"kind": "EventReport", "vid": 1001}}),
+            json!({"secs_tree": {"t": "U4", "v": 201}}),
+        ];
+        let mut builder = TextIndexBuilder::new();
+        for (row_id, body) in bodies.iter().enumerate() {
+            builder.push(row_id as u32, body);
+        }
+
+        let dir = TempDir::new("hsms-tix");
+        let path = dir.join("000.tix");
+        builder.write(&path).unwrap();
+        let index = TextIndex::read(&path).unwrap().unwrap();
+
+        for query in ["lot-a0", "a001", "eventrep", "001", "\"v\":201", "kind\":\"event", "nomatch", "u4\""] {
+            let query = query.to_lowercase();
+            let expected: Vec<u32> = bodies.iter().enumerate()
+                .filter(|(_, b)| searchable_text(b).contains(&query))
+                .map(|(i, _)| i as u32)
+                .collect();
+            let candidates = index.candidates(&query).unwrap().unwrap();
+            assert!(expected.iter().all(|r| candidates.contains(r)), "query {}", query);
+        }
+
+        assert_eq!(index.candidates("\"eventreport\"").unwrap().unwrap(), vec![1]);
+        assert_eq!(index.candidates("\"lot").unwrap().unwrap(), vec![0]);
+        assert_eq!(index.candidates("eventreport").unwrap().unwrap(), vec![1]);
+        assert!(index.candidates("nomatch").unwrap().unwrap().is_empty());
+        assert!(index.candidates("\":{").unwrap().is_none());
+
+        // Indexes written before the dictionary was split off are not used
+        std::fs::write(&path, rmp_serde::to_vec(&(vec!["a"], vec![vec![0u32]])).unwrap()).unwrap();
+        assert!(TextIndex::read(&path).unwrap().is_none());
+    }
+
+    /// 1M rows in 50k-row chunks, the default; run with
+    /// `cargo test --release -- --ignored text_search_1m`
+    #[test]
+    #[ignore = "perf: builds a 1M-row index"]
+    fn test_text_search_1m_rows_under_a_second() {
+        const ROWS: u32 = 1_000_000;
+        const CHUNK: u32 = 50_000;
+        let dir = TempDir::new("hsms-tix-perf");
+
+        let mut builder = TextIndexBuilder::new();
+        for row_id in 0..ROWS {
+            builder.push(row_id, &json!({
+                "secs_tree": {"t": "L", "items": [
+                    {"t": "U4", "v": row_id % 1000},
+                    {"t": "A", "v": format!("LOT-{:07}", row_id)},
+                    {"t": "A", "v": "PROCESS_STATE_CHANGE"},
+                ]},
+                "semantic": {"kind": "EventReport", "ceid": 200 + row_id % 50},
+            }));
+            if (row_id + 1) % CHUNK == 0 {
+                builder.write(&dir.join(format!("{:03}.tix", row_id / CHUNK))).unwrap();
+                builder.clear();
+            }
+        }
+
+        let started = std::time::Instant::now();
+        let mut found = Vec::new();
+        for chunk in 0..ROWS / CHUNK {
+            let index = TextIndex::read(&dir.join(format!("{:03}.tix", chunk))).unwrap().unwrap();
+            found.extend(index.candidates("lot-0765432").unwrap().unwrap());
+            index.candidates("\"items\"").unwrap();
+        }
+        let elapsed = started.elapsed();
+
+        assert_eq!(found, vec![765_432]);
+        assert!(elapsed < std::time::Duration::from_secs(1), "{:?}", elapsed);
+    }
+}
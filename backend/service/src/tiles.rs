@@ -0,0 +1,349 @@
+//! Level-of-detail timeline tiles (`tiles/level_XX.arrow`).
+//!
+//! Level `L` aggregates messages into `(bucket, dir, s)` cells with
+//! `bucket_ns(L) = 1 ms * 4^L`, buckets aligned to the epoch so that every
+//! level nests inside the next. Each cell keeps the message count and the
+//! first/last timestamp it covers, so a zoomed-out timeline draws from a
+//! bounded number of rows.
+//!
+//! Tiles are written per chunk: each chunk appends one batch to every level
+//! file, and a cell that straddles two chunks appears once per chunk until
+//! `read_tiles` merges it.
+//!
+//! Next to each level file, `level_XX.idx` holds one little-endian entry per
+//! batch: `offset: u64, len: u64, first_bucket_ns: i64, last_bucket_ns: i64`,
+//! so `read_tiles` only decodes the batches overlapping its range.
+
+use crate::models::ConvertedMessage;
+use arrow::array::{Array, Int64Array, Int8Array, UInt32Array, UInt8Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const TILE_LEVELS: usize = 16;
+pub const BASE_BUCKET_NS: i64 = 1_000_000;
+pub const LEVEL_FACTOR: i64 = 4;
+const INDEX_ENTRY_LEN: usize = 32;
+
+/// Bucket width of a level
+pub fn bucket_ns(level: usize) -> i64 {
+    BASE_BUCKET_NS * LEVEL_FACTOR.pow(level as u32)
+}
+
+pub fn level_path(dir: &Path, level: usize) -> PathBuf {
+    dir.join(format!("level_{:02}.arrow", level))
+}
+
+fn index_path(dir: &Path, level: usize) -> PathBuf {
+    dir.join(format!("level_{:02}.idx", level))
+}
+
+pub fn tile_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("bucket_ns", DataType::Int64, false),
+        Field::new("dir", DataType::Int8, false),
+        Field::new("s", DataType::UInt8, false),
+        Field::new("count", DataType::UInt32, false),
+        Field::new("t_min_ns", DataType::Int64, false),
+        Field::new("t_max_ns", DataType::Int64, false),
+    ]))
+}
+
+type CellKey = (i64, i8, u8);
+
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    count: u32,
+    t_min_ns: i64,
+    t_max_ns: i64,
+}
+
+impl Cell {
+    fn merge(&mut self, other: &Cell) {
+        self.count += other.count;
+        self.t_min_ns = self.t_min_ns.min(other.t_min_ns);
+        self.t_max_ns = self.t_max_ns.max(other.t_max_ns);
+    }
+}
+
+fn merge_into(cells: &mut BTreeMap<CellKey, Cell>, key: CellKey, cell: Cell) {
+    cells.entry(key).and_modify(|c| c.merge(&cell)).or_insert(cell);
+}
+
+/// Counts the bytes written through it, to locate batches in the stream
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// One level's stream and its batch index
+struct LevelWriter {
+    stream: StreamWriter<CountingWriter<BufWriter<File>>>,
+    index: BufWriter<File>,
+}
+
+impl LevelWriter {
+    fn create(dir: &Path, level: usize, schema: &SchemaRef) -> Result<Self, ArrowError> {
+        let file = CountingWriter { inner: BufWriter::new(File::create(level_path(dir, level))?), written: 0 };
+        Ok(Self {
+            stream: StreamWriter::try_new(file, schema)?,
+            index: BufWriter::new(File::create(index_path(dir, level))?),
+        })
+    }
+
+    fn write(&mut self, cells: &BTreeMap<CellKey, Cell>) -> Result<(), ArrowError> {
+        let (Some(first), Some(last)) = (cells.keys().next(), cells.keys().next_back()) else { return Ok(()) };
+        let offset = self.stream.get_ref().written;
+        self.stream.write(&cells_to_batch(cells)?)?;
+        let len = self.stream.get_ref().written - offset;
+
+        let mut entry = [0u8; INDEX_ENTRY_LEN];
+        entry[0..8].copy_from_slice(&offset.to_le_bytes());
+        entry[8..16].copy_from_slice(&len.to_le_bytes());
+        entry[16..24].copy_from_slice(&first.0.to_le_bytes());
+        entry[24..32].copy_from_slice(&last.0.to_le_bytes());
+        self.index.write_all(&entry)?;
+        Ok(())
+    }
+
+    /// The stream goes first, so every indexed batch is readable
+    fn flush(&mut self) -> Result<(), ArrowError> {
+        self.stream.flush()?;
+        self.index.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ArrowError> {
+        self.stream.finish()?;
+        self.index.flush()?;
+        Ok(())
+    }
+}
+
+/// Aggregates the chunk being written and appends it to every level file
+pub struct TileBuilder {
+    writers: Vec<LevelWriter>,
+    cells: BTreeMap<CellKey, Cell>,
+}
+
+impl TileBuilder {
+    pub fn create(dir: &Path) -> Result<Self, ArrowError> {
+        std::fs::create_dir_all(dir)?;
+        let schema = tile_schema();
+        let writers = (0..TILE_LEVELS)
+            .map(|level| LevelWriter::create(dir, level, &schema))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { writers, cells: BTreeMap::new() })
+    }
+
+    pub fn push(&mut self, msg: &ConvertedMessage) {
+        let key = (msg.ts_ns.div_euclid(BASE_BUCKET_NS) * BASE_BUCKET_NS, msg.dir, msg.s);
+        merge_into(&mut self.cells, key, Cell { count: 1, t_min_ns: msg.ts_ns, t_max_ns: msg.ts_ns });
+    }
+
+    /// Write the current chunk's cells to every level, each level folded from the one below
+    pub fn flush_chunk(&mut self) -> Result<(), ArrowError> {
+        let mut cells = std::mem::take(&mut self.cells);
+        for (level, writer) in self.writers.iter_mut().enumerate() {
+            if level > 0 {
+                let width = bucket_ns(level);
+                let mut coarser = BTreeMap::new();
+                for ((bucket, dir, s), cell) in cells {
+                    merge_into(&mut coarser, (bucket.div_euclid(width) * width, dir, s), cell);
+                }
+                cells = coarser;
+            }
+            writer.write(&cells)?;
+        }
+        Ok(())
+    }
+
+    /// Write the cells so far as a chunk and make them readable: `read_tiles`
+    /// stops cleanly at the end of a stream that is still being written
+    pub fn flush(&mut self) -> Result<(), ArrowError> {
+        self.flush_chunk()?;
+        for writer in &mut self.writers {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), ArrowError> {
+        if !self.cells.is_empty() {
+            self.flush_chunk()?;
+        }
+        for writer in &mut self.writers {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+fn cells_to_batch(cells: &BTreeMap<CellKey, Cell>) -> Result<RecordBatch, ArrowError> {
+    RecordBatch::try_new(tile_schema(), vec![
+        Arc::new(cells.keys().map(|k| k.0).collect::<Int64Array>()),
+        Arc::new(cells.keys().map(|k| k.1).collect::<Int8Array>()),
+        Arc::new(cells.keys().map(|k| k.2).collect::<UInt8Array>()),
+        Arc::new(cells.values().map(|c| c.count).collect::<UInt32Array>()),
+        Arc::new(cells.values().map(|c| c.t_min_ns).collect::<Int64Array>()),
+        Arc::new(cells.values().map(|c| c.t_max_ns).collect::<Int64Array>()),
+    ])
+}
+
+/// Cells of one level whose bucket overlaps `[from_ns, to_ns]` (0 = unbounded),
+/// merged across chunks and sorted by `(bucket_ns, dir, s)`
+pub fn read_tiles(dir: &Path, level: usize, from_ns: i64, to_ns: i64) -> Result<RecordBatch, Box<dyn std::error::Error>> {
+    let width = bucket_ns(level);
+    let overlaps = |first: i64, last: i64| {
+        (from_ns == 0 || last.saturating_add(width) > from_ns) && (to_ns == 0 || first <= to_ns)
+    };
+    let mut cells = BTreeMap::new();
+    let mut file = File::open(level_path(dir, level))?;
+
+    let Some(entries) = read_index(dir, level)? else {
+        // Written before the index: decode every batch
+        for batch in StreamReader::try_new(file, None)? {
+            merge_batch(&mut cells, &batch?, &overlaps)?;
+        }
+        return Ok(cells_to_batch(&cells)?);
+    };
+
+    // Batches are decoded one by one behind the schema message the stream starts with
+    let mut schema = vec![0u8; entries.first().map_or(0, |e| e.offset as usize)];
+    file.read_exact(&mut schema)?;
+    for entry in entries.iter().filter(|e| overlaps(e.first_bucket_ns, e.last_bucket_ns)) {
+        file.seek(SeekFrom::Start(entry.offset))?;
+        for batch in StreamReader::try_new(schema.as_slice().chain((&mut file).take(entry.len)), None)? {
+            merge_batch(&mut cells, &batch?, &overlaps)?;
+        }
+    }
+
+    Ok(cells_to_batch(&cells)?)
+}
+
+/// Where a batch is in a level file, and the buckets it covers
+#[derive(Debug, Clone, Copy)]
+struct BatchEntry {
+    offset: u64,
+    len: u64,
+    first_bucket_ns: i64,
+    last_bucket_ns: i64,
+}
+
+/// One entry per batch, or `None` for tiles written before the index
+fn read_index(dir: &Path, level: usize) -> std::io::Result<Option<Vec<BatchEntry>>> {
+    let bytes = match std::fs::read(index_path(dir, level)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let field = |entry: &[u8], i: usize| <[u8; 8]>::try_from(&entry[i * 8..i * 8 + 8]).unwrap_or_default();
+    // An entry still being written is left for the next read
+    Ok(Some(bytes.chunks_exact(INDEX_ENTRY_LEN)
+        .map(|entry| BatchEntry {
+            offset: u64::from_le_bytes(field(entry, 0)),
+            len: u64::from_le_bytes(field(entry, 1)),
+            first_bucket_ns: i64::from_le_bytes(field(entry, 2)),
+            last_bucket_ns: i64::from_le_bytes(field(entry, 3)),
+        })
+        .collect()))
+}
+
+fn merge_batch(
+    cells: &mut BTreeMap<CellKey, Cell>,
+    batch: &RecordBatch,
+    overlaps: &dyn Fn(i64, i64) -> bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let column = |i: usize| batch.column(i).as_any();
+    let bucket = column(0).downcast_ref::<Int64Array>().ok_or("bad tile column")?;
+    let dir = column(1).downcast_ref::<Int8Array>().ok_or("bad tile column")?;
+    let s = column(2).downcast_ref::<UInt8Array>().ok_or("bad tile column")?;
+    let count = column(3).downcast_ref::<UInt32Array>().ok_or("bad tile column")?;
+    let t_min = column(4).downcast_ref::<Int64Array>().ok_or("bad tile column")?;
+    let t_max = column(5).downcast_ref::<Int64Array>().ok_or("bad tile column")?;
+
+    for i in 0..batch.num_rows() {
+        let start = bucket.value(i);
+        if !overlaps(start, start) {
+            continue;
+        }
+        let cell = Cell { count: count.value(i), t_min_ns: t_min.value(i), t_max_ns: t_max.value(i) };
+        merge_into(cells, (start, dir.value(i), s.value(i)), cell);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{msg, TempDir};
+
+    #[test]
+    fn test_pyramid_levels_merge_across_chunks() {
+        let dir = TempDir::new("hsms-tiles");
+        let mut builder = TileBuilder::create(&dir).unwrap();
+
+        // Two chunks, with the 4 ms bucket at 4..8 ms split between them
+        for ts_ms in [0, 1, 1, 5] {
+            builder.push(&msg(6, 1).at_ms(ts_ms).dir(1));
+        }
+        builder.push(&msg(1, 1).at_ns(2_500_000).dir(-1));
+        builder.flush_chunk().unwrap();
+        for ts_ms in [6, 17] {
+            builder.push(&msg(6, 1).at_ms(ts_ms).dir(1));
+        }
+        builder.finish().unwrap();
+
+        let counts = |level: usize, from_ns: i64, to_ns: i64| {
+            let batch = read_tiles(&dir, level, from_ns, to_ns).unwrap();
+            let buckets = batch.column(0).as_any().downcast_ref::<Int64Array>().unwrap().values().to_vec();
+            let counts = batch.column(3).as_any().downcast_ref::<UInt32Array>().unwrap().values().to_vec();
+            buckets.into_iter().map(|b| b / 1_000_000).zip(counts).collect::<Vec<_>>()
+        };
+
+        assert_eq!(counts(0, 0, 0), vec![(0, 1), (1, 2), (2, 1), (5, 1), (6, 1), (17, 1)]);
+        assert_eq!(counts(1, 0, 0), vec![(0, 1), (0, 3), (4, 2), (16, 1)]);
+        assert_eq!(counts(2, 0, 0), vec![(0, 1), (0, 5), (16, 1)]);
+        // Buckets overlapping the range, even partially
+        assert_eq!(counts(1, 6_000_000, 16_000_000), vec![(4, 2), (16, 1)]);
+
+        let last = read_tiles(&dir, TILE_LEVELS - 1, 0, 0).unwrap();
+        assert_eq!(last.num_rows(), 2);
+        let t_max = last.column(5).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(t_max.value(1), 17_000_000);
+
+        // Batches outside the range are not decoded: garble the first chunk's
+        let entries = read_index(&dir, 0).unwrap().unwrap();
+        assert_eq!(entries.len(), 2);
+        let BatchEntry { offset, len, last_bucket_ns, .. } = entries[0];
+        assert_eq!(last_bucket_ns, 5_000_000);
+        let mut bytes = std::fs::read(level_path(&dir, 0)).unwrap();
+        bytes[offset as usize..(offset + len) as usize].fill(0xFF);
+        std::fs::write(level_path(&dir, 0), bytes).unwrap();
+        assert_eq!(counts(0, 6_000_000, 0), vec![(6, 1), (17, 1)]);
+        assert!(read_tiles(&dir, 0, 0, 0).is_err());
+
+        // Tiles written before the index are scanned whole
+        std::fs::remove_file(index_path(&dir, 2)).unwrap();
+        assert_eq!(counts(2, 0, 0), vec![(0, 1), (0, 5), (16, 1)]);
+    }
+}
@@ -0,0 +1,302 @@
+//! HSMS timer violations (`timers.json`, `GET /sessions/:id/timers`).
+//!
+//! `TimerTracker` replays the log against the equipment's `HsmsTimers`:
+//!
+//! - T3: a W-bit data primary answered later than T3, or not at all
+//! - T5: a Select.req less than T5 after a Separate.req (reconnect too soon)
+//! - T6: a Select/Deselect/Linktest.req answered later than T6, or not at all
+//! - T7: NOT SELECTED (after Deselect.rsp or a rejected Select.rsp) for longer
+//!   than T7 before a Select or Separate
+//! - linktest: more than `linktest_ms` between Select.rsp or one Linktest.req
+//!   and the next Linktest.req
+//!
+//! A timer still running when the log ends only counts once it has expired.
+//! T8 (inter-character timeout) needs byte timing the logs do not have.
+
+use crate::models::{ConvertedMessage, HsmsTimer, HsmsTimers, SessionTimers, TimerViolation};
+use crate::pairing::PAIRING_HORIZON_NS;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// Violations listed per session; the rest are only counted
+pub const MAX_VIOLATIONS: usize = 100_000;
+
+/// A row that started a timer
+#[derive(Debug, Clone, Copy)]
+struct Start {
+    row_id: u32,
+    ts_ns: i64,
+}
+
+impl Start {
+    fn of(msg: &ConvertedMessage) -> Self {
+        Self { row_id: msg.row_id, ts_ns: msg.ts_ns }
+    }
+}
+
+#[derive(Clone)]
+pub struct TimerTracker {
+    timers: HsmsTimers,
+    /// Unanswered W-bit data primaries by row_id (log order)
+    open_data: BTreeMap<u32, (Start, u8, u8)>,
+    /// Unanswered control requests by (sysbytes, reply dir): (SType, start)
+    open_control: HashMap<(u32, i8), (u8, Start)>,
+    separated: Option<Start>,
+    not_selected: Option<Start>,
+    /// Select.rsp or the last Linktest.req while selected
+    last_linktest: Option<Start>,
+    latest_ts_ns: i64,
+    violations: Vec<TimerViolation>,
+    dropped_violations: u64,
+}
+
+fn ms_to_ns(ms: u64) -> i64 {
+    i64::try_from(ms).unwrap_or(i64::MAX).saturating_mul(1_000_000)
+}
+
+impl TimerTracker {
+    pub fn new(timers: HsmsTimers) -> Self {
+        Self {
+            timers,
+            open_data: BTreeMap::new(),
+            open_control: HashMap::new(),
+            separated: None,
+            not_selected: None,
+            last_linktest: None,
+            latest_ts_ns: i64::MIN,
+            violations: Vec::new(),
+            dropped_violations: 0,
+        }
+    }
+
+    /// Feed the next message in log order, with the primary it answers if
+    /// the pairer matched one
+    pub fn observe(&mut self, msg: &ConvertedMessage, answers: Option<u32>) {
+        self.latest_ts_ns = self.latest_ts_ns.max(msg.ts_ns);
+        self.expire_data(msg.ts_ns);
+        if msg.s == 0 {
+            self.observe_control(msg);
+            return;
+        }
+
+        if msg.wbit == 1 && !msg.f.is_multiple_of(2) {
+            self.open_data.insert(msg.row_id, (Start::of(msg), msg.s, msg.f));
+        }
+        if let Some((start, s, f)) = answers.and_then(|row_id| self.open_data.remove(&row_id)) {
+            let limit_ns = ms_to_ns(self.timers.t3_ms);
+            let elapsed = msg.ts_ns - start.ts_ns;
+            if elapsed > limit_ns {
+                self.violation(HsmsTimer::T3, start, Some(msg), limit_ns, format!("S{}F{} answered after T3", s, f));
+            }
+        }
+    }
+
+    /// Primaries the pairer can no longer match are unanswered for good
+    fn expire_data(&mut self, now_ns: i64) {
+        let limit_ns = ms_to_ns(self.timers.t3_ms);
+        let horizon_ns = limit_ns.max(PAIRING_HORIZON_NS);
+        while let Some(entry) = self.open_data.first_entry() {
+            if entry.get().0.ts_ns.saturating_add(horizon_ns) >= now_ns {
+                break;
+            }
+            let (start, s, f) = entry.remove();
+            self.violation(HsmsTimer::T3, start, None, limit_ns, format!("S{}F{} never answered", s, f));
+        }
+    }
+
+    fn observe_control(&mut self, msg: &ConvertedMessage) {
+        let accepted = msg.body_json.pointer("/semantic/status").and_then(Value::as_u64).unwrap_or(0) == 0;
+        match msg.f {
+            1 => {
+                if let Some(separated) = self.separated.take() {
+                    let limit_ns = ms_to_ns(self.timers.t5_ms);
+                    if msg.ts_ns - separated.ts_ns < limit_ns {
+                        self.violation(HsmsTimer::T5, separated, Some(msg), limit_ns, "Select.req sooner than T5 after Separate.req".into());
+                    }
+                }
+                self.open_control.insert((msg.sysbytes, -msg.dir), (msg.f, Start::of(msg)));
+            }
+            3 | 5 => {
+                if msg.f == 5 {
+                    self.check_linktest(msg);
+                }
+                self.open_control.insert((msg.sysbytes, -msg.dir), (msg.f, Start::of(msg)));
+            }
+            2 | 4 | 6 | 7 => {
+                let request = self.open_control.get(&(msg.sysbytes, msg.dir)).copied();
+                if let Some((stype, start)) = request.filter(|(stype, _)| msg.f == 7 || msg.f == stype + 1) {
+                    self.open_control.remove(&(msg.sysbytes, msg.dir));
+                    let limit_ns = ms_to_ns(self.timers.t6_ms);
+                    if msg.ts_ns - start.ts_ns > limit_ns {
+                        self.violation(HsmsTimer::T6, start, Some(msg), limit_ns, format!("{} answered after T6", control_name(stype)));
+                    }
+                }
+                match msg.f {
+                    2 if accepted => {
+                        self.end_not_selected(msg);
+                        self.last_linktest = Some(Start::of(msg));
+                    }
+                    2 => {
+                        self.not_selected.get_or_insert(Start::of(msg));
+                    }
+                    4 if accepted => {
+                        self.not_selected = Some(Start::of(msg));
+                        self.last_linktest = None;
+                    }
+                    _ => {}
+                }
+            }
+            9 => {
+                self.end_not_selected(msg);
+                self.separated = Some(Start::of(msg));
+                self.last_linktest = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn end_not_selected(&mut self, msg: &ConvertedMessage) {
+        let Some(start) = self.not_selected.take() else {
+            return;
+        };
+        let limit_ns = ms_to_ns(self.timers.t7_ms);
+        if msg.ts_ns - start.ts_ns > limit_ns {
+            self.violation(HsmsTimer::T7, start, Some(msg), limit_ns, "NOT SELECTED for longer than T7".into());
+        }
+    }
+
+    fn check_linktest(&mut self, msg: &ConvertedMessage) {
+        let Some(interval_ms) = self.timers.linktest_ms else {
+            return;
+        };
+        let Some(last) = self.last_linktest.replace(Start::of(msg)) else {
+            return;
+        };
+        let limit_ns = ms_to_ns(interval_ms);
+        if msg.ts_ns - last.ts_ns > limit_ns {
+            self.violation(HsmsTimer::Linktest, last, Some(msg), limit_ns, "Linktest interval exceeded".into());
+        }
+    }
+
+    fn violation(&mut self, timer: HsmsTimer, start: Start, end: Option<&ConvertedMessage>, limit_ns: i64, message: String) {
+        if self.violations.len() >= MAX_VIOLATIONS {
+            self.dropped_violations += 1;
+            return;
+        }
+        let mut row_ids = vec![start.row_id];
+        row_ids.extend(end.map(|msg| msg.row_id));
+        self.violations.push(TimerViolation {
+            timer,
+            ts_ns: start.ts_ns,
+            row_ids,
+            elapsed_ns: end.map(|msg| msg.ts_ns - start.ts_ns),
+            limit_ns,
+            message,
+        });
+    }
+
+    /// Close the log: timers still running count if they have expired by its end
+    pub fn finish(mut self) -> SessionTimers {
+        let end_ns = self.latest_ts_ns;
+        let t3_ns = ms_to_ns(self.timers.t3_ms);
+        for (start, s, f) in std::mem::take(&mut self.open_data).into_values() {
+            if end_ns - start.ts_ns > t3_ns {
+                self.violation(HsmsTimer::T3, start, None, t3_ns, format!("S{}F{} never answered", s, f));
+            }
+        }
+        let t6_ns = ms_to_ns(self.timers.t6_ms);
+        let mut open_control: Vec<_> = std::mem::take(&mut self.open_control).into_values().collect();
+        open_control.sort_by_key(|(_, start)| start.row_id);
+        for (stype, start) in open_control {
+            if end_ns - start.ts_ns > t6_ns {
+                self.violation(HsmsTimer::T6, start, None, t6_ns, format!("{} never answered", control_name(stype)));
+            }
+        }
+        let t7_ns = ms_to_ns(self.timers.t7_ms);
+        if let Some(start) = self.not_selected.take().filter(|start| end_ns - start.ts_ns > t7_ns) {
+            self.violation(HsmsTimer::T7, start, None, t7_ns, "NOT SELECTED for longer than T7".into());
+        }
+
+        // Late replies are found after the rows around them
+        self.violations.sort_by_key(|v| (v.ts_ns, v.row_ids[0]));
+        SessionTimers {
+            timers: self.timers,
+            violations: self.violations,
+            dropped_violations: self.dropped_violations,
+        }
+    }
+}
+
+fn control_name(stype: u8) -> &'static str {
+    match stype {
+        1 => "Select.req",
+        3 => "Deselect.req",
+        _ => "Linktest.req",
+    }
+}
+
+/// Violations of one timer (or all) starting within `[from_ns, to_ns]` (0 = unbounded)
+pub fn filter_violations(timers: SessionTimers, timer: Option<HsmsTimer>, from_ns: i64, to_ns: i64) -> SessionTimers {
+    let violations = timers.violations.into_iter()
+        .filter(|v| timer.is_none_or(|timer| v.timer == timer))
+        .filter(|v| (from_ns == 0 || v.ts_ns >= from_ns) && (to_ns == 0 || v.ts_ns <= to_ns))
+        .collect();
+    SessionTimers { violations, ..timers }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg;
+    use serde_json::json;
+
+    /// Data primaries (odd `f`) set the W-bit; control replies accept
+    fn row(row_id: u32, ts_ms: i64, dir: i8, s: u8, f: u8, sysbytes: u32) -> ConvertedMessage {
+        msg(s, f).row(row_id).at_ms(ts_ms).dir(dir).wbit(u8::from(s > 0 && f % 2 == 1)).sysbytes(sysbytes)
+            .body(json!({"semantic": {"status": 0}}))
+    }
+
+    #[test]
+    fn test_timer_violations() {
+        let timers = HsmsTimers { t3_ms: 1_000, t5_ms: 1_000, t6_ms: 100, t7_ms: 500, linktest_ms: Some(2_000) };
+        let mut tracker = TimerTracker::new(timers);
+        let rows = [
+            (row(0, 0, 1, 0, 1, 1), None),              // Select.req
+            (row(1, 200, -1, 0, 2, 1), None),           // Select.rsp after T6
+            (row(2, 300, 1, 1, 3, 2), None),            // S1F3
+            (row(3, 400, -1, 1, 4, 2), Some(2)),        // answered in time
+            (row(4, 500, 1, 1, 3, 3), None),
+            (row(5, 1_600, -1, 1, 4, 3), Some(4)),      // answered after T3
+            (row(6, 3_000, 1, 0, 5, 4), None),          // linktest 2.8 s after select
+            (row(7, 3_050, -1, 0, 6, 4), None),
+            (row(8, 4_000, 1, 0, 3, 5), None),
+            (row(9, 4_010, -1, 0, 4, 5), None),         // deselected
+            (row(10, 5_000, 1, 0, 9, 6), None),         // separate after 1 s NOT SELECTED
+            (row(11, 5_200, 1, 0, 1, 7), None),         // reconnect sooner than T5
+            (row(12, 5_210, -1, 0, 2, 7), None),
+            (row(13, 5_300, 1, 2, 13, 8), None),        // never answered
+            (row(14, 7_000, 1, 0, 5, 9), None),         // never answered
+            (row(15, 8_000, -1, 6, 12, 10), None),
+        ];
+        for (msg, answers) in &rows {
+            tracker.observe(msg, *answers);
+        }
+        let timers = tracker.finish();
+
+        let found = timers.violations.iter()
+            .map(|v| (v.timer, v.row_ids.clone(), v.elapsed_ns.map(|ns| ns / 1_000_000)))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![
+            (HsmsTimer::T6, vec![0, 1], Some(200)),
+            (HsmsTimer::Linktest, vec![1, 6], Some(2_800)),
+            (HsmsTimer::T3, vec![4, 5], Some(1_100)),
+            (HsmsTimer::T7, vec![9, 10], Some(990)),
+            (HsmsTimer::T5, vec![10, 11], Some(200)),
+            (HsmsTimer::T3, vec![13], None),
+            (HsmsTimer::T6, vec![14], None),
+        ]);
+
+        let t3 = filter_violations(timers, Some(HsmsTimer::T3), 1_000_000_000, 0);
+        assert_eq!(t3.violations.len(), 1);
+    }
+}
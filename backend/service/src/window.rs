@@ -0,0 +1,170 @@
+//! Windowed reads for `messages.arrow`: chunk pruning by time range, exact
+//! slicing, and an opaque continuation cursor.
+
+use crate::arrow_io::read_arrow_chunk;
+use crate::models::ChunkStats;
+use arrow::array::{Array, Int64Array, UInt32Array};
+use arrow::compute::take_record_batch;
+use arrow::record_batch::RecordBatch;
+use std::path::PathBuf;
+
+/// Position of the next unread row in storage order. Chunks never change
+/// once written, so a cursor always resumes at the same row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PageCursor {
+    pub chunk: usize,
+    pub offset: usize,
+}
+
+impl PageCursor {
+    const PREFIX: &'static str = "c1.";
+
+    pub fn encode(&self) -> String {
+        format!("{}{:x}.{:x}", Self::PREFIX, self.chunk, self.offset)
+    }
+
+    pub fn decode(token: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid cursor '{}'", token);
+        let (chunk, offset) = token.strip_prefix(Self::PREFIX)
+            .and_then(|rest| rest.split_once('.'))
+            .ok_or_else(invalid)?;
+        Ok(Self {
+            chunk: usize::from_str_radix(chunk, 16).map_err(|_| invalid())?,
+            offset: usize::from_str_radix(offset, 16).map_err(|_| invalid())?,
+        })
+    }
+}
+
+pub struct WindowQuery {
+    pub from_ns: i64,
+    pub to_ns: i64,
+    pub limit: usize,
+    pub cursor: PageCursor,
+}
+
+impl WindowQuery {
+    fn contains(&self, ts_ns: i64) -> bool {
+        (self.from_ns == 0 || ts_ns >= self.from_ns) && (self.to_ns == 0 || ts_ns <= self.to_ns)
+    }
+}
+
+pub struct Window {
+    pub batches: Vec<RecordBatch>,
+    /// Where the next page starts; `None` once the range is exhausted
+    pub next: Option<PageCursor>,
+}
+
+/// Read up to `limit` rows in `[from_ns, to_ns]` starting at the cursor.
+/// Chunks whose stats fall outside the range are never opened; chunks
+/// without stats (older sessions) are always read.
+pub fn read_window(
+    chunks: &[PathBuf],
+    stats: &[ChunkStats],
+    query: &WindowQuery,
+) -> Result<Window, Box<dyn std::error::Error>> {
+    let may_match = |idx: usize| stats.get(idx).is_none_or(|s| s.overlaps(query.from_ns, query.to_ns));
+    let mut batches = Vec::new();
+    let mut taken = 0;
+
+    for (chunk_idx, path) in chunks.iter().enumerate().skip(query.cursor.chunk) {
+        if !may_match(chunk_idx) {
+            continue;
+        }
+        let skip = if chunk_idx == query.cursor.chunk { query.cursor.offset } else { 0 };
+
+        let mut chunk_offset = 0;
+        for batch in read_arrow_chunk(path)? {
+            let batch_start = chunk_offset;
+            chunk_offset += batch.num_rows();
+
+            let ts_ns = batch.column(0).as_any().downcast_ref::<Int64Array>()
+                .ok_or("ts_ns column is not Int64")?;
+            let mut indices = Vec::new();
+            for i in skip.saturating_sub(batch_start)..batch.num_rows() {
+                if !query.contains(ts_ns.value(i)) {
+                    continue;
+                }
+                if taken == query.limit {
+                    // A further matching row exists: resume there
+                    if !indices.is_empty() {
+                        batches.push(take_record_batch(&batch, &UInt32Array::from(indices))?);
+                    }
+                    let next = PageCursor { chunk: chunk_idx, offset: batch_start + i };
+                    return Ok(Window { batches, next: Some(next) });
+                }
+                indices.push(i as u32);
+                taken += 1;
+            }
+
+            if !indices.is_empty() {
+                let page = if indices.len() == batch.num_rows() {
+                    batch
+                } else {
+                    take_record_batch(&batch, &UInt32Array::from(indices))?
+                };
+                batches.push(page);
+            }
+        }
+
+        if taken == query.limit {
+            // Page is full at a chunk boundary; continue only if a later chunk may match
+            let next = (chunk_idx + 1..chunks.len()).find(|&idx| may_match(idx))
+                .map(|chunk| PageCursor { chunk, offset: 0 });
+            return Ok(Window { batches, next });
+        }
+    }
+
+    Ok(Window { batches, next: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{msg, TempDir};
+    use crate::arrow_io::{write_arrow_chunk, ArrowBuilder};
+
+    fn row_ids(window: &Window) -> Vec<u32> {
+        window.batches.iter()
+            .flat_map(|b| b.column(7).as_any().downcast_ref::<UInt32Array>().unwrap().values().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = PageCursor { chunk: 12, offset: 49_999 };
+        assert_eq!(PageCursor::decode(&cursor.encode()).unwrap(), cursor);
+        assert!(PageCursor::decode("12").is_err());
+        assert!(PageCursor::decode("c1.zz.0").is_err());
+    }
+
+    #[test]
+    fn test_window_prunes_slices_and_pages() {
+        let dir = TempDir::new("hsms-window");
+
+        // Three chunks of four rows, ts = row_id * 10
+        let mut chunks = Vec::new();
+        let mut stats = Vec::new();
+        for c in 0..3u32 {
+            let mut builder = ArrowBuilder::new();
+            (c * 4..c * 4 + 4).for_each(|r| builder.push(&msg(1, 1).row(r).at_ns(r as i64 * 10).dir(1).sysbytes(r)));
+            stats.push(builder.chunk_stats().unwrap());
+            let path = dir.join(format!("{:03}.arrow", c));
+            write_arrow_chunk(&builder.build_batch().unwrap(), &path).unwrap();
+            chunks.push(path);
+        }
+        // Pruned chunks are never opened
+        std::fs::write(&chunks[0], b"not arrow").unwrap();
+
+        let mut query = WindowQuery { from_ns: 50, to_ns: 100, limit: 3, cursor: PageCursor::default() };
+        let mut pages = Vec::new();
+        loop {
+            let window = read_window(&chunks, &stats, &query).unwrap();
+            pages.push(row_ids(&window));
+            match window.next {
+                Some(next) => query.cursor = PageCursor::decode(&next.encode()).unwrap(),
+                None => break,
+            }
+        }
+        assert_eq!(pages, vec![vec![5, 6, 7], vec![8, 9, 10]]);
+    }
+}
@@ -1,12 +1,43 @@
 import { tableFromIPC, type Table } from 'apache-arrow';
-import type { DataSource, SessionMeta, FilterExpr, HighlightExpr } from '../lib/types';
+import type {
+  DataSource,
+  SessionMeta,
+  SessionList,
+  SessionPatch,
+  SessionQuery,
+  SessionSummary,
+  UploadDetails,
+  FilterExpr,
+  HighlightExpr,
+  WindowQuery,
+  WindowPage,
+  StatsRequest,
+  StatsResponse,
+  LatencyRequest,
+  LatencyResponse,
+  TilesQuery,
+  AlarmsQuery,
+  SessionAlarms,
+  StatesQuery,
+  SessionStates,
+  TimersQuery,
+  SessionTimers,
+  ConformanceSummary,
+  LiveRequest,
+  LiveHandlers,
+  IngestProgress,
+} from '../lib/types';
 
 const API_BASE = import.meta.env.VITE_API_BASE || 'http://localhost:8080';
 
 export class RemoteDataSource implements DataSource {
-  async createSession(file: File): Promise<string> {
+  async createSession(file: File, onProgress?: (progress: IngestProgress) => void, details: UploadDetails = {}): Promise<string> {
     console.log(`[RemoteDataSource] Creating session for file: ${file.name} (${file.size} bytes)`);
     const formData = new FormData();
+    if (details.name !== undefined) formData.append('name', details.name);
+    if (details.equipment_id !== undefined) formData.append('equipment_id', details.equipment_id);
+    if (details.tags !== undefined) formData.append('tags', details.tags.join(','));
+    if (details.notes !== undefined) formData.append('notes', details.notes);
     formData.append('file', file);
 
     console.log(`[RemoteDataSource] Uploading to: ${API_BASE}/sessions`);
@@ -27,10 +58,33 @@ export class RemoteDataSource implements DataSource {
     }
 
     const data = await response.json();
-    console.log(`[RemoteDataSource] Session created successfully: ${data.session_id}`);
+    console.log(`[RemoteDataSource] Session created, ingesting: ${data.session_id}`);
+    await this.waitForIngest(data.session_id, onProgress);
+    console.log(`[RemoteDataSource] Session ingested: ${data.session_id}`);
     return data.session_id;
   }
 
+  // Follow /events until the session is ready, rejecting if ingest failed
+  private waitForIngest(sessionId: string, onProgress?: (progress: IngestProgress) => void): Promise<void> {
+    return new Promise((resolve, reject) => {
+      const source = new EventSource(`${API_BASE}/sessions/${sessionId}/events`);
+      source.addEventListener('progress', (e) => onProgress?.(JSON.parse((e as MessageEvent).data)));
+      source.addEventListener('ready', () => {
+        source.close();
+        resolve();
+      });
+      source.addEventListener('failed', (e) => {
+        source.close();
+        reject(new Error(`Failed to ingest session: ${(e as MessageEvent).data}`));
+      });
+      source.onerror = () => {
+        if (source.readyState === EventSource.CLOSED) {
+          reject(new Error('Lost the ingest progress stream'));
+        }
+      };
+    });
+  }
+
   async getMeta(sessionId: string): Promise<SessionMeta> {
     console.log(`[RemoteDataSource] Fetching metadata for session: ${sessionId}`);
     const response = await fetch(`${API_BASE}/sessions/${sessionId}/meta`);
@@ -45,16 +99,18 @@ export class RemoteDataSource implements DataSource {
     return meta;
   }
 
-  async fetchWindow(
-    sessionId: string,
-    query: { from_ns?: number; to_ns?: number; limit?: number; cursor?: number } = {}
-  ): Promise<Table> {
+  async fetchWindow(sessionId: string, query: WindowQuery = {}): Promise<Table> {
+    const page = await this.fetchWindowPage(sessionId, query);
+    return page.table;
+  }
+
+  async fetchWindowPage(sessionId: string, query: WindowQuery = {}): Promise<WindowPage> {
     console.log(`[RemoteDataSource] Fetching window for session: ${sessionId}`, query);
     const params = new URLSearchParams();
     if (query.from_ns !== undefined) params.append('from_ns', query.from_ns.toString());
     if (query.to_ns !== undefined) params.append('to_ns', query.to_ns.toString());
     if (query.limit !== undefined) params.append('limit', query.limit.toString());
-    if (query.cursor !== undefined) params.append('cursor', query.cursor.toString());
+    if (query.cursor !== undefined) params.append('cursor', query.cursor);
 
     const url = `${API_BASE}/sessions/${sessionId}/messages.arrow?${params}`;
     console.log(`[RemoteDataSource] Fetching from: ${url}`);
@@ -78,8 +134,9 @@ export class RemoteDataSource implements DataSource {
     console.log(`[RemoteDataSource] Received ${buffer.byteLength} bytes of Arrow data`);
 
     const table = tableFromIPC(new Uint8Array(buffer));
-    console.log(`[RemoteDataSource] Parsed Arrow table: ${table.numRows} rows, ${table.numCols} columns`);
-    return table;
+    const nextCursor = response.headers.get('X-Next-Cursor');
+    console.log(`[RemoteDataSource] Parsed Arrow table: ${table.numRows} rows, ${table.numCols} columns, next cursor: ${nextCursor}`);
+    return { table, nextCursor };
   }
 
   async search(
@@ -122,6 +179,116 @@ export class RemoteDataSource implements DataSource {
     return table;
   }
 
+  async getStats(sessionId: string, request: StatsRequest): Promise<StatsResponse> {
+    const response = await fetch(`${API_BASE}/sessions/${sessionId}/stats`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify(request),
+    });
+
+    if (!response.ok) {
+      const errorText = await response.text();
+      console.error(`[RemoteDataSource] Stats failed: ${response.status} ${response.statusText}`, errorText);
+      throw new Error(`Stats failed: ${response.statusText} - ${errorText}`);
+    }
+
+    return response.json();
+  }
+
+  async getLatency(sessionId: string, request: LatencyRequest): Promise<LatencyResponse> {
+    const response = await fetch(`${API_BASE}/sessions/${sessionId}/latency`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify(request),
+    });
+
+    if (!response.ok) {
+      const errorText = await response.text();
+      console.error(`[RemoteDataSource] Latency failed: ${response.status} ${response.statusText}`, errorText);
+      throw new Error(`Latency failed: ${response.statusText} - ${errorText}`);
+    }
+
+    return response.json();
+  }
+
+  async fetchTiles(sessionId: string, query: TilesQuery): Promise<Table> {
+    const params = new URLSearchParams({ level: query.level.toString() });
+    if (query.from_ns !== undefined) params.append('from_ns', query.from_ns.toString());
+    if (query.to_ns !== undefined) params.append('to_ns', query.to_ns.toString());
+
+    const response = await fetch(`${API_BASE}/sessions/${sessionId}/tiles?${params}`, {
+      headers: {
+        Accept: 'application/vnd.apache.arrow.stream',
+      },
+    });
+
+    if (!response.ok) {
+      console.error(`[RemoteDataSource] Failed to fetch tiles: ${response.status} ${response.statusText}`);
+      throw new Error(`Failed to fetch tiles: ${response.statusText}`);
+    }
+
+    return tableFromIPC(new Uint8Array(await response.arrayBuffer()));
+  }
+
+  async getAlarms(sessionId: string, query: AlarmsQuery = {}): Promise<SessionAlarms> {
+    const params = new URLSearchParams();
+    if (query.alid !== undefined) params.append('alid', query.alid.toString());
+    if (query.from_ns !== undefined) params.append('from_ns', query.from_ns.toString());
+    if (query.to_ns !== undefined) params.append('to_ns', query.to_ns.toString());
+
+    const response = await fetch(`${API_BASE}/sessions/${sessionId}/alarms?${params}`);
+
+    if (!response.ok) {
+      console.error(`[RemoteDataSource] Failed to fetch alarms: ${response.status} ${response.statusText}`);
+      throw new Error(`Failed to fetch alarms: ${response.statusText}`);
+    }
+
+    return response.json();
+  }
+
+  async getStates(sessionId: string, query: StatesQuery = {}): Promise<SessionStates> {
+    const params = new URLSearchParams();
+    if (query.machine !== undefined) params.append('machine', query.machine);
+    if (query.from_ns !== undefined) params.append('from_ns', query.from_ns.toString());
+    if (query.to_ns !== undefined) params.append('to_ns', query.to_ns.toString());
+
+    const response = await fetch(`${API_BASE}/sessions/${sessionId}/states?${params}`);
+
+    if (!response.ok) {
+      console.error(`[RemoteDataSource] Failed to fetch states: ${response.status} ${response.statusText}`);
+      throw new Error(`Failed to fetch states: ${response.statusText}`);
+    }
+
+    return response.json();
+  }
+
+  async getTimers(sessionId: string, query: TimersQuery = {}): Promise<SessionTimers> {
+    const params = new URLSearchParams();
+    if (query.timer !== undefined) params.append('timer', query.timer);
+    if (query.from_ns !== undefined) params.append('from_ns', query.from_ns.toString());
+    if (query.to_ns !== undefined) params.append('to_ns', query.to_ns.toString());
+
+    const response = await fetch(`${API_BASE}/sessions/${sessionId}/timers?${params}`);
+
+    if (!response.ok) {
+      console.error(`[RemoteDataSource] Failed to fetch timers: ${response.status} ${response.statusText}`);
+      throw new Error(`Failed to fetch timers: ${response.statusText}`);
+    }
+
+    return response.json();
+  }
+
+  async getConformance(sessionId: string): Promise<ConformanceSummary> {
+    const response = await fetch(`${API_BASE}/sessions/${sessionId}/conformance`);
+
+    if (!response.ok) {
+      console.error(`[RemoteDataSource] Failed to fetch conformance: ${response.status} ${response.statusText}`);
+      throw new Error(`Failed to fetch conformance: ${response.statusText}`);
+    }
+
+    return response.json();
+  }
+
   async getPayload(sessionId: string, rowId: number): Promise<any> {
     console.log(`[RemoteDataSource] Fetching payload for session: ${sessionId}, row: ${rowId}`);
     const response = await fetch(`${API_BASE}/sessions/${sessionId}/payload/${rowId}`);
@@ -136,6 +303,86 @@ export class RemoteDataSource implements DataSource {
     return payload;
   }
 
+  async startLive(request: LiveRequest): Promise<string> {
+    const response = await fetch(`${API_BASE}/sessions/live`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify(request),
+    });
+
+    if (!response.ok) {
+      const errorText = await response.text();
+      console.error(`[RemoteDataSource] Failed to start live session: ${response.status} ${response.statusText}`, errorText);
+      throw new Error(`Failed to start live session: ${response.statusText} - ${errorText}`);
+    }
+
+    const data = await response.json();
+    console.log(`[RemoteDataSource] Live session started: ${data.session_id}`);
+    return data.session_id;
+  }
+
+  subscribeLive(sessionId: string, handlers: LiveHandlers): () => void {
+    const source = new EventSource(`${API_BASE}/sessions/${sessionId}/live`);
+    source.addEventListener('rows', (e) => handlers.onRows(JSON.parse((e as MessageEvent).data)));
+    source.addEventListener('lagged', (e) => handlers.onLagged?.(Number((e as MessageEvent).data)));
+    source.addEventListener('finished', (e) => {
+      source.close();
+      handlers.onFinished?.(JSON.parse((e as MessageEvent).data));
+    });
+    source.addEventListener('failed', (e) => {
+      source.close();
+      handlers.onFailed?.((e as MessageEvent).data);
+    });
+    return () => source.close();
+  }
+
+  async stopLive(sessionId: string): Promise<SessionMeta> {
+    const response = await fetch(`${API_BASE}/sessions/${sessionId}/live`, {
+      method: 'DELETE',
+    });
+
+    if (!response.ok) {
+      console.error(`[RemoteDataSource] Failed to stop live session: ${response.status} ${response.statusText}`);
+      throw new Error(`Failed to stop live session: ${response.statusText}`);
+    }
+
+    return response.json();
+  }
+
+  async listSessions(query: SessionQuery = {}): Promise<SessionList> {
+    const params = new URLSearchParams();
+    for (const [key, value] of Object.entries(query)) {
+      if (value === undefined) continue;
+      params.append(key, Array.isArray(value) ? value.join(',') : value.toString());
+    }
+
+    const response = await fetch(`${API_BASE}/sessions?${params}`);
+
+    if (!response.ok) {
+      const errorText = await response.text();
+      console.error(`[RemoteDataSource] Failed to list sessions: ${response.status} ${response.statusText}`, errorText);
+      throw new Error(`Failed to list sessions: ${response.statusText} - ${errorText}`);
+    }
+
+    return response.json();
+  }
+
+  async updateSession(sessionId: string, patch: SessionPatch): Promise<SessionSummary> {
+    const response = await fetch(`${API_BASE}/sessions/${sessionId}`, {
+      method: 'PATCH',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify(patch),
+    });
+
+    if (!response.ok) {
+      const errorText = await response.text();
+      console.error(`[RemoteDataSource] Failed to update session: ${response.status} ${response.statusText}`, errorText);
+      throw new Error(`Failed to update session: ${response.statusText} - ${errorText}`);
+    }
+
+    return response.json();
+  }
+
   async deleteSession(sessionId: string): Promise<void> {
     console.log(`[RemoteDataSource] Deleting session: ${sessionId}`);
     const response = await fetch(`${API_BASE}/sessions/${sessionId}`, {
@@ -7,6 +7,170 @@ export interface SessionMeta {
   distinct_s: number[];
   distinct_f: number[];
   distinct_ceid: number[];
+  chunks?: ChunkStats[];
+  ceid_names?: Record<string, string>;
+  counts?: MessageCounts;
+  tile_levels?: number[]; // bucket width (ns) of each /tiles level
+  live?: boolean; // still growing: a live tail is appending to it
+  status?: SessionStatus; // absent for old sessions, which are ready
+  error?: string | null; // why ingest failed
+  parser?: string | null; // input format the upload was parsed as
+}
+
+export type SessionStatus = 'ingesting' | 'ready' | 'failed';
+
+// Catalog entry (info.json) of a session
+export interface SessionInfo {
+  name: string;
+  source: 'upload' | 'live';
+  filename: string; // uploaded file name, or the live address
+  created_ns: number;
+  size_bytes: number;
+  equipment_id: string | null;
+  profile: string | null;
+  tags: string[];
+  notes: string;
+  pinned: boolean; // kept by the TTL sweeper and quota eviction
+}
+
+// Optional catalog fields of an upload
+export interface UploadDetails {
+  name?: string;
+  equipment_id?: string;
+  tags?: string[];
+  notes?: string;
+}
+
+export interface SessionSummary extends SessionInfo {
+  session_id: string;
+  status: SessionStatus;
+  live: boolean;
+  error: string | null;
+  parser: string | null;
+  row_count: number;
+  t_min_ns: number;
+  t_max_ns: number;
+}
+
+export interface SessionList {
+  total: number; // matches before offset/limit
+  sessions: SessionSummary[];
+}
+
+export interface SessionQuery {
+  q?: string;
+  tags?: string[]; // all required
+  equipment_id?: string;
+  status?: SessionStatus;
+  source?: 'upload' | 'live';
+  pinned?: boolean;
+  created_from_ns?: number;
+  created_to_ns?: number;
+  from_ns?: number; // log overlaps [from_ns, to_ns]
+  to_ns?: number;
+  sort?: 'created' | 'name' | 'rows' | 'size' | 'start';
+  order?: 'asc' | 'desc';
+  offset?: number;
+  limit?: number; // default 50, at most 1000
+}
+
+// PATCH /sessions/:id: only the given fields change
+export interface SessionPatch {
+  name?: string;
+  tags?: string[];
+  notes?: string;
+  equipment_id?: string; // '' clears it
+  pinned?: boolean;
+}
+
+// `progress` event of GET /sessions/:id/events
+export interface IngestProgress {
+  bytes_read: number; // 0 for live sessions
+  bytes_total: number;
+  rows: number;
+  chunks: number;
+}
+
+// Object keys are the stringified S / F / CEID / dir values
+export interface MessageCounts {
+  total: number;
+  by_dir: Record<string, number>;
+  by_s: Record<string, number>;
+  by_f: Record<string, number>;
+  by_sxfy: Array<{ s: number; f: number; count: number }>;
+  by_ceid: Record<string, number>;
+}
+
+export interface StatsRequest extends FilterExpr {
+  query?: string;
+  bucket_ns?: number; // 0 = split the range into `buckets`
+  buckets?: number; // default 200
+}
+
+export interface StatsResponse extends MessageCounts {
+  histogram: {
+    from_ns: number;
+    bucket_ns: number;
+    counts: number[];
+  };
+}
+
+export interface LatencyRequest extends StatsRequest {
+  t3_ms?: number; // reply timeout, default 45000
+}
+
+// Nearest-rank percentiles; null when nothing was answered
+export interface LatencySummary {
+  count: number;
+  p50_ns: number | null;
+  p90_ns: number | null;
+  p99_ns: number | null;
+  max_ns: number | null;
+}
+
+export interface LatencyGroup extends LatencySummary {
+  s: number;
+  f: number;
+  dir: number;
+  timeouts: number;
+  buckets: (LatencySummary & { from_ns: number })[];
+}
+
+export interface T3Timeout {
+  row_id: number;
+  ts_ns: number;
+  s: number;
+  f: number;
+  dir: number;
+  latency_ns: number | null; // null = never answered
+}
+
+export interface LatencyResponse {
+  t3_ns: number;
+  from_ns: number;
+  bucket_ns: number;
+  groups: LatencyGroup[];
+  timeouts: T3Timeout[];
+  dropped_timeouts: number;
+}
+
+export interface ChunkStats {
+  rows: number;
+  first_row_id: number;
+  t_min_ns: number;
+  t_max_ns: number;
+}
+
+export interface WindowQuery {
+  from_ns?: number;
+  to_ns?: number;
+  limit?: number;
+  cursor?: string; // opaque, from the previous page
+}
+
+export interface WindowPage {
+  table: Table;
+  nextCursor: string | null;
 }
 
 export interface FilterExpr {
@@ -19,26 +183,194 @@ export interface FilterExpr {
   f: number[];
   ceid: number[];
   text: string;
+  has_violation?: boolean; // only rows with/without conformance violations
 }
 
 export interface HighlightExpr {
   ceid: number[];
   sxfy: Array<{ s: number; f: number }>;
   unanswered: boolean;
+  reply_window_ms?: number; // default 5000
+  timers?: boolean; // adds hl_timer
 }
 
 export interface SearchRequest extends FilterExpr {
+  query?: string; // boolean query, ANDed with the filter fields
   highlight?: HighlightExpr;
 }
 
+export interface TilesQuery {
+  level: number;
+  from_ns?: number;
+  to_ns?: number;
+}
+
+export interface AlarmsQuery {
+  alid?: number;
+  from_ns?: number;
+  to_ns?: number;
+}
+
+// Either end is null when it lies outside the log
+export interface AlarmInterval {
+  alid: number;
+  category: number; // ALCD without the set bit
+  altx: string | null;
+  set_ns: number | null;
+  clear_ns: number | null;
+  duration_ns: number | null;
+  set_row_id: number | null;
+  clear_row_id: number | null;
+}
+
+export interface AlarmInfo {
+  alid: number;
+  altx: string | null;
+  enabled: boolean | null;
+  set_count: number;
+}
+
+export interface SessionAlarms {
+  intervals: AlarmInterval[];
+  alarms: AlarmInfo[];
+}
+
+export type StateMachine = 'link' | 'communication' | 'control';
+
+export interface StatesQuery {
+  machine?: StateMachine;
+  from_ns?: number;
+  to_ns?: number;
+}
+
+export interface StateInterval {
+  machine: StateMachine;
+  state: string;
+  from_ns: number;
+  to_ns: number | null; // null: lasts to the end of the log
+  row_id: number; // row that caused the transition
+  inferred: boolean;
+}
+
+export interface StateAnomaly {
+  machine: StateMachine;
+  state: string;
+  ts_ns: number;
+  row_id: number;
+  message: string;
+}
+
+export interface SessionStates {
+  intervals: StateInterval[];
+  anomalies: StateAnomaly[];
+  dropped_anomalies: number;
+}
+
+export interface HsmsTimers {
+  t3_ms: number;
+  t5_ms: number;
+  t6_ms: number;
+  t7_ms: number;
+  linktest_ms: number | null;
+}
+
+export type HsmsTimer = 't3' | 't5' | 't6' | 't7' | 'linktest';
+
+export interface TimersQuery {
+  timer?: HsmsTimer;
+  from_ns?: number;
+  to_ns?: number;
+}
+
+export interface TimerViolation {
+  timer: HsmsTimer;
+  ts_ns: number;
+  row_ids: number[]; // starting row, then the ending row if any
+  elapsed_ns: number | null;
+  limit_ns: number;
+  message: string;
+}
+
+export interface SessionTimers {
+  timers: HsmsTimers;
+  violations: TimerViolation[];
+  dropped_violations: number;
+}
+
+export interface RuleCount {
+  rule: 'wbit_on_reply' | 'wbit' | 'direction' | 'body';
+  message: string;
+  count: number;
+  example_row_ids: number[];
+}
+
+export interface SxFyViolations {
+  s: number;
+  f: number;
+  rows: number;
+  rules: RuleCount[];
+}
+
+export interface ConformanceSummary {
+  rows_with_violations: number;
+  by_sxfy: SxFyViolations[];
+}
+
+// POST /sessions/live: connect to a tap, or listen for a forwarding host
+export interface LiveRequest {
+  mode: 'connect' | 'listen';
+  address: string; // host:port
+  profile?: string;
+  dictionary?: Record<string, unknown>;
+  name?: string; // defaults to the address
+  equipment_id?: string;
+  tags?: string[];
+}
+
+// A row as streamed by GET /sessions/:id/live (ts_ns as a JSON number)
+export interface LiveRow {
+  ts_ns: number;
+  dir: number;
+  s: number;
+  f: number;
+  wbit: number;
+  sysbytes: number;
+  ceid: number;
+  row_id: number;
+  reply_row_id: number | null;
+  latency_ns: number | null;
+  violation_count: number | null;
+  body_json: any;
+}
+
+export interface LiveHandlers {
+  onRows(rows: LiveRow[]): void;
+  onLagged?(missedBatches: number): void;
+  onFinished?(meta: SessionMeta): void;
+  onFailed?(error: string): void;
+}
+
 export interface DataSource {
-  createSession(file: File): Promise<string>;
+  // Resolves once the upload has been ingested
+  createSession(file: File, onProgress?: (progress: IngestProgress) => void, details?: UploadDetails): Promise<string>;
+  listSessions(query?: SessionQuery): Promise<SessionList>;
+  updateSession(sessionId: string, patch: SessionPatch): Promise<SessionSummary>;
+  startLive(request: LiveRequest): Promise<string>;
+  // Returns a function that closes the stream
+  subscribeLive(sessionId: string, handlers: LiveHandlers): () => void;
+  stopLive(sessionId: string): Promise<SessionMeta>;
   getMeta(sessionId: string): Promise<SessionMeta>;
-  fetchWindow(
-    sessionId: string,
-    query: { from_ns?: number; to_ns?: number; limit?: number; cursor?: number }
-  ): Promise<Table>;
+  fetchWindow(sessionId: string, query: WindowQuery): Promise<Table>;
+  fetchWindowPage(sessionId: string, query: WindowQuery): Promise<WindowPage>;
   search(sessionId: string, filter: FilterExpr, highlight?: HighlightExpr): Promise<Table>;
+  getStats(sessionId: string, request: StatsRequest): Promise<StatsResponse>;
+  getLatency(sessionId: string, request: LatencyRequest): Promise<LatencyResponse>;
+  // Columns: bucket_ns, dir, s, count, t_min_ns, t_max_ns
+  fetchTiles(sessionId: string, query: TilesQuery): Promise<Table>;
+  getAlarms(sessionId: string, query?: AlarmsQuery): Promise<SessionAlarms>;
+  getStates(sessionId: string, query?: StatesQuery): Promise<SessionStates>;
+  getTimers(sessionId: string, query?: TimersQuery): Promise<SessionTimers>;
+  getConformance(sessionId: string): Promise<ConformanceSummary>;
   getPayload(sessionId: string, rowId: number): Promise<any>;
   deleteSession(sessionId: string): Promise<void>;
 }